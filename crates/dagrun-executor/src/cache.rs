@@ -0,0 +1,254 @@
+//! Input-hash caching for `@inputs` and output caching for `@cache`
+//!
+//! The SHA-256 of a task's resolved command, its `@inputs` globs and every file
+//! they match is stored in `.dagrun-cache/<task>/inputs.sha256` after a
+//! successful run, with the run's stdout next to it in `output`. A later run
+//! with the same hash is skipped and replays that output, so a task run with
+//! other arguments or variables runs again.
+//!
+//! The stdout of a successful `@cache` task is stored in the user cache
+//! directory at `dagrun/<task>/<hash>`, hashing the key together with the
+//! task's resolved command and the directory it runs from, so another project
+//! or a changed command never replays a stale result. A later run with the
+//! same key, command and directory replays it.
+//!
+//! Either kind of entry only counts while every `@outputs` file of the task
+//! exists and is no older than the run that stored the entry; otherwise the
+//! task runs again.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// File timestamps come from a coarse clock that can lag the system clock, so
+/// an entry is dated this much before its run started
+const MTIME_SLACK: Duration = Duration::from_secs(1);
+
+fn inputs_dir(task: &str) -> PathBuf {
+    PathBuf::from(".dagrun-cache").join(task)
+}
+
+/// Hash `command`, the `patterns` and the contents of all files matching them,
+/// in sorted path order
+pub fn inputs_hash(command: &str, patterns: &[String]) -> io::Result<String> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let matches = glob::glob(pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        paths.extend(matches.filter_map(Result::ok).filter(|p| p.is_file()));
+    }
+    paths.sort();
+    paths.dedup();
+
+    let mut hasher = Sha256::new();
+    // NUL-separated, like output_path, so the parts can't run together
+    for part in std::iter::once(command).chain(patterns.iter().map(String::as_str)) {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    for path in &paths {
+        hasher.update(fs::read(path)?);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The stdout of the task's last successful run, if it ran with inputs
+/// hashing to `hash` and its `outputs` are still in place
+pub fn fresh_output(task: &str, hash: &str, outputs: &[String]) -> Option<String> {
+    let dir = inputs_dir(task);
+    let stored = fs::read_to_string(dir.join("inputs.sha256")).ok()?;
+    if stored.trim() != hash {
+        return None;
+    }
+    read_entry(&dir.join("output"), outputs)
+}
+
+/// Store the output of a run that started at `started`
+pub fn store(task: &str, hash: &str, output: &str, started: SystemTime) -> io::Result<()> {
+    let dir = inputs_dir(task);
+    fs::create_dir_all(&dir)?;
+    // output first, so a hash on disk always has the output of its run
+    write_entry(&dir.join("output"), output, started)?;
+    fs::write(dir.join("inputs.sha256"), hash)
+}
+
+/// Write `output` dated to the start of its run, which is what the outputs it
+/// produced are checked against
+fn write_entry(path: &Path, output: &str, started: SystemTime) -> io::Result<()> {
+    fs::write(path, output)?;
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(started.checked_sub(MTIME_SLACK).unwrap_or(started))
+}
+
+/// The stored output at `path`, unless one of `outputs` is missing or older
+/// than the entry
+fn read_entry(path: &Path, outputs: &[String]) -> Option<String> {
+    let written = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    if !outputs_valid(outputs, written) {
+        return None;
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// Whether every declared output exists and was modified no earlier than
+/// `written`
+fn outputs_valid(outputs: &[String], written: SystemTime) -> bool {
+    outputs.iter().all(|path| {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified >= written)
+    })
+}
+
+/// `~/.cache/dagrun` on Linux, or the platform's equivalent
+fn output_root() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("dagrun"))
+}
+
+fn output_path(root: &Path, project: &Path, task: &str, command: &str, key: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    // NUL-separated so no two (project, command, key) triples hash the same input
+    for part in [
+        project.as_os_str().as_encoded_bytes(),
+        command.as_bytes(),
+        key.as_bytes(),
+    ] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    root.join(task).join(hex(&hasher.finalize()))
+}
+
+/// The directory tasks run from, which identifies the project
+fn project_dir() -> io::Result<PathBuf> {
+    std::env::current_dir()?.canonicalize()
+}
+
+/// The stdout stored by the task's last successful run of `command` with `key`,
+/// if its `outputs` are still in place
+pub fn load_output(task: &str, command: &str, key: &str, outputs: &[String]) -> Option<String> {
+    let path = output_path(&output_root()?, &project_dir().ok()?, task, command, key);
+    read_entry(&path, outputs)
+}
+
+/// Store the output of a run of `command` with `key` that started at `started`
+pub fn store_output(
+    task: &str,
+    command: &str,
+    key: &str,
+    output: &str,
+    started: SystemTime,
+) -> io::Result<()> {
+    let root = output_root()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+    let path = output_path(&root, &project_dir()?, task, command, key);
+    fs::create_dir_all(path.parent().unwrap())?;
+    write_entry(&path, output, started)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_hash_tracks_contents() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        let pattern = vec![format!("{}/*.txt", dir.path().display())];
+
+        fs::write(&file, "one").unwrap();
+        let first = inputs_hash("make", &pattern).unwrap();
+        assert_eq!(first, inputs_hash("make", &pattern).unwrap());
+
+        fs::write(&file, "two").unwrap();
+        assert_ne!(first, inputs_hash("make", &pattern).unwrap());
+    }
+
+    #[test]
+    fn test_inputs_hash_tracks_command_and_patterns() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "one").unwrap();
+        let pattern = vec![format!("{}/*.txt", dir.path().display())];
+
+        let first = inputs_hash("deploy prod", &pattern).unwrap();
+        assert_ne!(first, inputs_hash("deploy staging", &pattern).unwrap());
+
+        // same files, but matched by another pattern
+        let other = vec![format!("{}/a.*", dir.path().display())];
+        assert_ne!(first, inputs_hash("deploy prod", &other).unwrap());
+    }
+
+    #[test]
+    fn test_entry_needs_its_outputs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let entry = dir.path().join("entry");
+        let artifact = dir.path().join("app");
+        let outputs = vec![artifact.display().to_string()];
+
+        let started = SystemTime::now();
+        write_entry(&entry, "built", started).unwrap();
+        assert_eq!(read_entry(&entry, &[]).as_deref(), Some("built"));
+        // never produced
+        assert_eq!(read_entry(&entry, &outputs), None);
+
+        fs::write(&artifact, "binary").unwrap();
+        assert_eq!(read_entry(&entry, &outputs).as_deref(), Some("built"));
+
+        // left over from before the run that stored the entry
+        fs::File::options()
+            .write(true)
+            .open(&artifact)
+            .unwrap()
+            .set_modified(started - Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(read_entry(&entry, &outputs), None);
+    }
+
+    #[test]
+    fn test_output_path_is_per_task_and_key() {
+        let root = Path::new("/cache/dagrun");
+        let project = Path::new("/src/app");
+        let path = output_path(root, project, "build", "cargo build", "3f2a  Cargo.lock");
+        assert_eq!(path.parent().unwrap(), root.join("build"));
+        assert_eq!(path.file_name().unwrap().len(), 64);
+        assert_eq!(
+            path,
+            output_path(root, project, "build", "cargo build", "3f2a  Cargo.lock")
+        );
+        assert_ne!(
+            path,
+            output_path(root, project, "build", "cargo build", "3f2b  Cargo.lock")
+        );
+        assert_ne!(
+            path,
+            output_path(root, project, "test", "cargo build", "3f2a  Cargo.lock")
+        );
+    }
+
+    #[test]
+    fn test_output_path_is_per_command_and_project() {
+        let root = Path::new("/cache/dagrun");
+        let path = output_path(root, Path::new("/src/app"), "build", "make", "k");
+        assert_ne!(
+            path,
+            output_path(root, Path::new("/src/app"), "build", "make all", "k")
+        );
+        assert_ne!(
+            path,
+            output_path(root, Path::new("/src/other"), "build", "make", "k")
+        );
+        // the separator keeps the parts from running together
+        assert_ne!(
+            output_path(root, Path::new("/a"), "t", "bc", "d"),
+            output_path(root, Path::new("/a"), "t", "b", "cd")
+        );
+    }
+}
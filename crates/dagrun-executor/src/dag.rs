@@ -1,8 +1,9 @@
 #![allow(dead_code)]
 
+use petgraph::Direction;
 use petgraph::algo::{is_cyclic_directed, toposort};
 use petgraph::graph::{DiGraph, NodeIndex};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
 use dr_ast::{Config, K8sMode, Task};
@@ -13,6 +14,12 @@ pub enum DagError {
     CycleDetected,
     #[error("task not found: {0}")]
     TaskNotFound(String),
+    #[error("task '{0}' is not in the dependency chain of '{1}'")]
+    NotInChain(String, String),
+    #[error("task '{0}' depends on unknown task '{1}'")]
+    UnknownDependency(String, String),
+    #[error("task '{0}' has unknown @on-failure handler '{1}'")]
+    UnknownHandler(String, String),
 }
 
 pub struct TaskGraph {
@@ -36,9 +43,17 @@ impl TaskGraph {
         for task in config.tasks.values() {
             let task_idx = node_map[&task.name];
             for dep in &task.depends_on {
-                let dep_idx = node_map[dep];
+                let dep_idx = *node_map
+                    .get(dep)
+                    .ok_or_else(|| DagError::UnknownDependency(task.name.clone(), dep.clone()))?;
                 graph.add_edge(dep_idx, task_idx, ());
             }
+            // handlers aren't edges: they only run when the task fails
+            if let Some(handler) = &task.on_failure
+                && !node_map.contains_key(handler)
+            {
+                return Err(DagError::UnknownHandler(task.name.clone(), handler.clone()));
+            }
         }
 
         if is_cyclic_directed(&graph) {
@@ -74,7 +89,7 @@ impl TaskGraph {
 
         // find all ancestors of target (tasks that must run before it)
         let mut required: HashMap<NodeIndex, bool> = HashMap::new();
-        self.collect_ancestors(*target_idx, &mut required);
+        self.collect_reachable(*target_idx, Direction::Incoming, &mut required);
         required.insert(*target_idx, true);
 
         // filter toposort to only required nodes
@@ -90,18 +105,106 @@ impl TaskGraph {
             .collect())
     }
 
-    fn collect_ancestors(&self, node: NodeIndex, visited: &mut HashMap<NodeIndex, bool>) {
-        for neighbor in self
-            .graph
-            .neighbors_directed(node, petgraph::Direction::Incoming)
-        {
+    /// `execution_order_for(target)` starting at `from`: only `from` and the
+    /// tasks in the chain that depend on it, directly or indirectly
+    pub fn execution_order_from(&self, target: &str, from: &str) -> Result<Vec<&Task>, DagError> {
+        let chain = self.execution_order_for(target)?;
+        let from_idx = self
+            .node_map
+            .get(from)
+            .filter(|_| chain.iter().any(|t| t.name == from))
+            .ok_or_else(|| DagError::NotInChain(from.to_string(), target.to_string()))?;
+
+        let mut after: HashMap<NodeIndex, bool> = HashMap::new();
+        self.collect_reachable(*from_idx, Direction::Outgoing, &mut after);
+        after.insert(*from_idx, true);
+
+        Ok(chain
+            .into_iter()
+            .filter(|t| after.contains_key(&self.node_map[&t.name]))
+            .collect())
+    }
+
+    /// `execution_order_for(target)` without the tasks in `skip`; their
+    /// dependents stay in the order
+    pub fn execution_order_excluding(
+        &self,
+        target: &str,
+        skip: &HashSet<String>,
+    ) -> Result<Vec<&Task>, DagError> {
+        if let Some(missing) = skip.iter().find(|name| !self.tasks.contains_key(*name)) {
+            return Err(DagError::TaskNotFound(missing.clone()));
+        }
+        Ok(self
+            .execution_order_for(target)?
+            .into_iter()
+            .filter(|t| !skip.contains(&t.name))
+            .collect())
+    }
+
+    /// collect nodes reachable from `node` (Incoming = ancestors, Outgoing = dependents)
+    fn collect_reachable(
+        &self,
+        node: NodeIndex,
+        direction: Direction,
+        visited: &mut HashMap<NodeIndex, bool>,
+    ) {
+        for neighbor in self.graph.neighbors_directed(node, direction) {
             if let std::collections::hash_map::Entry::Vacant(e) = visited.entry(neighbor) {
                 e.insert(true);
-                self.collect_ancestors(neighbor, visited);
+                self.collect_reachable(neighbor, direction, visited);
             }
         }
     }
 
+    /// get the subgraph of `name` and everything it depends on
+    pub fn subgraph_for(&self, name: &str) -> TaskGraph {
+        self.subgraph(name, Direction::Incoming)
+    }
+
+    /// get the subgraph of `name` and every task that depends on it
+    pub fn dependents_subgraph_for(&self, name: &str) -> TaskGraph {
+        self.subgraph(name, Direction::Outgoing)
+    }
+
+    fn subgraph(&self, name: &str, direction: Direction) -> TaskGraph {
+        let mut keep: HashMap<NodeIndex, bool> = HashMap::new();
+        if let Some(&idx) = self.node_map.get(name) {
+            keep.insert(idx, true);
+            self.collect_reachable(idx, direction, &mut keep);
+        }
+
+        let mut graph = DiGraph::new();
+        let mut node_map = HashMap::new();
+        for idx in keep.keys() {
+            let name = &self.graph[*idx];
+            node_map.insert(name.clone(), graph.add_node(name.clone()));
+        }
+        for edge in self.graph.raw_edges() {
+            let (from, to) = (&self.graph[edge.source()], &self.graph[edge.target()]);
+            if let (Some(&from), Some(&to)) = (node_map.get(from), node_map.get(to)) {
+                graph.add_edge(from, to, ());
+            }
+        }
+
+        // drop dependencies on tasks outside the subgraph so renderers don't draw them
+        let tasks = node_map
+            .keys()
+            .map(|name| {
+                let mut task = self.tasks[name].clone();
+                task.depends_on.retain(|d| node_map.contains_key(d));
+                task.service_deps.retain(|s| node_map.contains_key(s));
+                (name.clone(), task)
+            })
+            .collect();
+
+        TaskGraph {
+            graph,
+            node_map,
+            tasks,
+        }
+    }
+
     /// get tasks that can run in parallel (no deps on each other)
     pub fn parallel_groups(&self) -> Result<Vec<Vec<&Task>>, DagError> {
         let mut groups: Vec<Vec<&Task>> = Vec::new();
@@ -115,7 +218,7 @@ impl TaskGraph {
                 .filter(|idx| !completed.contains_key(idx))
                 .filter(|idx| {
                     self.graph
-                        .neighbors_directed(*idx, petgraph::Direction::Incoming)
+                        .neighbors_directed(*idx, Direction::Incoming)
                         .all(|dep| completed.contains_key(&dep))
                 })
                 .collect();
@@ -176,15 +279,7 @@ impl TaskGraph {
             let (color, shape, type_prefix) = if task.is_join() {
                 ("#e8e8e8", "diamond", "")
             } else if task.k8s.is_some() {
-                let mode = task
-                    .k8s
-                    .as_ref()
-                    .map(|k| match k.mode {
-                        K8sMode::Job => "job",
-                        K8sMode::Exec => "exec",
-                        K8sMode::Apply => "apply",
-                    })
-                    .unwrap_or("k8s");
+                let mode = task.k8s.as_ref().map(|k| k.mode.as_str()).unwrap_or("k8s");
                 ("#b8e6b8", "box", mode)
             } else if task.ssh.is_some() {
                 ("#a8d5ff", "box", "ssh")
@@ -248,6 +343,7 @@ impl TaskGraph {
                     Some(K8sMode::Job) => "☸job ",
                     Some(K8sMode::Exec) => "☸exec ",
                     Some(K8sMode::Apply) => "☸apply ",
+                    Some(K8sMode::Debug { .. }) => "☸debug ",
                     None => "☸ ",
                 }
             } else if task.ssh.is_some() {
@@ -320,7 +416,7 @@ impl TaskGraph {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dr_ast::DotenvSettings;
+    use dr_ast::{DotenvSettings, ErrorHandling, PagerMode};
 
     fn make_task(name: &str, run: &str, depends_on: Vec<&str>) -> Task {
         Task {
@@ -337,6 +433,16 @@ mod tests {
             k8s: None,
             service: None,
             shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
             span: None,
         }
     }
@@ -351,6 +457,10 @@ mod tests {
         let config = Config {
             tasks,
             dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
         };
         let graph = TaskGraph::from_config(config).unwrap();
         let groups = graph.parallel_groups().unwrap();
@@ -360,6 +470,117 @@ mod tests {
         assert_eq!(groups[1].len(), 1); // c runs after
     }
 
+    #[test]
+    fn test_subgraph_for() {
+        let mut tasks = HashMap::new();
+        tasks.insert("a".to_string(), make_task("a", "echo a", vec![]));
+        tasks.insert("b".to_string(), make_task("b", "echo b", vec!["a"]));
+        tasks.insert("c".to_string(), make_task("c", "echo c", vec!["b"]));
+        tasks.insert("d".to_string(), make_task("d", "echo d", vec!["a"]));
+        tasks.insert("e".to_string(), make_task("e", "echo e", vec!["c", "d"]));
+
+        let config = Config {
+            tasks,
+            dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
+        };
+        let graph = TaskGraph::from_config(config).unwrap();
+
+        let sub = graph.subgraph_for("c");
+        let mut names = sub.task_names();
+        names.sort();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(sub.parallel_groups().unwrap().len(), 3);
+
+        let sub = graph.dependents_subgraph_for("c");
+        let mut names = sub.task_names();
+        names.sort();
+        assert_eq!(names, ["c", "e"]);
+        // e's dependency on d is outside the subgraph
+        assert_eq!(sub.task("e").unwrap().depends_on, ["c"]);
+
+        assert!(graph.subgraph_for("missing").task_names().is_empty());
+    }
+
+    #[test]
+    fn test_execution_order_from() {
+        let mut tasks = HashMap::new();
+        tasks.insert("build".to_string(), make_task("build", "echo", vec![]));
+        tasks.insert("lint".to_string(), make_task("lint", "echo", vec![]));
+        tasks.insert(
+            "package".to_string(),
+            make_task("package", "echo", vec!["build"]),
+        );
+        tasks.insert(
+            "deploy".to_string(),
+            make_task("deploy", "echo", vec!["package", "lint"]),
+        );
+        tasks.insert("docs".to_string(), make_task("docs", "echo", vec![]));
+
+        let config = Config {
+            tasks,
+            dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
+        };
+        let graph = TaskGraph::from_config(config).unwrap();
+
+        let names = |order: Vec<&Task>| order.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            names(graph.execution_order_from("deploy", "package").unwrap()),
+            ["package", "deploy"]
+        );
+        assert!(matches!(
+            graph.execution_order_from("deploy", "docs"),
+            Err(DagError::NotInChain(..))
+        ));
+        assert!(matches!(
+            graph.execution_order_from("deploy", "missing"),
+            Err(DagError::NotInChain(..))
+        ));
+    }
+
+    #[test]
+    fn test_execution_order_excluding() {
+        let mut tasks = HashMap::new();
+        tasks.insert("build".to_string(), make_task("build", "echo", vec![]));
+        tasks.insert("test".to_string(), make_task("test", "echo", vec!["build"]));
+        tasks.insert(
+            "deploy".to_string(),
+            make_task("deploy", "echo", vec!["test"]),
+        );
+
+        let config = Config {
+            tasks,
+            dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
+        };
+        let graph = TaskGraph::from_config(config).unwrap();
+
+        let skip = HashSet::from(["test".to_string()]);
+        let names: Vec<_> = graph
+            .execution_order_excluding("deploy", &skip)
+            .unwrap()
+            .iter()
+            .map(|t| t.name.clone())
+            .collect();
+        assert_eq!(names, ["build", "deploy"]);
+
+        let skip = HashSet::from(["tset".to_string()]);
+        assert!(matches!(
+            graph.execution_order_excluding("deploy", &skip),
+            Err(DagError::TaskNotFound(name)) if name == "tset"
+        ));
+    }
+
     #[test]
     fn test_cycle_detection() {
         let mut tasks = HashMap::new();
@@ -369,8 +590,36 @@ mod tests {
         let config = Config {
             tasks,
             dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
         };
         let result = TaskGraph::from_config(config);
         assert!(matches!(result, Err(DagError::CycleDetected)));
     }
+
+    #[test]
+    fn test_unknown_task_references() {
+        let config = |task: Task| Config {
+            tasks: HashMap::from([(task.name.clone(), task)]),
+            dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
+        };
+
+        let result = TaskGraph::from_config(config(make_task("a", "echo a", vec!["missing"])));
+        assert!(
+            matches!(result, Err(DagError::UnknownDependency(task, dep)) if task == "a" && dep == "missing")
+        );
+
+        let mut task = make_task("a", "echo a", vec![]);
+        task.on_failure = Some("cleanup".to_string());
+        let result = TaskGraph::from_config(config(task));
+        assert!(
+            matches!(result, Err(DagError::UnknownHandler(task, handler)) if task == "a" && handler == "cleanup")
+        );
+    }
 }
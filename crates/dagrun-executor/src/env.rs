@@ -1,13 +1,14 @@
 //! Environment variable and dotenv support
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
 use dr_ast::{DotenvSettings, ReadinessCheck, ServiceKind};
 
-/// Load environment variables from dotenv files
-pub fn load_dotenv(settings: &DotenvSettings) -> Result<(), String> {
+/// Load environment variables from dotenv files. With `dotenv-override`, the
+/// names in `keep` (those from `--env-file`) are still left alone
+pub fn load_dotenv(settings: &DotenvSettings, keep: &HashSet<String>) -> Result<(), String> {
     if !settings.load {
         return Ok(());
     }
@@ -21,7 +22,12 @@ pub fn load_dotenv(settings: &DotenvSettings) -> Result<(), String> {
     for path in &paths {
         let p = Path::new(path);
         if p.exists() {
-            match dotenvy::from_path(p) {
+            let loaded = if settings.override_existing {
+                override_from_path(p, keep)
+            } else {
+                dotenvy::from_path(p)
+            };
+            match loaded {
                 Ok(_) => info!(path = %path, "loaded dotenv file"),
                 Err(e) => {
                     if settings.required {
@@ -38,6 +44,37 @@ pub fn load_dotenv(settings: &DotenvSettings) -> Result<(), String> {
     Ok(())
 }
 
+/// Set every variable in the dotenv file at `path`, except the names in `keep`
+fn override_from_path(path: &Path, keep: &HashSet<String>) -> Result<(), dotenvy::Error> {
+    for item in dotenvy::from_path_iter(path)? {
+        let (key, value) = item?;
+        if !keep.contains(&key) {
+            // SAFETY: dotenv files are loaded at startup, before any task runs
+            // or anything else reads the environment
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+    Ok(())
+}
+
+/// Load env files given with `--env-file`; every file must exist. Returns the
+/// names the files define, which the dagfile's dotenv files don't override
+///
+/// Loading never overrides variables that are already set, so files are read
+/// last to first to let later files win over earlier ones.
+pub fn load_env_files(paths: &[PathBuf]) -> Result<HashSet<String>, String> {
+    let mut names = HashSet::new();
+    for path in paths.iter().rev() {
+        let err = |e: dotenvy::Error| format!("{}: {}", path.display(), e);
+        dotenvy::from_path(path).map_err(err)?;
+        for item in dotenvy::from_path_iter(path).map_err(err)? {
+            names.insert(item.map_err(err)?.0);
+        }
+        info!(path = %path.display(), "loaded env file");
+    }
+    Ok(names)
+}
+
 /// Generate environment variables for a ready service
 /// If forwarded_port is Some, it means we have a tunnel and should expose the local endpoint
 pub fn service_env_vars(
@@ -56,6 +93,7 @@ pub fn service_env_vars(
     let kind_str = match kind {
         ServiceKind::Managed => "managed",
         ServiceKind::External => "external",
+        ServiceKind::Docker { .. } => "docker",
     };
     vars.insert(format!("{}_KIND", prefix), kind_str.to_string());
 
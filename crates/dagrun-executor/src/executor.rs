@@ -0,0 +1,2497 @@
+#![allow(dead_code)]
+
+use colored::Colorize;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{RwLock, Semaphore, watch};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, error, info, warn};
+
+use crate::progress::task_color;
+use crate::recorder::{NoOpRecorder, OutputChunk, Recorder, RunId, TaskExecutionId};
+
+use crate::cache;
+use crate::dag::TaskGraph;
+use crate::k8s::{self, ResourceTracker};
+use crate::service::ServiceManager;
+use crate::ssh::{self, SessionCache};
+use dr_ast::{ErrorHandling, FileTransfer, K8sMode, Shebang, SshConfig, Task};
+use glob::glob;
+use serde::Serialize;
+use shell_escape::escape;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+
+#[derive(Error, Debug)]
+pub enum ExecutorError {
+    #[error("task '{0}' failed after {1} attempts")]
+    TaskFailed(String, u32),
+    #[error("task '{0}' exited with code {1}")]
+    Exited(String, i32),
+    #[error("task '{0}' timed out after {1:?}")]
+    Timeout(String, Duration),
+    #[error("task '{0}' not found")]
+    TaskNotFound(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("dag error: {0}")]
+    Dag(#[from] crate::dag::DagError),
+    #[error("ssh error: {0}")]
+    Ssh(String),
+    #[error("k8s error: {0}")]
+    K8s(String),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("task '{0}' was cancelled")]
+    Cancelled(String),
+    #[error("task '{0}' did not produce artifact '{1}' at {2}")]
+    MissingArtifact(String, String, String),
+    #[error(
+        "task '{0}' uses {{{{artifact.{1}}}}}, but no task that ran before it produced that artifact"
+    )]
+    UnresolvedArtifact(String, String),
+}
+
+impl ExecutorError {
+    /// A failed process with a known exit code, or a generic task failure
+    fn exited(task: &Task, code: Option<i32>) -> Self {
+        match code {
+            Some(code) => ExecutorError::Exited(task.name.clone(), code),
+            None => ExecutorError::TaskFailed(task.name.clone(), 1),
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            ExecutorError::Exited(_, code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Pending,
+    Running,
+    Success,
+    Failed,
+    Skipped,
+}
+
+/// How `--dry-run` prints each task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRunFormat {
+    /// Shell commands, with annotations as `#` comments, that can be saved and run
+    Script,
+    /// One `[dry-run] <task>: <cmd>` line per task, with its ssh or k8s target
+    Lines,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TaskResult {
+    pub task_name: String,
+    pub status: TaskStatus,
+    pub attempts: u32,
+    /// Wall time from the first attempt to the last, 0 when nothing ran
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub output: String,
+    /// Exit code of the task's process (local, ssh or k8s), when it ran one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// shared state for tracking task outputs during execution
+type OutputStore = Arc<RwLock<HashMap<String, String>>>;
+
+/// absolute paths of `@artifact` files from successful tasks, keyed by `task.name`
+type ArtifactStore = Arc<RwLock<HashMap<String, PathBuf>>>;
+
+/// tasks claimed in this run; the receiver gets the result once the claiming caller
+/// finishes, and failed tasks are dropped so they can run again
+type TaskRuns = Arc<RwLock<HashMap<String, watch::Receiver<Option<TaskResult>>>>>;
+
+/// how long a cancelled task's process gets to exit after SIGTERM
+const TERMINATE_GRACE: Duration = Duration::from_secs(5);
+
+pub struct Executor {
+    pub graph: TaskGraph,
+    outputs: OutputStore,
+    /// values captured by `@output-capture`, keyed by variable name
+    captures: OutputStore,
+    artifact_store: ArtifactStore,
+    /// tasks running or already succeeded in this run, so none runs twice
+    completed_tasks: TaskRuns,
+    ssh_sessions: SessionCache,
+    services: Arc<ServiceManager>,
+    k8s_tracker: ResourceTracker,
+    recorder: Arc<dyn Recorder>,
+    /// config file recorded with each run
+    config_path: String,
+    error_handling: ErrorHandling,
+    /// env vars from `--env`, applied over everything else
+    cli_env: HashMap<String, String>,
+    /// task run attached to the terminal (`--interactive`)
+    interactive: Option<String>,
+    /// print commands instead of running them (`--dry-run`)
+    dry_run: Option<DryRunFormat>,
+    /// per-tag limits on concurrently running tasks (`concurrency-by-tag`)
+    tag_limits: HashMap<String, Arc<Semaphore>>,
+    /// limit on tasks running at once in a parallel group (`--concurrency`)
+    concurrency: Option<usize>,
+    /// stops running tasks and starts no more once cancelled (Ctrl-C)
+    cancel: CancellationToken,
+    /// task to start the chain at, skipping what comes before it (`--from`)
+    from: Option<String>,
+    /// tasks left out of the chain (`--skip`)
+    skip: HashSet<String>,
+    /// hand our own stdin to the first task run, unless it has `@pipe_from`;
+    /// cleared once a task has run
+    forward_stdin: AtomicBool,
+}
+
+impl Executor {
+    pub fn new(graph: TaskGraph, recorder: Arc<dyn Recorder>) -> Self {
+        let ssh_sessions = ssh::new_session_cache();
+        Executor {
+            graph,
+            outputs: Arc::new(RwLock::new(HashMap::new())),
+            captures: Arc::new(RwLock::new(HashMap::new())),
+            artifact_store: Arc::new(RwLock::new(HashMap::new())),
+            completed_tasks: Arc::new(RwLock::new(HashMap::new())),
+            ssh_sessions: ssh_sessions.clone(),
+            services: Arc::new(ServiceManager::with_ssh_cache(ssh_sessions)),
+            k8s_tracker: k8s::new_tracker(),
+            recorder,
+            config_path: String::new(),
+            error_handling: ErrorHandling::default(),
+            cli_env: HashMap::new(),
+            interactive: None,
+            dry_run: None,
+            tag_limits: HashMap::new(),
+            concurrency: None,
+            cancel: CancellationToken::new(),
+            from: None,
+            skip: HashSet::new(),
+            forward_stdin: AtomicBool::new(false),
+        }
+    }
+
+    /// Set the config file path recorded in run history
+    pub fn with_config_path(mut self, path: impl Into<String>) -> Self {
+        self.config_path = path.into();
+        self
+    }
+
+    /// Set what happens after a task fails (`set error-handling := ...`)
+    pub fn with_error_handling(mut self, error_handling: ErrorHandling) -> Self {
+        self.error_handling = error_handling;
+        self
+    }
+
+    /// Set env vars passed on the command line (`--env KEY=VALUE`)
+    pub fn with_env(mut self, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.cli_env.extend(env);
+        self
+    }
+
+    /// Run `task` with the terminal's stdin/stdout/stderr and no timeout (`--interactive`)
+    pub fn with_interactive(mut self, task: impl Into<String>) -> Self {
+        self.interactive = Some(task.into());
+        self
+    }
+
+    /// Print each task's commands in `format` instead of running them. Services
+    /// are still acquired and released, but never started
+    pub fn with_dry_run(mut self, format: Option<DryRunFormat>) -> Self {
+        self.dry_run = format;
+        self.services.set_dry_run(format.is_some());
+        self
+    }
+
+    /// Limit how many tasks with each tag run at once; later entries for a tag win
+    pub fn with_tag_concurrency(
+        mut self,
+        limits: impl IntoIterator<Item = (String, usize)>,
+    ) -> Self {
+        for (tag, limit) in limits {
+            self.tag_limits.insert(tag, Arc::new(Semaphore::new(limit)));
+        }
+        self
+    }
+
+    /// Limit how many tasks run at once; `None` runs every ready task together
+    pub fn with_concurrency(mut self, limit: Option<usize>) -> Self {
+        self.concurrency = limit;
+        self
+    }
+
+    /// Stop in-flight tasks and skip the rest when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Start a target's chain at `task`; tasks that don't depend on it are skipped
+    pub fn with_from(mut self, task: impl Into<String>) -> Self {
+        self.from = Some(task.into());
+        self
+    }
+
+    /// Pipe our stdin, when it isn't a terminal, into the first task that runs
+    pub fn with_forward_stdin(self, forward: bool) -> Self {
+        self.forward_stdin.store(forward, Ordering::Relaxed);
+        self
+    }
+
+    /// Leave `tasks` out of every chain; their dependents still run
+    pub fn with_skip(mut self, tasks: impl IntoIterator<Item = String>) -> Self {
+        self.skip.extend(tasks);
+        self
+    }
+
+    /// The target's chain split into tasks to run and tasks left out by `--from`
+    /// or `--skip`, both in execution order
+    fn plan(&self, target: &str) -> Result<(Vec<&Task>, Vec<&Task>), ExecutorError> {
+        let names = |tasks: Vec<&Task>| -> HashSet<String> {
+            tasks.into_iter().map(|t| t.name.clone()).collect()
+        };
+        let chain = self.graph.execution_order_for(target)?;
+        let from = match self.from {
+            Some(ref from) => Some(names(self.graph.execution_order_from(target, from)?)),
+            None => None,
+        };
+        let kept = names(self.graph.execution_order_excluding(target, &self.skip)?);
+        Ok(chain.into_iter().partition(|t| {
+            kept.contains(&t.name) && from.as_ref().is_none_or(|from| from.contains(&t.name))
+        }))
+    }
+
+    /// Semaphores for the task's limited tags, in tag order so tasks sharing
+    /// several tags always acquire them in the same order
+    fn tag_semaphores(&self, task: &Task) -> Vec<Arc<Semaphore>> {
+        let mut tags: Vec<&String> = task.tags.iter().collect();
+        tags.sort();
+        tags.dedup();
+        tags.into_iter()
+            .filter_map(|tag| self.tag_limits.get(tag).cloned())
+            .collect()
+    }
+
+    fn is_interactive(&self, task: &Task) -> bool {
+        self.interactive.as_deref() == Some(task.name.as_str())
+    }
+
+    fn stop_on_failure(&self) -> bool {
+        self.error_handling == ErrorHandling::StopOnFirstFailure
+    }
+
+    /// Create an executor without recording (uses NoOpRecorder).
+    pub fn new_without_recording(graph: TaskGraph) -> Self {
+        Self::new(graph, Arc::new(NoOpRecorder))
+    }
+
+    /// Build the task graph for `config` and an executor without recording
+    pub fn from_config(config: dr_ast::Config) -> Result<Self, ExecutorError> {
+        Ok(Self::new_without_recording(TaskGraph::from_config(config)?))
+    }
+
+    /// Register all services from the graph
+    pub async fn register_services(&self) {
+        for name in self.graph.task_names() {
+            if let Some(task) = self.graph.task(name)
+                && task.service.is_some()
+            {
+                self.services.register(task).await;
+            }
+        }
+    }
+
+    /// Close all SSH connections, stop services, and cleanup K8s resources
+    pub async fn close(&self) {
+        self.services.shutdown().await;
+        self.k8s_tracker.read().await.cleanup_all().await;
+        ssh::close_sessions(&self.ssh_sessions).await;
+    }
+
+    /// Run `target` after the tasks it depends on
+    pub async fn run_task(&self, target: &str) -> Result<Vec<TaskResult>, ExecutorError> {
+        self.run_task_with_args(target, &[]).await
+    }
+
+    /// Run task with positional arguments bound to parameters
+    pub async fn run_task_with_args(
+        &self,
+        target: &str,
+        task_args: &[String],
+    ) -> Result<Vec<TaskResult>, ExecutorError> {
+        let (tasks, left_out) = self.plan(target)?;
+
+        // Record run start
+        let run_id = self
+            .recorder
+            .record_run_start(&self.config_path, target, task_args)
+            .await
+            .ok();
+
+        let start = Instant::now();
+
+        // if no args, just run normally
+        let results = if task_args.is_empty() {
+            self.execute_sequential(tasks, run_id).await
+        } else {
+            // build name->value mapping from target task's parameters
+            let target_task = self
+                .graph
+                .task(target)
+                .ok_or_else(|| ExecutorError::TaskNotFound(target.to_string()))?;
+            let bindings = build_param_bindings(target_task, task_args)
+                .map_err(ExecutorError::InvalidArgument)?;
+
+            // apply bindings to all tasks in the chain
+            let mut results = Vec::new();
+            let mut blocked = HashSet::new();
+            for task in tasks {
+                if self.cancel.is_cancelled() {
+                    break;
+                }
+                if depends_on_blocked(task, &blocked) {
+                    blocked.insert(task.name.clone());
+                    results.push(skipped_result(task));
+                    continue;
+                }
+
+                let task_to_run = apply_bindings(task, &bindings)
+                    .map_err(|_| ExecutorError::TaskFailed(task.name.clone(), 0))?;
+
+                let result = self.execute_single_with_run(&task_to_run, run_id).await;
+                let failed = result.status == TaskStatus::Failed;
+                results.push(result);
+                if failed {
+                    self.run_failure_handler(task).await;
+                    if self.stop_on_failure() {
+                        break;
+                    }
+                    blocked.insert(task.name.clone());
+                }
+            }
+            Ok(results)
+        };
+        let results = results.map(|ran| {
+            left_out
+                .into_iter()
+                .map(left_out_result)
+                .chain(ran)
+                .collect::<Vec<_>>()
+        });
+
+        // Record run completion
+        if let Some(rid) = run_id {
+            let success = results
+                .as_ref()
+                .map(|r| r.iter().all(|t| t.status != TaskStatus::Failed))
+                .unwrap_or(false);
+            let _ = self
+                .recorder
+                .record_run_complete(rid, success, start.elapsed())
+                .await;
+        }
+
+        results
+    }
+
+    /// Run the target's whole dependency closure at once, ignoring ordering.
+    /// Unsafe by design: tasks may start before their dependencies finish.
+    pub async fn run_task_parallel(&self, target: &str) -> Result<Vec<TaskResult>, ExecutorError> {
+        let tasks = self.graph.execution_order_for(target)?;
+        self.execute_parallel(tasks, None).await
+    }
+
+    /// Run every task in the graph, each group of independent tasks at once
+    pub async fn run_all(&self) -> Result<Vec<TaskResult>, ExecutorError> {
+        let groups = self.graph.parallel_groups()?;
+
+        // Record run start (target is "all" for run_all)
+        let run_id = self
+            .recorder
+            .record_run_start(&self.config_path, "all", &[])
+            .await
+            .ok();
+
+        let start = Instant::now();
+        let mut all_results = Vec::new();
+        let mut blocked = HashSet::new();
+
+        for group in groups {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            // tasks downstream of a failure are skipped (error-handling := continue)
+            let (runnable, skipped): (Vec<&Task>, Vec<&Task>) = group
+                .into_iter()
+                .partition(|t| !depends_on_blocked(t, &blocked));
+            for task in skipped {
+                blocked.insert(task.name.clone());
+                all_results.push(skipped_result(task));
+            }
+
+            let results = self.execute_parallel(runnable, run_id).await?;
+
+            let any_failed = results.iter().any(|r| r.status == TaskStatus::Failed);
+            for result in &results {
+                if result.status == TaskStatus::Failed {
+                    blocked.insert(result.task_name.clone());
+                }
+            }
+            all_results.extend(results);
+
+            if any_failed && self.stop_on_failure() {
+                break;
+            }
+        }
+
+        // Record run completion
+        if let Some(rid) = run_id {
+            let success = all_results.iter().all(|r| r.status != TaskStatus::Failed);
+            let _ = self
+                .recorder
+                .record_run_complete(rid, success, start.elapsed())
+                .await;
+        }
+
+        Ok(all_results)
+    }
+
+    async fn execute_sequential(
+        &self,
+        tasks: Vec<&Task>,
+        run_id: Option<RunId>,
+    ) -> Result<Vec<TaskResult>, ExecutorError> {
+        let mut results = Vec::new();
+        let mut blocked = HashSet::new();
+
+        for task in tasks {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            // tasks downstream of a failure are skipped (error-handling := continue)
+            if depends_on_blocked(task, &blocked) {
+                blocked.insert(task.name.clone());
+                results.push(skipped_result(task));
+                continue;
+            }
+
+            if self.is_interactive(task) {
+                results.push(self.execute_interactive(task).await);
+                continue;
+            }
+
+            let claim = match claim_task(task, &self.completed_tasks).await {
+                Claim::Run(claim) => claim,
+                Claim::Done(result) => {
+                    let failed = result.status == TaskStatus::Failed;
+                    results.push(result);
+                    if failed {
+                        if self.stop_on_failure() {
+                            break;
+                        }
+                        blocked.insert(task.name.clone());
+                    }
+                    continue;
+                }
+            };
+
+            let task = &apply_captures(task, &self.captures).await;
+            let task = &apply_artifacts(task, &self.artifact_store).await;
+
+            // acquire service dependencies
+            let mut service_env = HashMap::new();
+            let mut service_failed = None;
+            for svc_name in &task.service_deps {
+                match self.services.acquire(svc_name).await {
+                    Ok(env) => service_env.extend(env),
+                    Err(e) => {
+                        service_failed = Some(e);
+                        break;
+                    }
+                }
+            }
+            service_env.extend(task.env.clone());
+            service_env.extend(self.cli_env.clone());
+
+            let result = if let Some(err) = service_failed {
+                TaskResult {
+                    task_name: task.name.clone(),
+                    status: TaskStatus::Failed,
+                    attempts: 0,
+                    duration_ms: 0,
+                    output: err,
+                    exit_code: None,
+                }
+            } else {
+                let stdin_data = self.collect_pipe_inputs(task).await;
+                let stdin = self.task_stdin(task, stdin_data.as_deref());
+                execute_with_retry(
+                    task,
+                    stdin,
+                    &self.ssh_sessions,
+                    &service_env,
+                    &self.k8s_tracker,
+                    &self.recorder,
+                    run_id,
+                    &self.cancel,
+                    self.dry_run,
+                )
+                .await
+            };
+
+            // release service dependencies
+            for svc_name in &task.service_deps {
+                self.services.release(svc_name).await;
+            }
+
+            // store output for downstream tasks
+            self.outputs
+                .write()
+                .await
+                .insert(task.name.clone(), result.output.clone());
+            store_capture(task, &result, &self.captures).await;
+            store_artifacts(task, &result, &self.artifact_store).await;
+            finish_task(task, &result, claim, &self.completed_tasks).await;
+
+            let failed = result.status == TaskStatus::Failed;
+            results.push(result);
+
+            if failed {
+                self.run_failure_handler(task).await;
+                if self.stop_on_failure() {
+                    break;
+                }
+                blocked.insert(task.name.clone());
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn execute_parallel(
+        &self,
+        tasks: Vec<&Task>,
+        run_id: Option<RunId>,
+    ) -> Result<Vec<TaskResult>, ExecutorError> {
+        let limit = self.concurrency.map(|n| Arc::new(Semaphore::new(n)));
+        let handles: Vec<_> = tasks
+            .into_iter()
+            .map(|task| {
+                let task = task.clone();
+                let outputs = self.outputs.clone();
+                let captures = self.captures.clone();
+                let artifact_store = self.artifact_store.clone();
+                let completed_tasks = self.completed_tasks.clone();
+                let ssh_sessions = self.ssh_sessions.clone();
+                let services = self.services.clone();
+                let k8s_tracker = self.k8s_tracker.clone();
+                let recorder = self.recorder.clone();
+                let cli_env = self.cli_env.clone();
+                let tag_semaphores = self.tag_semaphores(&task);
+                let limit = limit.clone();
+                let cancel = self.cancel.clone();
+                let dry_run = self.dry_run;
+
+                tokio::spawn(
+                    async move {
+                        let claim = match claim_task(&task, &completed_tasks).await {
+                            Claim::Run(claim) => claim,
+                            Claim::Done(result) => return result,
+                        };
+                        let task = apply_captures(&task, &captures).await;
+                        let task = apply_artifacts(&task, &artifact_store).await;
+
+                        // wait for a slot under every tag limit, then under --concurrency so a
+                        // task blocked on a tag doesn't hold one; held until the task finishes
+                        let mut _permits = Vec::new();
+                        for semaphore in tag_semaphores.into_iter().chain(limit) {
+                            _permits
+                                .push(semaphore.acquire_owned().await.expect("semaphore closed"));
+                        }
+
+                        // acquire service dependencies
+                        let mut service_env = HashMap::new();
+                        let mut service_failed = None;
+                        for svc_name in &task.service_deps {
+                            match services.acquire(svc_name).await {
+                                Ok(env) => service_env.extend(env),
+                                Err(e) => {
+                                    service_failed = Some(e);
+                                    break;
+                                }
+                            }
+                        }
+                        service_env.extend(task.env.clone());
+                        service_env.extend(cli_env);
+
+                        let result = if let Some(err) = service_failed {
+                            TaskResult {
+                                task_name: task.name.clone(),
+                                status: TaskStatus::Failed,
+                                attempts: 0,
+                                duration_ms: 0,
+                                output: err,
+                                exit_code: None,
+                            }
+                        } else {
+                            let stdin_data = collect_pipe_inputs_from_store(&task, &outputs).await;
+                            execute_with_retry(
+                                &task,
+                                TaskStdin::from(stdin_data.as_deref()),
+                                &ssh_sessions,
+                                &service_env,
+                                &k8s_tracker,
+                                &recorder,
+                                run_id,
+                                &cancel,
+                                dry_run,
+                            )
+                            .await
+                        };
+
+                        // release service dependencies
+                        for svc_name in &task.service_deps {
+                            services.release(svc_name).await;
+                        }
+
+                        outputs
+                            .write()
+                            .await
+                            .insert(task.name.clone(), result.output.clone());
+                        store_capture(&task, &result, &captures).await;
+                        store_artifacts(&task, &result, &artifact_store).await;
+                        finish_task(&task, &result, claim, &completed_tasks).await;
+
+                        result
+                    }
+                    // keep task spans under the run's span
+                    .in_current_span(),
+                )
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+
+        // handlers need the executor, so they run once the whole group is done
+        for result in &results {
+            if result.status == TaskStatus::Failed
+                && let Some(task) = self.graph.task(&result.task_name)
+            {
+                self.run_failure_handler(task).await;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Run the `@on-failure` task of a task that failed. Its result is only
+    /// logged, so it can't change the run's outcome
+    async fn run_failure_handler(&self, task: &Task) {
+        let Some(ref name) = task.on_failure else {
+            return;
+        };
+        // TaskGraph::from_config rejects unknown handlers
+        let Some(handler) = self.graph.task(name) else {
+            return;
+        };
+        info!(task = %task.name, handler = %name, "running on-failure handler");
+        // not claimed: a handler that already ran, say as a dependency of the
+        // failed task, still has to run for this failure
+        let result = self.execute_unclaimed(handler, None).await;
+        if result.status == TaskStatus::Failed {
+            warn!(task = %task.name, handler = %name, "on-failure handler failed");
+        }
+    }
+
+    async fn collect_pipe_inputs(&self, task: &Task) -> Option<String> {
+        collect_pipe_inputs_from_store(task, &self.outputs).await
+    }
+
+    /// The first task run gets our stdin when it's piped in, e.g.
+    /// `echo input | dr process`; `@pipe_from` takes precedence over it
+    fn task_stdin<'a>(&self, task: &Task, piped: Option<&'a str>) -> TaskStdin<'a> {
+        let first = self.forward_stdin.swap(false, Ordering::Relaxed);
+        if first && task.pipe_from.is_empty() && !std::io::stdin().is_terminal() {
+            return TaskStdin::Inherit;
+        }
+        TaskStdin::from(piped)
+    }
+
+    /// Execute a single task without run tracking (for backwards compatibility).
+    pub async fn execute_single(&self, task: &Task) -> TaskResult {
+        self.execute_single_with_run(task, None).await
+    }
+
+    /// Execute a single task with optional run tracking.
+    async fn execute_single_with_run(&self, task: &Task, run_id: Option<RunId>) -> TaskResult {
+        if self.is_interactive(task) {
+            return self.execute_interactive(task).await;
+        }
+        let claim = match claim_task(task, &self.completed_tasks).await {
+            Claim::Run(claim) => claim,
+            Claim::Done(result) => return result,
+        };
+        let result = self.execute_unclaimed(task, run_id).await;
+        finish_task(task, &result, claim, &self.completed_tasks).await;
+        result
+    }
+
+    /// Run a task even if it already ran in this executor, storing its output
+    /// for the tasks after it
+    async fn execute_unclaimed(&self, task: &Task, run_id: Option<RunId>) -> TaskResult {
+        let stdin_data = self.collect_pipe_inputs(task).await;
+        let stdin = self.task_stdin(task, stdin_data.as_deref());
+        let to_run = apply_captures(task, &self.captures).await;
+        let to_run = apply_artifacts(&to_run, &self.artifact_store).await;
+        let result = execute_with_retry(
+            &to_run,
+            stdin,
+            &self.ssh_sessions,
+            &self.cli_env,
+            &self.k8s_tracker,
+            &self.recorder,
+            run_id,
+            &self.cancel,
+            self.dry_run,
+        )
+        .await;
+        self.outputs
+            .write()
+            .await
+            .insert(task.name.clone(), result.output.clone());
+        store_capture(task, &result, &self.captures).await;
+        store_artifacts(task, &result, &self.artifact_store).await;
+        result
+    }
+
+    /// Run a local task attached to the terminal. Output isn't collected, there is
+    /// no timeout or retry, and service dependencies are acquired as usual.
+    async fn execute_interactive(&self, task: &Task) -> TaskResult {
+        let started = Instant::now();
+        let task = &apply_captures(task, &self.captures).await;
+        let task = &apply_artifacts(task, &self.artifact_store).await;
+        let mut service_env = HashMap::new();
+        let mut service_failed = None;
+        for svc_name in &task.service_deps {
+            match self.services.acquire(svc_name).await {
+                Ok(env) => service_env.extend(env),
+                Err(e) => {
+                    service_failed = Some(e);
+                    break;
+                }
+            }
+        }
+        service_env.extend(task.env.clone());
+        service_env.extend(self.cli_env.clone());
+
+        let status = match service_failed {
+            Some(err) => Err(err),
+            None => async {
+                check_artifact_refs(task)?;
+                run_interactive_command(task, &service_env).await
+            }
+            .await
+            .map_err(|e| e.to_string()),
+        };
+
+        for svc_name in &task.service_deps {
+            self.services.release(svc_name).await;
+        }
+
+        let (status, output, exit_code) = match status {
+            Ok(Some(0)) => (TaskStatus::Success, String::new(), Some(0)),
+            Ok(code) => (TaskStatus::Failed, String::new(), code),
+            Err(e) => (TaskStatus::Failed, e, None),
+        };
+        TaskResult {
+            task_name: task.name.clone(),
+            status,
+            attempts: 1,
+            duration_ms: started.elapsed().as_millis() as u64,
+            output,
+            exit_code,
+        }
+    }
+}
+
+/// Shell commands equivalent to running `task`; annotations become `#` comments
+/// so the output can be saved and run as a script
+fn dry_run_script(task: &Task) -> String {
+    let mut lines = vec![format!("# task: {}", task.name)];
+    for svc in &task.service_deps {
+        lines.push(format!("# [service] would start {}", svc));
+    }
+    if !task.pipe_from.is_empty() {
+        lines.push(format!("# stdin: output of {}", task.pipe_from.join(", ")));
+    }
+
+    let Some(cmd) = task.run.as_deref().filter(|_| !task.is_join()) else {
+        lines.push("# (join, nothing to run)".to_string());
+        return lines.join("\n");
+    };
+
+    let exports = |env: &HashMap<String, String>| {
+        let mut exports: Vec<String> = env
+            .iter()
+            .map(|(k, v)| format!("export {}={}", k, escape(v.into())))
+            .collect();
+        exports.sort();
+        exports
+    };
+
+    if let Some(ref ssh_config) = task.ssh {
+        let cmd = match &task.shebang {
+            Some(shebang) => wrap_shebang_for_remote(cmd, shebang),
+            None => cmd.to_string(),
+        };
+        let mut remote = cmd;
+        let mut env = ssh_config.env.clone();
+        env.extend(task.env.clone());
+        if !env.is_empty() {
+            remote = format!("{} && {}", exports(&env).join(" && "), remote);
+        }
+        if let Some(ref dir) = ssh_config.workdir {
+            remote = format!("cd {} && {}", dir, remote);
+        }
+        let port = ssh_config
+            .port
+            .map(|p| format!(" -p {}", p))
+            .unwrap_or_default();
+        let proxy = ssh_config
+            .proxy
+            .as_ref()
+            .map(|p| format!(" -J {}", p))
+            .unwrap_or_default();
+        lines.push(format!("# [ssh:{}]", ssh_config.host));
+        lines.push(format!(
+            "ssh{}{} {} {}",
+            port,
+            proxy,
+            ssh_config.destination(),
+            escape(remote.into())
+        ));
+    } else if let Some(ref k8s_config) = task.k8s {
+        let cmd = match &task.shebang {
+            Some(shebang) => wrap_shebang_for_remote(cmd, shebang),
+            None => cmd.to_string(),
+        };
+        let image = k8s_config.image.as_deref().unwrap_or("-");
+        lines.push(format!("# [k8s:{}/{}]", k8s_config.namespace, image));
+        let ns = &k8s_config.namespace;
+        lines.push(match k8s_config.mode {
+            K8sMode::Apply => {
+                let mut flags = k8s::apply_flags(k8s_config);
+                // server-side dry-run validates against the live schema and field owners
+                if k8s_config.server_side {
+                    flags.push("--dry-run=server");
+                }
+                let mut line = format!(
+                    "kubectl -n {} apply -f {}",
+                    ns,
+                    k8s_config.path.as_deref().unwrap_or(".")
+                );
+                for flag in flags {
+                    line.push(' ');
+                    line.push_str(flag);
+                }
+                line
+            }
+            K8sMode::Exec => format!(
+                "kubectl -n {} exec {} -- sh -c {}",
+                ns,
+                k8s_config
+                    .pod
+                    .clone()
+                    .or_else(|| k8s_config.selector.as_ref().map(|s| format!("-l {}", s)))
+                    .unwrap_or_default(),
+                escape(cmd.into())
+            ),
+            K8sMode::Debug { ref image } => format!(
+                "kubectl -n {} debug pod/{} -it --image={} -- sh -c {}",
+                ns,
+                k8s_config.pod.as_deref().unwrap_or("<pod>"),
+                image,
+                escape(cmd.into())
+            ),
+            K8sMode::Job => format!(
+                "kubectl -n {} run {} --rm -i --restart=Never --image={} -- sh -c {}",
+                ns,
+                task.name,
+                image,
+                escape(cmd.into())
+            ),
+        });
+    } else {
+        let mut script = exports(&task.env);
+        match &task.shebang {
+            Some(shebang) => script.push(wrap_shebang_for_remote(cmd, shebang)),
+            None => script.push(format!("sh -c {}", escape(cmd.into()))),
+        }
+        // subshell, since the shebang wrapper ends with `exit` and exports
+        // shouldn't leak into later tasks
+        if script.len() > 1 || task.shebang.is_some() {
+            lines.push(format!("(\n{}\n)", script.join("\n")));
+        } else {
+            lines.append(&mut script);
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// `[dry-run] <task>: <cmd>` lines for `task`: the command after substitution,
+/// with the remote workdir prepended and the ssh host or k8s target after it
+fn dry_run_lines(task: &Task) -> String {
+    let line = |text: &str| format!("[dry-run] {}: {}", task.name, text);
+    let mut lines: Vec<String> = task
+        .service_deps
+        .iter()
+        .map(|svc| line(&format!("would start service {}", svc)))
+        .collect();
+
+    let Some(cmd) = task.run.as_deref().filter(|_| !task.is_join()) else {
+        lines.push(line("(join, nothing to run)"));
+        return lines.join("\n");
+    };
+    let in_dir = |dir: &Option<String>| match dir {
+        Some(dir) => format!("cd {} && {}", dir, cmd),
+        None => cmd.to_string(),
+    };
+
+    lines.push(
+        if let Some(ref k8s_config) = task.k8s
+            && k8s_config.mode == K8sMode::Apply
+        {
+            let path = k8s_config.path.as_deref().unwrap_or(".");
+            line(&format!(
+                "kubectl apply -f {} (k8s apply in {})",
+                path,
+                k8s_target(k8s_config)
+            ))
+        } else if let Some(ref ssh_config) = task.ssh {
+            line(&format!(
+                "{} (ssh {})",
+                in_dir(&ssh_config.workdir),
+                ssh_config.destination()
+            ))
+        } else if let Some(ref k8s_config) = task.k8s {
+            let mode = match k8s_config.mode {
+                K8sMode::Exec => "exec",
+                K8sMode::Job => "job",
+                K8sMode::Apply => "apply",
+                K8sMode::Debug { .. } => "debug",
+            };
+            line(&format!(
+                "{} (k8s {} in {})",
+                in_dir(&k8s_config.workdir),
+                mode,
+                k8s_target(k8s_config)
+            ))
+        } else {
+            line(cmd)
+        },
+    );
+    lines.join("\n")
+}
+
+/// `context/namespace`, or just the namespace for the current context
+fn k8s_target(config: &dr_ast::K8sConfig) -> String {
+    match &config.context {
+        Some(context) => format!("{}/{}", context, config.namespace),
+        None => config.namespace.clone(),
+    }
+}
+
+/// Spawn a local task with inherited stdio and wait for it, returning its exit code
+async fn run_interactive_command(
+    task: &Task,
+    env: &HashMap<String, String>,
+) -> Result<Option<i32>, ExecutorError> {
+    if task.is_join() {
+        return Ok(Some(0));
+    }
+    let cmd = task.run.as_deref().unwrap_or("");
+
+    info!(task = %task.name, "running task interactively");
+
+    // keep the script file alive until the command exits
+    let mut script_file = None;
+    let mut command = if let Some(ref shebang) = task.shebang {
+        let file = create_script_file(cmd, &task.name)?;
+        let mut command = Command::new(&shebang.interpreter);
+        command.args(&shebang.args).arg(file.path());
+        script_file = Some(file);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    };
+
+    let status = command
+        .envs(env)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .await?;
+    drop(script_file);
+
+    Ok(status.code())
+}
+
+/// Substitute `{{NAME}}` for values captured earlier in this run
+async fn apply_captures(task: &Task, captures: &OutputStore) -> Task {
+    let captures = captures.read().await;
+    let run = task.run.as_ref().map(|body| {
+        let mut result = body.clone();
+        for (name, value) in captures.iter() {
+            result = result.replace(&format!("{{{{{}}}}}", name), value);
+        }
+        result
+    });
+    Task {
+        run,
+        ..task.clone()
+    }
+}
+
+/// Save a successful task's trimmed output under its `@output-capture` name
+async fn store_capture(task: &Task, result: &TaskResult, captures: &OutputStore) {
+    if let Some(ref name) = task.output_capture
+        && result.status == TaskStatus::Success
+    {
+        captures
+            .write()
+            .await
+            .insert(name.clone(), result.output.trim().to_string());
+    }
+}
+
+/// Replace `{{artifact.task.name}}` with the artifact's path, in the task's
+/// command and in its env values and `@upload`/`@download` paths
+async fn apply_artifacts(task: &Task, artifacts: &ArtifactStore) -> Task {
+    let artifacts = artifacts.read().await;
+    let mut task = task.clone();
+    for text in artifact_fields(&mut task) {
+        for (name, path) in artifacts.iter() {
+            *text = text.replace(
+                &format!("{{{{artifact.{}}}}}", name),
+                &path.to_string_lossy(),
+            );
+        }
+    }
+    task
+}
+
+/// The strings of a task that may reference an artifact
+fn artifact_fields(task: &mut Task) -> Vec<&mut String> {
+    let mut fields: Vec<&mut String> = task.run.iter_mut().collect();
+    fields.extend(task.env.values_mut());
+    let transfers = match (&mut task.ssh, &mut task.k8s) {
+        (Some(ssh), _) => {
+            fields.extend(ssh.env.values_mut());
+            ssh.upload.iter_mut().chain(ssh.download.iter_mut())
+        }
+        (None, Some(k8s)) => k8s.upload.iter_mut().chain(k8s.download.iter_mut()),
+        (None, None) => return fields,
+    };
+    for transfer in transfers {
+        fields.push(&mut transfer.local);
+        fields.push(&mut transfer.remote);
+    }
+    fields
+}
+
+/// An `{{artifact.*}}` reference left after [`apply_artifacts`], which the
+/// shell would otherwise get as literal text
+fn check_artifact_refs(task: &Task) -> Result<(), ExecutorError> {
+    let mut task = task.clone();
+    let unresolved = artifact_fields(&mut task).into_iter().find_map(|text| {
+        let start = text.find("{{artifact.")? + "{{artifact.".len();
+        let len = text[start..].find("}}")?;
+        Some(text[start..start + len].to_string())
+    });
+    match unresolved {
+        Some(name) => Err(ExecutorError::UnresolvedArtifact(task.name, name)),
+        None => Ok(()),
+    }
+}
+
+/// A declared `@artifact` that doesn't exist after the task ran; paths are
+/// local, so remote tasks need an `@download` for them
+fn check_artifacts(task: &Task) -> Result<(), ExecutorError> {
+    match task
+        .artifacts
+        .iter()
+        .find(|(_, path)| !Path::new(path).exists())
+    {
+        Some((name, path)) => Err(ExecutorError::MissingArtifact(
+            task.name.clone(),
+            name.clone(),
+            path.clone(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Save a successful task's `@artifact` paths for later tasks
+async fn store_artifacts(task: &Task, result: &TaskResult, artifacts: &ArtifactStore) {
+    if task.artifacts.is_empty() || result.status != TaskStatus::Success {
+        return;
+    }
+    let mut artifacts = artifacts.write().await;
+    for (name, path) in &task.artifacts {
+        let path = std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+        artifacts.insert(format!("{}.{}", task.name, name), path);
+    }
+}
+
+/// What a task reads on stdin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskStdin<'a> {
+    /// nothing, the task sees EOF
+    Null,
+    /// output of its `@pipe_from` tasks
+    Data(&'a str),
+    /// our own stdin, handed to the process as is rather than read up front,
+    /// so a task that never reads it doesn't wait for the writer to finish
+    Inherit,
+}
+
+impl<'a> From<Option<&'a str>> for TaskStdin<'a> {
+    fn from(data: Option<&'a str>) -> Self {
+        data.map_or(TaskStdin::Null, TaskStdin::Data)
+    }
+}
+
+impl<'a> TaskStdin<'a> {
+    /// The `@pipe_from` output to write to the task, if any
+    pub fn data(self) -> Option<&'a str> {
+        match self {
+            TaskStdin::Data(data) => Some(data),
+            TaskStdin::Null | TaskStdin::Inherit => None,
+        }
+    }
+
+    fn stdio(self) -> Stdio {
+        match self {
+            TaskStdin::Null => Stdio::null(),
+            TaskStdin::Data(_) => Stdio::piped(),
+            TaskStdin::Inherit => Stdio::inherit(),
+        }
+    }
+}
+
+async fn collect_pipe_inputs_from_store(task: &Task, outputs: &OutputStore) -> Option<String> {
+    if task.pipe_from.is_empty() {
+        return None;
+    }
+
+    let store = outputs.read().await;
+    let mut combined = String::new();
+
+    for source in &task.pipe_from {
+        if let Some(output) = store.get(source) {
+            combined.push_str(output);
+        }
+    }
+
+    if combined.is_empty() {
+        None
+    } else {
+        Some(combined)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_with_retry(
+    task: &Task,
+    stdin: TaskStdin<'_>,
+    ssh_sessions: &SessionCache,
+    service_env: &HashMap<String, String>,
+    k8s_tracker: &ResourceTracker,
+    recorder: &Arc<dyn Recorder>,
+    run_id: Option<RunId>,
+    cancel: &CancellationToken,
+    dry_run: Option<DryRunFormat>,
+) -> TaskResult {
+    let max_attempts = task.retry + 1;
+    let mut output = String::new();
+    let mut exit_code = None;
+    let run_start = Instant::now();
+
+    // tasks queued behind a concurrency limit when the run was cancelled
+    if cancel.is_cancelled() {
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Skipped,
+            attempts: 0,
+            duration_ms: 0,
+            output: ExecutorError::Cancelled(task.name.clone()).to_string(),
+            exit_code: None,
+        };
+    }
+
+    // nothing has run, so @when, the caches, artifacts and webhooks don't apply
+    if dry_run.is_some() {
+        let output = execute_once(
+            task,
+            stdin,
+            ssh_sessions,
+            service_env,
+            k8s_tracker,
+            recorder,
+            None,
+            cancel,
+            dry_run,
+        )
+        .await
+        .unwrap_or_default();
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Success,
+            attempts: 0,
+            duration_ms: 0,
+            output,
+            exit_code: None,
+        };
+    }
+
+    if let Err(e) = check_artifact_refs(task) {
+        error!(
+            task = %task.name,
+            progress = "failed",
+            error = %e,
+            "task can't run"
+        );
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Failed,
+            attempts: 0,
+            duration_ms: 0,
+            output: e.to_string(),
+            exit_code: None,
+        };
+    }
+
+    // @when: a false condition skips the task, without blocking its dependents
+    if !condition_met(task, service_env).await {
+        info!(task = %task.name, progress = "done", "condition not met, skipping task");
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Skipped,
+            attempts: 0,
+            duration_ms: 0,
+            output: String::new(),
+            exit_code: None,
+        };
+    }
+
+    // @inputs: skip when the command and the matched files are unchanged since
+    // the last success, replaying its output for downstream tasks
+    let inputs_hash = if task.inputs.is_empty() {
+        None
+    } else {
+        match cache::inputs_hash(task.run.as_deref().unwrap_or(""), &task.inputs) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!(task = %task.name, error = %e, "failed to hash inputs, running task");
+                None
+            }
+        }
+    };
+    if let Some(ref hash) = inputs_hash
+        && let Some(cached) = cache::fresh_output(&task.name, hash, &task.outputs)
+    {
+        info!(task = %task.name, progress = "done", "inputs unchanged, skipping task");
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Success,
+            attempts: 0,
+            duration_ms: 0,
+            output: cached,
+            exit_code: None,
+        };
+    }
+
+    // @cache: replay the output of an earlier successful run of the same
+    // command with the same key
+    let cache_key = output_cache_key(task, service_env).await;
+    if let Some(ref key) = cache_key
+        && let Some(cached) = cache::load_output(
+            &task.name,
+            task.run.as_deref().unwrap_or(""),
+            key,
+            &task.outputs,
+        )
+    {
+        info!(task = %task.name, progress = "cached", "output cached, skipping task");
+        return TaskResult {
+            task_name: task.name.clone(),
+            status: TaskStatus::Success,
+            attempts: 0,
+            duration_ms: 0,
+            output: cached,
+            exit_code: None,
+        };
+    }
+
+    for attempt in 1..=max_attempts {
+        // Record task start
+        let task_exec_id = if let Some(rid) = run_id {
+            recorder
+                .record_task_start(rid, &task.name, attempt)
+                .await
+                .ok()
+        } else {
+            None
+        };
+
+        info!(
+            task = %task.name,
+            progress = "start",
+            attempt,
+            max_attempts,
+            "running task"
+        );
+
+        let span = attempt_span(task, attempt);
+        let start = Instant::now();
+        let started = SystemTime::now();
+        let outcome = execute_once(
+            task,
+            stdin,
+            ssh_sessions,
+            service_env,
+            k8s_tracker,
+            recorder,
+            task_exec_id,
+            cancel,
+            None,
+        )
+        .instrument(span.clone())
+        .await
+        .and_then(|output| check_artifacts(task).map(|()| output));
+        span.record("task.duration_ms", start.elapsed().as_millis() as u64);
+        span.record(
+            "task.status",
+            match outcome {
+                Ok(_) => "success",
+                Err(ExecutorError::Cancelled(_)) => "skipped",
+                Err(_) => "failed",
+            },
+        );
+
+        match outcome {
+            Ok(task_output) => {
+                let duration = start.elapsed();
+                let duration_ms = duration.as_millis() as u64;
+
+                // Record task completion
+                if let Some(exec_id) = task_exec_id {
+                    let _ = recorder
+                        .record_task_complete(exec_id, TaskStatus::Success, duration)
+                        .await;
+                }
+
+                info!(
+                    task = %task.name,
+                    progress = "done",
+                    duration_ms,
+                    "task succeeded"
+                );
+                warn_missing_outputs(task);
+                if let Some(ref hash) = inputs_hash
+                    && let Err(e) = cache::store(&task.name, hash, &task_output, started)
+                {
+                    warn!(task = %task.name, error = %e, "failed to store inputs hash");
+                }
+                if let Some(ref key) = cache_key
+                    && let Err(e) = cache::store_output(
+                        &task.name,
+                        task.run.as_deref().unwrap_or(""),
+                        key,
+                        &task_output,
+                        started,
+                    )
+                {
+                    warn!(task = %task.name, error = %e, "failed to cache output");
+                }
+                let result = TaskResult {
+                    task_name: task.name.clone(),
+                    status: TaskStatus::Success,
+                    attempts: attempt,
+                    duration_ms: run_start.elapsed().as_millis() as u64,
+                    output: task_output,
+                    exit_code: Some(0),
+                };
+                send_webhook(task, &result, run_start.elapsed()).await;
+                return result;
+            }
+            Err(e @ ExecutorError::Cancelled(_)) => {
+                if let Some(exec_id) = task_exec_id {
+                    let _ = recorder
+                        .record_task_complete(exec_id, TaskStatus::Skipped, start.elapsed())
+                        .await;
+                }
+                warn!(task = %task.name, "task cancelled");
+                return TaskResult {
+                    task_name: task.name.clone(),
+                    status: TaskStatus::Skipped,
+                    attempts: attempt,
+                    duration_ms: run_start.elapsed().as_millis() as u64,
+                    output: e.to_string(),
+                    exit_code: None,
+                };
+            }
+            Err(e) => {
+                let duration = start.elapsed();
+                output = format!("{}", e);
+                exit_code = e.exit_code();
+
+                // Record task failure
+                if let Some(exec_id) = task_exec_id {
+                    let _ = recorder
+                        .record_task_complete(exec_id, TaskStatus::Failed, duration)
+                        .await;
+                }
+
+                if attempt < max_attempts {
+                    warn!(
+                        task = %task.name,
+                        progress = "retry",
+                        attempt,
+                        error = %e,
+                        "task failed, retrying"
+                    );
+                } else {
+                    error!(
+                        task = %task.name,
+                        progress = "failed",
+                        attempts = max_attempts,
+                        error = %e,
+                        "task failed permanently"
+                    );
+                }
+            }
+        }
+    }
+
+    let result = TaskResult {
+        task_name: task.name.clone(),
+        status: TaskStatus::Failed,
+        attempts: max_attempts,
+        duration_ms: run_start.elapsed().as_millis() as u64,
+        output,
+        exit_code,
+    };
+    send_webhook(task, &result, run_start.elapsed()).await;
+    result
+}
+
+/// Evaluate the task's `@when` condition locally with `sh -c`; true when it exits 0
+async fn condition_met(task: &Task, env: &HashMap<String, String>) -> bool {
+    let Some(ref condition) = task.condition else {
+        return true;
+    };
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(condition)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    match status {
+        Ok(status) => status.success(),
+        Err(e) => {
+            warn!(task = %task.name, error = %e, "failed to evaluate condition");
+            false
+        }
+    }
+}
+
+/// Trimmed stdout of the task's `@cache` key command; `None` (run uncached)
+/// when there is no `@cache` or the command fails
+async fn output_cache_key(task: &Task, env: &HashMap<String, String>) -> Option<String> {
+    let command = task.cache_key.as_ref()?;
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(output) => {
+            warn!(task = %task.name, status = %output.status, "cache key command failed, running uncached");
+            None
+        }
+        Err(e) => {
+            warn!(task = %task.name, error = %e, "failed to compute cache key, running uncached");
+            None
+        }
+    }
+}
+
+/// whether any of the task's dependencies failed or were skipped
+fn depends_on_blocked(task: &Task, blocked: &HashSet<String>) -> bool {
+    task.depends_on.iter().any(|d| blocked.contains(d))
+}
+
+fn skipped_result(task: &Task) -> TaskResult {
+    info!(task = %task.name, "skipping task, a dependency failed");
+    TaskResult {
+        task_name: task.name.clone(),
+        status: TaskStatus::Skipped,
+        attempts: 0,
+        duration_ms: 0,
+        output: String::new(),
+        exit_code: None,
+    }
+}
+
+enum Claim {
+    /// the caller runs the task, then passes this to `finish_task`
+    Run(watch::Sender<Option<TaskResult>>),
+    /// another caller ran the task in this run
+    Done(TaskResult),
+}
+
+/// Claim `task` for this caller under the write lock, or wait for the caller that
+/// already claimed it and take its result, with the stored output on success
+async fn claim_task(task: &Task, runs: &TaskRuns) -> Claim {
+    loop {
+        let mut rx = {
+            let mut runs = runs.write().await;
+            match runs.get(&task.name) {
+                Some(rx) => rx.clone(),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    runs.insert(task.name.clone(), rx);
+                    return Claim::Run(tx);
+                }
+            }
+        };
+        let earlier = rx.wait_for(Option::is_some).await.map(|r| r.clone());
+        match earlier {
+            Ok(Some(result)) if result.status == TaskStatus::Success => {
+                info!(task = %task.name, "task already ran, reusing its output");
+                return Claim::Done(TaskResult {
+                    attempts: 0,
+                    duration_ms: 0,
+                    exit_code: Some(0),
+                    ..result
+                });
+            }
+            Ok(Some(result)) => return Claim::Done(result),
+            // the claiming caller went away without a result, so take over its claim
+            _ => {
+                let mut runs = runs.write().await;
+                if runs.get(&task.name).is_some_and(|r| r.same_channel(&rx)) {
+                    runs.remove(&task.name);
+                }
+            }
+        }
+    }
+}
+
+/// Hand the result to callers waiting on the claim. A failed task is released so
+/// a later caller runs it again instead of being told it succeeded
+async fn finish_task(
+    task: &Task,
+    result: &TaskResult,
+    claim: watch::Sender<Option<TaskResult>>,
+    runs: &TaskRuns,
+) {
+    if result.status != TaskStatus::Success {
+        runs.write().await.remove(&task.name);
+    }
+    let _ = claim.send(Some(result.clone()));
+}
+
+fn left_out_result(task: &Task) -> TaskResult {
+    info!(task = %task.name, "skipping task, left out by --from or --skip");
+    TaskResult {
+        task_name: task.name.clone(),
+        status: TaskStatus::Skipped,
+        attempts: 0,
+        duration_ms: 0,
+        output: String::new(),
+        exit_code: None,
+    }
+}
+
+/// POST (or whatever method is configured) the task's webhook, if any.
+/// failures are logged and never affect the task result.
+async fn send_webhook(task: &Task, result: &TaskResult, duration: Duration) {
+    let Some(ref webhook) = task.webhook else {
+        return;
+    };
+
+    let status = match result.status {
+        TaskStatus::Success => "success",
+        TaskStatus::Failed => "failed",
+        TaskStatus::Skipped => "skipped",
+        TaskStatus::Running => "running",
+        TaskStatus::Pending => "pending",
+    };
+    let fill = |template: &str| {
+        template
+            .replace("{{name}}", &task.name)
+            .replace("{{status}}", status)
+            .replace("{{duration_ms}}", &duration.as_millis().to_string())
+            .replace("{{attempts}}", &result.attempts.to_string())
+    };
+
+    let method = match reqwest::Method::from_bytes(webhook.method.as_bytes()) {
+        Ok(m) => m,
+        Err(_) => {
+            warn!(task = %task.name, method = %webhook.method, "invalid webhook method");
+            return;
+        }
+    };
+
+    let mut request = reqwest::Client::new().request(method, fill(&webhook.url));
+    if let Some(ref body) = webhook.body {
+        request = request
+            .header("content-type", "application/json")
+            .body(fill(body));
+    }
+
+    match timeout(Duration::from_secs(10), request.send()).await {
+        Ok(Ok(resp)) if !resp.status().is_success() => {
+            warn!(task = %task.name, status = %resp.status(), "webhook returned error status");
+        }
+        Ok(Err(e)) => warn!(task = %task.name, error = %e, "webhook failed"),
+        Err(_) => warn!(task = %task.name, "webhook timed out"),
+        _ => {}
+    }
+}
+
+/// Span for one attempt at a task, exported over OTLP when enabled; status
+/// and duration are recorded once the attempt ends
+fn attempt_span(task: &Task, attempt: u32) -> tracing::Span {
+    let executor = if task.k8s.is_some() {
+        "k8s"
+    } else if task.ssh.is_some() {
+        "ssh"
+    } else {
+        "local"
+    };
+    tracing::info_span!(
+        "task",
+        task.name = %task.name,
+        task.attempt = attempt,
+        task.executor = executor,
+        task.status = tracing::field::Empty,
+        task.duration_ms = tracing::field::Empty,
+        ssh.host = task.ssh.as_ref().map(|s| s.host.as_str()),
+        k8s.context = task.k8s.as_ref().and_then(|k| k.context.as_deref()),
+    )
+}
+
+/// W3C `traceparent` for the current span, so remote commands can join the
+/// trace; `None` when spans aren't being exported
+fn traceparent() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context.is_valid().then(|| {
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_once(
+    task: &Task,
+    stdin: TaskStdin<'_>,
+    ssh_sessions: &SessionCache,
+    service_env: &HashMap<String, String>,
+    k8s_tracker: &ResourceTracker,
+    recorder: &Arc<dyn Recorder>,
+    task_exec_id: Option<TaskExecutionId>,
+    cancel: &CancellationToken,
+    dry_run: Option<DryRunFormat>,
+) -> Result<String, ExecutorError> {
+    if let Some(format) = dry_run {
+        println!(
+            "{}",
+            match format {
+                DryRunFormat::Script => dry_run_script(task),
+                DryRunFormat::Lines => dry_run_lines(task),
+            }
+        );
+        return Ok(String::new());
+    }
+
+    // handle join nodes - just pass through the stdin as output
+    if task.is_join() {
+        info!(task = %task.name, "join node - passing through input");
+        return Ok(stdin.data().unwrap_or("").to_string());
+    }
+
+    let cmd = task.run.as_deref().unwrap_or("");
+
+    // route to K8s if configured
+    if let Some(ref k8s_config) = task.k8s {
+        // wrap shebang scripts for remote execution; a copied script keeps its shebang line
+        let cmd = match &task.shebang {
+            Some(shebang) if k8s_config.use_kubectl_cp && k8s_config.mode == K8sMode::Exec => {
+                let line = std::iter::once(shebang.interpreter.as_str())
+                    .chain(shebang.args.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("#!{}\n{}", line, cmd)
+            }
+            Some(shebang) => wrap_shebang_for_remote(cmd, shebang),
+            None => cmd.to_string(),
+        };
+
+        // dropping the request leaves the job to the tracker's cleanup
+        let result = tokio::select! {
+            result = k8s::execute(
+                k8s_config,
+                &task.name,
+                &cmd,
+                service_env,
+                stdin.data(),
+                task.timeout,
+                k8s_tracker,
+            ) => result.map_err(|e| ExecutorError::K8s(e.to_string()))?,
+            _ = cancel.cancelled() => return Err(ExecutorError::Cancelled(task.name.clone())),
+        };
+
+        // Record K8s output (non-streaming for now)
+        if let Some(exec_id) = task_exec_id {
+            for line in result.stdout.lines() {
+                let _ = recorder
+                    .record_output_chunk(exec_id, OutputChunk::stdout(line.to_string()))
+                    .await;
+            }
+        }
+
+        if result.success {
+            return Ok(result.stdout);
+        } else {
+            return Err(ExecutorError::exited(task, result.exit_code));
+        }
+    }
+
+    // route to SSH if configured
+    if let Some(ref ssh_config) = task.ssh {
+        let cmd = task.run.as_ref().unwrap();
+        // wrap shebang scripts for remote execution
+        let cmd = if let Some(ref shebang) = task.shebang {
+            wrap_shebang_for_remote(cmd, shebang)
+        } else {
+            cmd.to_string()
+        };
+        return tokio::select! {
+            result = execute_remote(
+                task,
+                &cmd,
+                stdin,
+                ssh_config,
+                ssh_sessions,
+                service_env,
+                recorder,
+                task_exec_id,
+            ) => result,
+            _ = cancel.cancelled() => Err(ExecutorError::Cancelled(task.name.clone())),
+        };
+    }
+
+    let cmd = task.run.as_ref().unwrap();
+
+    // for shebang scripts, create a temp file and execute it directly
+    let future = async {
+        let mut child = if let Some(ref shebang) = task.shebang {
+            let script_file = create_script_file(cmd, &task.name)?;
+            let script_path = script_file.path().to_string_lossy().to_string();
+
+            // build command: interpreter [args...] script_path
+            let mut cmd_builder = Command::new(&shebang.interpreter);
+            for arg in &shebang.args {
+                cmd_builder.arg(arg);
+            }
+            cmd_builder.arg(&script_path);
+
+            // keep the temp file alive until command completes (moved into closure)
+            let child = cmd_builder
+                .envs(service_env)
+                .stdin(stdin.stdio())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+
+            // leak the script file handle so it stays around during execution
+            // it will be cleaned up when the NamedTempFile is dropped at end of scope
+            std::mem::forget(script_file);
+            child
+        } else {
+            Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .envs(service_env)
+                .stdin(stdin.stdio())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?
+        };
+
+        // write stdin if we have data to pipe
+        if let Some(data) = stdin.data()
+            && let Some(mut stdin) = child.stdin.take()
+        {
+            let data = data.to_string();
+            tokio::spawn(async move {
+                let _ = stdin.write_all(data.as_bytes()).await;
+            });
+        }
+
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        let stdout_is_tty = std::io::stdout().is_terminal();
+        let stderr_is_tty = std::io::stderr().is_terminal();
+
+        let task_name = task.name.clone();
+        let color = task_color(&task_name);
+        let recorder_stdout = recorder.clone();
+        let exec_id_stdout = task_exec_id;
+        let stdout_handle = tokio::spawn(async move {
+            let reader = BufReader::new(stdout);
+            let mut lines = reader.lines();
+            let mut collected = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stdout_is_tty {
+                    println!("  {} {}", format!("[{}]", task_name).color(color), line);
+                } else {
+                    println!("{}", line);
+                }
+
+                // Record output chunk
+                if let Some(exec_id) = exec_id_stdout {
+                    let _ = recorder_stdout
+                        .record_output_chunk(exec_id, OutputChunk::stdout(line.clone()))
+                        .await;
+                }
+
+                collected.push_str(&line);
+                collected.push('\n');
+            }
+            collected
+        });
+
+        let task_name = task.name.clone();
+        let color = task_color(&task_name);
+        let recorder_stderr = recorder.clone();
+        let exec_id_stderr = task_exec_id;
+        let stderr_handle = tokio::spawn(async move {
+            let reader = BufReader::new(stderr);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stderr_is_tty {
+                    eprintln!("  {} {}", format!("[{}]", task_name).color(color), line);
+                } else {
+                    eprintln!("{}", line);
+                }
+
+                // Record stderr chunk
+                if let Some(exec_id) = exec_id_stderr {
+                    let _ = recorder_stderr
+                        .record_output_chunk(exec_id, OutputChunk::stderr(line))
+                        .await;
+                }
+            }
+        });
+
+        let status = tokio::select! {
+            status = child.wait() => status?,
+            _ = cancel.cancelled() => {
+                terminate(&mut child).await;
+                return Err(ExecutorError::Cancelled(task.name.clone()));
+            }
+        };
+        let output = stdout_handle.await.unwrap();
+        stderr_handle.await.unwrap();
+
+        if status.success() {
+            Ok(output)
+        } else {
+            Err(ExecutorError::exited(task, status.code()))
+        }
+    };
+
+    if let Some(task_timeout) = task.timeout {
+        timeout(task_timeout, future)
+            .await
+            .map_err(|_| ExecutorError::Timeout(task.name.clone(), task_timeout))?
+    } else {
+        future.await
+    }
+}
+
+/// SIGTERM a cancelled task's process, then SIGKILL it if it is still running after a grace period
+async fn terminate(child: &mut tokio::process::Child) {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+
+    if let Some(pid) = child.id() {
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+    }
+    if timeout(TERMINATE_GRACE, child.wait()).await.is_err() {
+        let _ = child.kill().await;
+    }
+}
+
+/// Execute a task on a remote host via SSH
+#[allow(clippy::too_many_arguments)]
+async fn execute_remote(
+    task: &Task,
+    cmd: &str,
+    stdin: TaskStdin<'_>,
+    ssh_config: &SshConfig,
+    ssh_sessions: &SessionCache,
+    service_env: &HashMap<String, String>,
+    recorder: &Arc<dyn Recorder>,
+    task_exec_id: Option<TaskExecutionId>,
+) -> Result<String, ExecutorError> {
+    // a pooled connection may have dropped since it was opened
+    let session = ssh::get_live_session(ssh_config, ssh_sessions)
+        .await
+        .map_err(|e| ExecutorError::Ssh(e.to_string()))?;
+
+    info!(
+        task = %task.name,
+        host = %ssh_config.host,
+        "executing on remote host"
+    );
+
+    // upload files before command execution (expanding globs)
+    let uploads = expand_upload_globs(&ssh_config.upload);
+    for transfer in &uploads {
+        ssh::upload_file(
+            session.clone(),
+            &transfer.local,
+            &transfer.remote,
+            transfer.preserve_times,
+        )
+        .await
+        .map_err(|e| ExecutorError::Ssh(format!("upload failed: {}", e)))?;
+    }
+
+    // @env vars, overridden by service env vars
+    let mut env = ssh_config.env.clone();
+    env.extend(service_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+    if let Some(traceparent) = traceparent() {
+        env.insert("TRACEPARENT".to_string(), traceparent);
+    }
+
+    let result = ssh::execute_remote(
+        &session,
+        &task.name,
+        cmd,
+        &env,
+        ssh_config.workdir.as_deref(),
+        stdin,
+        Some(
+            ssh_config
+                .stderr_prefix
+                .as_deref()
+                .unwrap_or(ssh::DEFAULT_STDERR_PREFIX),
+        ),
+        ssh_config.pty,
+    )
+    .await
+    .map_err(|e| ExecutorError::Ssh(e.to_string()))?;
+
+    // Record SSH output (non-streaming for now), keeping the streams apart
+    // the same way local tasks do
+    if let Some(exec_id) = task_exec_id {
+        for line in result.stdout.lines() {
+            let _ = recorder
+                .record_output_chunk(exec_id, OutputChunk::stdout(line.to_string()))
+                .await;
+        }
+        for line in result.stderr.lines() {
+            let _ = recorder
+                .record_output_chunk(exec_id, OutputChunk::stderr(line.to_string()))
+                .await;
+        }
+    }
+
+    // download files after command execution (only on success)
+    if result.success {
+        for transfer in &ssh_config.download {
+            if !is_glob(&transfer.remote) {
+                ssh::download_file(
+                    session.clone(),
+                    &transfer.remote,
+                    &transfer.local,
+                    transfer.preserve_times,
+                )
+                .await
+                .map_err(|e| ExecutorError::Ssh(format!("download failed: {}", e)))?;
+                continue;
+            }
+
+            let count = ssh::download_glob(
+                session.clone(),
+                &transfer.remote,
+                &transfer.local,
+                transfer.preserve_times,
+            )
+            .await
+            .map_err(|e| ExecutorError::Ssh(format!("download failed: {}", e)))?;
+            if count == 0 {
+                if transfer.required {
+                    return Err(ExecutorError::Ssh(format!(
+                        "no remote files match {}",
+                        transfer.remote
+                    )));
+                }
+                warn!(task = %task.name, pattern = %transfer.remote, "no remote files to download");
+            }
+        }
+    }
+
+    if result.success {
+        Ok(result.stdout)
+    } else {
+        Err(ExecutorError::exited(task, result.exit_code))
+    }
+}
+
+/// create a temp script file with the given content, make it executable, return the path
+fn create_script_file(content: &str, task_name: &str) -> std::io::Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("justflow-{}-", task_name))
+        .suffix(".sh")
+        .tempfile()?;
+
+    file.write_all(content.as_bytes())?;
+    file.flush()?;
+
+    // make executable
+    let mut perms = file.as_file().metadata()?.permissions();
+    perms.set_mode(0o755);
+    file.as_file().set_permissions(perms)?;
+
+    Ok(file)
+}
+
+/// wrap a shebang script for remote execution (SSH/K8s) using heredoc
+fn wrap_shebang_for_remote(script: &str, shebang: &Shebang) -> String {
+    // generate a unique-ish temp path
+    let script_path = "/tmp/justflow_script_$$.sh";
+
+    // build the interpreter command
+    let interpreter_cmd = if shebang.args.is_empty() {
+        shebang.interpreter.clone()
+    } else {
+        format!("{} {}", shebang.interpreter, shebang.args.join(" "))
+    };
+
+    // create a self-contained command that:
+    // 1. writes script to temp file via heredoc
+    // 2. makes it executable
+    // 3. runs it with the interpreter
+    // 4. cleans up
+    format!(
+        r#"_jf_script="{script_path}"
+cat > "$_jf_script" << 'JUSTFLOW_SCRIPT_EOF'
+{script}
+JUSTFLOW_SCRIPT_EOF
+chmod +x "$_jf_script"
+{interpreter_cmd} "$_jf_script"
+_jf_exit=$?
+rm -f "$_jf_script"
+exit $_jf_exit"#,
+        script_path = script_path,
+        script = script,
+        interpreter_cmd = interpreter_cmd
+    )
+}
+
+/// Warn about `@outputs` files a successful task didn't produce
+fn warn_missing_outputs(task: &Task) {
+    for path in &task.outputs {
+        if !std::path::Path::new(path).exists() {
+            warn!(task = %task.name, path = %path, "declared output missing");
+        }
+    }
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains('*') || path.contains('?') || path.contains('[')
+}
+
+/// expand glob patterns in upload file transfers
+fn expand_upload_globs(transfers: &[FileTransfer]) -> Vec<FileTransfer> {
+    let mut result = Vec::new();
+
+    for transfer in transfers {
+        if !is_glob(&transfer.local) {
+            result.push(transfer.clone());
+            continue;
+        }
+
+        match glob(&transfer.local) {
+            Ok(paths) => {
+                let matched: Vec<_> = paths.filter_map(|p| p.ok()).collect();
+
+                if matched.is_empty() {
+                    warn!(pattern = %transfer.local, "glob pattern matched no files");
+                    continue;
+                }
+
+                if matched.len() > 1 && !transfer.remote.ends_with('/') {
+                    warn!(
+                        pattern = %transfer.local,
+                        "glob matched multiple files but remote path doesn't end with /"
+                    );
+                }
+
+                for path in matched {
+                    let filename = path
+                        .file_name()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    let remote = if transfer.remote.ends_with('/') {
+                        format!("{}{}", transfer.remote, filename)
+                    } else {
+                        format!("{}/{}", transfer.remote, filename)
+                    };
+
+                    result.push(FileTransfer {
+                        local: path.to_string_lossy().to_string(),
+                        remote,
+                        required: transfer.required,
+                        preserve_times: transfer.preserve_times,
+                    });
+                }
+            }
+            Err(e) => {
+                warn!(pattern = %transfer.local, error = %e, "invalid glob pattern");
+            }
+        }
+    }
+
+    result
+}
+
+/// Build a name->value mapping from positional args and task parameters
+fn build_param_bindings(task: &Task, args: &[String]) -> Result<HashMap<String, String>, String> {
+    let params = &task.parameters;
+
+    // validate argument count
+    let required_count = params.iter().filter(|p| p.default.is_none()).count();
+    if args.len() < required_count {
+        let param_names: Vec<_> = params
+            .iter()
+            .filter(|p| p.default.is_none())
+            .map(|p| p.name.as_str())
+            .collect();
+        return Err(format!(
+            "Task '{}' requires {} argument(s) ({}) but got {}",
+            task.name,
+            required_count,
+            param_names.join(", "),
+            args.len()
+        ));
+    }
+    if args.len() > params.len() {
+        return Err(format!(
+            "Task '{}' accepts {} argument(s) but got {}",
+            task.name,
+            params.len(),
+            args.len()
+        ));
+    }
+
+    // build parameter bindings
+    let mut bindings = HashMap::new();
+    for (i, param) in params.iter().enumerate() {
+        let value = if i < args.len() {
+            args[i].clone()
+        } else if let Some(default) = &param.default {
+            default.clone()
+        } else {
+            return Err(format!("Missing required argument '{}'", param.name));
+        };
+        param.check_value(&value)?;
+        bindings.insert(param.name.clone(), value);
+    }
+
+    Ok(bindings)
+}
+
+/// Apply pre-built bindings to a task, using defaults for unbound params
+fn apply_bindings(task: &Task, bindings: &HashMap<String, String>) -> Result<Task, String> {
+    let params = &task.parameters;
+
+    // build final bindings for this task
+    let mut task_bindings = HashMap::new();
+    for param in params {
+        let value = if let Some(v) = bindings.get(&param.name) {
+            v.clone()
+        } else if let Some(default) = &param.default {
+            default.clone()
+        } else {
+            return Err(format!(
+                "Task '{}' requires parameter '{}' but it was not provided",
+                task.name, param.name
+            ));
+        };
+        task_bindings.insert(param.name.clone(), value);
+    }
+
+    // substitute parameters in task body
+    let run = task.run.as_ref().map(|body| {
+        let mut result = body.clone();
+        for (name, value) in &task_bindings {
+            let pattern = format!("{{{{{}}}}}", name);
+            result = result.replace(&pattern, value);
+        }
+        result
+    });
+
+    Ok(Task {
+        run,
+        ..task.clone()
+    })
+}
+
+/// Bind positional args to task parameters (for --only mode)
+fn bind_task_params(task: &Task, args: &[String]) -> Result<Task, String> {
+    let bindings = build_param_bindings(task, args)?;
+    apply_bindings(task, &bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_dry_run_script() {
+        let mut task = Task {
+            name: "deploy".to_string(),
+            parameters: vec![],
+            run: Some("echo 'hi there'".to_string()),
+            depends_on: vec![],
+            service_deps: vec!["postgres".to_string()],
+            pipe_from: vec![],
+            timeout: None,
+            retry: 0,
+            join: false,
+            ssh: None,
+            k8s: None,
+            service: None,
+            shebang: None,
+            outputs: Vec::new(),
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
+            span: None,
+        };
+        assert_eq!(
+            dry_run_script(&task),
+            r#"# task: deploy
+# [service] would start postgres
+sh -c 'echo '\''hi there'\'''"#
+        );
+
+        task.ssh = Some(SshConfig {
+            host: "prod".to_string(),
+            user: Some("deploy".to_string()),
+            workdir: Some("/app".to_string()),
+            ..Default::default()
+        });
+        assert!(dry_run_script(&task).ends_with(
+            r#"# [ssh:prod]
+ssh deploy@prod 'cd /app && echo '\''hi there'\'''"#
+        ));
+
+        assert_eq!(
+            dry_run_lines(&task),
+            "[dry-run] deploy: would start service postgres\n\
+             [dry-run] deploy: cd /app && echo 'hi there' (ssh deploy@prod)"
+        );
+
+        task.ssh = None;
+        assert_eq!(
+            dry_run_lines(&task),
+            "[dry-run] deploy: would start service postgres\n\
+             [dry-run] deploy: echo 'hi there'"
+        );
+    }
+
+    #[test]
+    fn test_expand_upload_globs_no_glob() {
+        let transfers = vec![FileTransfer {
+            local: "./file.txt".to_string(),
+            remote: "/remote/file.txt".to_string(),
+            required: false,
+            preserve_times: true,
+        }];
+
+        let result = expand_upload_globs(&transfers);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].local, "./file.txt");
+        assert_eq!(result[0].remote, "/remote/file.txt");
+    }
+
+    #[test]
+    fn test_expand_upload_globs_with_pattern() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path();
+
+        fs::write(dir_path.join("a.rs"), "").unwrap();
+        fs::write(dir_path.join("b.rs"), "").unwrap();
+        fs::write(dir_path.join("c.txt"), "").unwrap();
+
+        let pattern = format!("{}/*.rs", dir_path.display());
+        let transfers = vec![FileTransfer {
+            local: pattern,
+            remote: "/remote/src/".to_string(),
+            required: false,
+            preserve_times: true,
+        }];
+
+        let result = expand_upload_globs(&transfers);
+        assert_eq!(result.len(), 2);
+
+        let locals: Vec<_> = result.iter().map(|t| &t.local).collect();
+        assert!(locals.iter().any(|l| l.ends_with("a.rs")));
+        assert!(locals.iter().any(|l| l.ends_with("b.rs")));
+
+        for t in &result {
+            assert!(t.remote.starts_with("/remote/src/"));
+            assert!(t.remote.ends_with(".rs"));
+        }
+    }
+
+    #[test]
+    fn test_param_bindings_check_types() {
+        let config =
+            dr_ast::parse_config("release version:int patch:bool=\"false\":\n    echo\n").unwrap();
+        let task = config.tasks.get("release").unwrap();
+
+        let bindings = build_param_bindings(task, &["3".to_string()]).unwrap();
+        assert_eq!(bindings.get("patch").map(String::as_str), Some("false"));
+
+        let err = build_param_bindings(task, &["3.1".to_string()]).unwrap_err();
+        assert_eq!(
+            err,
+            "invalid value '3.1' for parameter 'version': expected int"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_task_runs_once_per_executor() {
+        let dir = tempdir().unwrap();
+        let counter = dir.path().join("runs");
+        let config = dr_ast::parse_config(&format!(
+            "count:\n    echo run >> {}\n    echo counted\n",
+            counter.display()
+        ))
+        .unwrap();
+        let executor = Executor::from_config(config).unwrap();
+
+        let first = executor.run_task("count").await.unwrap();
+        let second = executor.run_all().await.unwrap();
+
+        assert_eq!(fs::read_to_string(&counter).unwrap(), "run\n");
+        assert_eq!(second[0].status, TaskStatus::Success);
+        assert_eq!(second[0].attempts, 0);
+        assert_eq!(second[0].output, first[0].output);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_callers_wait_for_the_running_task() {
+        let dir = tempdir().unwrap();
+        let counter = dir.path().join("runs");
+        let config = dr_ast::parse_config(&format!(
+            "count:\n    sleep 0.3\n    echo run >> {}\n    echo counted\n",
+            counter.display()
+        ))
+        .unwrap();
+        let executor = Executor::from_config(config).unwrap();
+
+        let (first, second) = tokio::join!(executor.run_task("count"), executor.run_task("count"),);
+
+        assert_eq!(fs::read_to_string(&counter).unwrap(), "run\n");
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first[0].status, TaskStatus::Success);
+        assert_eq!(second[0].status, TaskStatus::Success);
+        assert_eq!(first[0].output, second[0].output);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_task() {
+        let config =
+            dr_ast::parse_config("slow:\n    sleep 30\n\nafter: slow\n    echo done\n").unwrap();
+        let cancel = CancellationToken::new();
+        let executor = Executor::from_config(config)
+            .unwrap()
+            .with_cancellation(cancel.clone());
+
+        let started = Instant::now();
+        let run = executor.run_task("after");
+        let (results, _) = tokio::join!(run, async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+
+        // the running task is skipped and its dependent never starts
+        let results = results.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].task_name, "slow");
+        assert_eq!(results[0].status, TaskStatus::Skipped);
+        assert!(started.elapsed() < TERMINATE_GRACE);
+    }
+}
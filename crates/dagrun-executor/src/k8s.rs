@@ -2,14 +2,21 @@
 //!
 //! Execute tasks in Kubernetes clusters via:
 //! - kubectl exec into existing pods
-//! - ephemeral Job creation with completion waiting
+//! - ephemeral Job creation with completion waiting and live log streaming
 //! - kubectl apply for manifest folders with cleanup
+//!
+//! Every job created is also logged to `.dagrun/k8s-jobs.json`, one JSON object
+//! per line, so `dr gc --k8s` can delete jobs a killed run left behind once
+//! their TTL has passed.
 
 use colored::Colorize;
-use std::collections::BTreeMap;
-use std::io::IsTerminal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
@@ -18,8 +25,8 @@ use crate::progress::task_color;
 
 use k8s_openapi::api::batch::v1::Job;
 use k8s_openapi::api::core::v1::{
-    ConfigMapVolumeSource, Container, EnvVar, PodSpec, PodTemplateSpec, SecretVolumeSource, Volume,
-    VolumeMount,
+    ConfigMapVolumeSource, Container, EmptyDirVolumeSource, EnvVar, PodSpec, PodTemplateSpec,
+    SecretVolumeSource, Volume, VolumeMount,
 };
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::api::{Api, DeleteParams, LogParams, PostParams};
@@ -49,12 +56,16 @@ pub enum K8sError {
     Io(#[from] std::io::Error),
     #[error("infer config error: {0}")]
     InferConfig(#[from] kube::config::InferConfigError),
+    #[error("watch error: {0}")]
+    Watch(#[from] kube::runtime::watcher::Error),
 }
 
 /// Result of K8s task execution
 pub struct K8sOutput {
     pub stdout: String,
     pub success: bool,
+    /// exit code of the task's container, when known
+    pub exit_code: Option<i32>,
 }
 
 /// Tracks applied manifests for cleanup on shutdown
@@ -62,6 +73,8 @@ struct AppliedManifest {
     path: String,
     namespace: String,
     context: Option<String>,
+    /// applied with --prune
+    prune: bool,
 }
 
 /// Tracks running jobs for cleanup on Ctrl+C
@@ -72,6 +85,7 @@ struct TrackedJob {
 }
 
 /// Tracks K8s resources created during workflow for cleanup
+#[derive(Default)]
 pub struct K8sResourceTracker {
     applied: Vec<AppliedManifest>,
     jobs: Vec<TrackedJob>,
@@ -79,10 +93,7 @@ pub struct K8sResourceTracker {
 
 impl K8sResourceTracker {
     pub fn new() -> Self {
-        Self {
-            applied: vec![],
-            jobs: vec![],
-        }
+        Self::default()
     }
 
     /// Record that manifests were applied
@@ -92,6 +103,7 @@ impl K8sResourceTracker {
                 path: path.clone(),
                 namespace: config.namespace.clone(),
                 context: config.context.clone(),
+                prune: config.prune,
             });
         }
     }
@@ -123,7 +135,7 @@ impl K8sResourceTracker {
 
         // delete applied manifests (via kubectl for now - kube-rs doesn't have apply)
         for manifest in self.applied.iter().rev() {
-            info!(path = %manifest.path, namespace = %manifest.namespace, "cleaning up applied manifests");
+            info!(path = %manifest.path, namespace = %manifest.namespace, prune = manifest.prune, "cleaning up applied manifests");
             let _ = delete_manifests_kubectl(
                 &manifest.path,
                 &manifest.namespace,
@@ -134,6 +146,102 @@ impl K8sResourceTracker {
     }
 }
 
+/// A job in `.dagrun/k8s-jobs.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub name: String,
+    pub namespace: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// unix seconds when the job was created
+    pub created: u64,
+    /// the job's `ttlSecondsAfterFinished`, which already folds in `k8s-default-ttl`
+    pub ttl_seconds: u32,
+}
+
+fn job_log_path() -> PathBuf {
+    PathBuf::from(".dagrun").join("k8s-jobs.json")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Append a created job to the job log
+fn log_job(record: &JobRecord) -> io::Result<()> {
+    let path = job_log_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // one write per line, so concurrent runs never interleave
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+/// Split logged jobs into those whose TTL has passed at `now` and the rest
+fn partition_stale(records: Vec<JobRecord>, now: u64) -> (Vec<JobRecord>, Vec<JobRecord>) {
+    records
+        .into_iter()
+        .partition(|r| r.created + u64::from(r.ttl_seconds) <= now)
+}
+
+/// Delete logged jobs whose TTL has passed and drop them from the log,
+/// returning their names. A job that can't be deleted stays logged.
+pub async fn gc_jobs() -> Result<Vec<String>, K8sError> {
+    let path = job_log_path();
+    let records: Vec<JobRecord> = match fs::read_to_string(&path) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let (stale, mut kept) = partition_stale(records, unix_now());
+
+    let mut deleted = Vec::new();
+    for job in stale {
+        let result = match get_client(job.context.as_deref()).await {
+            Ok(client) => Api::<Job>::namespaced(client, &job.namespace)
+                .delete(&job.name, &DeleteParams::background())
+                .await
+                .map(|_| ()),
+            Err(e) => {
+                warn!(job = %job.name, error = %e, "no client for stale job, keeping it");
+                kept.push(job);
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => {
+                info!(job = %job.name, namespace = %job.namespace, "deleted stale job");
+                deleted.push(job.name);
+            }
+            // already gone, through its TTL or a finished run
+            Err(kube::Error::Api(status)) if status.is_not_found() => {}
+            Err(e) => {
+                warn!(job = %job.name, error = %e, "failed to delete stale job, keeping it");
+                kept.push(job);
+            }
+        }
+    }
+
+    let mut tmp = tempfile::NamedTempFile::new_in(path.parent().unwrap())?;
+    for job in &kept {
+        serde_json::to_writer(&mut tmp, job).map_err(io::Error::from)?;
+        tmp.write_all(b"\n")?;
+    }
+    tmp.persist(&path).map_err(|e| e.error)?;
+    Ok(deleted)
+}
+
 pub type ResourceTracker = Arc<RwLock<K8sResourceTracker>>;
 
 pub fn new_tracker() -> ResourceTracker {
@@ -187,18 +295,37 @@ fn sanitize_k8s_name(s: &str) -> String {
     }
 }
 
+/// TTL for finished jobs when neither the task nor `k8s-default-ttl` sets one
+const DEFAULT_JOB_TTL_SECONDS: u32 = 300;
+
+/// Where a job with a `setup_cmd` mounts the emptyDir its containers share,
+/// kept apart from `workdir` so the image's own files there stay visible.
+/// Also the workdir when the task doesn't set one.
+const SETUP_SHARED_DIR: &str = "/dagrun-shared";
+
 /// Build a K8s Job object
-fn build_job(config: &K8sConfig, job_name: &str, command: &str) -> Result<Job, K8sError> {
+fn build_job(
+    config: &K8sConfig,
+    job_name: &str,
+    command: &str,
+    env: &HashMap<String, String>,
+) -> Result<Job, K8sError> {
     let image = config
         .image
         .as_ref()
         .ok_or_else(|| K8sError::MissingField("image required for job mode".into()))?;
 
-    // workdir prefix
-    let full_command = match &config.workdir {
-        Some(dir) => format!("cd {} && {}", dir, command),
-        None => command.to_string(),
+    // a setup step shares files with the task through an emptyDir
+    let workdir = match (&config.workdir, &config.setup_cmd) {
+        (Some(dir), _) => Some(dir.as_str()),
+        (None, Some(_)) => Some(SETUP_SHARED_DIR),
+        (None, None) => None,
+    };
+    let in_workdir = |cmd: &str| match workdir {
+        Some(dir) => format!("cd {} && {}", dir, cmd),
+        None => cmd.to_string(),
     };
+    let full_command = in_workdir(command);
 
     // build resource requirements
     let resources = if config.cpu.is_some() || config.memory.is_some() {
@@ -273,8 +400,37 @@ fn build_job(config: &K8sConfig, job_name: &str, command: &str) -> Result<Job, K
         });
     }
 
-    // build environment variables from service env (passed in later)
-    let env: Vec<EnvVar> = vec![];
+    if config.setup_cmd.is_some() {
+        volume_mounts.push(VolumeMount {
+            name: "shared".to_string(),
+            mount_path: SETUP_SHARED_DIR.to_string(),
+            ..Default::default()
+        });
+        volumes.push(Volume {
+            name: "shared".to_string(),
+            empty_dir: Some(EmptyDirVolumeSource::default()),
+            ..Default::default()
+        });
+    }
+
+    // the task's @env, plus service and --env vars; sorted so the spec is stable
+    let shared_dir = config.setup_cmd.as_ref().map(|_| {
+        (
+            "DAGRUN_SHARED_DIR".to_string(),
+            SETUP_SHARED_DIR.to_string(),
+        )
+    });
+    let mut env: Vec<EnvVar> = env
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .chain(shared_dir)
+        .map(|(name, value)| EnvVar {
+            name,
+            value: Some(value),
+            ..Default::default()
+        })
+        .collect();
+    env.sort_by(|a, b| a.name.cmp(&b.name));
 
     // build node selector (convert HashMap to BTreeMap)
     let node_selector: Option<BTreeMap<String, String>> = config
@@ -294,17 +450,37 @@ fn build_job(config: &K8sConfig, job_name: &str, command: &str) -> Result<Job, K
         })
         .collect();
 
+    let volume_mounts = if volume_mounts.is_empty() {
+        None
+    } else {
+        Some(volume_mounts)
+    };
+
+    // the task container only starts once setup exits successfully
+    let init_containers = config.setup_cmd.as_ref().map(|setup| {
+        vec![Container {
+            name: "setup".to_string(),
+            image: Some(image.clone()),
+            command: Some(vec!["sh".to_string(), "-c".to_string()]),
+            args: Some(vec![in_workdir(setup)]),
+            resources: resources.clone(),
+            volume_mounts: volume_mounts.clone(),
+            env: if env.is_empty() {
+                None
+            } else {
+                Some(env.clone())
+            },
+            ..Default::default()
+        }]
+    });
+
     let container = Container {
         name: "task".to_string(),
         image: Some(image.clone()),
         command: Some(vec!["sh".to_string(), "-c".to_string()]),
         args: Some(vec![full_command]),
         resources,
-        volume_mounts: if volume_mounts.is_empty() {
-            None
-        } else {
-            Some(volume_mounts)
-        },
+        volume_mounts,
         env: if env.is_empty() { None } else { Some(env) },
         ..Default::default()
     };
@@ -320,7 +496,9 @@ fn build_job(config: &K8sConfig, job_name: &str, command: &str) -> Result<Job, K
             ..Default::default()
         },
         spec: Some(k8s_openapi::api::batch::v1::JobSpec {
-            ttl_seconds_after_finished: config.ttl_seconds.map(|t| t as i32),
+            ttl_seconds_after_finished: Some(
+                config.ttl_seconds.unwrap_or(DEFAULT_JOB_TTL_SECONDS) as i32
+            ),
             backoff_limit: Some(0),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
@@ -336,6 +514,7 @@ fn build_job(config: &K8sConfig, job_name: &str, command: &str) -> Result<Job, K
                     } else {
                         Some(tolerations)
                     },
+                    init_containers,
                     containers: vec![container],
                     volumes: if volumes.is_empty() {
                         None
@@ -358,6 +537,7 @@ pub async fn run_job(
     config: &K8sConfig,
     task_name: &str,
     command: &str,
+    env: &HashMap<String, String>,
     task_timeout: Option<Duration>,
     tracker: &ResourceTracker,
 ) -> Result<K8sOutput, K8sError> {
@@ -368,11 +548,31 @@ pub async fn run_job(
     info!(task = %task_name, job = %job_name, namespace = %config.namespace, "creating ephemeral job");
 
     // build and create job
-    let job = build_job(config, &job_name, command)?;
+    let job = build_job(config, &job_name, command, env)?;
     jobs.create(&PostParams::default(), &job).await?;
 
-    // track for cleanup
+    // track for cleanup, and log it in case this run is killed before that
     tracker.write().await.track_job(&job_name, config);
+    let record = JobRecord {
+        name: job_name.clone(),
+        namespace: config.namespace.clone(),
+        context: config.context.clone(),
+        created: unix_now(),
+        ttl_seconds: config.ttl_seconds.unwrap_or(DEFAULT_JOB_TTL_SECONDS),
+    };
+    if let Err(e) = log_job(&record) {
+        warn!(job = %job_name, error = %e, "failed to log job for dr gc --k8s");
+    }
+
+    // stream the pod's logs while the job runs
+    let streamed = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut log_stream = tokio::spawn(stream_job_logs(
+        client.clone(),
+        config.namespace.clone(),
+        job_name.clone(),
+        task_name.to_string(),
+        streamed.clone(),
+    ));
 
     // wait for completion
     let timeout_duration = task_timeout.unwrap_or(Duration::from_secs(3600));
@@ -380,21 +580,43 @@ pub async fn run_job(
     let cond = await_condition(jobs.clone(), &job_name, conditions::is_job_completed());
     let result = tokio::time::timeout(timeout_duration, cond).await;
 
-    // get logs regardless of outcome
-    let logs = get_job_logs(&client, &config.namespace, &job_name)
-        .await
-        .unwrap_or_default();
+    if result.is_err() {
+        log_stream.abort();
+    }
 
-    // print logs
-    let is_tty = std::io::stdout().is_terminal();
-    let color = task_color(task_name);
-    for line in logs.lines() {
-        if is_tty {
-            println!("  {} {}", format!("[{}]", task_name).color(color), line);
+    // the stream ends once the container exits, so this shouldn't wait long
+    let complete = match tokio::time::timeout(LOG_STREAM_GRACE, &mut log_stream).await {
+        Ok(Ok(Ok(()))) => true,
+        Ok(Ok(Err(e))) => {
+            warn!(job = %job_name, error = %e, "log streaming failed, fetching logs");
+            false
+        }
+        Ok(Err(_)) => false,
+        Err(_) => {
+            // stop the stream before reading what it printed
+            log_stream.abort();
+            let _ = log_stream.await;
+            false
+        }
+    };
+    let printed = std::mem::take(&mut *streamed.lock().unwrap());
+
+    // fall back to fetching the full log, printing only what the stream missed
+    let logs = if complete {
+        printed.join("\n")
+    } else {
+        let logs = get_job_logs(&client, &config.namespace, &job_name)
+            .await
+            .unwrap_or_default();
+        if logs.is_empty() {
+            printed.join("\n")
         } else {
-            println!("{}", line);
+            for line in logs.lines().skip(printed.len()) {
+                print_job_log_line(task_name, line);
+            }
+            logs
         }
-    }
+    };
 
     // check result
     let success = match result {
@@ -420,21 +642,75 @@ pub async fn run_job(
         }
     };
 
+    // read the exit code before the job (and its pod) is deleted
+    let exit_code = get_job_exit_code(&client, &config.namespace, &job_name).await;
+
     // cleanup (TTL will also handle it)
     let _ = jobs.delete(&job_name, &DeleteParams::default()).await;
     tracker.write().await.untrack_job(&job_name);
 
-    if success {
-        Ok(K8sOutput {
-            stdout: logs,
-            success: true,
-        })
+    Ok(K8sOutput {
+        stdout: logs,
+        success,
+        exit_code,
+    })
+}
+
+/// How long to wait for the log stream to drain after the job completes
+const LOG_STREAM_GRACE: Duration = Duration::from_secs(10);
+
+fn print_job_log_line(task_name: &str, line: &str) {
+    if std::io::stdout().is_terminal() {
+        let color = task_color(task_name);
+        println!("  {} {}", format!("[k8s:{}]", task_name).color(color), line);
     } else {
-        Ok(K8sOutput {
-            stdout: logs,
-            success: false,
-        })
+        println!("{}", line);
+    }
+}
+
+/// Wait for the job's pod to start, then follow its logs until the container exits.
+/// Every line is printed as it arrives and pushed onto `printed`.
+async fn stream_job_logs(
+    client: Client,
+    namespace: String,
+    job_name: String,
+    task_name: String,
+    printed: Arc<std::sync::Mutex<Vec<String>>>,
+) -> Result<(), K8sError> {
+    use futures::{AsyncBufReadExt, StreamExt, TryStreamExt};
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::runtime::{WatchStreamExt, watcher};
+
+    let pods: Api<Pod> = Api::namespaced(client, &namespace);
+    let selector = format!("job-name={}", job_name);
+
+    // logs are only available once the container has started
+    let mut events = watcher(pods.clone(), watcher::Config::default().labels(&selector))
+        .applied_objects()
+        .boxed();
+    let mut pod_name = None;
+    while let Some(pod) = events.try_next().await? {
+        let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+        if matches!(phase, Some("Running" | "Succeeded" | "Failed")) {
+            pod_name = pod.metadata.name;
+            break;
+        }
     }
+    let Some(pod_name) = pod_name else {
+        return Ok(());
+    };
+
+    let params = LogParams {
+        follow: true,
+        ..Default::default()
+    };
+    let mut lines = pods.log_stream(&pod_name, &params).await?.lines();
+    while let Some(line) = lines.try_next().await? {
+        print_job_log_line(&task_name, &line);
+        printed.lock().unwrap().push(line);
+    }
+
+    Ok(())
 }
 
 /// Get logs from a job's pod
@@ -463,6 +739,29 @@ async fn get_job_logs(
     Ok(String::new())
 }
 
+/// Exit code of the job pod's terminated container
+async fn get_job_exit_code(client: &Client, namespace: &str, job_name: &str) -> Option<i32> {
+    use k8s_openapi::api::core::v1::Pod;
+
+    let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let label_selector = format!("job-name={}", job_name);
+    let pod_list = pods
+        .list(&kube::api::ListParams::default().labels(&label_selector))
+        .await
+        .ok()?;
+
+    pod_list
+        .items
+        .first()?
+        .status
+        .as_ref()?
+        .container_statuses
+        .as_ref()?
+        .iter()
+        .find_map(|cs| cs.state.as_ref()?.terminated.as_ref())
+        .map(|t| t.exit_code)
+}
+
 /// Apply manifests from a path (uses kubectl since kube-rs doesn't have apply)
 pub async fn apply_manifests(
     config: &K8sConfig,
@@ -482,6 +781,7 @@ pub async fn apply_manifests(
     }
     cmd.arg("-n").arg(&config.namespace);
     cmd.args(["apply", "-f", path]);
+    cmd.args(apply_flags(config));
 
     let output = cmd.output().await?;
 
@@ -505,6 +805,21 @@ pub async fn apply_manifests(
     Ok(())
 }
 
+/// Extra `kubectl apply` flags for an apply-mode config
+pub fn apply_flags(config: &K8sConfig) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if config.server_side {
+        flags.extend(["--server-side", "--field-manager=dagrun"]);
+        if config.force_conflicts {
+            flags.push("--force-conflicts");
+        }
+    }
+    if config.prune {
+        flags.extend(["--prune", "--all"]);
+    }
+    flags
+}
+
 /// Wait for resources to become ready
 pub async fn wait_for_resources(config: &K8sConfig) -> Result<(), K8sError> {
     if config.wait_for.is_empty() {
@@ -723,11 +1038,85 @@ pub async fn download_file(
     Ok(())
 }
 
+/// `kubectl exec` into the pod, ready for the command after `--`
+fn kubectl_exec(config: &K8sConfig, pod: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("kubectl");
+    if let Some(ref ctx) = config.context {
+        cmd.arg("--context").arg(ctx);
+    }
+    cmd.arg("-n").arg(&config.namespace);
+    cmd.arg("exec");
+    if let Some(ref container) = config.container {
+        cmd.arg("-c").arg(container);
+    }
+    cmd.arg(pod).arg("--");
+    cmd
+}
+
+/// Start the command after `--` with env(1) when there are vars to set, since
+/// `kubectl exec` and `kubectl debug` have no flag for them
+fn with_env(cmd: &mut std::process::Command, env: &HashMap<String, String>) {
+    if env.is_empty() {
+        return;
+    }
+    let mut vars: Vec<_> = env.iter().collect();
+    vars.sort();
+    cmd.arg("env");
+    for (key, value) in vars {
+        cmd.arg(format!("{}={}", key, value));
+    }
+}
+
+/// Copy a local script into the pod with kubectl cp, run it there, and remove it.
+/// Scripts starting with `#!` run through their interpreter, others through `sh`.
+pub async fn copy_and_exec(
+    config: &K8sConfig,
+    pod: &str,
+    script_path: &Path,
+    env: &HashMap<String, String>,
+) -> Result<K8sOutput, K8sError> {
+    let file_name = script_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "dagrun-script.sh".to_string());
+    let remote_path = format!("/tmp/{}", file_name);
+    upload_file(config, pod, &script_path.to_string_lossy(), &remote_path).await?;
+
+    let runner = if std::fs::read_to_string(script_path)?.starts_with("#!") {
+        ""
+    } else {
+        "sh "
+    };
+    let run = match &config.workdir {
+        Some(dir) => format!("cd {} && {}{}", dir, runner, remote_path),
+        None => format!("{}{}", runner, remote_path),
+    };
+
+    let mut cmd = kubectl_exec(config, pod);
+    with_env(cmd.as_std_mut(), env);
+    let output = cmd
+        .arg("sh")
+        .arg("-c")
+        .arg(format!(
+            "chmod +x {path} && {run}; _rc=$?; rm -f {path}; exit $_rc",
+            path = remote_path,
+            run = run
+        ))
+        .output()
+        .await?;
+    Ok(K8sOutput {
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        success: output.status.success(),
+        exit_code: output.status.code(),
+    })
+}
+
 /// Execute command via kubectl exec (kube-rs exec is complex, use kubectl for now)
 pub async fn exec_in_pod(
     config: &K8sConfig,
     task_name: &str,
     command: &str,
+    env: &HashMap<String, String>,
     _stdin_data: Option<&str>,
 ) -> Result<K8sOutput, K8sError> {
     let pod = find_pod(config).await?;
@@ -739,28 +1128,33 @@ pub async fn exec_in_pod(
 
     info!(task = %task_name, pod = %pod, namespace = %config.namespace, "executing in pod");
 
-    let mut cmd = tokio::process::Command::new("kubectl");
-    if let Some(ref ctx) = config.context {
-        cmd.arg("--context").arg(ctx);
-    }
-    cmd.arg("-n").arg(&config.namespace);
-    cmd.arg("exec");
-
-    if let Some(ref container) = config.container {
-        cmd.arg("-c").arg(container);
-    }
-
-    cmd.arg(&pod).arg("--").arg("sh").arg("-c");
-
-    let full_cmd = match &config.workdir {
-        Some(dir) => format!("cd {} && {}", dir, command),
-        None => command.to_string(),
+    let K8sOutput {
+        stdout,
+        success,
+        exit_code,
+    } = if config.use_kubectl_cp {
+        let mut script = tempfile::Builder::new()
+            .prefix("dagrun-")
+            .suffix(".sh")
+            .tempfile()?;
+        script.write_all(command.as_bytes())?;
+        script.flush()?;
+        copy_and_exec(config, &pod, script.path(), env).await?
+    } else {
+        let full_cmd = match &config.workdir {
+            Some(dir) => format!("cd {} && {}", dir, command),
+            None => command.to_string(),
+        };
+        let mut cmd = kubectl_exec(config, &pod);
+        with_env(cmd.as_std_mut(), env);
+        let output = cmd.arg("sh").arg("-c").arg(&full_cmd).output().await?;
+        K8sOutput {
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            success: output.status.success(),
+            exit_code: output.status.code(),
+        }
     };
-    cmd.arg(&full_cmd);
-
-    let output = cmd.output().await?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
     let is_tty = std::io::stdout().is_terminal();
     let color = task_color(task_name);
     for line in stdout.lines() {
@@ -771,8 +1165,6 @@ pub async fn exec_in_pod(
         }
     }
 
-    let success = output.status.success();
-
     // download files after execution (only on success)
     if success {
         for transfer in &config.download {
@@ -780,7 +1172,53 @@ pub async fn exec_in_pod(
         }
     }
 
-    Ok(K8sOutput { stdout, success })
+    Ok(K8sOutput {
+        stdout,
+        success,
+        exit_code,
+    })
+}
+
+/// Attach an ephemeral debug container to the target pod with `kubectl debug`.
+/// The session uses the terminal's stdin/stdout/stderr, so no output is captured.
+pub async fn debug_pod(
+    config: &K8sConfig,
+    task_name: &str,
+    image: &str,
+    command: &str,
+    env: &HashMap<String, String>,
+) -> Result<K8sOutput, K8sError> {
+    let pod = find_pod(config).await?;
+
+    info!(task = %task_name, pod = %pod, namespace = %config.namespace, image = %image, "attaching debug container");
+
+    let mut cmd = std::process::Command::new("kubectl");
+    if let Some(ref ctx) = config.context {
+        cmd.arg("--context").arg(ctx);
+    }
+    cmd.arg("-n").arg(&config.namespace);
+    cmd.arg("debug").arg(format!("pod/{}", pod)).arg("-it");
+    cmd.arg(format!("--image={}", image));
+    if let Some(ref container) = config.container {
+        cmd.arg(format!("--target={}", container));
+    }
+    cmd.arg("--");
+    with_env(&mut cmd, env);
+    cmd.arg("sh").arg("-c").arg(command);
+    cmd.stdin(std::process::Stdio::inherit())
+        .stdout(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::inherit());
+
+    // blocks until the session ends, so keep it off the async workers
+    let status = tokio::task::spawn_blocking(move || cmd.status())
+        .await
+        .map_err(std::io::Error::other)??;
+
+    Ok(K8sOutput {
+        stdout: String::new(),
+        success: status.success(),
+        exit_code: status.code(),
+    })
 }
 
 /// Main execution entry point
@@ -788,6 +1226,7 @@ pub async fn execute(
     config: &K8sConfig,
     task_name: &str,
     command: &str,
+    env: &HashMap<String, String>,
     stdin_data: Option<&str>,
     task_timeout: Option<Duration>,
     tracker: &ResourceTracker,
@@ -800,17 +1239,19 @@ pub async fn execute(
     }
 
     // execute the task
-    let result = match config.mode {
-        K8sMode::Job => run_job(config, task_name, command, task_timeout, tracker).await,
+    let result = match &config.mode {
+        K8sMode::Debug { image } => debug_pod(config, task_name, image, command, env).await,
+        K8sMode::Job => run_job(config, task_name, command, env, task_timeout, tracker).await,
         K8sMode::Apply => {
             apply_manifests(config, tracker).await?;
             wait_for_resources(config).await?;
             Ok(K8sOutput {
                 stdout: String::new(),
                 success: true,
+                exit_code: None,
             })
         }
-        K8sMode::Exec => exec_in_pod(config, task_name, command, stdin_data).await,
+        K8sMode::Exec => exec_in_pod(config, task_name, command, env, stdin_data).await,
     };
 
     // stop port forwards after task completes
@@ -820,3 +1261,131 @@ pub async fn execute(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_stale_jobs_by_ttl() {
+        let job = |name: &str, created, ttl_seconds| JobRecord {
+            name: name.to_string(),
+            namespace: "default".to_string(),
+            context: None,
+            created,
+            ttl_seconds,
+        };
+        let (stale, kept) = partition_stale(
+            vec![
+                job("old", 1_000, 300),
+                job("default-ttl", 1_000, 3600),
+                job("new", 1_200, 300),
+            ],
+            1_300,
+        );
+        assert_eq!(stale, vec![job("old", 1_000, 300)]);
+        assert_eq!(
+            kept,
+            vec![job("default-ttl", 1_000, 3600), job("new", 1_200, 300)]
+        );
+    }
+
+    #[test]
+    fn test_build_job_passes_env() {
+        let config = K8sConfig {
+            image: Some("alpine".to_string()),
+            ..Default::default()
+        };
+        let env = HashMap::from([
+            ("STAGE".to_string(), "prod".to_string()),
+            ("REGION".to_string(), "us-east-1".to_string()),
+        ]);
+        let job = build_job(&config, "deploy-abc", "./deploy.sh", &env).unwrap();
+        let pod = job.spec.unwrap().template.spec.unwrap();
+
+        let vars: Vec<_> = pod.containers[0]
+            .env
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|var| (var.name.as_str(), var.value.as_deref().unwrap()))
+            .collect();
+        assert_eq!(vars, vec![("REGION", "us-east-1"), ("STAGE", "prod")]);
+    }
+
+    #[test]
+    fn test_build_job_setup_cmd_init_container() {
+        let config = K8sConfig {
+            image: Some("python:3.12".to_string()),
+            setup_cmd: Some("pip install -r requirements.txt".to_string()),
+            ..Default::default()
+        };
+        let job = build_job(&config, "train-abc", "python train.py", &HashMap::new()).unwrap();
+        let pod = job.spec.unwrap().template.spec.unwrap();
+
+        let init = pod.init_containers.unwrap();
+        assert_eq!(init.len(), 1);
+        assert_eq!(
+            init[0].args.as_ref().unwrap()[0],
+            "cd /dagrun-shared && pip install -r requirements.txt"
+        );
+        assert_eq!(
+            pod.containers[0].args.as_ref().unwrap()[0],
+            "cd /dagrun-shared && python train.py"
+        );
+        assert!(pod.volumes.unwrap()[0].empty_dir.is_some());
+    }
+
+    #[test]
+    fn test_build_job_setup_cmd_keeps_workdir() {
+        let config = K8sConfig {
+            image: Some("python:3.12".to_string()),
+            workdir: Some("/app".to_string()),
+            setup_cmd: Some(
+                "pip install --target $DAGRUN_SHARED_DIR -r requirements.txt".to_string(),
+            ),
+            ..Default::default()
+        };
+        let job = build_job(&config, "train-abc", "python train.py", &HashMap::new()).unwrap();
+        let pod = job.spec.unwrap().template.spec.unwrap();
+
+        // the image's /app is left alone; the emptyDir gets its own path
+        let init = &pod.init_containers.unwrap()[0];
+        let task = &pod.containers[0];
+        for container in [init, task] {
+            let mounts = container.volume_mounts.as_ref().unwrap();
+            assert_eq!(mounts.len(), 1);
+            assert_eq!(mounts[0].mount_path, "/dagrun-shared");
+            let env = container.env.as_ref().unwrap();
+            assert_eq!(env[0].name, "DAGRUN_SHARED_DIR");
+            assert_eq!(env[0].value.as_deref(), Some("/dagrun-shared"));
+        }
+        assert_eq!(task.args.as_ref().unwrap()[0], "cd /app && python train.py");
+    }
+
+    #[test]
+    fn test_apply_flags_server_side() {
+        let config = K8sConfig {
+            mode: K8sMode::Apply,
+            server_side: true,
+            force_conflicts: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_flags(&config),
+            [
+                "--server-side",
+                "--field-manager=dagrun",
+                "--force-conflicts"
+            ]
+        );
+
+        // force_conflicts is meaningless for client-side apply
+        let config = K8sConfig {
+            force_conflicts: true,
+            prune: true,
+            ..Default::default()
+        };
+        assert_eq!(apply_flags(&config), ["--prune", "--all"]);
+    }
+}
@@ -0,0 +1,34 @@
+//! Task execution for dagrun configurations.
+//!
+//! Runs the tasks of a parsed [`Config`](dr_ast::Config) locally, over SSH, or
+//! in Kubernetes, along with their services, retries, and run history. The
+//! `dr` binary is a thin CLI over this crate.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use dagrun_executor::Executor;
+//!
+//! # async fn run() -> Result<(), dagrun_executor::ExecutorError> {
+//! let config = dr_ast::parse_config("build:\n    cargo build\n").unwrap();
+//! let executor = Executor::from_config(config)?;
+//! executor.register_services().await;
+//! let results = executor.run_task("build").await?;
+//! println!("{} tasks ran", results.len());
+//! executor.close().await;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod cache;
+pub mod dag;
+pub mod env;
+pub mod executor;
+pub mod k8s;
+pub mod progress;
+pub mod recorder;
+pub mod service;
+pub mod ssh;
+
+pub use dag::{DagError, TaskGraph};
+pub use executor::{DryRunFormat, Executor, ExecutorError, TaskResult, TaskStatus};
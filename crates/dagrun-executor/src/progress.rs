@@ -75,6 +75,19 @@ where
                     let _ = writeln!(stderr, "✓ {} {}", task, duration);
                 }
             }
+            "cached" => {
+                if self.is_tty {
+                    let _ = writeln!(
+                        stderr,
+                        "{} {} {}",
+                        "✓".green(),
+                        "[cached]".dimmed(),
+                        task.color(color)
+                    );
+                } else {
+                    let _ = writeln!(stderr, "✓ [cached] {}", task);
+                }
+            }
             "retry" => {
                 let attempt = visitor.attempt.unwrap_or(0);
                 if self.is_tty {
@@ -1,17 +1,28 @@
 //! Service lifecycle management
-
-use std::collections::HashMap;
+//!
+//! Services with `@service metrics=URL` are scraped in the background once
+//! ready. Recent scrapes stay in memory, and the newest is also written to
+//! `.dagrun/metrics/<service>.json` for `dr status --metrics`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::process::{Child, Command};
 use tokio::sync::RwLock;
-use tokio::time::{sleep, timeout};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, timeout};
 use tracing::{error, info, warn};
 
 use crate::env::service_env_vars;
+use crate::executor::TaskStdin;
 use crate::ssh::{self, SessionCache};
 use dr_ast::{LogOutput, ReadinessCheck, ServiceConfig, ServiceKind, SshConfig, Task};
 
@@ -33,6 +44,23 @@ struct PortForwardInfo {
     remote_port: u16,
 }
 
+/// How often a service's metrics endpoint is scraped
+const METRICS_SCRAPE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of scrapes kept per service
+const METRICS_HISTORY: usize = 10;
+
+/// One scrape of a service's Prometheus metrics endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSample {
+    pub scraped_at: SystemTime,
+    /// series (name plus labels) -> value
+    pub values: HashMap<String, f64>,
+}
+
+/// Ring buffer of recent scrapes, newest last
+type MetricsBuffer = Arc<RwLock<VecDeque<MetricsSample>>>;
+
 /// Running service instance
 struct ServiceInstance {
     task: Task,
@@ -42,7 +70,12 @@ struct ServiceInstance {
     remote_pid: Option<u32>,
     /// active SSH port forward for tunneled services
     port_forward: Option<PortForwardInfo>,
+    /// container ID for docker-backed services
+    container_id: Option<String>,
     ref_count: usize,
+    metrics: MetricsBuffer,
+    /// background scraper for `@service metrics=...`
+    scraper: Option<JoinHandle<()>>,
 }
 
 /// Manages service lifecycles
@@ -50,6 +83,8 @@ pub struct ServiceManager {
     services: Arc<RwLock<HashMap<String, ServiceInstance>>>,
     ssh_sessions: SessionCache,
     insecure_tls: bool,
+    /// count acquisitions without starting or stopping anything (`--dry-run`)
+    dry_run: AtomicBool,
 }
 
 impl ServiceManager {
@@ -70,9 +105,15 @@ impl ServiceManager {
             services: Arc::new(RwLock::new(HashMap::new())),
             ssh_sessions,
             insecure_tls,
+            dry_run: AtomicBool::new(false),
         }
     }
 
+    /// With `dry_run`, acquiring a service hands out its env vars without starting it
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.store(dry_run, Ordering::Relaxed);
+    }
+
     /// Register a service (doesn't start it yet)
     pub async fn register(&self, task: &Task) {
         if task.service.is_none() {
@@ -89,7 +130,10 @@ impl ServiceManager {
                     child: None,
                     remote_pid: None,
                     port_forward: None,
+                    container_id: None,
                     ref_count: 0,
+                    metrics: Arc::new(RwLock::new(VecDeque::new())),
+                    scraper: None,
                 },
             );
         }
@@ -118,6 +162,16 @@ impl ServiceManager {
             )
         };
 
+        if self.dry_run.load(Ordering::Relaxed) {
+            info!(service = %name, "dry run, not starting service");
+            return Ok(service_env_vars(
+                name,
+                &config.kind,
+                config.ready.as_ref(),
+                None,
+            ));
+        }
+
         match state {
             ServiceState::Ready => {
                 let forwarded_port = {
@@ -171,11 +225,12 @@ impl ServiceManager {
             if let Some(svc) = services.get_mut(name) {
                 svc.ref_count = svc.ref_count.saturating_sub(1);
                 svc.ref_count == 0
+                    && !self.dry_run.load(Ordering::Relaxed)
                     && svc
                         .task
                         .service
                         .as_ref()
-                        .map(|s| s.kind == ServiceKind::Managed)
+                        .map(|s| stops_on_release(&s.kind))
                         .unwrap_or(false)
             } else {
                 false
@@ -207,6 +262,17 @@ impl ServiceManager {
             return self.wait_for_ready(name, config, None).await;
         }
 
+        if let ServiceKind::Docker {
+            image,
+            port,
+            extra_args,
+        } = &config.kind
+        {
+            return self
+                .start_docker_service(name, image, *port, extra_args, config)
+                .await;
+        }
+
         // start the process
         let cmd = task
             .run
@@ -239,12 +305,28 @@ impl ServiceManager {
             info!(service = %name, "preflight check passed");
         }
 
-        let mut child = Command::new("sh")
+        // a listener already on the readiness port would make the check pass
+        // for the wrong process, or the new one would fail to bind
+        if let Some(ReadinessCheck::Tcp { port, .. }) = config.ready
+            && !config.allow_existing_port
+            && port_in_use(port).await
+        {
+            let msg = format!("port {} is already bound by another process", port);
+            self.mark_failed(name, &msg).await;
+            return Err(msg);
+        }
+
+        let mut command = Command::new("sh");
+        command
             .arg("-c")
             .arg(cmd)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+            .stderr(Stdio::piped());
+        // own process group, so stopping the service reaches what `sh -c` started too
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command
             .spawn()
             .map_err(|e| format!("failed to start service '{}': {}", name, e))?;
 
@@ -310,9 +392,18 @@ impl ServiceManager {
                 Some(dir) => format!("cd {} && {}", dir, preflight),
                 None => preflight.clone(),
             };
-            let result = ssh::execute_remote(&session, name, &preflight_cmd, None, None)
-                .await
-                .map_err(|e| format!("preflight failed: {}", e))?;
+            let result = ssh::execute_remote(
+                &session,
+                name,
+                &preflight_cmd,
+                &HashMap::new(),
+                None,
+                TaskStdin::Null,
+                None,
+                false,
+            )
+            .await
+            .map_err(|e| format!("preflight failed: {}", e))?;
 
             if !result.success {
                 return Err(format!("preflight check failed: {}", result.stderr.trim()));
@@ -333,9 +424,18 @@ impl ServiceManager {
             ),
         };
 
-        let output = ssh::execute_remote(&session, name, &full_cmd, None, None)
-            .await
-            .map_err(|e| format!("failed to start remote service '{}': {}", name, e))?;
+        let output = ssh::execute_remote(
+            &session,
+            name,
+            &full_cmd,
+            &HashMap::new(),
+            None,
+            TaskStdin::Null,
+            None,
+            false,
+        )
+        .await
+        .map_err(|e| format!("failed to start remote service '{}': {}", name, e))?;
 
         if !output.success {
             return Err(format!("remote service '{}' failed to start", name));
@@ -412,6 +512,69 @@ impl ServiceManager {
         self.wait_for_ready(name, config, tunneled_check).await
     }
 
+    /// Pull an image and run it detached, publishing the service port
+    async fn start_docker_service(
+        &self,
+        name: &str,
+        image: &str,
+        port: u16,
+        extra_args: &[String],
+        config: &ServiceConfig,
+    ) -> Result<(), String> {
+        info!(service = %name, image = %image, "pulling docker image");
+        let pull = Command::new("docker")
+            .args(["pull", image])
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| format!("failed to run docker: {}", e))?;
+        if !pull.status.success() {
+            let msg = format!(
+                "docker pull {} failed: {}",
+                image,
+                String::from_utf8_lossy(&pull.stderr).trim()
+            );
+            self.mark_failed(name, &msg).await;
+            return Err(msg);
+        }
+
+        info!(service = %name, image = %image, port = port, "starting docker container");
+        let run = Command::new("docker")
+            .args(["run", "-d", "--rm", "-p", &format!("{}:{}", port, port)])
+            .args(extra_args)
+            .arg(image)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| format!("failed to run docker: {}", e))?;
+        if !run.status.success() {
+            let msg = format!(
+                "docker run {} failed: {}",
+                image,
+                String::from_utf8_lossy(&run.stderr).trim()
+            );
+            self.mark_failed(name, &msg).await;
+            return Err(msg);
+        }
+
+        let container_id = String::from_utf8_lossy(&run.stdout).trim().to_string();
+        {
+            let mut services = self.services.write().await;
+            if let Some(svc) = services.get_mut(name) {
+                svc.container_id = Some(container_id);
+            }
+        }
+
+        self.wait_for_ready(name, config, None).await
+    }
+
+    async fn mark_failed(&self, name: &str, msg: &str) {
+        let mut services = self.services.write().await;
+        if let Some(svc) = services.get_mut(name) {
+            svc.state = ServiceState::Failed(msg.to_string());
+        }
+    }
+
     /// Wait for service to become ready
     /// If tunneled_check is provided, use it instead of config.ready (for SSH tunneled services)
     async fn wait_for_ready(
@@ -427,6 +590,7 @@ impl ServiceManager {
             let mut services = self.services.write().await;
             if let Some(svc) = services.get_mut(name) {
                 svc.state = ServiceState::Ready;
+                self.start_metrics_scraper(name, svc, config);
             }
             return Ok(());
         };
@@ -478,6 +642,7 @@ impl ServiceManager {
                 let mut services = self.services.write().await;
                 if let Some(svc) = services.get_mut(name) {
                     svc.state = ServiceState::Ready;
+                    self.start_metrics_scraper(name, svc, config);
                 }
                 return Ok(());
             }
@@ -498,6 +663,64 @@ impl ServiceManager {
         Err(msg)
     }
 
+    /// Spawn the background metrics scraper if the service has `metrics=...`
+    fn start_metrics_scraper(&self, name: &str, svc: &mut ServiceInstance, config: &ServiceConfig) {
+        let Some(url) = config.metrics_url.clone() else {
+            return;
+        };
+        if svc.scraper.is_some() {
+            return;
+        }
+
+        let client = match reqwest::Client::builder()
+            .danger_accept_invalid_certs(self.insecure_tls)
+            .timeout(Duration::from_secs(5))
+            .build()
+        {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(service = %name, error = %e, "failed to build metrics client");
+                return;
+            }
+        };
+
+        info!(service = %name, url = %url, "scraping service metrics");
+
+        let name = name.to_string();
+        let metrics = svc.metrics.clone();
+        svc.scraper = Some(tokio::spawn(async move {
+            let mut ticker = interval(METRICS_SCRAPE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let body = match client.get(&url).send().await {
+                    Ok(resp) if resp.status().is_success() => resp.text().await,
+                    Ok(resp) => {
+                        warn!(service = %name, status = %resp.status(), "metrics scrape failed");
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                };
+                match body {
+                    Ok(body) => {
+                        let sample = MetricsSample {
+                            scraped_at: SystemTime::now(),
+                            values: parse_prometheus_text(&body),
+                        };
+                        if let Err(e) = save_metrics(&name, &sample) {
+                            warn!(service = %name, error = %e, "failed to save metrics");
+                        }
+                        let mut buf = metrics.write().await;
+                        if buf.len() == METRICS_HISTORY {
+                            buf.pop_front();
+                        }
+                        buf.push_back(sample);
+                    }
+                    Err(e) => warn!(service = %name, error = %e, "metrics scrape failed"),
+                }
+            }
+        }));
+    }
+
     /// Check if service is ready
     async fn check_readiness(&self, ready: &ReadinessCheck) -> bool {
         match ready {
@@ -540,16 +763,20 @@ impl ServiceManager {
 
     /// Stop a managed service
     async fn stop_service(&self, name: &str) {
-        let (config, child, remote_pid, ssh_config, port_forward) = {
+        let (config, child, remote_pid, ssh_config, port_forward, container_id) = {
             let mut services = self.services.write().await;
             if let Some(svc) = services.get_mut(name) {
                 svc.state = ServiceState::Stopping;
+                if let Some(scraper) = svc.scraper.take() {
+                    scraper.abort();
+                }
                 (
                     svc.task.service.clone(),
                     svc.child.take(),
                     svc.remote_pid.take(),
                     svc.task.ssh.clone(),
                     svc.port_forward.take(),
+                    svc.container_id.take(),
                 )
             } else {
                 return;
@@ -558,6 +785,12 @@ impl ServiceManager {
 
         let config = config.unwrap_or_default();
 
+        // docker containers run with --rm, so stopping also removes them
+        if let Some(id) = container_id {
+            self.stop_docker_service(name, &id, &config).await;
+            return;
+        }
+
         // handle remote service shutdown
         if let (Some(pid), Some(ssh_config)) = (remote_pid, ssh_config) {
             self.stop_remote_service(name, pid, &ssh_config, port_forward)
@@ -583,7 +816,7 @@ impl ServiceManager {
             use nix::unistd::Pid;
 
             if let Some(pid) = child.id() {
-                let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+                let _ = kill(Pid::from_raw(-(pid as i32)), Signal::SIGTERM);
             }
         }
 
@@ -594,6 +827,13 @@ impl ServiceManager {
             }
             _ => {
                 warn!(service = %name, "service did not stop gracefully, killing");
+                #[cfg(unix)]
+                if let Some(pid) = child.id() {
+                    use nix::sys::signal::{Signal, kill};
+                    use nix::unistd::Pid;
+
+                    let _ = kill(Pid::from_raw(-(pid as i32)), Signal::SIGKILL);
+                }
                 let _ = child.kill().await;
 
                 // wait for kill to take effect
@@ -613,6 +853,34 @@ impl ServiceManager {
         }
     }
 
+    /// Stop a docker-backed service
+    async fn stop_docker_service(&self, name: &str, container_id: &str, config: &ServiceConfig) {
+        info!(service = %name, container = %container_id, "stopping docker container");
+
+        let grace = config.shutdown_grace.as_secs().max(1).to_string();
+        match Command::new("docker")
+            .args(["stop", "-t", &grace, container_id])
+            .stdin(Stdio::null())
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => {
+                info!(service = %name, "docker container stopped");
+            }
+            Ok(output) => warn!(
+                service = %name,
+                error = %String::from_utf8_lossy(&output.stderr).trim(),
+                "docker stop failed"
+            ),
+            Err(e) => warn!(service = %name, error = %e, "failed to run docker stop"),
+        }
+
+        let mut services = self.services.write().await;
+        if let Some(svc) = services.get_mut(name) {
+            svc.state = ServiceState::Stopped;
+        }
+    }
+
     /// Stop a remote service via SSH
     async fn stop_remote_service(
         &self,
@@ -654,7 +922,17 @@ impl ServiceManager {
                     "kill {} 2>/dev/null || kill -9 {} 2>/dev/null || true",
                     pid, pid
                 );
-                let _ = ssh::execute_remote(&session, name, &kill_cmd, None, None).await;
+                let _ = ssh::execute_remote(
+                    &session,
+                    name,
+                    &kill_cmd,
+                    &HashMap::new(),
+                    None,
+                    TaskStdin::Null,
+                    None,
+                    false,
+                )
+                .await;
                 info!(service = %name, "remote service stopped");
             }
             Err(e) => {
@@ -671,6 +949,13 @@ impl ServiceManager {
 
     /// Stop all managed services
     pub async fn shutdown(&self) {
+        // external services are never stopped, so their scrapers end here
+        for svc in self.services.write().await.values_mut() {
+            if let Some(scraper) = svc.scraper.take() {
+                scraper.abort();
+            }
+        }
+
         let names: Vec<String> = {
             let services = self.services.read().await;
             services
@@ -679,7 +964,7 @@ impl ServiceManager {
                     svc.task
                         .service
                         .as_ref()
-                        .map(|s| s.kind == ServiceKind::Managed)
+                        .map(|s| stops_on_release(&s.kind))
                         .unwrap_or(false)
                         && svc.state != ServiceState::Stopped
                 })
@@ -698,6 +983,90 @@ impl ServiceManager {
         let services = self.services.read().await;
         services.get(name).map(|s| s.state.clone())
     }
+
+    /// Most recent metrics scrape for a service, if any
+    pub async fn latest_metrics(&self, name: &str) -> Option<MetricsSample> {
+        let metrics = {
+            let services = self.services.read().await;
+            services.get(name)?.metrics.clone()
+        };
+        metrics.read().await.back().cloned()
+    }
+}
+
+fn metrics_dir() -> PathBuf {
+    PathBuf::from(".dagrun").join("metrics")
+}
+
+/// Replace the service's saved scrape, through a rename so readers never see
+/// half a file
+fn save_metrics(service: &str, sample: &MetricsSample) -> io::Result<()> {
+    let dir = metrics_dir();
+    fs::create_dir_all(&dir)?;
+    let mut tmp = tempfile::NamedTempFile::new_in(&dir)?;
+    serde_json::to_writer(&mut tmp, sample)?;
+    tmp.write_all(b"\n")?;
+    tmp.persist(dir.join(format!("{}.json", service)))
+        .map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// The newest saved scrape of each service, sorted by service name
+pub fn saved_metrics() -> io::Result<Vec<(String, MetricsSample)>> {
+    let entries = match fs::read_dir(metrics_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut saved = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Some(service) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if let Ok(sample) = serde_json::from_str(&fs::read_to_string(&path)?) {
+            saved.push((service.to_string(), sample));
+        }
+    }
+    saved.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(saved)
+}
+
+/// Parse the Prometheus text exposition format into series -> value
+fn parse_prometheus_text(body: &str) -> HashMap<String, f64> {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            // label values may contain spaces, so split after the closing brace
+            let split_at = match line.find('{') {
+                Some(_) => line.rfind('}')? + 1,
+                None => line.find(char::is_whitespace)?,
+            };
+            let (series, rest) = line.split_at(split_at);
+            let value = rest.split_whitespace().next()?.parse().ok()?;
+            Some((series.to_string(), value))
+        })
+        .collect()
+}
+
+/// true if something on this host is already accepting connections on `port`
+async fn port_in_use(port: u16) -> bool {
+    timeout(
+        Duration::from_millis(500),
+        TcpStream::connect(("127.0.0.1", port)),
+    )
+    .await
+    .map(|r| r.is_ok())
+    .unwrap_or(false)
+}
+
+/// Services dagrun starts itself, and so stops once nothing holds them
+fn stops_on_release(kind: &ServiceKind) -> bool {
+    matches!(kind, ServiceKind::Managed | ServiceKind::Docker { .. })
 }
 
 impl Default for ServiceManager {
@@ -725,6 +1094,16 @@ mod tests {
             ssh: None,
             k8s: None,
             shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
             span: None,
             service: Some(ServiceConfig {
                 kind: ServiceKind::Managed,
@@ -736,6 +1115,8 @@ mod tests {
                 log: LogOutput::Quiet,
                 forward: false,
                 preflight: None,
+                metrics_url: None,
+                allow_existing_port: false,
             }),
         }
     }
@@ -780,6 +1161,8 @@ mod tests {
         // give it a moment to stop
         tokio::time::sleep(Duration::from_millis(100)).await;
         assert_eq!(mgr.state("tcp-test").await, Some(ServiceState::Stopped));
+        // nc itself is gone, not just the `sh -c` around it
+        assert!(!port_in_use(19876).await);
     }
 
     #[tokio::test]
@@ -864,6 +1247,16 @@ mod tests {
             ssh: None,
             k8s: None,
             shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
             span: None,
             service: Some(ServiceConfig {
                 kind: ServiceKind::Managed,
@@ -878,6 +1271,8 @@ mod tests {
                 log: LogOutput::Quiet,
                 forward: false,
                 preflight: None,
+                metrics_url: None,
+                allow_existing_port: false,
             }),
         };
 
@@ -890,6 +1285,53 @@ mod tests {
         mgr.shutdown().await;
     }
 
+    #[tokio::test]
+    async fn test_port_already_bound() {
+        // something else is already listening on the readiness port
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mgr = ServiceManager::new();
+        let task = Task {
+            name: "dup".to_string(),
+            parameters: vec![],
+            run: Some("sleep 30".to_string()),
+            depends_on: vec![],
+            service_deps: vec![],
+            pipe_from: vec![],
+            timeout: None,
+            retry: 0,
+            join: false,
+            ssh: None,
+            k8s: None,
+            shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
+            span: None,
+            service: Some(ServiceConfig {
+                ready: Some(ReadinessCheck::Tcp {
+                    host: "127.0.0.1".to_string(),
+                    port,
+                }),
+                log: LogOutput::Quiet,
+                ..Default::default()
+            }),
+        };
+
+        mgr.register(&task).await;
+        let err = mgr.acquire("dup").await.unwrap_err();
+        assert!(err.contains(&format!("port {} is already bound", port)));
+        mgr.shutdown().await;
+    }
+
     #[tokio::test]
     async fn test_external_service() {
         // start a listener in background first
@@ -919,6 +1361,16 @@ mod tests {
             ssh: None,
             k8s: None,
             shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
             span: None,
             service: Some(ServiceConfig {
                 kind: ServiceKind::External,
@@ -933,6 +1385,8 @@ mod tests {
                 log: LogOutput::Quiet,
                 forward: false,
                 preflight: None,
+                metrics_url: None,
+                allow_existing_port: false,
             }),
         };
 
@@ -985,6 +1439,30 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_dry_run_does_not_start_service() {
+        let mgr = ServiceManager::new();
+        mgr.set_dry_run(true);
+
+        let task = make_service_task(
+            "dry-run-test",
+            "nc -l 19881",
+            ReadinessCheck::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: 19881,
+            },
+        );
+
+        mgr.register(&task).await;
+
+        let env = mgr.acquire("dry-run-test").await.unwrap();
+        assert!(!env.is_empty());
+        assert_eq!(mgr.state("dry-run-test").await, Some(ServiceState::Stopped));
+
+        mgr.release("dry-run-test").await;
+        assert_eq!(mgr.state("dry-run-test").await, Some(ServiceState::Stopped));
+    }
+
     #[tokio::test]
     async fn test_ssh_service_registration() {
         // test that a service with SSH config can be registered
@@ -1010,9 +1488,23 @@ mod tests {
                 upload: vec![],
                 download: vec![],
                 env: HashMap::new(),
+                stderr_prefix: None,
+                pty: false,
+                password_env: None,
+                proxy: None,
             }),
             k8s: None,
             shebang: None,
+            inputs: Vec::new(),
+            output_capture: None,
+            tags: Vec::new(),
+            outputs: Vec::new(),
+            webhook: None,
+            env: HashMap::new(),
+            condition: None,
+            cache_key: None,
+            artifacts: HashMap::new(),
+            on_failure: None,
             span: None,
             service: Some(ServiceConfig {
                 kind: ServiceKind::Managed,
@@ -1026,6 +1518,8 @@ mod tests {
                 log: LogOutput::Quiet,
                 forward: false,
                 preflight: None,
+                metrics_url: None,
+                allow_existing_port: false,
             }),
         };
 
@@ -1040,4 +1534,23 @@ mod tests {
         // acquiring would fail without actual SSH, but that's expected
         // this test just verifies the registration path works
     }
+
+    #[test]
+    fn test_parse_prometheus_text() {
+        let body = r#"
+# HELP http_requests_total Total requests
+# TYPE http_requests_total counter
+http_requests_total{method="get",path="/a b"} 1027 1395066363000
+process_open_fds 12
+up NaN
+"#;
+        let values = parse_prometheus_text(body);
+        assert_eq!(values.len(), 3);
+        assert_eq!(
+            values[r#"http_requests_total{method="get",path="/a b"}"#],
+            1027.0
+        );
+        assert_eq!(values["process_open_fds"], 12.0);
+        assert!(values["up"].is_nan());
+    }
 }
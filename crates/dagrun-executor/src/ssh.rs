@@ -0,0 +1,785 @@
+//! SSH remote execution support
+//!
+//! Execute tasks on remote hosts via SSH using the openssh crate.
+
+use bytes::BytesMut;
+use colored::Colorize;
+use openssh::{ForwardType, KnownHosts, Session, SessionBuilder, Socket, Stdio};
+use openssh_sftp_client::metadata::MetaDataBuilder;
+use openssh_sftp_client::{Sftp, UnixTimeStamp};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::executor::TaskStdin;
+use crate::progress::task_color;
+
+use dr_ast::SshConfig;
+
+/// Cache of SSH sessions for connection reuse
+pub type SessionCache = Arc<RwLock<HashMap<String, Arc<Session>>>>;
+
+pub fn new_session_cache() -> SessionCache {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Get or create an SSH session for the given config
+pub async fn get_session(
+    config: &SshConfig,
+    cache: &SessionCache,
+) -> Result<Arc<Session>, openssh::Error> {
+    let key = session_key(config);
+
+    // check cache first
+    {
+        let cache_read = cache.read().await;
+        if let Some(session) = cache_read.get(&key) {
+            return Ok(session.clone());
+        }
+    }
+
+    // create new session. ssh reads ~/.ssh/config itself, so an alias is
+    // passed through as-is and keeps every setting of its Host block
+    info!(host = %config.host, user = ?config.user, "establishing SSH connection");
+
+    let mut builder = SessionBuilder::default();
+    builder.known_hosts_check(KnownHosts::Accept);
+
+    if let Some(port) = config.port {
+        builder.port(port);
+    }
+
+    // ssh -J: the bastion's own settings come from ~/.ssh/config, as with ssh
+    if let Some(ref proxy) = config.proxy {
+        info!(host = %config.host, proxy = %proxy, "connecting through jump host");
+        builder.jump_hosts([proxy]);
+    }
+
+    if let Some(path) = identity_path(config) {
+        builder.keyfile(&path);
+    }
+
+    // key auth first; a host with password_env falls back to its password
+    let session = match builder.connect(&config.destination()).await {
+        Ok(session) => session,
+        Err(e) => {
+            let Some(ref var) = config.password_env else {
+                return Err(e);
+            };
+            info!(host = %config.host, error = %e, "key authentication failed, trying password");
+            connect_with_password(config, var).await?
+        }
+    };
+    let session = Arc::new(session);
+
+    // cache the session
+    {
+        let mut cache_write = cache.write().await;
+        cache_write.insert(key, session.clone());
+    }
+
+    Ok(session)
+}
+
+/// The `identity` file with a leading `~` expanded to the home directory
+fn identity_path(config: &SshConfig) -> Option<String> {
+    let identity = config.identity.as_ref()?;
+    Some(match dirs::home_dir() {
+        Some(home) if identity.starts_with('~') => {
+            identity.replacen('~', &home.to_string_lossy(), 1)
+        }
+        _ => identity.clone(),
+    })
+}
+
+/// Open a master connection that logs in with the password in the `var` env
+/// var. openssh always starts its master with `BatchMode=yes`, which rules out
+/// password prompts, so this starts one itself with `SSH_ASKPASS` pointing at a
+/// script that prints the variable. The password is never read into dr: only
+/// ssh and the script see it, through the environment.
+async fn connect_with_password(config: &SshConfig, var: &str) -> Result<Session, openssh::Error> {
+    let connect_err = |msg: String| openssh::Error::Connect(std::io::Error::other(msg));
+    if std::env::var_os(var).is_none() {
+        return Err(connect_err(format!(
+            "password_env variable {} is not set",
+            var
+        )));
+    }
+    let script = askpass_script(var)
+        .ok_or_else(|| connect_err(format!("invalid password_env variable name: {}", var)))?;
+
+    let dir = tempfile::Builder::new()
+        .prefix(".ssh-connection")
+        .tempdir()
+        .map_err(openssh::Error::Master)?;
+    let askpass = dir.path().join("askpass");
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o700)
+            .open(&askpass)
+            .map_err(openssh::Error::Master)?;
+        std::io::Write::write_all(&mut file, script.as_bytes()).map_err(openssh::Error::Master)?;
+    }
+
+    let status = tokio::process::Command::new("ssh")
+        .args(password_master_args(config, dir.path()))
+        .env("SSH_ASKPASS", &askpass)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .map_err(openssh::Error::Connect)?;
+    // only needed while logging in
+    let _ = std::fs::remove_file(&askpass);
+
+    if !status.success() {
+        let log = std::fs::read_to_string(dir.path().join("log")).unwrap_or_default();
+        return Err(connect_err(format!(
+            "password authentication failed: {}",
+            log.trim()
+        )));
+    }
+    Ok(Session::new_process_mux(dir))
+}
+
+/// A `SSH_ASKPASS` script printing the env var `var`, if it's a plain name
+fn askpass_script(var: &str) -> Option<String> {
+    let mut chars = var.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| format!("#!/bin/sh\nprintf '%s\\n' \"${}\"\n", var))
+}
+
+/// Arguments for a backgrounded master connection with its control socket and
+/// log in `dir`, the way openssh starts one but allowing a password prompt
+fn password_master_args(config: &SshConfig, dir: &Path) -> Vec<std::ffi::OsString> {
+    let mut args: Vec<std::ffi::OsString> = vec![
+        "-E".into(),
+        dir.join("log").into(),
+        "-S".into(),
+        dir.join("master").into(),
+        "-M".into(),
+        "-f".into(),
+        "-N".into(),
+    ];
+    for option in [
+        "ControlPersist=yes",
+        "BatchMode=no",
+        "NumberOfPasswordPrompts=1",
+        "PreferredAuthentications=publickey,keyboard-interactive,password",
+        "StrictHostKeyChecking=no",
+    ] {
+        args.extend(["-o".into(), option.into()]);
+    }
+    if let Some(port) = config.port {
+        args.extend(["-p".into(), port.to_string().into()]);
+    }
+    if let Some(path) = identity_path(config) {
+        args.extend([
+            "-o".into(),
+            "IdentitiesOnly=yes".into(),
+            "-i".into(),
+            path.into(),
+        ]);
+    }
+    if let Some(ref proxy) = config.proxy {
+        args.extend(["-J".into(), proxy.into()]);
+    }
+    args.push(config.destination().into());
+    args
+}
+
+/// Cache key for a config's session; the same host reached through a
+/// different jump host gets its own connection
+fn session_key(config: &SshConfig) -> String {
+    match &config.proxy {
+        Some(proxy) => format!("{} via {}", config.destination(), proxy),
+        None => config.destination(),
+    }
+}
+
+/// Prefix for remote stderr lines when `@ssh stderr_prefix=` isn't set
+pub const DEFAULT_STDERR_PREFIX: &str = "[stderr]";
+
+/// Execute a command on a remote host with streaming output
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_remote(
+    session: &Session,
+    task_name: &str,
+    command: &str,
+    env: &HashMap<String, String>,
+    workdir: Option<&str>,
+    stdin: TaskStdin<'_>,
+    stderr_prefix: Option<&str>,
+    pty: bool,
+) -> Result<RemoteOutput, openssh::Error> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let full_command = match workdir {
+        Some(dir) => format!("cd {} && {}", dir, command),
+        None => command.to_string(),
+    };
+
+    info!(command = %full_command, "executing remote command");
+
+    // env vars go to env(1) as separate arguments rather than into the command
+    // string, so quoting and `exec` in the command are left alone
+    let mut args = Vec::new();
+    if !env.is_empty() {
+        // openssh's multiplexed sessions have no way to send env requests, and
+        // servers reject them without AcceptEnv anyway
+        debug!(task = %task_name, "passing env vars to the remote command via env(1)");
+        let mut vars: Vec<_> = env.iter().collect();
+        vars.sort();
+        args.push("env".to_string());
+        args.extend(
+            vars.into_iter()
+                .map(|(key, value)| format!("{}={}", key, value)),
+        );
+    }
+    args.extend(["sh".to_string(), "-c".to_string(), full_command]);
+
+    if pty {
+        if matches!(stdin, TaskStdin::Data(_) | TaskStdin::Inherit) {
+            warn!(task = %task_name, "stdin piping is not supported with pty=true, ignoring piped input");
+        }
+        return execute_remote_tty(session, task_name, &args).await;
+    }
+
+    let mut cmd = session.command(&args[0]);
+    cmd.args(&args[1..]);
+    cmd.stdin(match stdin {
+        TaskStdin::Null => Stdio::null(),
+        TaskStdin::Data(_) => Stdio::piped(),
+        TaskStdin::Inherit => Stdio::inherit(),
+    });
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let mut child = cmd.spawn().await?;
+
+    if let Some(data) = stdin.data()
+        && let Some(mut stdin) = child.stdin().take()
+    {
+        let _ = stdin.write_all(data.as_bytes()).await;
+        drop(stdin);
+    }
+
+    // stream stdout and stderr in real-time
+    let stdout_handle = child.stdout().take();
+    let stderr_handle = child.stderr().take();
+
+    let stdout_is_tty = std::io::stdout().is_terminal();
+    let stderr_is_tty = std::io::stderr().is_terminal();
+
+    let task_name_stdout = task_name.to_string();
+    let color = task_color(task_name);
+    let stdout_task = tokio::spawn(async move {
+        let mut lines_collected = Vec::new();
+        if let Some(stdout) = stdout_handle {
+            let reader = BufReader::new(stdout);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stdout_is_tty {
+                    println!(
+                        "  {} {}",
+                        format!("[{}]", task_name_stdout).color(color),
+                        line
+                    );
+                } else {
+                    println!("{}", line);
+                }
+                lines_collected.push(line);
+            }
+        }
+        lines_collected.join("\n")
+    });
+
+    let task_name_stderr = task_name.to_string();
+    let color = task_color(task_name);
+    // only the terminal copy is prefixed, collected stderr stays as-is
+    let stderr_prefix = match stderr_prefix {
+        Some(p) if !p.is_empty() => format!("{} ", p),
+        _ => String::new(),
+    };
+    let stderr_task = tokio::spawn(async move {
+        let mut lines_collected = Vec::new();
+        if let Some(stderr) = stderr_handle {
+            let reader = BufReader::new(stderr);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stderr_is_tty {
+                    eprintln!(
+                        "  {} {}{}",
+                        format!("[{}]", task_name_stderr).color(color),
+                        stderr_prefix.dimmed(),
+                        line
+                    );
+                } else {
+                    eprintln!("{}{}", stderr_prefix, line);
+                }
+                lines_collected.push(line);
+            }
+        }
+        lines_collected.join("\n")
+    });
+
+    let status = child.wait().await?;
+    let stdout = stdout_task.await.unwrap_or_default();
+    let stderr = stderr_task.await.unwrap_or_default();
+
+    Ok(RemoteOutput {
+        stdout,
+        stderr,
+        success: status.success(),
+        exit_code: status.code(),
+    })
+}
+
+/// Run `args` on the remote host with a pseudo-terminal. openssh only runs
+/// commands with `ssh -T`, so this runs `ssh -tt` over the session's control
+/// socket instead; the terminal merges stderr into stdout.
+async fn execute_remote_tty(
+    session: &Session,
+    task_name: &str,
+    args: &[String],
+) -> Result<RemoteOutput, openssh::Error> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let remote_command = args
+        .iter()
+        .map(|arg| shell_escape::escape(arg.into()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // like openssh's own commands: -p 9 keeps ssh from opening a new connection
+    // if the master has gone away, and the host is ignored once -S is given
+    let mut cmd = tokio::process::Command::new("ssh");
+    cmd.arg("-S")
+        .arg(session.control_socket())
+        .args(["-o", "BatchMode=yes", "-q", "-tt", "-p", "9", "none", "--"])
+        .arg(remote_command)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null());
+    let mut child = cmd.spawn().map_err(openssh::Error::Ssh)?;
+
+    let is_tty = std::io::stdout().is_terminal();
+    let color = task_color(task_name);
+    let mut lines_collected = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            // the remote terminal ends lines with \r\n
+            let line = line.strip_suffix('\r').unwrap_or(&line).to_string();
+            if is_tty {
+                println!("  {} {}", format!("[{}]", task_name).color(color), line);
+            } else {
+                println!("{}", line);
+            }
+            lines_collected.push(line);
+        }
+    }
+    let status = child.wait().await.map_err(openssh::Error::Ssh)?;
+
+    Ok(RemoteOutput {
+        stdout: lines_collected.join("\n"),
+        stderr: String::new(),
+        success: status.success(),
+        exit_code: status.code(),
+    })
+}
+
+#[allow(dead_code)]
+pub struct RemoteOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+}
+
+/// Spawn a streaming command that runs indefinitely, printing output as it comes
+/// The entire command lifecycle happens in the spawned task to avoid lifetime issues
+pub fn spawn_streaming_command(
+    session: Arc<Session>,
+    task_name: String,
+    command: String,
+) -> tokio::task::JoinHandle<()> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let is_tty = std::io::stdout().is_terminal();
+    let color = task_color(&task_name);
+
+    tokio::spawn(async move {
+        let mut cmd = session.command("sh");
+        cmd.arg("-c").arg(&command);
+        cmd.stdin(Stdio::null());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::null());
+
+        let Ok(mut child) = cmd.spawn().await else {
+            return;
+        };
+        let stdout = child.stdout().take();
+
+        if let Some(stdout) = stdout {
+            let reader = BufReader::new(stdout);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if is_tty {
+                    println!("  {} {}", format!("[{}]", task_name).color(color), line);
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+        let _ = child.wait().await;
+    })
+}
+
+/// Whether the session can still run commands (runs `true` on the remote)
+pub async fn ping_session(session: &Session) -> bool {
+    session
+        .command("true")
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// Get a cached session, reconnecting once if the cached connection has dropped
+pub async fn get_live_session(
+    config: &SshConfig,
+    cache: &SessionCache,
+) -> Result<Arc<Session>, openssh::Error> {
+    let session = get_session(config, cache).await?;
+    if ping_session(&session).await {
+        return Ok(session);
+    }
+
+    warn!(host = %config.host, "cached SSH connection is dead, reconnecting");
+    cache.write().await.remove(&session_key(config));
+    get_session(config, cache).await
+}
+
+/// Close all cached sessions (sessions will be dropped, triggering cleanup)
+pub async fn close_sessions(cache: &SessionCache) {
+    let mut cache_write = cache.write().await;
+    for (host, _session) in cache_write.drain() {
+        info!(host = %host, "closing SSH connection");
+        // session is dropped here, which closes the connection
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TransferError {
+    #[error("ssh error: {0}")]
+    Ssh(#[from] openssh::Error),
+    #[error("sftp error: {0}")]
+    Sftp(#[from] openssh_sftp_client::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Upload a local file to the remote host via SFTP
+pub async fn upload_file(
+    session: Arc<Session>,
+    local_path: &str,
+    remote_path: &str,
+    preserve_times: bool,
+) -> Result<(), TransferError> {
+    info!(local = %local_path, remote = %remote_path, "uploading file");
+
+    let local = Path::new(local_path);
+    let contents = tokio::fs::read(local).await?;
+
+    let sftp = Sftp::from_clonable_session(session, Default::default()).await?;
+    let mut remote_file = sftp.create(remote_path).await?;
+    remote_file.write_all(&contents).await?;
+    if preserve_times {
+        // sftp creates the file with the current time; keep the local mtime so
+        // remote build tools comparing mtimes see it as unchanged
+        let metadata = tokio::fs::metadata(local).await?;
+        let accessed = UnixTimeStamp::new(metadata.accessed()?).map_err(std::io::Error::other)?;
+        let modified = UnixTimeStamp::new(metadata.modified()?).map_err(std::io::Error::other)?;
+        remote_file
+            .set_metadata(MetaDataBuilder::new().time(accessed, modified).create())
+            .await?;
+    }
+    remote_file.close().await?;
+    sftp.close().await?;
+
+    info!(remote = %remote_path, bytes = contents.len(), "upload complete");
+    Ok(())
+}
+
+/// Download a file from the remote host via SFTP
+pub async fn download_file(
+    session: Arc<Session>,
+    remote_path: &str,
+    local_path: &str,
+    preserve_times: bool,
+) -> Result<(), TransferError> {
+    info!(remote = %remote_path, local = %local_path, "downloading file");
+
+    let sftp = Sftp::from_clonable_session(session, Default::default()).await?;
+    let mut remote_file = sftp.open(remote_path).await?;
+    let remote_mtime = if preserve_times {
+        remote_file
+            .metadata()
+            .await?
+            .modified()
+            .map(|ts| ts.as_system_time())
+    } else {
+        None
+    };
+
+    let mut contents = Vec::new();
+    loop {
+        let buf = BytesMut::with_capacity(8192);
+        match remote_file.read(8192, buf).await? {
+            Some(data) => contents.extend_from_slice(&data),
+            None => break,
+        }
+    }
+    remote_file.close().await?;
+    sftp.close().await?;
+
+    let local = Path::new(local_path);
+    if let Some(parent) = local.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(local, &contents).await?;
+    if let Some(mtime) = remote_mtime {
+        std::fs::File::options()
+            .write(true)
+            .open(local)?
+            .set_modified(mtime)?;
+    }
+
+    info!(local = %local_path, bytes = contents.len(), "download complete");
+    Ok(())
+}
+
+/// Single-quote everything in a glob except `*`, `?`, `[...]` and a leading
+/// `~/`, so the remote shell expands the pattern but nothing else in it
+fn quote_glob(pattern: &str) -> String {
+    let mut out = String::new();
+    let mut literal = String::new();
+    let flush = |literal: &mut String, out: &mut String| {
+        if !literal.is_empty() {
+            out.push_str(&format!("'{}'", literal.replace('\'', r"'\''")));
+            literal.clear();
+        }
+    };
+
+    let rest = match pattern.strip_prefix("~/") {
+        Some(rest) => {
+            out.push_str("~/");
+            rest
+        }
+        None => pattern,
+    };
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' => {
+                flush(&mut literal, &mut out);
+                out.push(c);
+            }
+            '[' if rest.contains(']') => {
+                flush(&mut literal, &mut out);
+                out.push(c);
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            _ => literal.push(c),
+        }
+    }
+    flush(&mut literal, &mut out);
+    out
+}
+
+/// Download every remote file matching a glob into a local directory.
+/// Directories that match are skipped. Returns how many files were downloaded.
+pub async fn download_glob(
+    session: Arc<Session>,
+    remote_pattern: &str,
+    local_dir: &str,
+    preserve_times: bool,
+) -> Result<usize, TransferError> {
+    info!(pattern = %remote_pattern, local = %local_dir, "expanding remote glob");
+
+    // let the remote shell expand the pattern, printing regular files
+    // NUL-separated; an unmatched pattern stays literal and fails the -f test
+    let output = session
+        .command("sh")
+        .arg("-c")
+        .arg(format!(
+            r#"for f in {}; do [ -f "$f" ] && printf '%s\0' "$f"; done; true"#,
+            quote_glob(remote_pattern)
+        ))
+        .output()
+        .await?;
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let paths: Vec<&str> = listing.split('\0').filter(|p| !p.is_empty()).collect();
+
+    tokio::fs::create_dir_all(local_dir).await?;
+
+    for remote_path in &paths {
+        let filename = Path::new(remote_path)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let local_path = Path::new(local_dir).join(filename);
+        download_file(
+            session.clone(),
+            remote_path,
+            &local_path.to_string_lossy(),
+            preserve_times,
+        )
+        .await?;
+    }
+
+    Ok(paths.len())
+}
+
+/// Find an available local port for forwarding
+pub fn find_available_port() -> std::io::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    Ok(port)
+}
+
+/// Set up local port forwarding through SSH
+/// Traffic to local_port will be forwarded to remote_host:remote_port on the remote side
+pub async fn setup_port_forward(
+    session: &Session,
+    local_port: u16,
+    remote_host: &str,
+    remote_port: u16,
+) -> Result<(), openssh::Error> {
+    info!(
+        local_port = local_port,
+        remote_host = %remote_host,
+        remote_port = remote_port,
+        "setting up SSH port forward"
+    );
+
+    let local_socket = Socket::new("127.0.0.1", local_port);
+    let remote_socket = Socket::new(remote_host, remote_port);
+
+    session
+        .request_port_forward(ForwardType::Local, local_socket, remote_socket)
+        .await
+}
+
+/// Close an existing port forward
+pub async fn close_port_forward(
+    session: &Session,
+    local_port: u16,
+    remote_host: &str,
+    remote_port: u16,
+) -> Result<(), openssh::Error> {
+    info!(
+        local_port = local_port,
+        remote_host = %remote_host,
+        remote_port = remote_port,
+        "closing SSH port forward"
+    );
+
+    let local_socket = Socket::new("127.0.0.1", local_port);
+    let remote_socket = Socket::new(remote_host, remote_port);
+
+    session
+        .close_port_forward(ForwardType::Local, local_socket, remote_socket)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_askpass_script() {
+        assert_eq!(
+            askpass_script("SSH_PASSWORD").unwrap(),
+            "#!/bin/sh\nprintf '%s\\n' \"$SSH_PASSWORD\"\n"
+        );
+        // what ssh gets when it runs the script
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(askpass_script("DR_TEST_PASSWORD").unwrap())
+            .env("DR_TEST_PASSWORD", "it's $ecret")
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "it's $ecret\n");
+
+        assert!(askpass_script("X; rm -rf /").is_none());
+        assert!(askpass_script("1PASS").is_none());
+        assert!(askpass_script("").is_none());
+    }
+
+    #[test]
+    fn test_password_master_args() {
+        let config = SshConfig {
+            host: "legacy-host".to_string(),
+            user: Some("admin".to_string()),
+            port: Some(2222),
+            password_env: Some("SSH_PASSWORD".to_string()),
+            ..Default::default()
+        };
+        let args: Vec<String> = password_master_args(&config, Path::new("/tmp/conn"))
+            .into_iter()
+            .map(|a| a.into_string().unwrap())
+            .collect();
+        let joined = args.join(" ");
+        assert!(joined.starts_with("-E /tmp/conn/log -S /tmp/conn/master -M -f -N"));
+        assert!(joined.contains("-o BatchMode=no"));
+        assert!(!joined.contains("BatchMode=yes"));
+        assert!(joined.contains("-p 2222"));
+        assert_eq!(args.last().unwrap(), "admin@legacy-host");
+    }
+
+    #[test]
+    fn test_quote_glob() {
+        assert_eq!(quote_glob("/app/out/*.log"), "'/app/out/'*'.log'");
+        assert_eq!(quote_glob("~/my logs/run-?.txt"), "~/'my logs/run-'?'.txt'");
+        assert_eq!(quote_glob("/data/[ab]*"), "'/data/'[ab]*");
+        assert_eq!(quote_glob("/tmp/it's; rm *"), r"'/tmp/it'\''s; rm '*");
+    }
+
+    #[test]
+    fn test_session_key_includes_proxy() {
+        let direct = SshConfig {
+            host: "10.0.0.5".to_string(),
+            user: Some("deploy".to_string()),
+            ..Default::default()
+        };
+        let proxied = SshConfig {
+            proxy: Some("ops@bastion:2222".to_string()),
+            ..direct.clone()
+        };
+        assert_eq!(session_key(&direct), "deploy@10.0.0.5");
+        assert_eq!(
+            session_key(&proxied),
+            "deploy@10.0.0.5 via ops@bastion:2222"
+        );
+    }
+}
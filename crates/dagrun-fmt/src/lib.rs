@@ -0,0 +1,326 @@
+//! Canonical formatting for dagfiles.
+//!
+//! Rewrites a parsed [`SourceFile`] with 4-space task bodies, annotations
+//! directly above their task, each run of consecutive variables sorted by name
+//! (keeping any variable after the ones it uses) and a single blank line
+//! between tasks. Comments directly above an item move with it. Items never
+//! move past an include or any other non-variable item.
+//!
+//! # Example
+//!
+//! ```
+//! let formatted = dagrun_fmt::format_source("build:\n\tcargo build\n").unwrap();
+//! assert_eq!(formatted, "build:\n    cargo build\n");
+//! ```
+
+use dr_ast::{BodyLine, Item, ParseError, SourceFile, Spanned, TaskDecl, parse};
+
+/// Indentation for task body lines
+const INDENT: &str = "    ";
+
+/// Parse and format `source`, or return the parse errors if it doesn't parse cleanly
+pub fn format_source(source: &str) -> Result<String, Vec<ParseError>> {
+    let (file, errors) = parse(source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(format(source, &file))
+}
+
+/// Format a parsed file. `source` is the text it was parsed from; annotations,
+/// task headers and body lines are copied from it so quoting inside a line is kept
+pub fn format(source: &str, file: &SourceFile) -> String {
+    // variables are sorted only among their neighbours: moving one past an
+    // include could change which definition wins
+    let mut units = Vec::new();
+    let mut run = Vec::new();
+    for unit in collect_units(source, file) {
+        if unit.group == Group::Variables {
+            run.push(unit);
+        } else {
+            units.extend(sort_variables(std::mem::take(&mut run)));
+            units.push(unit);
+        }
+    }
+    units.extend(sort_variables(run));
+
+    let mut out = String::new();
+    let mut prev: Option<Group> = None;
+    for unit in &units {
+        // like items stay together, everything else gets one blank line
+        if prev.is_some_and(|p| p != unit.group || p == Group::Alone) {
+            out.push('\n');
+        }
+        out.push_str(&unit.text);
+        out.push('\n');
+        prev = Some(unit.group);
+    }
+    out
+}
+
+/// Sort variables by name, except where that would change what they resolve
+/// to. A variable only sees the ones defined above it, so two variables keep
+/// their order when either mentions the other, or when they share a name.
+fn sort_variables(mut variables: Vec<Unit>) -> Vec<Unit> {
+    let name = |u: &Unit| u.sort_key.clone().unwrap_or_default();
+    let mentions = |u: &Unit, other: &Unit| u.text.contains(&format!("{{{{{}}}}}", name(other)));
+
+    let mut sorted = Vec::with_capacity(variables.len());
+    while !variables.is_empty() {
+        // the first by name among those with nothing above them they must follow
+        let free = (0..variables.len())
+            .filter(|&i| {
+                variables[..i].iter().all(|earlier| {
+                    name(earlier) != name(&variables[i])
+                        && !mentions(&variables[i], earlier)
+                        && !mentions(earlier, &variables[i])
+                })
+            })
+            .min_by_key(|&i| name(&variables[i]))
+            .unwrap_or(0);
+        sorted.push(variables.remove(free));
+    }
+    sorted
+}
+
+/// How a unit is separated from its neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Variables,
+    Sets,
+    Includes,
+    /// tasks, blocks and free-standing comments
+    Alone,
+}
+
+/// An item with the comments directly above it, rendered
+struct Unit {
+    group: Group,
+    sort_key: Option<String>,
+    text: String,
+}
+
+fn collect_units(source: &str, file: &SourceFile) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut comments: Vec<&Spanned<Item>> = Vec::new();
+
+    for item in &file.items {
+        if let Item::Comment(_) = &item.node {
+            // a blank line ends a comment block
+            if let Some(last) = comments.last()
+                && blank_line_between(source, last, item)
+            {
+                units.push(comment_unit(source, &comments));
+                comments.clear();
+            }
+            comments.push(item);
+            continue;
+        }
+
+        // comments attach to the item right below them, including ones
+        // between a task's annotations and its name
+        let mut lines: Vec<String> = Vec::new();
+        if comments
+            .last()
+            .is_some_and(|last| !blank_line_between(source, last, item))
+        {
+            lines.extend(comments.iter().map(|c| trimmed(source, c)));
+        } else if !comments.is_empty() {
+            units.push(comment_unit(source, &comments));
+        }
+        comments.clear();
+
+        let (group, sort_key) = match &item.node {
+            Item::Variable(var) => {
+                let value = var.value.span.text(source).trim_end();
+                lines.push(format!("{} := {}", var.name.node, value));
+                (Group::Variables, Some(var.name.node.clone()))
+            }
+            Item::Task(task) => {
+                format_task(source, task, &mut lines);
+                (Group::Alone, None)
+            }
+            Item::SetDirective(_) => {
+                lines.push(trimmed(source, item));
+                (Group::Sets, None)
+            }
+            Item::Include(_) => {
+                lines.push(trimmed(source, item));
+                (Group::Includes, None)
+            }
+            // lua and context blocks are copied as written
+            _ => {
+                lines.push(trimmed(source, item));
+                (Group::Alone, None)
+            }
+        };
+        units.push(Unit {
+            group,
+            sort_key,
+            text: lines.join("\n"),
+        });
+    }
+
+    if !comments.is_empty() {
+        units.push(comment_unit(source, &comments));
+    }
+    units
+}
+
+/// Whether only whitespace, including an empty line, separates `a` from `b`;
+/// comments between a task's annotations are never separated from it
+fn blank_line_between(source: &str, a: &Spanned<Item>, b: &Spanned<Item>) -> bool {
+    let gap = source
+        .get(a.span.end as usize..b.span.start as usize)
+        .unwrap_or("");
+    gap.trim().is_empty() && gap.matches('\n').count() > 1
+}
+
+fn comment_unit(source: &str, comments: &[&Spanned<Item>]) -> Unit {
+    Unit {
+        group: Group::Alone,
+        sort_key: None,
+        text: comments
+            .iter()
+            .map(|c| trimmed(source, c))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn trimmed(source: &str, item: &Spanned<Item>) -> String {
+    item.span.text(source).trim_end().to_string()
+}
+
+fn format_task(source: &str, task: &TaskDecl, lines: &mut Vec<String>) {
+    for ann in &task.annotations {
+        lines.push(ann.span.text(source).trim_end().to_string());
+    }
+
+    // the header is everything on the task name's line
+    let start = task.name.span.start as usize;
+    let end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    lines.push(source[start..end].trim_end().to_string());
+
+    let Some(body) = &task.body else {
+        return;
+    };
+    // keep indentation relative to the first line, e.g. for python bodies
+    let base = body
+        .lines
+        .iter()
+        .find(|l| !matches!(l.node, BodyLine::Empty))
+        .map(|l| {
+            let text = l.span.text(source);
+            &text[..text.len() - text.trim_start().len()]
+        })
+        .unwrap_or("");
+    let mut body_lines: Vec<String> = body
+        .lines
+        .iter()
+        .map(|line| match line.node {
+            BodyLine::Empty => String::new(),
+            _ => {
+                let text = line.span.text(source).trim_end();
+                let text = text.strip_prefix(base).unwrap_or(text.trim_start());
+                format!("{}{}", INDENT, text)
+            }
+        })
+        .collect();
+    while body_lines.last().is_some_and(|l| l.is_empty()) {
+        body_lines.pop();
+    }
+    lines.extend(body_lines);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_layout() {
+        let source =
+            "zeta := 2\nbuild:\n\tcargo build\n\n\n\ntest: build\n\tcargo test\nalpha := 1\n";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "zeta := 2\n\nbuild:\n    cargo build\n\ntest: build\n    cargo test\n\nalpha := 1\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_and_annotations_stay_with_task() {
+        let source = "# header\n\n# builds it\n@timeout 5m\n# note\n@retry 2\nbuild:\n  if true; then\n    echo hi\n  fi\n";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "# header\n\n# builds it\n# note\n@timeout 5m\n@retry 2\nbuild:\n    if true; then\n      echo hi\n    fi\n"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+# tooling
+set dotenv-load := true
+version := `git describe`
+name := dagrun
+
+@lua
+print("hi")
+@end
+@ssh host={{name}}.example.com user=deploy
+deploy target="prod": build
+	echo "deploying {{name}} {{version}} to {{target}}"
+	./deploy.sh
+
+
+build:
+        #!/usr/bin/env python3
+        for i in range(3):
+            print(i)
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+        // same tasks with the same commands
+        let before = dr_ast::parse_config(source).unwrap();
+        let after = dr_ast::parse_config(&formatted).unwrap();
+        assert_eq!(before.tasks.len(), after.tasks.len());
+        for (name, task) in &before.tasks {
+            let other = &after.tasks[name];
+            let run = |t: &dr_ast::Task| t.run.as_deref().map(str::trim_end).map(String::from);
+            assert_eq!(run(task), run(other), "{}", name);
+            assert_eq!(task.depends_on, other.depends_on);
+        }
+    }
+
+    #[test]
+    fn test_variables_stay_after_the_ones_they_use() {
+        let source = "zz := hello\naa := {{zz}}-world\nmid := `echo {{aa}}`\nbb := {{later}}\nlater := x\nb0 := 1\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "b0 := 1\nbb := {{later}}\nlater := x\nzz := hello\naa := {{zz}}-world\nmid := `echo {{aa}}`\n"
+        );
+        assert_eq!(
+            dr_ast::resolve_variables(&formatted).unwrap(),
+            dr_ast::resolve_variables(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_variables_do_not_cross_includes() {
+        // nothing crosses the include, even where sorting would move it
+        let source = "b := 2\na := 1\n@include shared.dr\nx := local\nc := 3\n";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "a := 1\nb := 2\n\n@include shared.dr\n\nc := 3\nx := local\n"
+        );
+    }
+
+    #[test]
+    fn test_refuses_invalid_source() {
+        assert!(format_source("build: dep:\n\techo\n").is_err());
+    }
+}
@@ -2,6 +2,7 @@ use crate::{Span, Spanned};
 
 /// Root of the AST - a complete dagrun file
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SourceFile {
     /// All items in the file, in source order
     pub items: Vec<Spanned<Item>>,
@@ -9,6 +10,7 @@ pub struct SourceFile {
 
 /// Top-level item in a dagrun file
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Item {
     /// Variable assignment: `name := value` or `name := \`command\``
     Variable(VariableDecl),
@@ -16,12 +18,16 @@ pub enum Item {
     Task(TaskDecl),
     /// Lua block: `@lua ... @end`
     LuaBlock(LuaBlock),
-    /// Context block: `@context name ... @end`
+    /// Context block: `@context name [extends parent] ... @end`
     ContextBlock(ContextBlock),
     /// Set directive: `set key := value`
     SetDirective(SetDirective),
+    /// Include directive: `@include path`
+    Include(IncludeDirective),
     /// Comment line (preserved for documentation)
     Comment(Comment),
+    /// A line that failed to parse; the error is in the parser's error list
+    Error,
 }
 
 // ============================================================================
@@ -29,6 +35,7 @@ pub enum Item {
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VariableDecl {
     /// Variable name
     pub name: Spanned<String>,
@@ -39,6 +46,7 @@ pub struct VariableDecl {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum VariableValue {
     /// Static string value
     Static(String),
@@ -47,6 +55,7 @@ pub enum VariableValue {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ShellExpansion {
     /// The opening backtick span
     pub open_span: Span,
@@ -60,17 +69,64 @@ pub struct ShellExpansion {
 // Task Parameters
 // ============================================================================
 
-/// Task parameter definition: `name` or `name="default"`
+/// Task parameter definition: `name`, `name:int` or `name="default"`
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Parameter {
     /// Parameter name
     pub name: Spanned<String>,
+    /// Declared type from `name:type` (None = any string)
+    pub param_type: Option<ParameterType>,
+    /// Allowed values from `name{a,b,c}` (None = unconstrained)
+    pub allowed_values: Option<Vec<Spanned<String>>>,
     /// Default value (None = required, Some = optional)
     pub default: Option<Spanned<ParameterDefault>>,
 }
 
+/// Declared type of a parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterType {
+    Str,
+    Int,
+    Bool,
+    Float,
+}
+
+impl ParameterType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "str" => Some(ParameterType::Str),
+            "int" => Some(ParameterType::Int),
+            "bool" => Some(ParameterType::Bool),
+            "float" => Some(ParameterType::Float),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParameterType::Str => "str",
+            ParameterType::Int => "int",
+            ParameterType::Bool => "bool",
+            ParameterType::Float => "float",
+        }
+    }
+
+    /// Whether `value` parses as this type
+    pub fn accepts(self, value: &str) -> bool {
+        match self {
+            ParameterType::Str => true,
+            ParameterType::Int => value.parse::<i64>().is_ok(),
+            ParameterType::Bool => matches!(value, "true" | "false"),
+            ParameterType::Float => value.parse::<f64>().is_ok(),
+        }
+    }
+}
+
 /// Default value for a parameter
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ParameterDefault {
     /// Literal string value: `"value"`
     Literal(String),
@@ -83,6 +139,7 @@ pub enum ParameterDefault {
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TaskDecl {
     /// Annotations preceding this task
     pub annotations: Vec<Spanned<Annotation>>,
@@ -98,7 +155,32 @@ pub struct TaskDecl {
     pub body: Option<TaskBody>,
 }
 
+impl TaskDecl {
+    /// The `@matrix` axis and values, if the task has one
+    pub fn matrix(&self) -> Option<(&Spanned<String>, &[Spanned<String>])> {
+        self.annotations
+            .iter()
+            .find_map(|ann| match &ann.node.kind {
+                AnnotationKind::Matrix { axis, values } => Some((axis, values.as_slice())),
+                _ => None,
+            })
+    }
+
+    /// Names the task runs under: `name-value` for each `@matrix` value, or
+    /// just its own name
+    pub fn expanded_names(&self) -> Vec<String> {
+        match self.matrix() {
+            Some((_, values)) => values
+                .iter()
+                .map(|value| format!("{}-{}", self.name.node, value.node))
+                .collect(),
+            None => vec![self.name.node.clone()],
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Dependency {
     /// Regular task dependency
     Task(String),
@@ -107,6 +189,7 @@ pub enum Dependency {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TaskBody {
     /// Full span of the body
     pub span: Span,
@@ -115,6 +198,7 @@ pub struct TaskBody {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BodyLine {
     /// Shebang line: `#!/path/to/interpreter`
     Shebang(Shebang),
@@ -125,6 +209,7 @@ pub enum BodyLine {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Shebang {
     /// The `#!` prefix span
     pub prefix_span: Span,
@@ -135,12 +220,14 @@ pub struct Shebang {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct CommandLine {
     /// Segments of the command (text and interpolations)
     pub segments: Vec<Spanned<CommandSegment>>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CommandSegment {
     /// Literal text
     Text(String),
@@ -149,6 +236,7 @@ pub enum CommandSegment {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Interpolation {
     /// Opening `{{` span
     pub open_span: Span,
@@ -163,6 +251,7 @@ pub struct Interpolation {
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Annotation {
     /// The `@` symbol span
     pub at_span: Span,
@@ -171,6 +260,7 @@ pub struct Annotation {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum AnnotationKind {
     /// `@timeout duration`
     Timeout(Spanned<String>),
@@ -217,12 +307,49 @@ pub enum AnnotationKind {
     /// `@k8s-forward local:resource:remote`
     K8sForward(PortForwardAnnotation),
 
-    /// `@env KEY=VALUE`
-    Env(EnvAnnotation),
+    /// `@env KEY=VALUE ...`
+    Env(Vec<Spanned<KeyValue>>),
 
     /// `@use contextname`
     Use(Spanned<String>),
 
+    /// `@outputs path1 path2 ...`
+    Outputs(Vec<Spanned<String>>),
+
+    /// `@inputs glob1 glob2 ...`
+    Inputs(Vec<Spanned<String>>),
+
+    /// `@output-capture NAME`
+    OutputCapture(Spanned<String>),
+
+    /// `@tags tag1 tag2 ...`
+    Tags(Vec<Spanned<String>>),
+
+    /// `@when shell-condition`
+    When(Spanned<String>),
+
+    /// `@cache key="shell-command"`
+    Cache(Spanned<String>),
+
+    /// `@artifact name=path ...`
+    Artifact(Vec<Spanned<KeyValue>>),
+
+    /// `@on-failure task`
+    OnFailure(Spanned<String>),
+
+    /// `@matrix AXIS val1,val2,...`
+    Matrix {
+        axis: Spanned<String>,
+        values: Vec<Spanned<String>>,
+    },
+
+    /// `@webhook url=https://... method=POST body={"task":"{{name}}"}`
+    Webhook {
+        url: Spanned<String>,
+        method: Spanned<String>,
+        body: Option<Spanned<String>>,
+    },
+
     /// Unknown annotation (preserved for error recovery/linting)
     Unknown {
         name: Spanned<String>,
@@ -231,12 +358,16 @@ pub enum AnnotationKind {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SshAnnotation {
+    /// Positional host (`@ssh user@host ...`)
+    pub host: Option<Spanned<String>>,
     /// Key-value pairs (host=, user=, port=, workdir=, identity=)
     pub options: Vec<Spanned<KeyValue>>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct KeyValue {
     pub key: Spanned<String>,
     pub eq_span: Span,
@@ -244,18 +375,25 @@ pub struct KeyValue {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FileTransferAnnotation {
+    /// First path (the remote side for downloads)
     pub local: Spanned<String>,
     pub colon_span: Span,
+    /// Second path (the local side for downloads)
     pub remote: Spanned<String>,
+    /// Trailing options, e.g. `required=true`
+    pub options: Vec<Spanned<KeyValue>>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ServiceAnnotation {
     pub options: Vec<Spanned<KeyValue>>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct K8sAnnotation {
     /// Mode keyword (job, exec, apply) if present
     pub mode: Option<Spanned<String>>,
@@ -264,6 +402,7 @@ pub struct K8sAnnotation {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ConfigMountAnnotation {
     pub name: Spanned<String>,
     pub colon_span: Span,
@@ -271,6 +410,7 @@ pub struct ConfigMountAnnotation {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct PortForwardAnnotation {
     pub local_port: Spanned<String>,
     pub first_colon: Span,
@@ -279,18 +419,12 @@ pub struct PortForwardAnnotation {
     pub remote_port: Spanned<String>,
 }
 
-#[derive(Debug, Clone)]
-pub struct EnvAnnotation {
-    pub key: Spanned<String>,
-    pub eq_span: Span,
-    pub value: Spanned<String>,
-}
-
 // ============================================================================
 // Lua Blocks
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct LuaBlock {
     /// `@lua` token span
     pub open_span: Span,
@@ -305,11 +439,23 @@ pub struct LuaBlock {
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IncludeDirective {
+    /// `@include` span
+    pub open_span: Span,
+    /// Path as written, relative to the including file
+    pub path: Spanned<String>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ContextBlock {
     /// `@context` token span
     pub open_span: Span,
     /// Context name (e.g., "default", "remote")
     pub name: Spanned<String>,
+    /// Parent context from `@context name extends parent`
+    pub extends: Option<Spanned<String>>,
     /// Annotations within this context
     pub annotations: Vec<Spanned<Annotation>>,
     /// `@end` token span (may be missing)
@@ -321,6 +467,7 @@ pub struct ContextBlock {
 // ============================================================================
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SetDirective {
     /// `set` keyword span
     pub set_span: Span,
@@ -333,9 +480,195 @@ pub struct SetDirective {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Comment {
-    /// Full comment text (including `#`)
+    /// Full comment text (including `#` or `//`)
     pub text: String,
-    /// Whether this appears to be a doc comment (starts with `##`)
+    /// Whether this appears to be a doc comment (starts with `##` or `///`)
     pub is_doc: bool,
 }
+
+// ============================================================================
+// Span shifting
+// ============================================================================
+
+/// Move every span in a node by `delta` bytes, so items after an edit can be
+/// reused by an incremental reparse instead of being parsed again
+pub trait Shift {
+    fn shift(&mut self, delta: i64);
+}
+
+impl Shift for Span {
+    fn shift(&mut self, delta: i64) {
+        // placeholder spans (e.g. shebang args) stay unset
+        if *self == Span::default() {
+            return;
+        }
+        self.start = (self.start as i64 + delta) as u32;
+        self.end = (self.end as i64 + delta) as u32;
+    }
+}
+
+impl Shift for String {
+    fn shift(&mut self, _: i64) {}
+}
+
+impl<T: Shift> Shift for Spanned<T> {
+    fn shift(&mut self, delta: i64) {
+        self.span.shift(delta);
+        self.node.shift(delta);
+    }
+}
+
+impl<T: Shift> Shift for Option<T> {
+    fn shift(&mut self, delta: i64) {
+        if let Some(inner) = self {
+            inner.shift(delta);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Vec<T> {
+    fn shift(&mut self, delta: i64) {
+        for inner in self {
+            inner.shift(delta);
+        }
+    }
+}
+
+/// `Shift` for structs by listing their span-carrying fields
+macro_rules! shift_fields {
+    ($($ty:ty { $($field:ident),* })*) => {
+        $(impl Shift for $ty {
+            fn shift(&mut self, delta: i64) {
+                $(self.$field.shift(delta);)*
+            }
+        })*
+    };
+}
+
+shift_fields! {
+    SourceFile { items }
+    VariableDecl { name, assign_span, value }
+    ShellExpansion { open_span, command, close_span }
+    Parameter { name, allowed_values, default }
+    TaskDecl { annotations, name, parameters, colon_span, dependencies, body }
+    TaskBody { span, lines }
+    Shebang { prefix_span, interpreter, args }
+    CommandLine { segments }
+    Interpolation { open_span, name, close_span }
+    Annotation { at_span, kind }
+    SshAnnotation { host, options }
+    KeyValue { key, eq_span, value }
+    FileTransferAnnotation { local, colon_span, remote, options }
+    ServiceAnnotation { options }
+    K8sAnnotation { mode, options }
+    ConfigMountAnnotation { name, colon_span, path }
+    PortForwardAnnotation { local_port, first_colon, resource, second_colon, remote_port }
+    LuaBlock { open_span, content, close_span }
+    IncludeDirective { open_span, path }
+    ContextBlock { open_span, name, extends, annotations, close_span }
+    SetDirective { set_span, key, assign_span, value }
+}
+
+impl Shift for Comment {
+    fn shift(&mut self, _: i64) {}
+}
+
+impl Shift for Item {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            Item::Variable(var) => var.shift(delta),
+            Item::Task(task) => task.shift(delta),
+            Item::LuaBlock(lua) => lua.shift(delta),
+            Item::ContextBlock(ctx) => ctx.shift(delta),
+            Item::SetDirective(set) => set.shift(delta),
+            Item::Include(include) => include.shift(delta),
+            Item::Comment(comment) => comment.shift(delta),
+            Item::Error => {}
+        }
+    }
+}
+
+impl Shift for VariableValue {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            VariableValue::Static(_) => {}
+            VariableValue::Shell(shell) => shell.shift(delta),
+        }
+    }
+}
+
+impl Shift for ParameterDefault {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            ParameterDefault::Literal(_) => {}
+            ParameterDefault::Variable(interp) => interp.shift(delta),
+        }
+    }
+}
+
+impl Shift for Dependency {
+    fn shift(&mut self, _: i64) {}
+}
+
+impl Shift for BodyLine {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            BodyLine::Shebang(shebang) => shebang.shift(delta),
+            BodyLine::Command(cmd) => cmd.shift(delta),
+            BodyLine::Empty => {}
+        }
+    }
+}
+
+impl Shift for CommandSegment {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            CommandSegment::Text(_) => {}
+            CommandSegment::Interpolation(interp) => interp.shift(delta),
+        }
+    }
+}
+
+impl Shift for AnnotationKind {
+    fn shift(&mut self, delta: i64) {
+        match self {
+            AnnotationKind::Timeout(s)
+            | AnnotationKind::Retry(s)
+            | AnnotationKind::Use(s)
+            | AnnotationKind::OutputCapture(s)
+            | AnnotationKind::When(s)
+            | AnnotationKind::Cache(s)
+            | AnnotationKind::OnFailure(s) => s.shift(delta),
+            AnnotationKind::PipeFrom(v)
+            | AnnotationKind::Outputs(v)
+            | AnnotationKind::Inputs(v)
+            | AnnotationKind::Tags(v) => v.shift(delta),
+            AnnotationKind::Join => {}
+            AnnotationKind::Matrix { axis, values } => {
+                axis.shift(delta);
+                values.shift(delta);
+            }
+            AnnotationKind::Ssh(ssh) => ssh.shift(delta),
+            AnnotationKind::Upload(t)
+            | AnnotationKind::Download(t)
+            | AnnotationKind::K8sUpload(t)
+            | AnnotationKind::K8sDownload(t) => t.shift(delta),
+            AnnotationKind::Service(svc) | AnnotationKind::Extern(svc) => svc.shift(delta),
+            AnnotationKind::K8s(k8s) => k8s.shift(delta),
+            AnnotationKind::K8sConfigmap(m) | AnnotationKind::K8sSecret(m) => m.shift(delta),
+            AnnotationKind::K8sForward(f) => f.shift(delta),
+            AnnotationKind::Env(kvs) | AnnotationKind::Artifact(kvs) => kvs.shift(delta),
+            AnnotationKind::Webhook { url, method, body } => {
+                url.shift(delta);
+                method.shift(delta);
+                body.shift(delta);
+            }
+            AnnotationKind::Unknown { name, rest } => {
+                name.shift(delta);
+                rest.shift(delta);
+            }
+        }
+    }
+}
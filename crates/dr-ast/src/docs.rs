@@ -5,6 +5,8 @@
 pub struct AnnotationDoc {
     pub name: &'static str,
     pub syntax: &'static str,
+    /// LSP snippet inserted when the annotation is completed, `$N` marks tab stops
+    pub snippet: &'static str,
     pub description: &'static str,
     pub options: &'static [(&'static str, &'static str)],
     pub example: &'static str,
@@ -32,16 +34,77 @@ impl AnnotationDoc {
 
         md
     }
+
+    /// markdown for a single `key=` option, for hovering over the key itself
+    pub fn option_markdown(&self, key: &str) -> Option<String> {
+        let (opt, desc) = self
+            .options
+            .iter()
+            .find(|(opt, _)| opt.split('=').next() == Some(key))?;
+        let value = opt.split_once('=').map_or("", |(_, v)| v);
+        let kind = match value {
+            "true" | "false" => "boolean",
+            v if !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()) => "integer",
+            _ => "string",
+        };
+        let required = if self.requires_option(key) {
+            "yes"
+        } else {
+            "no"
+        };
+        Some(format!(
+            "**@{}** `{}`\n\n{}\n\n- **Type:** {}\n- **Required:** {}\n- **Example:** `@{} {}`",
+            self.name, key, desc, kind, required, self.name, opt
+        ))
+    }
+
+    /// whether `key=` appears in the syntax line outside `[...]`
+    fn requires_option(&self, key: &str) -> bool {
+        let mut depth = 0;
+        let mut outside = String::new();
+        for c in self.syntax.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ if depth == 0 => outside.push(c),
+                _ => {}
+            }
+        }
+        outside
+            .split_whitespace()
+            .any(|word| word.starts_with(&format!("{}=", key)))
+    }
 }
 
 pub const SSH: AnnotationDoc = AnnotationDoc {
     name: "ssh",
     syntax: "#@ssh user@host [options]",
+    snippet: "ssh host=$1 user=$2 workdir=$3",
     description: "Execute task on remote host via SSH",
     options: &[
+        (
+            "host=user@host",
+            "Remote host (required unless given positionally)",
+        ),
         ("workdir=/path", "Remote working directory"),
         ("identity=/path", "SSH identity file"),
+        (
+            "password_env=VAR",
+            "Env var holding a password, tried when key authentication fails",
+        ),
         ("port=22", "SSH port"),
+        (
+            "proxy=user@bastion:22",
+            "Jump host to connect through (like ssh -J)",
+        ),
+        (
+            "stderr_prefix=text",
+            "Prefix for remote stderr lines (default [stderr])",
+        ),
+        (
+            "pty=true",
+            "Allocate a remote pseudo-terminal (stderr is merged into stdout)",
+        ),
     ],
     example: "#@ssh deploy@prod.example.com workdir=/app",
 };
@@ -49,35 +112,73 @@ pub const SSH: AnnotationDoc = AnnotationDoc {
 pub const K8S: AnnotationDoc = AnnotationDoc {
     name: "k8s",
     syntax: "#@k8s [mode] [options]",
-    description: "Execute task in Kubernetes pod",
+    snippet: "k8s ${1|job,exec,apply,debug|} namespace=$2 pod=$3",
+    description: "Execute task in Kubernetes pod (modes: job, exec, apply, debug)",
     options: &[
         ("namespace=ns", "Kubernetes namespace"),
         ("pod=name", "Pod name or selector"),
         ("container=name", "Container name"),
         ("workdir=/path", "Working directory in container"),
+        (
+            "image=name",
+            "Container image (job mode; debug container in debug mode)",
+        ),
+        ("path=./dir", "Manifest directory (apply mode)"),
+        (
+            "prune=true",
+            "Delete resources no longer in path (apply mode)",
+        ),
+        (
+            "server_side=true",
+            "Use kubectl apply --server-side with field manager dagrun (apply mode)",
+        ),
+        (
+            "force_conflicts=true",
+            "Take over fields owned by other managers (server-side apply)",
+        ),
+        (
+            "use_kubectl_cp=true",
+            "Copy the script into the pod with kubectl cp instead of sh -c (exec mode)",
+        ),
+        (
+            "setup_cmd=\"cmd\"",
+            "Run cmd in an init container first; files it writes to $DAGRUN_SHARED_DIR are visible to the task (job mode)",
+        ),
     ],
     example: "#@k8s exec namespace=prod pod=api-server container=app",
 };
 
 pub const UPLOAD: AnnotationDoc = AnnotationDoc {
     name: "upload",
-    syntax: "#@upload local_path:remote_path",
+    syntax: "#@upload local_path:remote_path [preserve_times=false]",
+    snippet: "upload ${1:local_path}:${2:remote_path}",
     description: "Upload file before task execution",
-    options: &[],
+    options: &[(
+        "preserve_times=false",
+        "Skip copying the local modification time to the remote file",
+    )],
     example: "#@ssh user@host\n#@upload ./config.yaml:/etc/app/config.yaml",
 };
 
 pub const DOWNLOAD: AnnotationDoc = AnnotationDoc {
     name: "download",
-    syntax: "#@download remote_path:local_path",
-    description: "Download file after task execution",
-    options: &[],
+    syntax: "#@download remote_path:local_path [required=true] [preserve_times=false]",
+    snippet: "download ${1:remote_path}:${2:local_path}",
+    description: "Download file after task execution (remote globs download into a directory)",
+    options: &[
+        ("required=true", "Fail if a remote glob matches no files"),
+        (
+            "preserve_times=false",
+            "Skip copying the remote modification time to the local file",
+        ),
+    ],
     example: "#@ssh user@host\n#@download /var/log/app.log:./logs/app.log",
 };
 
 pub const K8S_UPLOAD: AnnotationDoc = AnnotationDoc {
     name: "k8s-upload",
     syntax: "#@k8s-upload local_path:remote_path",
+    snippet: "k8s-upload ${1:local_path}:${2:remote_path}",
     description: "Upload file to Kubernetes pod before task execution",
     options: &[],
     example: "#@k8s namespace=prod pod=api\n#@k8s-upload ./script.sh:/tmp/script.sh",
@@ -86,6 +187,7 @@ pub const K8S_UPLOAD: AnnotationDoc = AnnotationDoc {
 pub const K8S_DOWNLOAD: AnnotationDoc = AnnotationDoc {
     name: "k8s-download",
     syntax: "#@k8s-download remote_path:local_path",
+    snippet: "k8s-download ${1:remote_path}:${2:local_path}",
     description: "Download file from Kubernetes pod after task execution",
     options: &[],
     example: "#@k8s namespace=prod pod=api\n#@k8s-download /tmp/output.log:./output.log",
@@ -94,6 +196,7 @@ pub const K8S_DOWNLOAD: AnnotationDoc = AnnotationDoc {
 pub const K8S_CONFIGMAP: AnnotationDoc = AnnotationDoc {
     name: "k8s-configmap",
     syntax: "#@k8s-configmap name:/mount/path",
+    snippet: "k8s-configmap ${1:name}:${2:/mount/path}",
     description: "Mount a ConfigMap into the pod",
     options: &[],
     example: "#@k8s namespace=prod\n#@k8s-configmap app-config:/etc/config",
@@ -102,6 +205,7 @@ pub const K8S_CONFIGMAP: AnnotationDoc = AnnotationDoc {
 pub const K8S_SECRET: AnnotationDoc = AnnotationDoc {
     name: "k8s-secret",
     syntax: "#@k8s-secret name:/mount/path",
+    snippet: "k8s-secret ${1:name}:${2:/mount/path}",
     description: "Mount a Secret into the pod",
     options: &[],
     example: "#@k8s namespace=prod\n#@k8s-secret db-creds:/etc/secrets",
@@ -110,6 +214,7 @@ pub const K8S_SECRET: AnnotationDoc = AnnotationDoc {
 pub const K8S_FORWARD: AnnotationDoc = AnnotationDoc {
     name: "k8s-forward",
     syntax: "#@k8s-forward local_port:resource:remote_port",
+    snippet: "k8s-forward ${1:local_port}:${2:svc/name}:${3:remote_port}",
     description: "Port forward to a Kubernetes resource during task execution",
     options: &[],
     example: "#@k8s namespace=prod\n#@k8s-forward 5432:svc/postgres:5432",
@@ -118,6 +223,7 @@ pub const K8S_FORWARD: AnnotationDoc = AnnotationDoc {
 pub const TIMEOUT: AnnotationDoc = AnnotationDoc {
     name: "timeout",
     syntax: "#@timeout duration",
+    snippet: "timeout ${1:30s}",
     description: "Set task execution timeout",
     options: &[],
     example: "#@timeout 10m",
@@ -126,6 +232,7 @@ pub const TIMEOUT: AnnotationDoc = AnnotationDoc {
 pub const RETRY: AnnotationDoc = AnnotationDoc {
     name: "retry",
     syntax: "#@retry count",
+    snippet: "retry ${1:3}",
     description: "Retry task on failure",
     options: &[],
     example: "#@retry 3",
@@ -134,6 +241,7 @@ pub const RETRY: AnnotationDoc = AnnotationDoc {
 pub const SERVICE: AnnotationDoc = AnnotationDoc {
     name: "service",
     syntax: "#@service [options]",
+    snippet: "service name=$1 ready_pattern=$2",
     description: "Mark task as a background service that other tasks can depend on",
     options: &[
         ("name=svc", "Service name for dependencies"),
@@ -141,6 +249,14 @@ pub const SERVICE: AnnotationDoc = AnnotationDoc {
             "ready_pattern=regex",
             "Pattern to match in output when service is ready",
         ),
+        (
+            "metrics=url",
+            "Prometheus endpoint scraped every 30s while ready",
+        ),
+        (
+            "allow_existing_port=true",
+            "Start even if the tcp: ready port is already bound",
+        ),
     ],
     example: "#@service name=db ready_pattern=ready\nstart_db:\n  docker run postgres",
 };
@@ -148,10 +264,15 @@ pub const SERVICE: AnnotationDoc = AnnotationDoc {
 pub const EXTERN: AnnotationDoc = AnnotationDoc {
     name: "extern",
     syntax: "#@extern [options]",
+    snippet: "extern name=$1 check=$2",
     description: "Declare an external service dependency (not managed by dagrun)",
     options: &[
         ("name=svc", "Service name"),
         ("check=cmd", "Command to check if service is available"),
+        (
+            "kind=docker",
+            "Pull image= and run it, publishing port= (extra docker flags via args=)",
+        ),
     ],
     example: "#@extern name=redis check=\"redis-cli ping\"",
 };
@@ -159,19 +280,135 @@ pub const EXTERN: AnnotationDoc = AnnotationDoc {
 pub const PIPE_FROM: AnnotationDoc = AnnotationDoc {
     name: "pipe_from",
     syntax: "#@pipe_from task1, task2, ...",
+    snippet: "pipe_from $1",
     description: "Pipe stdout from specified tasks as stdin to this task",
     options: &[],
     example: "#@pipe_from generate_data\nprocess:\n  jq '.items[]'",
 };
 
+pub const OUTPUTS: AnnotationDoc = AnnotationDoc {
+    name: "outputs",
+    syntax: "#@outputs path1 path2 ...",
+    snippet: "outputs $1",
+    description: "Declare files the task produces; an @inputs or @cache hit only counts while they exist and are newer than the cached run",
+    options: &[],
+    example: "#@outputs ./target/release/myapp\nrelease:\n  cargo build --release",
+};
+
+pub const INPUTS: AnnotationDoc = AnnotationDoc {
+    name: "inputs",
+    syntax: "#@inputs glob1 glob2 ...",
+    snippet: "inputs $1",
+    description: "Skip the task when the contents of the matched files are unchanged since its last successful run",
+    options: &[],
+    example: "#@inputs src/**/*.rs Cargo.toml Cargo.lock\nbuild:\n  cargo build",
+};
+
+pub const OUTPUT_CAPTURE: AnnotationDoc = AnnotationDoc {
+    name: "output-capture",
+    syntax: "#@output-capture NAME",
+    snippet: "output-capture ${1:NAME}",
+    description: "Capture the task's trimmed stdout as {{NAME}} for tasks that run after it",
+    options: &[],
+    example: "#@output-capture VERSION
+version:
+  git describe --tags
+
+release: version
+  echo releasing {{VERSION}}",
+};
+
+pub const TAGS: AnnotationDoc = AnnotationDoc {
+    name: "tags",
+    syntax: "#@tags tag1 tag2 ...",
+    snippet: "tags $1",
+    description: "Label the task; `set concurrency-by-tag := deploy=1` caps how many tasks with a tag run at once",
+    options: &[],
+    example: "#@tags deploy\ndeploy-api:\n  ./deploy.sh api",
+};
+
+pub const WHEN: AnnotationDoc = AnnotationDoc {
+    name: "when",
+    syntax: "#@when <shell condition>",
+    snippet: "when $1",
+    description: "Run the task only if the condition exits 0, otherwise skip it; dependents still run",
+    options: &[],
+    example: "#@when test -f .needs-migration\nmigrate: deploy\n  ./migrate.sh",
+};
+
+pub const CACHE: AnnotationDoc = AnnotationDoc {
+    name: "cache",
+    syntax: "#@cache key=\"<shell command>\"",
+    snippet: "cache key=\"$1\"",
+    description: "Skip the task and replay its stdout when an earlier successful run had the same key; stored under ~/.cache/dagrun/<task>/<key>",
+    options: &[(
+        "key=\"command\"",
+        "Command whose trimmed stdout is the cache key",
+    )],
+    example: "#@cache key=\"sha256sum Cargo.lock src/*.rs\"\nbuild:\n  cargo build --release",
+};
+
+pub const ARTIFACT: AnnotationDoc = AnnotationDoc {
+    name: "artifact",
+    syntax: "#@artifact name=path ...",
+    snippet: "artifact ${1:name}=$2",
+    description: "Declare files the task produces; the task fails if one is missing, and later tasks get the path as {{artifact.task.name}}",
+    options: &[],
+    example: "#@artifact bin=target/release/app\nbuild:\n  cargo build --release\n\nship: build\n  scp {{artifact.build.bin}} prod:/opt/app",
+};
+
+pub const ON_FAILURE: AnnotationDoc = AnnotationDoc {
+    name: "on-failure",
+    syntax: "#@on-failure <task>",
+    snippet: "on-failure $1",
+    description: "Run another task when this one fails, e.g. to clean up or send an alert; the handler's result doesn't change the run's exit code",
+    options: &[],
+    example: "#@on-failure rollback\ndeploy:\n  ./deploy.sh\n\nrollback:\n  ./rollback.sh",
+};
+
+pub const MATRIX: AnnotationDoc = AnnotationDoc {
+    name: "matrix",
+    syntax: "#@matrix AXIS val1,val2,...",
+    snippet: "matrix ${1:axis} $2",
+    description: "Expand the task into one task per value, named `task-value`, with {{AXIS}} set to the value",
+    options: &[],
+    example: "#@matrix target x86_64-unknown-linux-gnu,aarch64-apple-darwin\nbuild:\n  cargo build --target {{target}}",
+};
+
+pub const ENV: AnnotationDoc = AnnotationDoc {
+    name: "env",
+    syntax: "#@env KEY=VALUE ...",
+    snippet: "env ${1:KEY}=$2",
+    description: "Set environment variables for the task's command; values can use {{variables}}",
+    options: &[],
+    example: "#@env RUST_LOG=debug PORT=8080\nserve:\n  cargo run",
+};
+
 pub const JOIN: AnnotationDoc = AnnotationDoc {
     name: "join",
     syntax: "#@join",
+    snippet: "join",
     description: "Wait for all dependencies to complete (implicit barrier)",
     options: &[],
     example: "#@join\nfinalize: task1 task2 task3\n  echo \"all done\"",
 };
 
+pub const WEBHOOK: AnnotationDoc = AnnotationDoc {
+    name: "webhook",
+    syntax: "#@webhook url=URL [method=POST] [body=TEMPLATE]",
+    snippet: "webhook url=$1",
+    description: "Send an HTTP request after the task finishes",
+    options: &[
+        ("url=https://...", "Webhook URL"),
+        ("method=POST", "HTTP method"),
+        (
+            "body={...}",
+            "Request body (rest of line); {{name}}, {{status}}, {{duration_ms}}, {{attempts}} are filled in",
+        ),
+    ],
+    example: "#@webhook url=https://hooks.example.com/done body={\"task\":\"{{name}}\",\"status\":\"{{status}}\"}",
+};
+
 /// get doc for an annotation by name
 pub fn get_annotation_doc(name: &str) -> Option<&'static AnnotationDoc> {
     match name {
@@ -190,6 +427,17 @@ pub fn get_annotation_doc(name: &str) -> Option<&'static AnnotationDoc> {
         "extern" => Some(&EXTERN),
         "pipe_from" => Some(&PIPE_FROM),
         "join" => Some(&JOIN),
+        "outputs" => Some(&OUTPUTS),
+        "inputs" => Some(&INPUTS),
+        "output-capture" => Some(&OUTPUT_CAPTURE),
+        "tags" => Some(&TAGS),
+        "when" => Some(&WHEN),
+        "cache" => Some(&CACHE),
+        "artifact" => Some(&ARTIFACT),
+        "on-failure" => Some(&ON_FAILURE),
+        "matrix" => Some(&MATRIX),
+        "env" => Some(&ENV),
+        "webhook" => Some(&WEBHOOK),
         _ => None,
     }
 }
@@ -211,4 +459,61 @@ pub const ANNOTATION_NAMES: &[&str] = &[
     "extern",
     "pipe_from",
     "join",
+    "outputs",
+    "inputs",
+    "output-capture",
+    "tags",
+    "when",
+    "cache",
+    "artifact",
+    "on-failure",
+    "matrix",
+    "env",
+    "webhook",
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_annotation_has_a_snippet() {
+        for name in ANNOTATION_NAMES {
+            let doc = get_annotation_doc(name).unwrap();
+            assert!(doc.snippet.starts_with(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn option_markdown_by_key() {
+        let doc = SSH.option_markdown("workdir").unwrap();
+        assert_eq!(
+            doc,
+            "**@ssh** `workdir`\n\nRemote working directory\n\n- **Type:** string\n- **Required:** no\n- **Example:** `@ssh workdir=/path`"
+        );
+        assert!(SSH.option_markdown("nope").is_none());
+
+        assert!(
+            SSH.option_markdown("port")
+                .unwrap()
+                .contains("**Type:** integer")
+        );
+        assert!(
+            SSH.option_markdown("pty")
+                .unwrap()
+                .contains("**Type:** boolean")
+        );
+        assert!(
+            WEBHOOK
+                .option_markdown("url")
+                .unwrap()
+                .contains("**Required:** yes")
+        );
+        assert!(
+            WEBHOOK
+                .option_markdown("method")
+                .unwrap()
+                .contains("**Required:** no")
+        );
+    }
+}
@@ -13,6 +13,7 @@ pub enum TokenKind {
     Quote,       // "
     OpenBrace,   // {
     CloseBrace,  // }
+    Backslash,   // \ right before a newline, a line continuation
 
     // content
     Identifier(String),
@@ -22,7 +23,8 @@ pub enum TokenKind {
     Whitespace,
 
     // special markers
-    Shebang, // #! at start of line
+    Shebang,                  // #! at start of line
+    Comment { text: String }, // # or // leading a line, prefix stripped
 
     // always emitted at end
     Eof,
@@ -55,6 +57,7 @@ pub struct Lexer<'a> {
     bytes: &'a [u8],
     pos: usize,
     at_line_start: bool,
+    line_has_content: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -64,6 +67,7 @@ impl<'a> Lexer<'a> {
             bytes: source.as_bytes(),
             pos: 0,
             at_line_start: true,
+            line_has_content: false,
         }
     }
 
@@ -71,6 +75,11 @@ impl<'a> Lexer<'a> {
         let mut tokens = Vec::new();
         loop {
             let tok = self.next_token();
+            match tok.kind {
+                TokenKind::Newline => self.line_has_content = false,
+                TokenKind::Whitespace | TokenKind::Indent => {}
+                _ => self.line_has_content = true,
+            }
             let is_eof = tok.kind == TokenKind::Eof;
             tokens.push(tok);
             if is_eof {
@@ -111,6 +120,25 @@ impl<'a> Lexer<'a> {
             }
         }
 
+        // comment: # or // as the first thing on a line
+        if !self.line_has_content
+            && let Some(prefix_len) = self.comment_prefix_len()
+        {
+            self.pos += prefix_len;
+            let text_start = self.pos;
+            while let Some(b) = self.peek() {
+                if b == b'\n' || b == b'\r' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            let text = self.source[text_start..self.pos].to_string();
+            return Token::new(
+                TokenKind::Comment { text },
+                Span::new(start as u32, self.pos as u32),
+            );
+        }
+
         let ch = self.advance();
 
         match ch {
@@ -172,6 +200,10 @@ impl<'a> Lexer<'a> {
                 TokenKind::CloseBrace,
                 Span::new(start as u32, self.pos as u32),
             ),
+            b'\\' if self.at_newline() => Token::new(
+                TokenKind::Backslash,
+                Span::new(start as u32, self.pos as u32),
+            ),
             _ if is_ident_start(ch) => {
                 while self.peek().is_some_and(is_ident_continue) {
                     self.pos += 1;
@@ -188,6 +220,9 @@ impl<'a> Lexer<'a> {
                     if is_delimiter(b) || b == b'\n' || b == b'\r' {
                         break;
                     }
+                    if b == b'\\' && self.at_newline_after(1) {
+                        break;
+                    }
                     self.pos += 1;
                 }
                 let text = &self.source[start..self.pos];
@@ -209,9 +244,29 @@ impl<'a> Lexer<'a> {
         b
     }
 
+    fn at_newline(&self) -> bool {
+        self.at_newline_after(0)
+    }
+
+    fn at_newline_after(&self, n: usize) -> bool {
+        matches!(self.bytes.get(self.pos + n), Some(b'\n' | b'\r'))
+    }
+
     fn check_str(&self, s: &str) -> bool {
         self.source[self.pos..].starts_with(s)
     }
+
+    fn comment_prefix_len(&self) -> Option<usize> {
+        if self.check_str("#!") {
+            None
+        } else if self.check_str("#") {
+            Some(1)
+        } else if self.check_str("//") {
+            Some(2)
+        } else {
+            None
+        }
+    }
 }
 
 fn is_ident_start(b: u8) -> bool {
@@ -309,9 +364,55 @@ mod tests {
         assert!(tokens.contains(&TokenKind::Shebang));
     }
 
+    #[test]
+    fn comments() {
+        assert_eq!(
+            lex("# hash comment"),
+            vec![
+                TokenKind::Comment {
+                    text: " hash comment".to_string()
+                },
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(
+            lex("// slash comment"),
+            vec![
+                TokenKind::Comment {
+                    text: " slash comment".to_string()
+                },
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_markers_mid_line_are_not_comments() {
+        let tokens = lex("url := http://example.com#frag");
+        assert!(
+            !tokens
+                .iter()
+                .any(|t| matches!(t, TokenKind::Comment { .. }))
+        );
+        assert!(tokens.contains(&TokenKind::Hash));
+    }
+
     #[test]
     fn shell_expansion() {
         let tokens = lex("ver := `git rev-parse HEAD`");
         assert!(tokens.contains(&TokenKind::Backtick));
     }
+
+    #[test]
+    fn backslash_only_before_newline() {
+        let tokens = lex("a := x \\\n  y\\z");
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| **t == TokenKind::Backslash)
+                .count(),
+            1
+        );
+        assert!(tokens.contains(&TokenKind::Text("\\z".to_string())));
+    }
 }
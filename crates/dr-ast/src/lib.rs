@@ -28,18 +28,50 @@ pub mod parser;
 pub mod semantic;
 pub mod semantic_parser;
 pub mod span;
+pub mod validate;
 
 // re-export syntactic AST (for LSP that needs raw spans)
 pub use ast::*;
 pub use error::{ParseError, ParseErrorKind};
-pub use parser::parse;
-pub use span::{Span, Spanned};
+pub use parser::{parse, parse_items_from_range};
+pub use span::{Span, SpanDisplay, Spanned};
 
 // re-export semantic types (for executor)
 pub use semantic::{
-    Config, ConfigMount, DotenvSettings, FileTransfer, K8sConfig, K8sMode, LogOutput, PortForward,
-    ReadinessCheck, ServiceConfig, ServiceKind, Shebang, SshConfig, Task, TaskParameter,
+    Config, ConfigMount, DotenvSettings, ErrorHandling, FileTransfer, K8sConfig, K8sMode,
+    LogOutput, PagerMode, PortForward, ReadinessCheck, ServiceConfig, ServiceKind, Shebang,
+    SshConfig, Task, TaskParameter, WebhookConfig,
 };
 
 // re-export semantic parser
-pub use semantic_parser::{ParseConfigError, extract_lua_blocks, parse_config};
+pub use semantic_parser::{
+    ParseConfigError, ParseConfigOptions, extract_lua_blocks, included_paths, parse_config,
+    parse_config_at, parse_config_with, resolve_variables,
+};
+
+/// Parse `source` and serialize the syntactic AST to JSON, exactly as the parser
+/// produced it (comments and error items included, parse errors dropped)
+#[cfg(feature = "serde")]
+pub fn to_json(source: &str) -> Result<String, serde_json::Error> {
+    let (file, _) = parse(source);
+    serde_json::to_string(&file)
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json("# build it\nbuild:\n\tcargo build\n").unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let items = value["items"].as_array().unwrap();
+        assert_eq!(items[0]["node"]["Comment"]["text"], "# build it");
+        assert_eq!(items[1]["node"]["Task"]["name"]["node"], "build");
+        assert_eq!(
+            items[1]["node"]["Task"]["name"]["span"],
+            serde_json::json!({"start": 11, "end": 16})
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use crate::ast::{
     Annotation, AnnotationKind, BodyLine, CommandLine, CommandSegment, Comment,
-    ConfigMountAnnotation, ContextBlock, Dependency, EnvAnnotation, FileTransferAnnotation,
+    ConfigMountAnnotation, ContextBlock, Dependency, FileTransferAnnotation, IncludeDirective,
     Interpolation, Item, K8sAnnotation, KeyValue, LuaBlock, Parameter, ParameterDefault,
-    PortForwardAnnotation, ServiceAnnotation, SetDirective, Shebang, ShellExpansion, SourceFile,
-    SshAnnotation, TaskBody, TaskDecl, VariableDecl, VariableValue,
+    ParameterType, PortForwardAnnotation, ServiceAnnotation, SetDirective, Shebang, ShellExpansion,
+    Shift, SourceFile, SshAnnotation, TaskBody, TaskDecl, VariableDecl, VariableValue,
 };
 use crate::error::{ParseError, ParseErrorKind};
 use crate::lexer::{Lexer, Token, TokenKind};
@@ -17,6 +17,42 @@ pub fn parse(source: &str) -> (SourceFile, Vec<ParseError>) {
     (file, parser.errors)
 }
 
+/// Parse only the items in `source[start..end]`, for incremental reparsing.
+///
+/// Both offsets must sit at line starts where the parser is back at the top
+/// level: `start` after a previous item, `end` at an item or EOF. Returns `None` when the range doesn't stand on its own: annotations at the
+/// end belong to a task past `end`, a block it opens isn't closed by `end`, or
+/// its last line ends in a `\` continuation.
+pub fn parse_items_from_range(
+    source: &str,
+    start: u32,
+    end: u32,
+) -> Option<(Vec<Spanned<Item>>, Vec<ParseError>)> {
+    let range = &source[start as usize..end as usize];
+    if range.ends_with("\\\n") || range.ends_with("\\\r\n") {
+        return None;
+    }
+
+    let mut tokens = Lexer::new(range).tokenize();
+    for token in &mut tokens {
+        token.span.shift(start as i64);
+    }
+
+    let mut parser = Parser::new(tokens, source);
+    let (items, pending) = parser.parse_items();
+    if end as usize != source.len() {
+        let unclosed = parser
+            .errors
+            .iter()
+            .any(|e| e.kind == ParseErrorKind::UnclosedLuaBlock);
+        if !pending.is_empty() || unclosed {
+            return None;
+        }
+    }
+    parser.report_orphans(pending);
+    Some((items, parser.errors))
+}
+
 struct Parser<'a> {
     tokens: Vec<Token>,
     pos: usize,
@@ -35,6 +71,13 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_file(&mut self) -> SourceFile {
+        let (items, pending) = self.parse_items();
+        self.report_orphans(pending);
+        SourceFile { items }
+    }
+
+    /// Items up to EOF, plus annotations still waiting for a task
+    fn parse_items(&mut self) -> (Vec<Spanned<Item>>, Vec<Spanned<Annotation>>) {
         let mut items = Vec::new();
         let mut pending_annotations: Vec<Spanned<Annotation>> = Vec::new();
 
@@ -44,27 +87,75 @@ impl<'a> Parser<'a> {
                 break;
             }
 
-            // try to parse an item
-            match self.parse_item(&mut pending_annotations) {
-                Ok(Some(item)) => items.push(item),
-                Ok(None) => {}
-                Err(e) => {
-                    self.errors.push(e);
-                    self.recover_to_next_line();
+            if let Some(item) = self.parse_item_recover(&mut pending_annotations) {
+                items.push(item);
+            }
+        }
+
+        (items, pending_annotations)
+    }
+
+    /// Parse one item without failing: an error is recorded, the rest of its
+    /// line skipped along with any indented body below it, and an `Item::Error`
+    /// covering them returned in its place
+    fn parse_item_recover(
+        &mut self,
+        pending_annotations: &mut Vec<Spanned<Annotation>>,
+    ) -> Option<Spanned<Item>> {
+        let start_pos = self.pos;
+        let start = self.peek().span;
+        match self.parse_item(pending_annotations) {
+            Ok(item) => item,
+            Err(e) => {
+                self.errors.push(e);
+                self.skip_to_newline();
+                let mut end = if self.pos > start_pos {
+                    self.prev_span()
+                } else {
+                    start
+                };
+                if !self.at_end() {
+                    self.advance();
+                }
+                // a bad task header would otherwise report every body line
+                if let Some(body_end) = self.skip_indented_lines() {
+                    end = body_end;
                 }
+                Some(Spanned::new(Item::Error, start.merge(end)))
+            }
+        }
+    }
+
+    /// Skip indented lines, and blank lines between them, returning the span of
+    /// the last one skipped
+    fn skip_indented_lines(&mut self) -> Option<Span> {
+        let mut end = None;
+        loop {
+            let saved_pos = self.pos;
+            while self.check(TokenKind::Newline) {
+                self.advance();
+            }
+            if !self.check(TokenKind::Indent) {
+                // blank lines past the body belong to whatever comes next
+                self.pos = saved_pos;
+                return end;
+            }
+            self.skip_to_newline();
+            end = Some(self.prev_span());
+            if !self.at_end() {
+                self.advance();
             }
         }
+    }
 
-        // report orphaned annotations
-        for ann in pending_annotations {
+    fn report_orphans(&mut self, pending: Vec<Spanned<Annotation>>) {
+        for ann in pending {
             self.errors.push(ParseError::new(
                 ParseErrorKind::OrphanedAnnotation,
                 ann.span,
                 "annotation not followed by task",
             ));
         }
-
-        SourceFile { items }
     }
 
     fn parse_item(
@@ -76,19 +167,21 @@ impl<'a> Parser<'a> {
         let tok = self.peek();
 
         match &tok.kind {
-            // comment line (not annotation - those start with @)
-            TokenKind::Hash => {
-                let start = tok.span;
-                self.advance();
-
-                // regular comment
-                let text = self.consume_to_newline();
-                let end_pos = start.start + 1 + text.len() as u32;
-                let span = start.merge(Span::new(end_pos, end_pos));
+            // comment line, `#` or `//` (not annotation - those start with @)
+            TokenKind::Comment { text } => {
+                let span = tok.span;
+                let full = tok.text(self.source);
+                // `##` and `///` are doc comments
+                let is_doc = if full.starts_with("//") {
+                    text.starts_with('/')
+                } else {
+                    text.starts_with('#')
+                };
                 let comment = Comment {
-                    text: format!("#{}", text),
-                    is_doc: text.starts_with('#'),
+                    text: full.to_string(),
+                    is_doc,
                 };
+                self.advance();
                 Ok(Some(Spanned::new(Item::Comment(comment), span)))
             }
 
@@ -151,6 +244,9 @@ impl<'a> Parser<'a> {
                     if name == "context" {
                         return self.parse_context_block(at_span, pending_annotations);
                     }
+                    if name == "include" {
+                        return self.parse_include(at_span, pending_annotations);
+                    }
                     // regular annotation
                     let ann = self.parse_annotation(at_span)?;
                     pending_annotations.push(ann);
@@ -185,7 +281,11 @@ impl<'a> Parser<'a> {
     fn parse_annotation(&mut self, at_span: Span) -> Result<Spanned<Annotation>, ParseError> {
         self.skip_whitespace();
 
-        let name_tok = self.advance();
+        let name_tok = if self.at_line_end() {
+            self.peek()
+        } else {
+            self.advance()
+        };
         let name = match &name_tok.kind {
             TokenKind::Identifier(s) => s.clone(),
             _ => {
@@ -271,21 +371,44 @@ impl<'a> Parser<'a> {
                 Ok(AnnotationKind::K8sForward(pf))
             }
             "env" => {
-                self.skip_whitespace();
-                let key = self.parse_identifier()?;
-                self.skip_whitespace();
-                let eq_span = self.expect(TokenKind::Equals)?.span;
-                let value = self.parse_rest_of_line_trimmed();
-                Ok(AnnotationKind::Env(EnvAnnotation {
-                    key,
-                    eq_span,
-                    value,
-                }))
+                let vars = self.parse_key_value_options();
+                Ok(AnnotationKind::Env(vars))
             }
             "use" => {
                 let context_name = self.parse_rest_of_line_trimmed();
                 Ok(AnnotationKind::Use(context_name))
             }
+            "outputs" => {
+                let paths = self.parse_whitespace_separated_words();
+                Ok(AnnotationKind::Outputs(paths))
+            }
+            "inputs" => {
+                let patterns = self.parse_whitespace_separated_words();
+                Ok(AnnotationKind::Inputs(patterns))
+            }
+            "output-capture" => {
+                let name = self.parse_rest_of_line_trimmed();
+                Ok(AnnotationKind::OutputCapture(name))
+            }
+            "tags" => {
+                let tags = self.parse_whitespace_separated_words();
+                Ok(AnnotationKind::Tags(tags))
+            }
+            "when" => {
+                let condition = self.parse_rest_of_line_trimmed();
+                Ok(AnnotationKind::When(condition))
+            }
+            "cache" => self.parse_cache_annotation(name_span),
+            "artifact" => {
+                let artifacts = self.parse_key_value_options();
+                Ok(AnnotationKind::Artifact(artifacts))
+            }
+            "on-failure" => {
+                let handler = self.parse_rest_of_line_trimmed();
+                Ok(AnnotationKind::OnFailure(handler))
+            }
+            "matrix" => self.parse_matrix_annotation(name_span),
+            "webhook" => self.parse_webhook_annotation(name_span),
             _ => {
                 let rest = if self.at_line_end() {
                     None
@@ -301,8 +424,128 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_ssh_annotation(&mut self) -> Result<SshAnnotation, ParseError> {
+        self.skip_whitespace();
+
+        // a leading bare word is the host: `@ssh user@host port=2222`
+        let start_pos = self.pos;
+        let host = if self.at_line_end() || self.try_parse_key_value().is_some() {
+            None
+        } else {
+            let source = self.source;
+            let start = self.peek().span;
+            let mut text = String::new();
+            while !self.at_line_end() && !self.check(TokenKind::Whitespace) {
+                text.push_str(self.advance().text(source));
+            }
+            Some(Spanned::new(text, start.merge(self.prev_span())))
+        };
+        if host.is_none() {
+            self.pos = start_pos;
+        }
+
         let options = self.parse_key_value_options();
-        Ok(SshAnnotation { options })
+        Ok(SshAnnotation { host, options })
+    }
+
+    fn parse_cache_annotation(&mut self, name_span: Span) -> Result<AnnotationKind, ParseError> {
+        self.skip_whitespace();
+        match self.try_parse_key_value() {
+            Some(kv) if kv.node.key.node == "key" && !kv.node.value.node.is_empty() => {
+                Ok(AnnotationKind::Cache(kv.node.value))
+            }
+            _ => Err(ParseError::new(
+                ParseErrorKind::InvalidAnnotation,
+                name_span,
+                "cache requires key=\"<command>\"",
+            )),
+        }
+    }
+
+    fn parse_matrix_annotation(&mut self, name_span: Span) -> Result<AnnotationKind, ParseError> {
+        let axis = self.parse_identifier()?;
+        self.skip_whitespace();
+
+        // values are comma separated, optionally with spaces: `a, b, c`
+        let source = self.source;
+        let mut values = Vec::new();
+        while !self.at_line_end() {
+            let start = self.peek().span;
+            let mut value = String::new();
+            while !self.at_line_end()
+                && !self.check(TokenKind::Comma)
+                && !self.check(TokenKind::Whitespace)
+            {
+                value.push_str(self.advance().text(source));
+            }
+            if !value.is_empty() {
+                values.push(Spanned::new(value, start.merge(self.prev_span())));
+            }
+            self.skip_whitespace();
+            if self.check(TokenKind::Comma) {
+                self.advance();
+                self.skip_whitespace();
+            }
+        }
+
+        if values.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidAnnotation,
+                name_span.merge(axis.span),
+                "@matrix needs at least one value",
+            ));
+        }
+        Ok(AnnotationKind::Matrix { axis, values })
+    }
+
+    fn parse_webhook_annotation(&mut self, name_span: Span) -> Result<AnnotationKind, ParseError> {
+        let mut url = None;
+        let mut method = None;
+        let mut body = None;
+
+        while !self.at_line_end() {
+            self.skip_whitespace();
+            if self.at_line_end() {
+                break;
+            }
+
+            // body= takes the rest of the line verbatim so JSON quotes survive
+            if let TokenKind::Identifier(key) = &self.peek().kind
+                && key == "body"
+            {
+                let start_pos = self.pos;
+                self.advance();
+                if self.check(TokenKind::Equals) {
+                    self.advance();
+                    body = Some(self.parse_rest_of_line_trimmed());
+                    break;
+                }
+                self.pos = start_pos;
+            }
+
+            if let Some(kv) = self.try_parse_key_value() {
+                match kv.node.key.node.as_str() {
+                    "url" => url = Some(kv.node.value),
+                    "method" => method = Some(kv.node.value),
+                    _ => {}
+                }
+            } else {
+                self.advance();
+            }
+        }
+
+        let Some(url) = url else {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidAnnotation,
+                name_span,
+                "webhook requires url=",
+            ));
+        };
+        // defaulted method gets an empty span since it isn't in the source
+        let method = method.unwrap_or_else(|| {
+            Spanned::new("POST".to_string(), Span::new(name_span.end, name_span.end))
+        });
+
+        Ok(AnnotationKind::Webhook { url, method, body })
     }
 
     fn parse_file_transfer(&mut self) -> Result<FileTransferAnnotation, ParseError> {
@@ -310,11 +553,13 @@ impl<'a> Parser<'a> {
         let local = self.parse_path_segment()?;
         let colon_span = self.expect(TokenKind::Colon)?.span;
         let remote = self.parse_path_segment()?;
+        let options = self.parse_key_value_options();
 
         Ok(FileTransferAnnotation {
             local,
             colon_span,
             remote,
+            options,
         })
     }
 
@@ -360,7 +605,7 @@ impl<'a> Parser<'a> {
         if !self.at_line_end() {
             let tok = self.peek();
             if let TokenKind::Identifier(name) = &tok.kind
-                && matches!(name.as_str(), "job" | "exec" | "apply")
+                && matches!(name.as_str(), "job" | "exec" | "apply" | "debug")
             {
                 mode = Some(Spanned::new(name.clone(), tok.span));
                 self.advance();
@@ -446,7 +691,7 @@ impl<'a> Parser<'a> {
 
             let cmd_span = Span::new(
                 open_span.end,
-                close_span.map_or(self.pos as u32, |s| s.start),
+                close_span.map_or(self.peek().span.start, |s| s.start),
             );
             Spanned::new(
                 VariableValue::Shell(ShellExpansion {
@@ -457,12 +702,33 @@ impl<'a> Parser<'a> {
                 value_start.merge(self.prev_span()),
             )
         } else {
-            // static value - rest of line
-            let text = self.consume_to_newline();
-            let trimmed = text.trim().to_string();
+            // static value - rest of line, continued onto the next one by a
+            // trailing `\`. the next line's indent is dropped and nothing is
+            // put between the lines, so a space before the `\` is kept
+            let mut text = String::new();
+            let mut end;
+            loop {
+                let mut continued = false;
+                while !self.at_line_end() {
+                    if self.check(TokenKind::Backslash) {
+                        continued = true;
+                    } else {
+                        text.push_str(self.peek().text(self.source));
+                    }
+                    self.advance();
+                }
+                end = self.prev_span();
+                if !continued || !self.next_line_has_content() {
+                    break;
+                }
+                self.advance(); // newline
+                while matches!(self.peek().kind, TokenKind::Indent | TokenKind::Whitespace) {
+                    self.advance();
+                }
+            }
             Spanned::new(
-                VariableValue::Static(trimmed),
-                value_start.merge(self.prev_span()),
+                VariableValue::Static(text.trim().to_string()),
+                value_start.merge(end),
             )
         };
 
@@ -549,11 +815,35 @@ impl<'a> Parser<'a> {
         Ok(params)
     }
 
-    /// Parse a single parameter: `name` or `name="default"` or `name={{var}}`
+    /// Parse a single parameter: `name`, `name:int`, `name{a,b}`, `name="default"` or `name={{var}}`
     fn parse_parameter(&mut self) -> Result<Spanned<Parameter>, ParseError> {
         let name = self.parse_identifier()?;
         let start_span = name.span;
 
+        // `name:type`; only known type names, so `task arg:dep` still reads as a dependency
+        let mut type_span = None;
+        let mut param_type = None;
+        if self.check(TokenKind::Colon)
+            && let Some(Token {
+                kind: TokenKind::Identifier(ty),
+                span,
+            }) = self.tokens.get(self.pos + 1)
+            && let Some(ty) = ParameterType::from_name(ty)
+        {
+            type_span = Some(*span);
+            param_type = Some(ty);
+            self.advance();
+            self.advance();
+        }
+
+        // check for allowed values ({a,b,c})
+        let (allowed_values, values_span) = if self.check(TokenKind::OpenBrace) {
+            let (values, span) = self.parse_allowed_values()?;
+            (Some(values), Some(span))
+        } else {
+            (None, None)
+        };
+
         // check for default value (=)
         let default = if self.check(TokenKind::Equals) {
             self.advance(); // consume =
@@ -562,14 +852,68 @@ impl<'a> Parser<'a> {
             None
         };
 
-        let end_span = default.as_ref().map(|d| d.span).unwrap_or(name.span);
+        let end_span = default
+            .as_ref()
+            .map(|d| d.span)
+            .or(values_span)
+            .or(type_span)
+            .unwrap_or(name.span);
 
         Ok(Spanned::new(
-            Parameter { name, default },
+            Parameter {
+                name,
+                param_type,
+                allowed_values,
+                default,
+            },
             start_span.merge(end_span),
         ))
     }
 
+    /// Parse an allowed-values list: `{dev,staging,prod}`
+    fn parse_allowed_values(&mut self) -> Result<(Vec<Spanned<String>>, Span), ParseError> {
+        let open_span = self.advance().span; // consume {
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let tok = self.advance().clone();
+            match tok.kind {
+                TokenKind::Identifier(s) | TokenKind::Text(s) => {
+                    values.push(Spanned::new(s, tok.span));
+                }
+                TokenKind::CloseBrace if values.is_empty() => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Expected,
+                        open_span.merge(tok.span),
+                        "expected at least one allowed value",
+                    ));
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Expected,
+                        tok.span,
+                        "expected allowed value",
+                    ));
+                }
+            }
+
+            self.skip_whitespace();
+            let tok = self.advance().clone();
+            match tok.kind {
+                TokenKind::Comma => continue,
+                TokenKind::CloseBrace => return Ok((values, open_span.merge(tok.span))),
+                _ => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Expected,
+                        tok.span,
+                        "expected ',' or '}' in allowed values",
+                    ));
+                }
+            }
+        }
+    }
+
     /// Parse parameter default value: quoted string or {{variable}}
     fn parse_parameter_default(&mut self) -> Result<Spanned<ParameterDefault>, ParseError> {
         let start_span = self.peek().span;
@@ -668,7 +1012,11 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_dependency(&mut self) -> Result<Spanned<Dependency>, ParseError> {
-        let tok = self.advance();
+        let tok = if self.at_line_end() {
+            self.peek()
+        } else {
+            self.advance()
+        };
         let name = match &tok.kind {
             TokenKind::Identifier(s) => s.clone(),
             _ => {
@@ -684,7 +1032,11 @@ impl<'a> Parser<'a> {
         // check for service: prefix
         if self.check(TokenKind::Colon) {
             self.advance();
-            let service_name_tok = self.advance();
+            let service_name_tok = if self.at_line_end() {
+                self.peek()
+            } else {
+                self.advance()
+            };
             let service_name = match &service_name_tok.kind {
                 TokenKind::Identifier(s) => s.clone(),
                 _ => {
@@ -897,7 +1249,7 @@ impl<'a> Parser<'a> {
         self.skip_to_newline();
         self.advance(); // newline
 
-        let content_start = self.pos as u32;
+        let content_start = self.peek().span.start;
         let mut content = String::new();
         let mut close_span = None;
 
@@ -934,7 +1286,8 @@ impl<'a> Parser<'a> {
             ));
         }
 
-        let content_span = Span::new(content_start, self.pos as u32);
+        let content_end = close_span.map_or(self.peek().span.start, |s| s.start);
+        let content_span = Span::new(content_start, content_end.max(content_start));
         let full_span = at_span.merge(close_span.unwrap_or(content_span));
 
         Ok(Some(Spanned::new(
@@ -947,6 +1300,39 @@ impl<'a> Parser<'a> {
         )))
     }
 
+    fn parse_include(
+        &mut self,
+        at_span: Span,
+        pending_annotations: &mut Vec<Spanned<Annotation>>,
+    ) -> Result<Option<Spanned<Item>>, ParseError> {
+        for ann in pending_annotations.drain(..) {
+            self.errors.push(ParseError::new(
+                ParseErrorKind::OrphanedAnnotation,
+                ann.span,
+                "annotation before include",
+            ));
+        }
+
+        // consume "include"
+        let open_span = at_span.merge(self.advance().span);
+        self.skip_whitespace();
+
+        let path = self.parse_rest_of_line_trimmed();
+        if path.node.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::Expected,
+                open_span,
+                "expected a path after @include",
+            ));
+        }
+
+        let span = open_span.merge(path.span);
+        Ok(Some(Spanned::new(
+            Item::Include(IncludeDirective { open_span, path }),
+            span,
+        )))
+    }
+
     fn parse_context_block(
         &mut self,
         at_span: Span,
@@ -968,6 +1354,15 @@ impl<'a> Parser<'a> {
 
         // parse context name
         let name = self.parse_identifier()?;
+        self.skip_whitespace();
+
+        // optional `extends parent`
+        let mut extends = None;
+        if matches!(&self.peek().kind, TokenKind::Identifier(kw) if kw == "extends") {
+            self.advance();
+            self.skip_whitespace();
+            extends = Some(self.parse_identifier()?);
+        }
 
         self.skip_to_newline();
         if !self.at_end() && self.check(TokenKind::Newline) {
@@ -1024,6 +1419,7 @@ impl<'a> Parser<'a> {
             Item::ContextBlock(ContextBlock {
                 open_span: at_span,
                 name,
+                extends,
                 annotations,
                 close_span,
             }),
@@ -1135,6 +1531,15 @@ impl<'a> Parser<'a> {
         matches!(self.peek().kind, TokenKind::Newline | TokenKind::Eof)
     }
 
+    /// Whether the line after the current newline has anything past its indent
+    fn next_line_has_content(&self) -> bool {
+        self.tokens[self.pos..]
+            .iter()
+            .skip(1)
+            .find(|t| !matches!(t.kind, TokenKind::Indent | TokenKind::Whitespace))
+            .is_some_and(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+    }
+
     fn skip_to_newline(&mut self) {
         while !self.at_line_end() {
             self.advance();
@@ -1150,15 +1555,13 @@ impl<'a> Parser<'a> {
         s
     }
 
-    fn recover_to_next_line(&mut self) {
-        self.skip_to_newline();
-        if !self.at_end() {
-            self.advance();
-        }
-    }
-
     fn parse_identifier(&mut self) -> Result<Spanned<String>, ParseError> {
-        let tok = self.advance();
+        // never swallow the newline, or the next line is parsed as part of this one
+        let tok = if self.at_line_end() {
+            self.peek()
+        } else {
+            self.advance()
+        };
         match &tok.kind {
             TokenKind::Identifier(s) => Ok(Spanned::new(s.clone(), tok.span)),
             _ => Err(ParseError::new(
@@ -1200,15 +1603,45 @@ impl<'a> Parser<'a> {
         items
     }
 
+    fn parse_whitespace_separated_words(&mut self) -> Vec<Spanned<String>> {
+        let source = self.source;
+        let mut words = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.at_line_end() {
+                break;
+            }
+
+            let start = self.peek().span;
+            let mut text = String::new();
+            while !self.at_line_end() && !self.check(TokenKind::Whitespace) {
+                text.push_str(self.advance().text(source));
+            }
+            words.push(Spanned::new(text, start.merge(self.prev_span())));
+        }
+
+        words
+    }
+
     fn try_parse_key_value(&mut self) -> Option<Spanned<KeyValue>> {
         let start_pos = self.pos;
-        let key_tok = self.peek().clone();
-
-        let key = match &key_tok.kind {
-            TokenKind::Identifier(s) => s.clone(),
-            _ => return None,
-        };
-        self.advance();
+        let key_span = self.peek().span;
+
+        // the key is identifiers and `{{var}}` markers with nothing between
+        // them, e.g. `{{env}}_pool`; the semantic parser expands it
+        let mut key = String::new();
+        let mut key_end_span = key_span;
+        while let TokenKind::Identifier(_) | TokenKind::OpenBrace | TokenKind::CloseBrace =
+            self.peek().kind
+        {
+            key.push_str(self.peek().text(self.source));
+            key_end_span = self.advance().span;
+        }
+        if key.is_empty() {
+            return None;
+        }
+        let key_span = key_span.merge(key_end_span);
         self.skip_whitespace();
 
         if !self.check(TokenKind::Equals) {
@@ -1299,10 +1732,10 @@ impl<'a> Parser<'a> {
         }
 
         let val_span = val_start_span.merge(val_end_span);
-        let span = key_tok.span.merge(val_end_span);
+        let span = key_span.merge(val_end_span);
         Some(Spanned::new(
             KeyValue {
-                key: Spanned::new(key, key_tok.span),
+                key: Spanned::new(key, key_span),
                 eq_span,
                 value: Spanned::new(value, val_span),
             },
@@ -1320,7 +1753,18 @@ impl<'a> Parser<'a> {
             }
 
             if let Some(kv) = self.try_parse_key_value() {
-                options.push(kv);
+                if balanced_key(&kv.node.key.node) {
+                    options.push(kv);
+                } else {
+                    self.errors.push(ParseError::new(
+                        ParseErrorKind::InvalidKeyValue,
+                        kv.node.key.span,
+                        format!(
+                            "unbalanced braces in option key '{}': use {{{{var}}}}",
+                            kv.node.key.node
+                        ),
+                    ));
+                }
             } else {
                 // skip unknown token
                 self.advance();
@@ -1331,6 +1775,24 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Whether every brace in an option key is part of a complete `{{name}}`
+fn balanced_key(key: &str) -> bool {
+    let mut rest = key;
+    while let Some(idx) = rest.find(['{', '}']) {
+        let Some(inner) = rest[idx..].strip_prefix("{{") else {
+            return false;
+        };
+        let Some(end) = inner.find("}}") else {
+            return false;
+        };
+        if end == 0 || inner[..end].contains(['{', '}']) {
+            return false;
+        }
+        rest = &inner[end + 2..];
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1375,6 +1837,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_continued_variable() {
+        let src =
+            "flags := --release \\\n  --locked \\\n  --offline\nbuild:\n\tcargo build {{flags}}";
+        let (file, errors) = parse(src);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        assert_eq!(file.items.len(), 2);
+
+        if let Item::Variable(var) = &file.items[0].node {
+            if let VariableValue::Static(v) = &var.value.node {
+                assert_eq!(v, "--release --locked --offline");
+            } else {
+                panic!("expected static value");
+            }
+            assert_eq!(
+                file.items[0].span.text(src),
+                &src[..src.find("\nbuild").unwrap()]
+            );
+        } else {
+            panic!("expected variable");
+        }
+    }
+
+    #[test]
+    fn parse_continued_url() {
+        let src = "url := https://example.com/api/\\\n    v1/items?page=1\\\n    &size=50\n";
+        let (file, errors) = parse(src);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+
+        if let Item::Variable(var) = &file.items[0].node
+            && let VariableValue::Static(v) = &var.value.node
+        {
+            assert_eq!(v, "https://example.com/api/v1/items?page=1&size=50");
+        } else {
+            panic!("expected static variable");
+        }
+    }
+
+    #[test]
+    fn backslash_mid_line_is_literal() {
+        let (file, errors) = parse("re := a\\b");
+        assert!(errors.is_empty());
+
+        if let Item::Variable(var) = &file.items[0].node
+            && let VariableValue::Static(v) = &var.value.node
+        {
+            assert_eq!(v, "a\\b");
+        } else {
+            panic!("expected static variable");
+        }
+    }
+
     #[test]
     fn parse_shell_variable() {
         let (file, errors) = parse("hash := `git rev-parse HEAD`");
@@ -1389,6 +1903,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_slash_comments() {
+        let (file, errors) = parse("// build it\n/// doc\n@timeout 5m\nbuild:\n\tcargo build");
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        assert_eq!(file.items.len(), 3);
+
+        if let Item::Comment(c) = &file.items[0].node {
+            assert_eq!(c.text, "// build it");
+            assert!(!c.is_doc);
+        } else {
+            panic!("expected comment");
+        }
+        if let Item::Comment(c) = &file.items[1].node {
+            assert!(c.is_doc);
+        } else {
+            panic!("expected comment");
+        }
+        if let Item::Task(task) = &file.items[2].node {
+            assert_eq!(task.annotations.len(), 1);
+        } else {
+            panic!("expected task");
+        }
+    }
+
     #[test]
     fn parse_annotation() {
         let (file, errors) = parse("@timeout 5m\nbuild:\n\tcargo build");
@@ -1408,14 +1946,14 @@ mod tests {
             parse("@ssh host=user@host.example.com user=deploy\nremote:\n\techo hi");
         assert!(errors.is_empty());
 
-        if let Item::Task(task) = &file.items[0].node {
-            if let AnnotationKind::Ssh(ssh) = &task.annotations[0].node.kind {
-                assert_eq!(ssh.options.len(), 2);
-                assert_eq!(ssh.options[0].node.key.node, "host");
-                assert_eq!(ssh.options[0].node.value.node, "user@host.example.com");
-                assert_eq!(ssh.options[1].node.key.node, "user");
-                assert_eq!(ssh.options[1].node.value.node, "deploy");
-            }
+        if let Item::Task(task) = &file.items[0].node
+            && let AnnotationKind::Ssh(ssh) = &task.annotations[0].node.kind
+        {
+            assert_eq!(ssh.options.len(), 2);
+            assert_eq!(ssh.options[0].node.key.node, "host");
+            assert_eq!(ssh.options[0].node.value.node, "user@host.example.com");
+            assert_eq!(ssh.options[1].node.key.node, "user");
+            assert_eq!(ssh.options[1].node.value.node, "deploy");
         }
     }
 
@@ -1425,17 +1963,33 @@ mod tests {
             parse("@ssh host=wseaton@10.14.217.13 workdir=/home/wseaton/git/vllm\ntest:\n\tpwd");
         assert!(errors.is_empty());
 
-        if let Item::Task(task) = &file.items[0].node {
-            if let AnnotationKind::Ssh(ssh) = &task.annotations[0].node.kind {
-                assert_eq!(ssh.options.len(), 2);
-                assert_eq!(ssh.options[0].node.key.node, "host");
-                assert_eq!(ssh.options[0].node.value.node, "wseaton@10.14.217.13");
-                assert_eq!(ssh.options[1].node.key.node, "workdir");
-                assert_eq!(ssh.options[1].node.value.node, "/home/wseaton/git/vllm");
-            }
+        if let Item::Task(task) = &file.items[0].node
+            && let AnnotationKind::Ssh(ssh) = &task.annotations[0].node.kind
+        {
+            assert_eq!(ssh.options.len(), 2);
+            assert_eq!(ssh.options[0].node.key.node, "host");
+            assert_eq!(ssh.options[0].node.value.node, "wseaton@10.14.217.13");
+            assert_eq!(ssh.options[1].node.key.node, "workdir");
+            assert_eq!(ssh.options[1].node.value.node, "/home/wseaton/git/vllm");
         }
     }
 
+    #[test]
+    fn parse_ssh_annotation_positional_host() {
+        let (file, errors) = parse("@ssh deploy@10.0.0.5 port=2222\ntest:\n\tpwd");
+        assert!(errors.is_empty());
+
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::Ssh(ssh) = &task.annotations[0].node.kind else {
+            panic!("expected ssh annotation");
+        };
+        assert_eq!(ssh.host.as_ref().unwrap().node, "deploy@10.0.0.5");
+        assert_eq!(ssh.options.len(), 1);
+        assert_eq!(ssh.options[0].node.key.node, "port");
+    }
+
     #[test]
     fn spans_are_tracked() {
         let source = "build:\n\techo hello";
@@ -1499,6 +2053,89 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_task_with_allowed_values() {
+        let (file, errors) =
+            parse("deploy env{dev, staging,prod} region{us,eu}=\"us\":\n\techo {{env}}");
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+
+        if let Item::Task(task) = &file.items[0].node {
+            assert_eq!(task.parameters.len(), 2);
+            let values: Vec<_> = task.parameters[0]
+                .node
+                .allowed_values
+                .iter()
+                .flatten()
+                .map(|v| v.node.as_str())
+                .collect();
+            assert_eq!(values, ["dev", "staging", "prod"]);
+            assert!(task.parameters[1].node.default.is_some());
+            assert_eq!(
+                task.parameters[1]
+                    .node
+                    .allowed_values
+                    .as_ref()
+                    .unwrap()
+                    .len(),
+                2
+            );
+        } else {
+            panic!("expected task");
+        }
+
+        let (_, errors) = parse("deploy env{}:\n\techo");
+        assert!(!errors.is_empty());
+    }
+
+    #[test]
+    fn parse_task_with_typed_parameters() {
+        let (file, errors) = parse("release version:int patch:bool=\"false\": build\n\techo");
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let types: Vec<_> = task.parameters.iter().map(|p| p.node.param_type).collect();
+        assert_eq!(types, [Some(ParameterType::Int), Some(ParameterType::Bool)]);
+        assert_eq!(task.dependencies.len(), 1);
+
+        // an unknown type name is still a dependency
+        let (file, _) = parse("deploy target:build\n\techo");
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        assert_eq!(task.parameters[0].node.param_type, None);
+        assert_eq!(task.dependencies.len(), 1);
+    }
+
+    #[test]
+    fn parse_include() {
+        let source = "@include ./tasks/build.dr\n\nbuild:\n\techo\n";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let Item::Include(include) = &file.items[0].node else {
+            panic!("expected include, got {:?}", file.items[0].node);
+        };
+        assert_eq!(include.path.node, "./tasks/build.dr");
+        assert_eq!(include.path.span.text(source), "./tasks/build.dr");
+    }
+
+    #[test]
+    fn parse_context_extends() {
+        let source = "@context prod extends default\n@timeout 10m\n@end\n";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+
+        if let Item::ContextBlock(ctx) = &file.items[0].node {
+            assert_eq!(ctx.name.node, "prod");
+            let parent = ctx.extends.as_ref().expect("expected parent context");
+            assert_eq!(parent.node, "default");
+            assert_eq!(parent.span.text(source), "default");
+            assert_eq!(ctx.annotations.len(), 1);
+        } else {
+            panic!("expected context block, got {:?}", file.items[0].node);
+        }
+    }
+
     #[test]
     fn parse_context_block() {
         let source = "@context default\n@ssh host=user@host\n@timeout 5m\n@end\n";
@@ -1538,4 +2175,268 @@ mod tests {
             panic!("expected task");
         }
     }
+
+    #[test]
+    fn parse_outputs_annotation() {
+        let source = "@outputs ./target/release/app  ./target/release/app.d\nbuild:\n\tcargo build";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty());
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::Outputs(paths) = &task.annotations[0].node.kind else {
+            panic!("expected @outputs");
+        };
+        let paths: Vec<_> = paths.iter().map(|p| p.span.text(source)).collect();
+        assert_eq!(
+            paths,
+            vec!["./target/release/app", "./target/release/app.d"]
+        );
+    }
+
+    #[test]
+    fn parse_output_capture_annotation() {
+        let (file, errors) = parse("@output-capture VERSION\nversion:\n\tgit describe");
+        assert!(errors.is_empty());
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::OutputCapture(name) = &task.annotations[0].node.kind else {
+            panic!("expected @output-capture");
+        };
+        assert_eq!(name.node, "VERSION");
+    }
+
+    #[test]
+    fn parse_interpolated_option_key() {
+        let source = "@env {{prefix}}_HOME=/opt PATH=/bin\ntask:\n\techo";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::Env(vars) = &task.annotations[0].node.kind else {
+            panic!("expected @env");
+        };
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars[0].node.key.node, "{{prefix}}_HOME");
+        assert_eq!(vars[0].node.key.span.text(source), "{{prefix}}_HOME");
+        assert_eq!(vars[0].node.value.node, "/opt");
+        assert_eq!(vars[1].node.key.node, "PATH");
+    }
+
+    #[test]
+    fn parse_matrix_annotation() {
+        let source = "@matrix target x86_64-unknown-linux-gnu, aarch64-apple-darwin,wasm32\nbuild:\n\tcargo build --target {{target}}";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let (axis, values) = task.matrix().expect("expected @matrix");
+        assert_eq!(axis.node, "target");
+        let values: Vec<_> = values.iter().map(|v| v.span.text(source)).collect();
+        assert_eq!(
+            values,
+            ["x86_64-unknown-linux-gnu", "aarch64-apple-darwin", "wasm32"]
+        );
+        assert_eq!(
+            task.expanded_names(),
+            [
+                "build-x86_64-unknown-linux-gnu",
+                "build-aarch64-apple-darwin",
+                "build-wasm32"
+            ]
+        );
+
+        let (_, errors) = parse("@matrix target\nbuild:\n\techo\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "@matrix needs at least one value");
+    }
+
+    #[test]
+    fn reject_unbalanced_option_key() {
+        for key in [
+            "{{prefix_HOME",
+            "prefix}}_HOME",
+            "{prefix}_HOME",
+            "{{}}_HOME",
+            "{{{a}}}",
+        ] {
+            let source = format!("@env {}=/opt PATH=/bin\ntask:\n\techo", key);
+            let (file, errors) = parse(&source);
+            assert_eq!(errors.len(), 1, "{}: {:?}", key, errors);
+            assert_eq!(errors[0].kind, ParseErrorKind::InvalidKeyValue);
+            assert_eq!(errors[0].span.text(&source), key);
+            // the other options still parse
+            let Item::Task(task) = &file.items[0].node else {
+                panic!("expected task");
+            };
+            let AnnotationKind::Env(vars) = &task.annotations[0].node.kind else {
+                panic!("expected @env");
+            };
+            assert_eq!(vars.len(), 1);
+            assert_eq!(vars[0].node.key.node, "PATH");
+        }
+    }
+
+    #[test]
+    fn parse_when_annotation() {
+        let source = "@when test -f .migrate\nmigrate: deploy\n\t./migrate.sh";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::When(condition) = &task.annotations[0].node.kind else {
+            panic!("expected @when");
+        };
+        assert_eq!(condition.node, "test -f .migrate");
+        assert_eq!(condition.span.text(source), "test -f .migrate");
+    }
+
+    #[test]
+    fn parse_cache_annotation() {
+        let source = "@cache key=\"sha256sum Cargo.lock\"\nbuild:\n\tcargo build";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::Cache(key) = &task.annotations[0].node.kind else {
+            panic!("expected @cache");
+        };
+        assert_eq!(key.node, "sha256sum Cargo.lock");
+
+        let (_, errors) = parse("@cache sha256sum Cargo.lock\nbuild:\n\tcargo build");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cache requires key=\"<command>\"");
+    }
+
+    #[test]
+    fn parse_on_failure_annotation() {
+        let source = "@on-failure cleanup\ndeploy:\n\t./deploy.sh";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::OnFailure(handler) = &task.annotations[0].node.kind else {
+            panic!("expected @on-failure");
+        };
+        assert_eq!(handler.node, "cleanup");
+        assert_eq!(handler.span.text(source), "cleanup");
+    }
+
+    #[test]
+    fn parse_artifact_annotation() {
+        let source = "@artifact bin=target/app.zip docs=out/docs.tar\nbuild:\n\tmake";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+        let Item::Task(task) = &file.items[0].node else {
+            panic!("expected task");
+        };
+        let AnnotationKind::Artifact(artifacts) = &task.annotations[0].node.kind else {
+            panic!("expected @artifact");
+        };
+        let pairs: Vec<_> = artifacts
+            .iter()
+            .map(|kv| (kv.node.key.node.as_str(), kv.node.value.node.as_str()))
+            .collect();
+        assert_eq!(pairs, [("bin", "target/app.zip"), ("docs", "out/docs.tar")]);
+    }
+
+    #[test]
+    fn parse_webhook_annotation() {
+        let source = "@webhook url=https://hooks.example.com/done body={\"task\":\"{{name}}\"}\ntask:\n\techo hi";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "errors: {:?}", errors);
+
+        if let Item::Task(task) = &file.items[0].node {
+            if let AnnotationKind::Webhook { url, method, body } = &task.annotations[0].node.kind {
+                assert_eq!(url.node, "https://hooks.example.com/done");
+                assert_eq!(method.node, "POST");
+                assert_eq!(body.as_ref().unwrap().node, "{\"task\":\"{{name}}\"}");
+            } else {
+                panic!("expected Webhook annotation");
+            }
+        } else {
+            panic!("expected task");
+        }
+    }
+
+    #[test]
+    fn parse_lua_block_content_span() {
+        let source = "build:\n\techo\n\n@lua\nfoo()\n@end\n";
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let Item::LuaBlock(block) = &file.items[1].node else {
+            panic!("expected lua block, got {:?}", file.items[1].node);
+        };
+        assert_eq!(block.content.span.text(source), "foo()\n");
+    }
+
+    #[test]
+    fn error_at_line_end_keeps_next_line() {
+        let source = "build: dep:\ntest:\n\techo {{\nlint:\n\techo\n";
+        let (file, _) = parse(source);
+        let names: Vec<_> = file
+            .items
+            .iter()
+            .filter_map(|item| match &item.node {
+                Item::Task(task) => Some(task.name.node.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["test", "lint"]);
+    }
+
+    #[test]
+    fn every_bad_line_gets_an_error_item() {
+        let source = "oops\nbuild:\n\tcargo build\n@\n: nope\ntest: build\n\tcargo test\n";
+        let (file, errors) = parse(source);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+
+        let kinds: Vec<_> = file
+            .items
+            .iter()
+            .map(|item| match &item.node {
+                Item::Task(task) => task.name.node.as_str(),
+                Item::Error => "<error>",
+                _ => "<other>",
+            })
+            .collect();
+        assert_eq!(kinds, ["<error>", "build", "<error>", "<error>", "test"]);
+        assert_eq!(file.items[0].span.text(source), "oops");
+        assert_eq!(file.items[3].span.text(source), ": nope");
+    }
+
+    #[test]
+    fn bad_task_header_skips_its_body() {
+        let source = "b(: c\n    echo one\n\n    echo two\n\nok:\n    echo ok\n";
+        let (file, errors) = parse(source);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+
+        assert!(matches!(file.items[0].node, Item::Error));
+        assert_eq!(
+            file.items[0].span.text(source),
+            "b(: c\n    echo one\n\n    echo two"
+        );
+        assert!(matches!(&file.items[1].node, Item::Task(task) if task.name.node == "ok"));
+    }
+
+    #[test]
+    fn parse_items_from_range_shifts_spans() {
+        let source = "a:\n\techo\n\nb: a\n\techo\n";
+        let start = source.find("b:").unwrap() as u32;
+        let (items, errors) = parse_items_from_range(source, start, source.len() as u32).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].span.start, start);
+
+        // annotations that belong to a task past the range
+        let source = "@timeout 5m\nb:\n\techo\n";
+        let end = source.find("b:").unwrap() as u32;
+        assert!(parse_items_from_range(source, 0, end).is_none());
+    }
 }
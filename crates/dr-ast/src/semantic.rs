@@ -2,6 +2,7 @@
 //! They carry optional spans for LSP support.
 
 use crate::Span;
+use crate::ast::ParameterType;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::time::Duration;
@@ -11,6 +12,12 @@ use std::time::Duration;
 pub struct Config {
     pub tasks: HashMap<String, Task>,
     pub dotenv: DotenvSettings,
+    pub error_handling: ErrorHandling,
+    /// TTL for k8s jobs that don't set `ttl_seconds` (`set k8s-default-ttl := N`)
+    pub k8s_default_ttl: Option<u32>,
+    pub pager: PagerMode,
+    /// Max concurrent tasks per tag (`set concurrency-by-tag := deploy=1 test=4`)
+    pub concurrency_by_tag: HashMap<String, usize>,
 }
 
 impl Config {
@@ -18,6 +25,10 @@ impl Config {
         Config {
             tasks: HashMap::new(),
             dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
         }
     }
 
@@ -38,6 +49,27 @@ pub struct DotenvSettings {
     pub load: bool,
     pub paths: Vec<String>,
     pub required: bool,
+    /// file values replace variables already set in the process environment
+    pub override_existing: bool,
+}
+
+/// What the executor does after a task fails (`set error-handling := ...`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorHandling {
+    /// stop the whole run at the first failure
+    #[default]
+    StopOnFirstFailure,
+    /// keep running tasks that don't depend on a failed one
+    Continue,
+}
+
+/// Whether `dr run` pages task output through `less` (`set pager := ...`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PagerMode {
+    /// page when stdout is a terminal
+    Auto,
+    #[default]
+    Never,
 }
 
 /// A task/recipe definition
@@ -67,6 +99,35 @@ pub struct Task {
     pub service: Option<ServiceConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub shebang: Option<Shebang>,
+    /// Files the task is declared to produce (`@outputs`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+    /// Glob patterns whose contents decide if the task must re-run (`@inputs`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Runtime variable set from the task's trimmed stdout (`@output-capture`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_capture: Option<String>,
+    /// Labels for per-tag concurrency limits (`@tags`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookConfig>,
+    /// Environment variables for the task's command (`@env`)
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Shell condition that must exit 0 for the task to run (`@when`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Shell command whose output keys the task's output cache (`@cache`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// Files the task produces, by name (`@artifact`)
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub artifacts: HashMap<String, String>,
+    /// Task to run if this one fails (`@on-failure`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
     /// span of the task definition (for LSP)
     #[serde(skip)]
     pub span: Option<Span>,
@@ -88,10 +149,41 @@ pub struct TaskParameter {
     pub name: String,
     /// None = required, Some = optional with default
     pub default: Option<String>,
+    /// Declared type from `name:type` (None = any string)
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub param_type: Option<ParameterType>,
+    /// None = any value, Some = must be one of these
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<String>>,
     #[serde(skip)]
     pub span: Option<Span>,
 }
 
+impl TaskParameter {
+    /// Check a bound value against the declared type and `allowed_values`
+    pub fn check_value(&self, value: &str) -> Result<(), String> {
+        if let Some(ty) = self.param_type
+            && !ty.accepts(value)
+        {
+            return Err(format!(
+                "invalid value '{}' for parameter '{}': expected {}",
+                value,
+                self.name,
+                ty.name()
+            ));
+        }
+        match &self.allowed_values {
+            Some(allowed) if !allowed.iter().any(|v| v == value) => Err(format!(
+                "invalid value '{}' for parameter '{}': expected one of: {}",
+                value,
+                self.name,
+                allowed.join(", ")
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Parsed shebang
 #[derive(Debug, Clone, Serialize)]
 pub struct Shebang {
@@ -118,6 +210,19 @@ impl Shebang {
 pub struct FileTransfer {
     pub local: String,
     pub remote: String,
+    /// Fail if a download glob matches nothing (`required=true`)
+    pub required: bool,
+    /// Copy the source mtime onto the transferred file (`preserve_times=false` to skip)
+    pub preserve_times: bool,
+}
+
+/// Webhook sent after a task finishes
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub method: String,
+    /// Body template; `{{name}}`, `{{status}}`, `{{duration_ms}}`, `{{attempts}}` are filled in
+    pub body: Option<String>,
 }
 
 /// SSH configuration
@@ -133,6 +238,16 @@ pub struct SshConfig {
     /// Environment variables set via `@env KEY=VALUE`
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub env: HashMap<String, String>,
+    /// Prefix for remote stderr lines (`[stderr]` when unset, empty to disable)
+    pub stderr_prefix: Option<String>,
+    /// Run the command under a remote pseudo-terminal (`@ssh pty=true`)
+    pub pty: bool,
+    /// Env var holding a password, tried when key auth fails (`@ssh password_env=VAR`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// Jump host to connect through, `[user@]host[:port]` (`@ssh proxy=...`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
 }
 
 impl SshConfig {
@@ -149,6 +264,12 @@ impl SshConfig {
 pub enum ServiceKind {
     Managed,
     External,
+    /// Container started by dagrun from `@extern kind=docker image=... port=...`
+    Docker {
+        image: String,
+        port: u16,
+        extra_args: Vec<String>,
+    },
 }
 
 /// Readiness check for services
@@ -254,6 +375,10 @@ pub struct ServiceConfig {
     pub log: LogOutput,
     pub forward: bool,
     pub preflight: Option<String>,
+    /// Prometheus endpoint scraped while the service is ready
+    pub metrics_url: Option<String>,
+    /// start even if the tcp readiness port is already accepting connections
+    pub allow_existing_port: bool,
 }
 
 impl Default for ServiceConfig {
@@ -268,6 +393,8 @@ impl Default for ServiceConfig {
             log: LogOutput::Stream,
             forward: false,
             preflight: None,
+            metrics_url: None,
+            allow_existing_port: false,
         }
     }
 }
@@ -279,6 +406,21 @@ pub enum K8sMode {
     #[default]
     Job,
     Apply,
+    /// attach an ephemeral container to an existing pod (`kubectl debug`)
+    Debug {
+        image: String,
+    },
+}
+
+impl K8sMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            K8sMode::Exec => "exec",
+            K8sMode::Job => "job",
+            K8sMode::Apply => "apply",
+            K8sMode::Debug { .. } => "debug",
+        }
+    }
 }
 
 /// ConfigMap/Secret mount
@@ -314,6 +456,17 @@ pub struct K8sConfig {
     pub service_account: Option<String>,
     pub ttl_seconds: Option<u32>,
     pub path: Option<String>,
+    /// apply mode: delete cluster resources no longer present under `path`
+    pub prune: bool,
+    /// apply mode: `kubectl apply --server-side` with dagrun as the field manager
+    pub server_side: bool,
+    /// apply mode: take ownership of fields other managers hold (server-side only)
+    pub force_conflicts: bool,
+    /// exec mode: `kubectl cp` the script into the pod instead of passing it to `sh -c`
+    pub use_kubectl_cp: bool,
+    /// job mode: run in an init container before the task; the two share
+    /// a directory at `$DAGRUN_SHARED_DIR`
+    pub setup_cmd: Option<String>,
     pub wait_for: Vec<String>,
     #[serde(
         serialize_with = "serialize_duration_opt",
@@ -343,8 +496,13 @@ impl Default for K8sConfig {
             node_selector: None,
             tolerations: vec![],
             service_account: None,
-            ttl_seconds: Some(300),
+            ttl_seconds: None,
             path: None,
+            prune: false,
+            server_side: false,
+            force_conflicts: false,
+            use_kubectl_cp: false,
+            setup_cmd: None,
             wait_for: vec![],
             wait_timeout: None,
             upload: vec![],
@@ -1,22 +1,66 @@
 //! Semantic parser - converts syntactic AST to semantic Config
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Duration;
 
 use crate::Span;
 use crate::Spanned;
 use crate::ast::{
-    self, Annotation, AnnotationKind, BodyLine, CommandSegment, Dependency, Item, VariableValue,
+    self, Annotation, AnnotationKind, BodyLine, CommandSegment, Dependency, Item, KeyValue,
+    VariableValue,
 };
 use crate::parser;
 use crate::semantic::{
-    Config, ConfigMount, DotenvSettings, FileTransfer, K8sConfig, K8sMode, LogOutput, PortForward,
-    ReadinessCheck, ServiceConfig, ServiceKind, Shebang, SshConfig, Task, TaskParameter,
+    Config, ConfigMount, DotenvSettings, ErrorHandling, FileTransfer, K8sConfig, K8sMode,
+    LogOutput, PagerMode, PortForward, ReadinessCheck, ServiceConfig, ServiceKind, Shebang,
+    SshConfig, Task, TaskParameter, WebhookConfig,
 };
 
+/// Options controlling how strictly [`parse_config_with`] treats its input
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseConfigOptions {
+    /// Reject unknown annotations such as `@sssh` instead of ignoring them
+    pub strict_annotations: bool,
+}
+
 /// Parse a dagrun source file into a semantic Config
 pub fn parse_config(source: &str) -> Result<Config, ParseConfigError> {
+    parse_config_with(source, ParseConfigOptions::default())
+}
+
+/// Parse a dagrun source file with explicit [`ParseConfigOptions`]
+pub fn parse_config_with(
+    source: &str,
+    options: ParseConfigOptions,
+) -> Result<Config, ParseConfigError> {
+    parse_config_inner(source, options, &mut Vec::new()).map(|(config, _)| config)
+}
+
+/// Parse the dagfile read from `path`, resolving `@include`s relative to it
+pub fn parse_config_at(source: &str, path: &Path) -> Result<Config, ParseConfigError> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    parse_config_inner(source, ParseConfigOptions::default(), &mut vec![path])
+        .map(|(config, _)| config)
+}
+
+/// The variables of a dagrun source file, with `{{var}}` references and
+/// shell expansions resolved the way tasks see them
+pub fn resolve_variables(source: &str) -> Result<HashMap<String, String>, ParseConfigError> {
+    parse_config_inner(source, ParseConfigOptions::default(), &mut Vec::new())
+        .map(|(_, variables)| variables)
+}
+
+/// Parse `source`, with `include_stack` holding the chain of files currently
+/// being included (innermost last) for relative paths and cycle detection.
+/// Returns the variables too so an including file can use them.
+fn parse_config_inner(
+    source: &str,
+    options: ParseConfigOptions,
+    include_stack: &mut Vec<PathBuf>,
+) -> Result<(Config, HashMap<String, String>), ParseConfigError> {
     let (ast, parse_errors) = parser::parse(source);
 
     // collect parse errors but continue
@@ -30,7 +74,18 @@ pub fn parse_config(source: &str) -> Result<Config, ParseConfigError> {
 
     let mut ctx = Context::new(source);
 
+    // included tasks and variables come first, wherever the @include is, so the
+    // including file can use and override them
+    for item in &ast.items {
+        if let Item::Include(include) = &item.node {
+            let (config, variables) = include_file(&include.path, options, include_stack)?;
+            ctx.variables.extend(variables);
+            ctx.tasks.extend(config.tasks);
+        }
+    }
+
     // first pass: collect variables and contexts
+    let mut context_blocks = Vec::new();
     for item in &ast.items {
         match &item.node {
             Item::Variable(var) => match ctx.eval_variable_value(&var.value.node) {
@@ -39,23 +94,25 @@ pub fn parse_config(source: &str) -> Result<Config, ParseConfigError> {
                 }
                 Err(e) => errors.push(e),
             },
-            Item::ContextBlock(context) => {
-                ctx.contexts
-                    .insert(context.name.node.clone(), context.annotations.clone());
-            }
+            Item::ContextBlock(context) => context_blocks.push(context),
             _ => {}
         }
     }
+    ctx.contexts = resolve_contexts(&context_blocks)?;
 
     // second pass: process tasks, lua blocks, set directives
     for item in &ast.items {
         match &item.node {
-            Item::Task(task_decl) => match ctx.lower_task(task_decl, item.span) {
-                Ok(task) => {
-                    ctx.tasks.insert(task.name.clone(), task);
+            Item::Task(task_decl) => {
+                for result in ctx.lower_matrix_task(task_decl, item.span) {
+                    match result {
+                        Ok(task) => {
+                            ctx.tasks.insert(task.name.clone(), task);
+                        }
+                        Err(e) => errors.push(e),
+                    }
                 }
-                Err(e) => errors.push(e),
-            },
+            }
             Item::LuaBlock(lua) => {
                 // lua blocks need special handling - we'll skip for now and let caller handle
                 // since mlua is a heavy dependency
@@ -65,21 +122,139 @@ pub fn parse_config(source: &str) -> Result<Config, ParseConfigError> {
                 let value = set.value.as_ref().map(|v| v.node.as_str());
                 ctx.handle_set_directive(&set.key.node, value);
             }
-            Item::Variable(_) | Item::Comment(_) | Item::ContextBlock(_) => {}
+            Item::Variable(_)
+            | Item::Comment(_)
+            | Item::ContextBlock(_)
+            | Item::Include(_)
+            | Item::Error => {}
         }
     }
 
-    // return errors if any are fatal (for now, treat all as warnings)
+    // strict mode turns typos like `@sssh` into hard errors
+    let unknown = if options.strict_annotations || ctx.strict_annotations {
+        unknown_annotations(&ast.items)
+    } else {
+        Vec::new()
+    };
+
+    // a task that fails to lower is dropped with a warning, unless its error
+    // was marked fatal
+    let fatal = ctx.fatal_errors.take();
+    errors.retain(|e| !fatal.contains(e));
     if !errors.is_empty() {
         for e in &errors {
-            eprintln!("warning: {}", e.message);
+            eprintln!("warning: {} ({})", e.message, e.span.display(source));
+        }
+    }
+
+    if let Some(first) = fatal.into_iter().chain(unknown).next() {
+        return Err(first);
+    }
+
+    // jobs without an explicit ttl_seconds fall back to k8s-default-ttl
+    if let Some(ttl) = ctx.k8s_default_ttl {
+        for k8s in ctx.tasks.values_mut().filter_map(|t| t.k8s.as_mut()) {
+            k8s.ttl_seconds.get_or_insert(ttl);
         }
     }
 
-    Ok(Config {
+    let config = Config {
         tasks: ctx.tasks,
         dotenv: ctx.dotenv,
-    })
+        error_handling: ctx.error_handling,
+        k8s_default_ttl: ctx.k8s_default_ttl,
+        pager: ctx.pager,
+        concurrency_by_tag: ctx.concurrency_by_tag,
+    };
+    Ok((config, ctx.variables))
+}
+
+/// Parse an `@include`d file. Only its tasks and variables are used; `set`
+/// directives stay local to the file that declares them.
+fn include_file(
+    path: &Spanned<String>,
+    options: ParseConfigOptions,
+    include_stack: &mut Vec<PathBuf>,
+) -> Result<(Config, HashMap<String, String>), ParseConfigError> {
+    let err = |message: String| ParseConfigError {
+        span: path.span,
+        message,
+    };
+
+    let resolved = resolve_include(include_stack.last(), &path.node)
+        .map_err(|e| err(format!("cannot include {}: {}", path.node, e)))?;
+
+    if include_stack.contains(&resolved) {
+        let chain: Vec<String> = include_stack
+            .iter()
+            .chain(std::iter::once(&resolved))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(err(format!("circular include: {}", chain.join(" -> "))));
+    }
+
+    let source = std::fs::read_to_string(&resolved)
+        .map_err(|e| err(format!("cannot include {}: {}", path.node, e)))?;
+
+    include_stack.push(resolved);
+    let result = parse_config_inner(&source, options, include_stack);
+    include_stack.pop();
+
+    result.map_err(|e| err(format!("in {}: {}", path.node, e.message)))
+}
+
+/// The file `@include path` in `including` refers to: relative to the including
+/// file's directory, or the working directory for a source with no path
+fn resolve_include(including: Option<&PathBuf>, path: &str) -> std::io::Result<PathBuf> {
+    let base = including.and_then(|p| p.parent()).unwrap_or(Path::new(""));
+    base.join(path).canonicalize()
+}
+
+/// Every file the dagfile at `path` includes, directly or through another
+/// include, resolved the way [`parse_config_at`] resolves them. Includes that
+/// don't exist are left out.
+pub fn included_paths(source: &str, path: &Path) -> Vec<PathBuf> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut found = Vec::new();
+    let mut pending = vec![(path, source.to_string())];
+    while let Some((including, source)) = pending.pop() {
+        let (ast, _) = parser::parse(&source);
+        for item in &ast.items {
+            let Item::Include(include) = &item.node else {
+                continue;
+            };
+            let Ok(resolved) = resolve_include(Some(&including), &include.path.node) else {
+                continue;
+            };
+            if found.contains(&resolved) {
+                continue;
+            }
+            found.push(resolved.clone());
+            if let Ok(source) = std::fs::read_to_string(&resolved) {
+                pending.push((resolved, source));
+            }
+        }
+    }
+    found
+}
+
+/// Errors for every `AnnotationKind::Unknown` on tasks and in contexts
+fn unknown_annotations(items: &[Spanned<Item>]) -> Vec<ParseConfigError> {
+    items
+        .iter()
+        .flat_map(|item| match &item.node {
+            Item::Task(task) => task.annotations.as_slice(),
+            Item::ContextBlock(ctx) => ctx.annotations.as_slice(),
+            _ => &[],
+        })
+        .filter_map(|ann| match &ann.node.kind {
+            AnnotationKind::Unknown { name, .. } => Some(ParseConfigError {
+                span: name.span,
+                message: format!("unknown annotation '@{}'", name.node),
+            }),
+            _ => None,
+        })
+        .collect()
 }
 
 /// Get lua blocks from a parsed file (for external lua processing)
@@ -97,7 +272,7 @@ pub fn extract_lua_blocks(source: &str) -> Vec<String> {
         .collect()
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParseConfigError {
     pub span: Span,
     pub message: String,
@@ -118,7 +293,14 @@ struct Context<'a> {
     tasks: HashMap<String, Task>,
     contexts: HashMap<String, Vec<Spanned<Annotation>>>,
     dotenv: DotenvSettings,
+    error_handling: ErrorHandling,
+    k8s_default_ttl: Option<u32>,
+    pager: PagerMode,
+    concurrency_by_tag: HashMap<String, usize>,
+    strict_annotations: bool,
     lua_blocks: Vec<String>,
+    /// errors that fail the whole parse instead of just dropping their task
+    fatal_errors: RefCell<Vec<ParseConfigError>>,
 }
 
 /// Accumulated state from processing annotations
@@ -131,6 +313,16 @@ struct AnnotationState {
     ssh: Option<SshConfig>,
     service: Option<ServiceConfig>,
     k8s: Option<K8sConfig>,
+    webhook: Option<WebhookConfig>,
+    outputs: Vec<String>,
+    inputs: Vec<String>,
+    output_capture: Option<String>,
+    tags: Vec<String>,
+    env: HashMap<String, String>,
+    condition: Option<String>,
+    cache_key: Option<String>,
+    artifacts: HashMap<String, String>,
+    on_failure: Option<Spanned<String>>,
 }
 
 impl<'a> Context<'a> {
@@ -141,10 +333,23 @@ impl<'a> Context<'a> {
             tasks: HashMap::new(),
             contexts: HashMap::new(),
             dotenv: DotenvSettings::default(),
+            error_handling: ErrorHandling::default(),
+            k8s_default_ttl: None,
+            pager: PagerMode::default(),
+            concurrency_by_tag: HashMap::new(),
+            strict_annotations: false,
             lua_blocks: Vec::new(),
+            fatal_errors: RefCell::new(Vec::new()),
         }
     }
 
+    /// Mark `err` as fatal to the whole parse, for mistakes that shouldn't
+    /// be papered over by dropping the task
+    fn fatal(&self, err: ParseConfigError) -> ParseConfigError {
+        self.fatal_errors.borrow_mut().push(err.clone());
+        err
+    }
+
     fn eval_variable_value(&self, value: &VariableValue) -> Result<String, ParseConfigError> {
         match value {
             VariableValue::Static(s) => Ok(self.substitute_variables(s)),
@@ -167,6 +372,33 @@ impl<'a> Context<'a> {
         result
     }
 
+    /// Lower a task once per `@matrix` value, with the axis variable set to
+    /// the value and the name suffixed with it; tasks without one lower once
+    fn lower_matrix_task(
+        &mut self,
+        task_decl: &ast::TaskDecl,
+        task_span: Span,
+    ) -> Vec<Result<Task, ParseConfigError>> {
+        let Some((axis, values)) = task_decl.matrix() else {
+            return vec![self.lower_task(task_decl, task_span)];
+        };
+
+        let names = task_decl.expanded_names();
+        let mut results = Vec::new();
+        for (value, name) in values.iter().zip(names) {
+            let shadowed = self.variables.insert(axis.node.clone(), value.node.clone());
+            results.push(
+                self.lower_task(task_decl, task_span)
+                    .map(|task| Task { name, ..task }),
+            );
+            match shadowed {
+                Some(previous) => self.variables.insert(axis.node.clone(), previous),
+                None => self.variables.remove(&axis.node),
+            };
+        }
+        results
+    }
+
     fn lower_task(
         &self,
         task_decl: &ast::TaskDecl,
@@ -200,6 +432,21 @@ impl<'a> Context<'a> {
         // then apply task-level annotations (these override context)
         self.apply_annotations(&task_decl.annotations, &mut state)?;
 
+        // the remote side gets @env through the ssh config
+        if let Some(ssh) = state.ssh.as_mut() {
+            ssh.env = state.env.clone();
+        }
+
+        // a task can't be its own failure handler
+        if let Some(handler) = &state.on_failure
+            && handler.node == name
+        {
+            return Err(self.fatal(ParseConfigError {
+                span: handler.span,
+                message: format!("task '{}' can't be its own @on-failure handler", name),
+            }));
+        }
+
         // extract dependencies
         let mut depends_on = Vec::new();
         let mut service_deps = Vec::new();
@@ -227,6 +474,16 @@ impl<'a> Context<'a> {
             k8s: state.k8s,
             service: state.service,
             shebang,
+            outputs: state.outputs,
+            inputs: state.inputs,
+            output_capture: state.output_capture,
+            tags: state.tags,
+            webhook: state.webhook,
+            env: state.env,
+            condition: state.condition,
+            cache_key: state.cache_key,
+            artifacts: state.artifacts,
+            on_failure: state.on_failure.map(|handler| handler.node),
             span: Some(task_span),
         })
     }
@@ -259,25 +516,54 @@ impl<'a> Context<'a> {
                 AnnotationKind::Join => {
                     state.join = true;
                 }
+                AnnotationKind::Outputs(paths) => {
+                    state
+                        .outputs
+                        .extend(paths.iter().map(|p| self.substitute_variables(&p.node)));
+                }
+                AnnotationKind::Inputs(patterns) => {
+                    state
+                        .inputs
+                        .extend(patterns.iter().map(|p| self.substitute_variables(&p.node)));
+                }
+                AnnotationKind::OutputCapture(name) => {
+                    state.output_capture = Some(name.node.clone());
+                }
+                AnnotationKind::Tags(tags) => {
+                    state.tags.extend(tags.iter().map(|t| t.node.clone()));
+                }
+                AnnotationKind::When(condition) => {
+                    state.condition = Some(self.substitute_variables(&condition.node));
+                }
+                AnnotationKind::Cache(key) => {
+                    state.cache_key = Some(self.substitute_variables(&key.node));
+                }
+                AnnotationKind::OnFailure(handler) => {
+                    state.on_failure = Some(handler.clone());
+                }
                 AnnotationKind::Ssh(ssh_ann) => {
-                    state.ssh = Some(self.lower_ssh_annotation(ssh_ann));
+                    state.ssh = Some(self.lower_ssh_annotation(ssh_ann)?);
                 }
                 AnnotationKind::Upload(ft) => {
                     if let Some(ref mut s) = state.ssh {
-                        s.upload.push(self.lower_file_transfer(ft));
+                        s.upload.push(self.lower_file_transfer(ft)?);
                     }
                 }
                 AnnotationKind::Download(ft) => {
                     if let Some(ref mut s) = state.ssh {
-                        s.download.push(self.lower_file_transfer(ft));
+                        s.download.push(self.lower_download(ft)?);
                     }
                 }
                 AnnotationKind::Service(svc) => {
-                    state.service = Some(self.lower_service_annotation(svc, ServiceKind::Managed)?);
+                    state.service =
+                        Some(self.lower_service_annotation(svc, ServiceKind::Managed, ann.span)?);
                 }
                 AnnotationKind::Extern(svc) => {
-                    state.service =
-                        Some(self.lower_service_annotation(svc, ServiceKind::External)?);
+                    state.service = Some(self.lower_service_annotation(
+                        svc,
+                        ServiceKind::External,
+                        ann.span,
+                    )?);
                 }
                 AnnotationKind::K8s(k8s_ann) => {
                     state.k8s = Some(self.lower_k8s_annotation(k8s_ann)?);
@@ -300,12 +586,12 @@ impl<'a> Context<'a> {
                 }
                 AnnotationKind::K8sUpload(ft) => {
                     if let Some(ref mut k) = state.k8s {
-                        k.upload.push(self.lower_file_transfer(ft));
+                        k.upload.push(self.lower_file_transfer(ft)?);
                     }
                 }
                 AnnotationKind::K8sDownload(ft) => {
                     if let Some(ref mut k) = state.k8s {
-                        k.download.push(self.lower_file_transfer(ft));
+                        k.download.push(self.lower_download(ft)?);
                     }
                 }
                 AnnotationKind::K8sForward(pf) => {
@@ -315,14 +601,30 @@ impl<'a> Context<'a> {
                         k.forwards.push(forward);
                     }
                 }
-                AnnotationKind::Env(env_ann) => {
-                    let key = env_ann.key.node.clone();
-                    let value = self.substitute_variables(&env_ann.value.node);
-                    if let Some(ref mut s) = state.ssh {
-                        s.env.insert(key, value);
+                AnnotationKind::Env(vars) => {
+                    for kv in vars {
+                        let key = self.expand_key(kv)?;
+                        let value = self.substitute_variables(&kv.node.value.node);
+                        state.env.insert(key, value);
                     }
                 }
-                AnnotationKind::Use(_) | AnnotationKind::Unknown { .. } => {}
+                AnnotationKind::Artifact(artifacts) => {
+                    for kv in artifacts {
+                        let name = self.expand_key(kv)?;
+                        let path = self.substitute_variables(&kv.node.value.node);
+                        state.artifacts.insert(name, path);
+                    }
+                }
+                AnnotationKind::Webhook { url, method, body } => {
+                    state.webhook = Some(WebhookConfig {
+                        url: self.substitute_variables(&url.node),
+                        method: method.node.to_uppercase(),
+                        body: body.as_ref().map(|b| self.substitute_variables(&b.node)),
+                    });
+                }
+                AnnotationKind::Use(_)
+                | AnnotationKind::Matrix { .. }
+                | AnnotationKind::Unknown { .. } => {}
             }
         }
         Ok(())
@@ -348,6 +650,12 @@ impl<'a> Context<'a> {
                 TaskParameter {
                     name: p.node.name.node.clone(),
                     default,
+                    param_type: p.node.param_type,
+                    allowed_values: p
+                        .node
+                        .allowed_values
+                        .as_ref()
+                        .map(|vals| vals.iter().map(|v| v.node.clone()).collect()),
                     span: Some(p.span),
                 }
             })
@@ -416,46 +724,118 @@ impl<'a> Context<'a> {
         (run, shebang)
     }
 
-    fn lower_ssh_annotation(&self, ssh: &ast::SshAnnotation) -> SshConfig {
+    fn lower_ssh_annotation(
+        &self,
+        ssh: &ast::SshAnnotation,
+    ) -> Result<SshConfig, ParseConfigError> {
         let mut config = SshConfig::default();
+        if let Some(host) = &ssh.host {
+            config.host = self.substitute_variables(&host.node);
+        }
 
         for opt in &ssh.options {
-            let key = &opt.node.key.node;
+            let key = self.expand_key(opt)?;
             let value = self.substitute_variables(&opt.node.value.node);
             match key.as_str() {
                 "host" => config.host = value,
                 "user" => config.user = Some(value),
                 "port" => config.port = value.parse().ok(),
                 "workdir" => config.workdir = Some(value),
+                "stderr_prefix" => config.stderr_prefix = Some(value),
+                "pty" => config.pty = value == "true",
                 "identity" => config.identity = Some(value),
+                "proxy" => config.proxy = Some(value),
+                "password_env" => config.password_env = Some(value),
+                "password" => {
+                    return Err(self.fatal(ParseConfigError {
+                        span: opt.span,
+                        message: "passwords can't be written in @ssh; use password_env=VAR to read one from the environment".to_string(),
+                    }));
+                }
                 _ => {}
             }
         }
 
-        config
+        Ok(config)
     }
 
-    fn lower_file_transfer(&self, ft: &ast::FileTransferAnnotation) -> FileTransfer {
-        FileTransfer {
+    fn lower_file_transfer(
+        &self,
+        ft: &ast::FileTransferAnnotation,
+    ) -> Result<FileTransfer, ParseConfigError> {
+        let mut required = false;
+        let mut preserve_times = true;
+        for opt in &ft.options {
+            let value = opt.node.value.node.as_str();
+            match self.expand_key(opt)?.as_str() {
+                "required" => required = matches!(value, "true" | "1"),
+                "preserve_times" => preserve_times = !matches!(value, "false" | "0"),
+                _ => {}
+            }
+        }
+        Ok(FileTransfer {
             local: self.substitute_variables(&ft.local.node),
             remote: self.substitute_variables(&ft.remote.node),
+            required,
+            preserve_times,
+        })
+    }
+
+    /// downloads are written `remote:local`, the reverse of uploads
+    fn lower_download(
+        &self,
+        ft: &ast::FileTransferAnnotation,
+    ) -> Result<FileTransfer, ParseConfigError> {
+        let transfer = self.lower_file_transfer(ft)?;
+        Ok(FileTransfer {
+            local: transfer.remote,
+            remote: transfer.local,
+            ..transfer
+        })
+    }
+
+    /// Expand `{{var}}` in an option key; a key that expands to nothing is an error
+    fn expand_key(&self, opt: &Spanned<KeyValue>) -> Result<String, ParseConfigError> {
+        let key = self.substitute_variables(&opt.node.key.node);
+        if key.is_empty() {
+            return Err(self.fatal(ParseConfigError {
+                span: opt.node.key.span,
+                message: format!("option key '{}' expands to nothing", opt.node.key.node),
+            }));
         }
+        Ok(key)
     }
 
     fn lower_service_annotation(
         &self,
         svc: &ast::ServiceAnnotation,
         kind: ServiceKind,
+        span: Span,
     ) -> Result<ServiceConfig, ParseConfigError> {
         let mut config = ServiceConfig {
             kind,
             ..Default::default()
         };
 
+        // docker-backed extern options
+        let mut docker = false;
+        let mut image = None;
+        let mut port = None;
+        let mut extra_args = Vec::new();
+
         for opt in &svc.options {
-            let key = &opt.node.key.node;
+            let key = self.expand_key(opt)?;
             let value = self.substitute_variables(&opt.node.value.node);
             match key.as_str() {
+                "kind" => docker = value == "docker",
+                "image" => image = Some(value),
+                "port" => {
+                    port = Some(value.parse::<u16>().map_err(|_| ParseConfigError {
+                        span: opt.span,
+                        message: format!("invalid port '{}'", value),
+                    })?);
+                }
+                "args" => extra_args = value.split_whitespace().map(String::from).collect(),
                 "ready" => config.ready = ReadinessCheck::parse(&value),
                 "startup_timeout" => {
                     config.startup_timeout =
@@ -497,10 +877,35 @@ impl<'a> Context<'a> {
                 "preflight" => {
                     config.preflight = Some(value);
                 }
+                "metrics" => {
+                    config.metrics_url = Some(value);
+                }
+                "allow_existing_port" => {
+                    config.allow_existing_port = value == "true" || value == "1";
+                }
                 _ => {}
             }
         }
 
+        if docker && config.kind == ServiceKind::External {
+            let (Some(image), Some(port)) = (image, port) else {
+                return Err(ParseConfigError {
+                    span,
+                    message: "kind=docker requires image= and port=".to_string(),
+                });
+            };
+            // wait for the published port unless told otherwise
+            config.ready.get_or_insert(ReadinessCheck::Tcp {
+                host: "127.0.0.1".to_string(),
+                port,
+            });
+            config.kind = ServiceKind::Docker {
+                image,
+                port,
+                extra_args,
+            };
+        }
+
         Ok(config)
     }
 
@@ -515,12 +920,15 @@ impl<'a> Context<'a> {
                 "exec" => K8sMode::Exec,
                 "job" => K8sMode::Job,
                 "apply" => K8sMode::Apply,
+                "debug" => K8sMode::Debug {
+                    image: String::new(),
+                },
                 _ => K8sMode::Job,
             };
         }
 
         for opt in &k8s.options {
-            let key = &opt.node.key.node;
+            let key = self.expand_key(opt)?;
             let value = self.substitute_variables(&opt.node.value.node);
             match key.as_str() {
                 "context" => config.context = Some(value),
@@ -534,6 +942,11 @@ impl<'a> Context<'a> {
                 "service_account" => config.service_account = Some(value),
                 "ttl_seconds" => config.ttl_seconds = value.parse().ok(),
                 "path" => config.path = Some(value),
+                "prune" => config.prune = value == "true",
+                "server_side" => config.server_side = value == "true",
+                "force_conflicts" => config.force_conflicts = value == "true",
+                "use_kubectl_cp" => config.use_kubectl_cp = value == "true",
+                "setup_cmd" => config.setup_cmd = Some(value),
                 "workdir" => config.workdir = Some(value),
                 "wait_timeout" => {
                     config.wait_timeout =
@@ -546,6 +959,15 @@ impl<'a> Context<'a> {
             }
         }
 
+        if let K8sMode::Debug { image } = &mut config.mode {
+            *image = config.image.clone().ok_or_else(|| {
+                self.fatal(ParseConfigError {
+                    span: k8s.mode.as_ref().map_or(Span::default(), |m| m.span),
+                    message: "@k8s debug requires image=".to_string(),
+                })
+            })?;
+        }
+
         Ok(config)
     }
 
@@ -596,11 +1018,103 @@ impl<'a> Context<'a> {
                 let v = value.unwrap_or("true");
                 self.dotenv.required = v == "true" || v == "1";
             }
+            "dotenv-override" => {
+                let v = value.unwrap_or("true");
+                self.dotenv.override_existing = v == "true" || v == "1";
+            }
+            "error-handling" => match value.unwrap_or("stop") {
+                "continue" => self.error_handling = ErrorHandling::Continue,
+                "stop" => self.error_handling = ErrorHandling::StopOnFirstFailure,
+                other => eprintln!("warning: unknown error-handling mode '{}'", other),
+            },
+            "k8s-default-ttl" => match value.map(str::parse::<u32>) {
+                Some(Ok(ttl)) => self.k8s_default_ttl = Some(ttl),
+                _ => eprintln!("warning: k8s-default-ttl expects a number of seconds"),
+            },
+            "pager" => match value.unwrap_or("auto") {
+                "auto" => self.pager = PagerMode::Auto,
+                "never" => self.pager = PagerMode::Never,
+                other => eprintln!("warning: unknown pager mode '{}'", other),
+            },
+            "concurrency-by-tag" => {
+                for entry in value.unwrap_or("").split_whitespace() {
+                    match entry.split_once('=').map(|(t, n)| (t, n.parse::<usize>())) {
+                        Some((tag, Ok(limit))) if limit > 0 => {
+                            self.concurrency_by_tag.insert(tag.to_string(), limit);
+                        }
+                        _ => eprintln!(
+                            "warning: concurrency-by-tag expects TAG=N with N > 0, got '{}'",
+                            entry
+                        ),
+                    }
+                }
+            }
+            "strict-annotations" => {
+                let v = value.unwrap_or("true");
+                self.strict_annotations = v == "true" || v == "1";
+            }
             _ => {}
         }
     }
 }
 
+/// Flatten each context with its `extends` chain. A context's annotations replace
+/// any inherited annotations of the same kind. Extending an undefined context or
+/// closing a cycle is an error.
+fn resolve_contexts(
+    blocks: &[&ast::ContextBlock],
+) -> Result<HashMap<String, Vec<Spanned<Annotation>>>, ParseConfigError> {
+    let by_name: HashMap<&str, &ast::ContextBlock> = blocks
+        .iter()
+        .map(|block| (block.name.node.as_str(), *block))
+        .collect();
+
+    let mut resolved = HashMap::new();
+    for block in blocks {
+        // walk up to the root, child first
+        let mut chain = vec![*block];
+        let mut current = *block;
+        while let Some(parent) = &current.extends {
+            let Some(parent_block) = by_name.get(parent.node.as_str()) else {
+                return Err(ParseConfigError {
+                    span: parent.span,
+                    message: format!(
+                        "context '{}' extends undefined context '{}'",
+                        current.name.node, parent.node
+                    ),
+                });
+            };
+            if chain.iter().any(|c| c.name.node == parent_block.name.node) {
+                let path: Vec<&str> = chain.iter().map(|c| c.name.node.as_str()).collect();
+                return Err(ParseConfigError {
+                    span: parent.span,
+                    message: format!(
+                        "circular context inheritance: {} -> {}",
+                        path.join(" -> "),
+                        parent.node
+                    ),
+                });
+            }
+            chain.push(parent_block);
+            current = parent_block;
+        }
+
+        let mut merged: Vec<Spanned<Annotation>> = Vec::new();
+        for context in chain.iter().rev() {
+            merged.retain(|inherited| {
+                !context.annotations.iter().any(|ann| {
+                    std::mem::discriminant(&ann.node.kind)
+                        == std::mem::discriminant(&inherited.node.kind)
+                })
+            });
+            merged.extend(context.annotations.iter().cloned());
+        }
+        resolved.insert(block.name.node.clone(), merged);
+    }
+
+    Ok(resolved)
+}
+
 fn parse_duration(s: &str) -> Result<Duration, String> {
     s.parse::<humantime::Duration>()
         .map(|d| d.into())
@@ -681,6 +1195,28 @@ svc:
         assert_eq!(svc.preflight, Some("test -f /tmp/marker".to_string()));
     }
 
+    #[test]
+    fn test_service_metrics_url() {
+        let source = r#"
+port := 9090
+@service ready=http://localhost:{{port}}/health metrics=http://localhost:{{port}}/metrics
+api:
+    ./serve
+"#;
+        let config = parse_config(source).unwrap();
+        let svc = config.tasks["api"].service.as_ref().unwrap();
+        assert_eq!(
+            svc.metrics_url.as_deref(),
+            Some("http://localhost:9090/metrics")
+        );
+
+        let config = parse_config("@service ready=tcp:127.0.0.1:80\napi:\n    ./serve\n").unwrap();
+        assert_eq!(
+            config.tasks["api"].service.as_ref().unwrap().metrics_url,
+            None
+        );
+    }
+
     #[test]
     fn test_command_readiness_with_quotes() {
         let source = r#"
@@ -724,6 +1260,50 @@ svc:
         assert_eq!(task.parameters[0].default, Some("world".to_string()));
     }
 
+    #[test]
+    fn test_task_parameters_with_allowed_values() {
+        let source = r#"deploy env{dev,staging,prod}:
+    echo "deploying to {{env}}"
+"#;
+        let config = parse_config(source).unwrap();
+        let param = &config.tasks.get("deploy").unwrap().parameters[0];
+        assert_eq!(
+            param.allowed_values,
+            Some(vec!["dev".into(), "staging".into(), "prod".into()])
+        );
+        assert!(param.check_value("staging").is_ok());
+        assert_eq!(
+            param.check_value("qa").unwrap_err(),
+            "invalid value 'qa' for parameter 'env': expected one of: dev, staging, prod"
+        );
+    }
+
+    #[test]
+    fn test_typed_task_parameters() {
+        let source = r#"release version:int patch:bool="false": build
+    echo "{{version}} {{patch}}"
+
+build:
+    echo
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("release").unwrap();
+        assert_eq!(task.depends_on, vec!["build"]);
+        let [version, patch] = &task.parameters[..] else {
+            panic!("expected two parameters");
+        };
+        assert_eq!(version.param_type, Some(ast::ParameterType::Int));
+        assert_eq!(patch.param_type, Some(ast::ParameterType::Bool));
+        assert_eq!(patch.default.as_deref(), Some("false"));
+
+        assert!(version.check_value("3").is_ok());
+        assert_eq!(
+            version.check_value("three").unwrap_err(),
+            "invalid value 'three' for parameter 'version': expected int"
+        );
+        assert!(patch.check_value("yes").is_err());
+    }
+
     #[test]
     fn test_default_context_applied() {
         let source = r#"
@@ -846,6 +1426,12 @@ simple:
         assert!(task.timeout.is_none());
     }
 
+    #[test]
+    fn test_include_missing_file() {
+        let err = parse_config("@include ./no/such/file.dr\n\nbuild:\n\techo\n").unwrap_err();
+        assert!(err.message.starts_with("cannot include ./no/such/file.dr"));
+    }
+
     #[test]
     fn test_bare_set_dotenv_load() {
         let config = parse_config("set dotenv-load\ntask:\n\techo hi").unwrap();
@@ -858,6 +1444,24 @@ simple:
         assert!(config.dotenv.required);
     }
 
+    #[test]
+    fn test_set_dotenv_override() {
+        let config = parse_config(
+            "set dotenv-override := true
+task:
+	echo hi",
+        )
+        .unwrap();
+        assert!(config.dotenv.override_existing);
+        let config = parse_config(
+            "set dotenv-load
+task:
+	echo hi",
+        )
+        .unwrap();
+        assert!(!config.dotenv.override_existing);
+    }
+
     #[test]
     fn test_env_annotation_on_ssh_task() {
         let source = r#"
@@ -868,7 +1472,7 @@ build:
 "#;
         let config = parse_config(source).unwrap();
         let task = config.tasks.get("build").unwrap();
-        assert!(task.ssh.is_some());
+        assert_eq!(task.env.get("TMPDIR"), Some(&"/tmp/build".to_string()));
         let ssh = task.ssh.as_ref().unwrap();
         assert_eq!(ssh.env.get("TMPDIR"), Some(&"/tmp/build".to_string()));
     }
@@ -884,9 +1488,24 @@ task:
 "#;
         let config = parse_config(source).unwrap();
         let task = config.tasks.get("task").unwrap();
-        let ssh = task.ssh.as_ref().unwrap();
-        assert_eq!(ssh.env.get("FOO"), Some(&"bar".to_string()));
-        assert_eq!(ssh.env.get("BAZ"), Some(&"qux".to_string()));
+        assert_eq!(task.env.get("FOO"), Some(&"bar".to_string()));
+        assert_eq!(task.env.get("BAZ"), Some(&"qux".to_string()));
+    }
+
+    #[test]
+    fn test_env_annotation_local_task() {
+        let source = r#"
+port := 8080
+
+@env RUST_LOG=debug PORT={{port}}
+serve:
+    cargo run
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("serve").unwrap();
+        assert!(task.ssh.is_none());
+        assert_eq!(task.env.get("RUST_LOG"), Some(&"debug".to_string()));
+        assert_eq!(task.env.get("PORT"), Some(&"8080".to_string()));
     }
 
     #[test]
@@ -901,7 +1520,541 @@ task:
 "#;
         let config = parse_config(source).unwrap();
         let task = config.tasks.get("task").unwrap();
-        let ssh = task.ssh.as_ref().unwrap();
-        assert_eq!(ssh.env.get("TMPDIR"), Some(&"/opt/build/tmp".to_string()));
+        assert_eq!(task.env.get("TMPDIR"), Some(&"/opt/build/tmp".to_string()));
+    }
+
+    #[test]
+    fn test_continued_variable_substitution() {
+        let source = r#"
+target := x86_64-unknown-linux-gnu
+flags := --release \
+    --target {{target}} \
+    --locked
+build:
+    cargo build {{flags}}
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("build").unwrap();
+        assert_eq!(
+            task.run.as_deref().map(str::trim),
+            Some("cargo build --release --target x86_64-unknown-linux-gnu --locked")
+        );
+    }
+
+    #[test]
+    fn test_interpolated_option_keys() {
+        let source = r#"
+prefix := APP
+@env {{prefix}}_HOME=/opt/app
+task:
+    ls
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("task").unwrap();
+        assert_eq!(task.env.get("APP_HOME"), Some(&"/opt/app".to_string()));
+
+        let source = r#"
+empty :=
+@env {{empty}}=1
+task:
+    ls
+"#;
+        let err = parse_config(source).unwrap_err();
+        assert!(
+            err.message.contains("expands to nothing"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn test_matrix_expands_tasks() {
+        let source = r#"
+target := host
+out := ./out
+
+@matrix target linux,darwin
+@env TARGET={{target}}
+build:
+    cargo build --target {{target}} --out-dir {{out}}
+
+check: build-linux
+    echo {{target}}
+"#;
+        let config = parse_config(source).unwrap();
+        assert!(!config.tasks.contains_key("build"));
+
+        let linux = config.tasks.get("build-linux").unwrap();
+        assert_eq!(
+            linux.run.as_deref().map(str::trim),
+            Some("cargo build --target linux --out-dir ./out")
+        );
+        assert_eq!(linux.env.get("TARGET"), Some(&"linux".to_string()));
+
+        let darwin = config.tasks.get("build-darwin").unwrap();
+        assert_eq!(darwin.env.get("TARGET"), Some(&"darwin".to_string()));
+
+        // the axis only shadows the global variable inside the matrix task
+        let check = config.tasks.get("check").unwrap();
+        assert_eq!(check.run.as_deref().map(str::trim), Some("echo host"));
+        assert_eq!(check.depends_on, ["build-linux"]);
+    }
+
+    #[test]
+    fn test_when_annotation() {
+        let source = r#"
+flag := .needs-migration
+@when test -f {{flag}}
+migrate:
+    ./migrate.sh
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("migrate").unwrap();
+        assert_eq!(task.condition.as_deref(), Some("test -f .needs-migration"));
+    }
+
+    #[test]
+    fn test_artifact_annotation() {
+        let source = r#"
+out := target/release
+@artifact bin={{out}}/app
+build:
+    cargo build --release
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("build").unwrap();
+        assert_eq!(
+            task.artifacts.get("bin").map(String::as_str),
+            Some("target/release/app")
+        );
+    }
+
+    #[test]
+    fn test_cache_annotation() {
+        let source = r#"
+lockfile := Cargo.lock
+@cache key="sha256sum {{lockfile}}"
+build:
+    cargo build
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("build").unwrap();
+        assert_eq!(task.cache_key.as_deref(), Some("sha256sum Cargo.lock"));
+    }
+
+    #[test]
+    fn test_on_failure_annotation() {
+        let source = r#"
+@on-failure cleanup
+deploy:
+    ./deploy.sh
+
+cleanup:
+    rm -rf tmp
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("deploy").unwrap();
+        assert_eq!(task.on_failure.as_deref(), Some("cleanup"));
+
+        let err = parse_config("@on-failure deploy\ndeploy:\n\t./deploy.sh\n").unwrap_err();
+        assert_eq!(
+            err.message,
+            "task 'deploy' can't be its own @on-failure handler"
+        );
+    }
+
+    #[test]
+    fn test_webhook_annotation() {
+        let source = r#"
+host := hooks.example.com
+@webhook url=https://{{host}}/done method=put body={"task":"{{name}}","status":"{{status}}"}
+task:
+    echo hi
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("task").unwrap();
+        let webhook = task.webhook.as_ref().unwrap();
+        assert_eq!(webhook.url, "https://hooks.example.com/done");
+        assert_eq!(webhook.method, "PUT");
+        assert_eq!(
+            webhook.body.as_deref(),
+            Some(r#"{"task":"{{name}}","status":"{{status}}"}"#)
+        );
+    }
+
+    #[test]
+    fn test_error_handling_directive() {
+        let config = parse_config("build:\n    echo hi").unwrap();
+        assert_eq!(config.error_handling, ErrorHandling::StopOnFirstFailure);
+
+        let config = parse_config("set error-handling := continue\nbuild:\n    echo hi").unwrap();
+        assert_eq!(config.error_handling, ErrorHandling::Continue);
+    }
+
+    #[test]
+    fn test_multiple_k8s_forwards() {
+        let source = r#"
+@k8s exec namespace=prod pod=api
+@k8s-forward 5432:svc/postgres:5432
+@k8s-forward 6379:svc/redis:6379
+task:
+    echo hi
+"#;
+        let config = parse_config(source).unwrap();
+        let task = config.tasks.get("task").unwrap();
+        let forwards = &task.k8s.as_ref().unwrap().forwards;
+        assert_eq!(forwards.len(), 2);
+        assert_eq!(forwards[0].local_port, 5432);
+        assert_eq!(forwards[0].resource, "postgres");
+        assert_eq!(forwards[0].resource_type.as_deref(), Some("svc"));
+        assert_eq!(forwards[1].local_port, 6379);
+        assert_eq!(forwards[1].remote_port, 6379);
+        assert_eq!(forwards[1].resource, "redis");
+    }
+
+    #[test]
+    fn test_k8s_default_ttl() {
+        let source = r#"
+set k8s-default-ttl := 3600
+
+@k8s job image=alpine
+fallback:
+    echo hi
+
+@k8s job image=alpine ttl_seconds=60
+explicit:
+    echo hi
+"#;
+        let config = parse_config(source).unwrap();
+        assert_eq!(config.k8s_default_ttl, Some(3600));
+        let ttl = |name: &str| config.tasks[name].k8s.as_ref().unwrap().ttl_seconds;
+        assert_eq!(ttl("fallback"), Some(3600));
+        assert_eq!(ttl("explicit"), Some(60));
+    }
+
+    #[test]
+    fn test_tags_and_concurrency_by_tag() {
+        let source = r#"
+set concurrency-by-tag := deploy=1 test=4
+
+@tags deploy prod
+deploy-api:
+    ./deploy.sh api
+"#;
+        let config = parse_config(source).unwrap();
+        assert_eq!(config.tasks["deploy-api"].tags, ["deploy", "prod"]);
+        assert_eq!(config.concurrency_by_tag.get("deploy"), Some(&1));
+        assert_eq!(config.concurrency_by_tag.get("test"), Some(&4));
+    }
+
+    #[test]
+    fn test_outputs_annotation() {
+        let source = r#"
+out := target/release
+
+@outputs {{out}}/myapp {{out}}/myapp.d
+build:
+    cargo build --release
+"#;
+        let config = parse_config(source).unwrap();
+        assert_eq!(
+            config.tasks["build"].outputs,
+            vec!["target/release/myapp", "target/release/myapp.d"]
+        );
+    }
+
+    #[test]
+    fn test_k8s_apply_prune() {
+        let source = r#"
+@k8s apply path=./manifests prune=true
+sync:
+
+@k8s apply path=./manifests
+deploy:
+"#;
+        let config = parse_config(source).unwrap();
+        let k8s = |name: &str| config.tasks[name].k8s.clone().unwrap();
+        assert_eq!(k8s("sync").mode, K8sMode::Apply);
+        assert!(k8s("sync").prune);
+        assert!(!k8s("deploy").prune);
+    }
+
+    #[test]
+    fn test_k8s_apply_server_side() {
+        let source = r#"
+@k8s apply path=./manifests server_side=true force_conflicts=true
+sync:
+
+@k8s apply path=./manifests
+deploy:
+"#;
+        let config = parse_config(source).unwrap();
+        let k8s = |name: &str| config.tasks[name].k8s.clone().unwrap();
+        assert!(k8s("sync").server_side);
+        assert!(k8s("sync").force_conflicts);
+        assert!(!k8s("deploy").server_side);
+        assert!(!k8s("deploy").force_conflicts);
+    }
+
+    #[test]
+    fn test_k8s_exec_use_kubectl_cp() {
+        let source = r#"
+@k8s exec pod=api use_kubectl_cp=true
+migrate:
+    ./migrate.sh
+"#;
+        let config = parse_config(source).unwrap();
+        let k8s = config.tasks["migrate"].k8s.as_ref().unwrap();
+        assert_eq!(k8s.mode, K8sMode::Exec);
+        assert!(k8s.use_kubectl_cp);
+    }
+
+    #[test]
+    fn test_ssh_password_env() {
+        let source = r#"
+@ssh host=legacy-host password_env=SSH_PASSWORD
+legacy:
+    uptime
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["legacy"].ssh.as_ref().unwrap();
+        assert_eq!(ssh.password_env.as_deref(), Some("SSH_PASSWORD"));
+
+        let err = parse_config("@ssh host=legacy-host password=secret\nlegacy:\n    uptime\n")
+            .unwrap_err();
+        assert!(err.message.contains("password_env"));
+    }
+
+    #[test]
+    fn test_ssh_proxy() {
+        let source = r#"
+bastion := jump.example.com
+@ssh host=10.0.0.5 user=deploy proxy=ops@{{bastion}}:2222
+internal:
+    uptime
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["internal"].ssh.as_ref().unwrap();
+        assert_eq!(ssh.proxy.as_deref(), Some("ops@jump.example.com:2222"));
+    }
+
+    #[test]
+    fn test_k8s_debug_mode() {
+        let source = r#"
+@k8s debug selector=app=api image=busybox
+inspect:
+    netstat -tlnp
+"#;
+        let config = parse_config(source).unwrap();
+        let k8s = config.tasks["inspect"].k8s.as_ref().unwrap();
+        assert_eq!(
+            k8s.mode,
+            K8sMode::Debug {
+                image: "busybox".to_string()
+            }
+        );
+
+        let err = parse_config("@k8s debug pod=api\ninspect:\n    ls\n").unwrap_err();
+        assert!(err.message.contains("requires image="));
+    }
+
+    #[test]
+    fn test_k8s_job_setup_cmd() {
+        let source = r#"
+@k8s job image=python:3.12 setup_cmd="pip install -r requirements.txt"
+train:
+    python train.py
+"#;
+        let config = parse_config(source).unwrap();
+        let k8s = config.tasks["train"].k8s.as_ref().unwrap();
+        assert_eq!(
+            k8s.setup_cmd.as_deref(),
+            Some("pip install -r requirements.txt")
+        );
+    }
+
+    #[test]
+    fn test_docker_extern() {
+        let source = r#"
+@extern kind=docker image=redis:7 port=6379 args="--network host"
+redis:
+"#;
+        let config = parse_config(source).unwrap();
+        let svc = config.tasks["redis"].service.as_ref().unwrap();
+        match &svc.kind {
+            ServiceKind::Docker {
+                image,
+                port,
+                extra_args,
+            } => {
+                assert_eq!(image, "redis:7");
+                assert_eq!(*port, 6379);
+                assert_eq!(extra_args, &["--network", "host"]);
+            }
+            other => panic!("expected docker service, got {:?}", other),
+        }
+        assert!(matches!(
+            svc.ready,
+            Some(ReadinessCheck::Tcp { port: 6379, .. })
+        ));
+    }
+
+    #[test]
+    fn test_ssh_positional_host() {
+        let source = r#"
+server := deploy@10.0.0.5
+
+@ssh {{server}} port=2222 workdir=/app
+remote:
+    pwd
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["remote"].ssh.as_ref().unwrap();
+        assert_eq!(ssh.host, "deploy@10.0.0.5");
+        assert_eq!(ssh.port, Some(2222));
+        assert_eq!(ssh.workdir.as_deref(), Some("/app"));
+    }
+
+    #[test]
+    fn test_ssh_stderr_prefix() {
+        let source = r#"
+@ssh host=example.com stderr_prefix="[remote err]"
+prefixed:
+    ls /missing
+
+@ssh host=example.com
+default:
+    ls /missing
+"#;
+        let config = parse_config(source).unwrap();
+        let prefix = |name: &str| {
+            config.tasks[name]
+                .ssh
+                .as_ref()
+                .unwrap()
+                .stderr_prefix
+                .clone()
+        };
+        assert_eq!(prefix("prefixed").as_deref(), Some("[remote err]"));
+        assert_eq!(prefix("default"), None);
+    }
+
+    #[test]
+    fn test_strict_annotations() {
+        let source = "@sssh host=example.com\ndeploy:\n    echo hi\n";
+        assert!(parse_config(source).is_ok());
+
+        let strict = ParseConfigOptions {
+            strict_annotations: true,
+        };
+        let err = parse_config_with(source, strict).unwrap_err();
+        assert_eq!(err.message, "unknown annotation '@sssh'");
+
+        let with_directive = format!("set strict-annotations := true\n{}", source);
+        assert!(parse_config(&with_directive).is_err());
+    }
+
+    #[test]
+    fn test_ssh_pty() {
+        let source = r#"
+@ssh host=example.com pty=true
+top:
+    top -bn1
+"#;
+        let config = parse_config(source).unwrap();
+        assert!(config.tasks["top"].ssh.as_ref().unwrap().pty);
+    }
+
+    #[test]
+    fn test_context_extends() {
+        let source = r#"
+@context base
+@ssh host=example.com workdir=/app
+@timeout 5m
+@end
+
+@context prod extends base
+@timeout 10m
+@retry 2
+@end
+
+@use prod
+deploy:
+    ./deploy.sh
+"#;
+        let config = parse_config(source).unwrap();
+        let task = &config.tasks["deploy"];
+        assert_eq!(task.ssh.as_ref().unwrap().host, "example.com");
+        assert_eq!(task.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(task.retry, 2);
+    }
+
+    #[test]
+    fn test_context_extends_cycle() {
+        let err = parse_config(
+            "@context a extends b\n@timeout 1m\n@end\n@context b extends a\n@retry 1\n@end\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.message, "circular context inheritance: a -> b -> a");
+    }
+
+    #[test]
+    fn test_context_extends_undefined() {
+        let err = parse_config("@context prod extends base\n@retry 1\n@end\n").unwrap_err();
+        assert_eq!(
+            err.message,
+            "context 'prod' extends undefined context 'base'"
+        );
+    }
+
+    #[test]
+    fn test_download_paths_remote_then_local() {
+        let source = r#"
+@ssh host=example.com
+@upload ./app.tar:/tmp/app.tar
+@download /tmp/results.txt:./results.txt
+collect:
+    ./run.sh
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["collect"].ssh.as_ref().unwrap();
+        assert_eq!(ssh.upload[0].local, "./app.tar");
+        assert_eq!(ssh.upload[0].remote, "/tmp/app.tar");
+        assert_eq!(ssh.download[0].remote, "/tmp/results.txt");
+        assert_eq!(ssh.download[0].local, "./results.txt");
+    }
+
+    #[test]
+    fn test_download_glob_required() {
+        let source = r#"
+@ssh host=example.com
+@upload ./app.tar:/tmp/app.tar
+@download /remote/logs/*.log:./logs/ required=true
+collect:
+    ./run.sh
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["collect"].ssh.as_ref().unwrap();
+        assert_eq!(ssh.upload[0].local, "./app.tar");
+        assert_eq!(ssh.upload[0].remote, "/tmp/app.tar");
+        assert!(!ssh.upload[0].required);
+        assert_eq!(ssh.download[0].remote, "/remote/logs/*.log");
+        assert_eq!(ssh.download[0].local, "./logs/");
+        assert!(ssh.download[0].required);
+    }
+
+    #[test]
+    fn test_transfer_preserve_times() {
+        let source = r#"
+@ssh host=example.com
+@upload ./src:/app/src
+@download /app/out.bin:./out.bin preserve_times=false
+build:
+    make
+"#;
+        let config = parse_config(source).unwrap();
+        let ssh = config.tasks["build"].ssh.as_ref().unwrap();
+        assert!(ssh.upload[0].preserve_times);
+        assert!(!ssh.download[0].preserve_times);
     }
 }
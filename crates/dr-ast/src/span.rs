@@ -1,5 +1,6 @@
 /// A source location represented as a byte offset range
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
     /// Start byte offset (inclusive)
     pub start: u32,
@@ -46,10 +47,45 @@ impl Span {
     pub fn text<'a>(&self, source: &'a str) -> &'a str {
         &source[self.start as usize..self.end as usize]
     }
+
+    /// 1-based line and column of the span start within source
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let mut offset = (self.start as usize).min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Format the span start as `line L, column C` against source
+    pub fn display<'a>(&self, source: &'a str) -> SpanDisplay<'a> {
+        SpanDisplay {
+            span: *self,
+            source,
+        }
+    }
+}
+
+/// Human-readable span location, see [`Span::display`]
+pub struct SpanDisplay<'a> {
+    span: Span,
+    source: &'a str,
+}
+
+impl std::fmt::Display for SpanDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, column) = self.span.line_col(self.source);
+        write!(f, "line {}, column {}", line, column)
+    }
 }
 
 /// Wrapper that attaches a span to any value
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Spanned<T> {
     pub node: T,
     pub span: Span,
@@ -111,4 +147,17 @@ mod tests {
         let span = Span::new(6, 11);
         assert_eq!(span.text(source), "world");
     }
+
+    #[test]
+    fn span_line_col() {
+        let source = "build:\n\tcargo build\n@bad";
+        assert_eq!(Span::new(0, 5).line_col(source), (1, 1));
+        assert_eq!(Span::new(8, 13).line_col(source), (2, 2));
+        assert_eq!(
+            Span::new(20, 24).display(source).to_string(),
+            "line 3, column 1"
+        );
+        // offsets past the end clamp to the last position
+        assert_eq!(Span::point(100).line_col(source), (3, 5));
+    }
 }
@@ -0,0 +1,193 @@
+//! Config checks shared by the LSP and `dr validate --strict`
+//!
+//! These flag configs that parse fine but almost certainly don't do what the
+//! author meant.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{
+    Annotation, AnnotationKind, BodyLine, CommandSegment, ContextBlock, Item, ServiceAnnotation,
+    SourceFile, TaskDecl,
+};
+use crate::span::{Span, Spanned};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Warning,
+    Hint,
+}
+
+/// Stable identifier for each check, e.g. for LSP quick fixes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintCode {
+    ServiceWithoutReady,
+    IneffectiveStartupTimeout,
+    MissingTimeout,
+}
+
+impl LintCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintCode::ServiceWithoutReady => "service-without-ready",
+            LintCode::IneffectiveStartupTimeout => "ineffective-startup-timeout",
+            LintCode::MissingTimeout => "missing-timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub level: LintLevel,
+    pub code: LintCode,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Run every check over a parsed file
+pub fn lint(ast: &SourceFile) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let contexts: HashMap<&str, &ContextBlock> = ast
+        .items
+        .iter()
+        .filter_map(|item| match &item.node {
+            Item::ContextBlock(ctx) => Some((ctx.name.node.as_str(), ctx)),
+            _ => None,
+        })
+        .collect();
+
+    for item in &ast.items {
+        let Item::Task(task) = &item.node else {
+            continue;
+        };
+        for ann in &task.annotations {
+            if let AnnotationKind::Service(svc) = &ann.node.kind {
+                check_service_annotation_semantics(svc, ann.span, &mut lints);
+            }
+        }
+        check_missing_timeout(task, &contexts, &mut lints);
+    }
+
+    lints
+}
+
+/// A `@service` without `ready=` counts as ready the moment it starts
+fn check_service_annotation_semantics(svc: &ServiceAnnotation, span: Span, lints: &mut Vec<Lint>) {
+    if svc.options.iter().any(|kv| kv.node.key.node == "ready") {
+        return;
+    }
+
+    lints.push(Lint {
+        level: LintLevel::Warning,
+        code: LintCode::ServiceWithoutReady,
+        message: "@service has no ready= check, so it is considered ready immediately".to_string(),
+        span,
+    });
+
+    if let Some(timeout) = svc
+        .options
+        .iter()
+        .find(|kv| kv.node.key.node == "startup_timeout")
+    {
+        lints.push(Lint {
+            level: LintLevel::Hint,
+            code: LintCode::IneffectiveStartupTimeout,
+            message: "startup_timeout has no effect without a ready= check".to_string(),
+            span: timeout.span,
+        });
+    }
+}
+
+/// A task that calls curl or wget with no `@timeout` (directly or via `@use`)
+/// waits forever on a server that never answers
+fn check_missing_timeout(
+    task: &TaskDecl,
+    contexts: &HashMap<&str, &ContextBlock>,
+    lints: &mut Vec<Lint>,
+) {
+    let Some(body) = &task.body else {
+        return;
+    };
+    let makes_http_call = body.lines.iter().any(|line| match &line.node {
+        BodyLine::Command(cmd) => cmd.segments.iter().any(|seg| match &seg.node {
+            CommandSegment::Text(text) => text
+                .split_whitespace()
+                .any(|word| matches!(word, "curl" | "wget" | "http" | "https")),
+            CommandSegment::Interpolation(_) => false,
+        }),
+        _ => false,
+    });
+    if !makes_http_call || has_timeout(&task.annotations, contexts, &mut HashSet::new()) {
+        return;
+    }
+
+    lints.push(Lint {
+        level: LintLevel::Hint,
+        code: LintCode::MissingTimeout,
+        message: format!(
+            "task '{}' makes HTTP calls but has no @timeout",
+            task.name.node
+        ),
+        span: task.name.span,
+    });
+}
+
+fn has_timeout<'a>(
+    annotations: &'a [Spanned<Annotation>],
+    contexts: &HashMap<&str, &'a ContextBlock>,
+    seen: &mut HashSet<&'a str>,
+) -> bool {
+    annotations.iter().any(|ann| match &ann.node.kind {
+        AnnotationKind::Timeout(_) => true,
+        AnnotationKind::Use(name) => context_has_timeout(&name.node, contexts, seen),
+        _ => false,
+    })
+}
+
+fn context_has_timeout<'a>(
+    name: &'a str,
+    contexts: &HashMap<&str, &'a ContextBlock>,
+    seen: &mut HashSet<&'a str>,
+) -> bool {
+    // guard against `extends` cycles
+    if !seen.insert(name) {
+        return false;
+    }
+    let Some(&ctx) = contexts.get(name) else {
+        return false;
+    };
+    has_timeout(&ctx.annotations, contexts, seen)
+        || ctx
+            .extends
+            .as_ref()
+            .is_some_and(|parent| context_has_timeout(&parent.node, contexts, seen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_service_without_ready() {
+        let (ast, _) = parse(
+            "@service startup_timeout=5s\nserver:\n\t./serve\n\n@service ready=tcp:127.0.0.1:8080\napi:\n\t./api\n",
+        );
+        let lints = lint(&ast);
+        assert_eq!(lints.len(), 2);
+        assert_eq!(lints[0].level, LintLevel::Warning);
+        assert_eq!(lints[1].level, LintLevel::Hint);
+        assert!(lints[1].message.contains("startup_timeout"));
+    }
+
+    #[test]
+    fn test_http_call_without_timeout() {
+        let (ast, _) = parse(
+            "@context net\n@timeout 10s\n@end\n\nfetch:\n\tcurl -sf https://example.com\n\n@use net\nsync:\n\twget https://example.com\n\n@timeout 5s\nping:\n\tcurl localhost\n",
+        );
+        let lints = lint(&ast);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, LintCode::MissingTimeout);
+        assert!(lints[0].message.contains("'fetch'"));
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
 
+use dr_ast::validate::{LintCode, LintLevel};
 use dr_ast::{
-    AnnotationKind, BodyLine, CommandSegment, Dependency, Item, KeyValue, ParseError, SourceFile,
-    Span, Spanned, parse,
+    AnnotationKind, BodyLine, CommandSegment, Dependency, Item, KeyValue, ParameterDefault,
+    ParseError, ParseErrorKind, Shift, SourceFile, Span, Spanned, parse, parse_items_from_range,
 };
 use tokio::sync::RwLock;
 use tower_lsp_server::jsonrpc::Result;
+use tower_lsp_server::ls_types::request::{GotoTypeDefinitionParams, GotoTypeDefinitionResponse};
 use tower_lsp_server::ls_types::*;
 use tower_lsp_server::{Client, LanguageServer};
 
@@ -29,6 +31,18 @@ const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
     SemanticTokenModifier::READONLY,    // 2
 ];
 
+// commands handled via workspace/executeCommand
+const RUN_TASK_COMMAND: &str = "dagrun.runTask";
+
+/// Sent instead of running the task in the server when the client asked to run
+/// tasks itself, e.g. in an editor terminal. Params: `{"task": "<name>", "uri": "<dagfile>"}`
+enum RunTaskNotification {}
+
+impl notification::Notification for RunTaskNotification {
+    type Params = LSPAny;
+    const METHOD: &'static str = "dagrun/runTask";
+}
+
 // ============================================================================
 // Shebang / Language Detection
 // ============================================================================
@@ -46,7 +60,7 @@ fn detect_language(body: &dr_ast::TaskBody) -> BodyLanguage {
             let args: Vec<&str> = shebang.args.iter().map(|a| a.node.as_str()).collect();
 
             // uv script shebang: #!/usr/bin/env -S uv run --script
-            if interp.ends_with("/env") && args.iter().any(|a| *a == "uv") {
+            if interp.ends_with("/env") && args.contains(&"uv") {
                 return BodyLanguage::Python;
             }
 
@@ -74,9 +88,55 @@ fn detect_language(body: &dr_ast::TaskBody) -> BodyLanguage {
     BodyLanguage::Shell
 }
 
+/// Client settings, from `initializationOptions` or `workspace/didChangeConfiguration`
+#[derive(Debug, Clone)]
+struct Settings {
+    /// duration inserted by the "add @timeout" quick fix
+    default_timeout: String,
+    /// send `dagrun/runTask` to the client instead of running tasks in the server
+    run_in_client: bool,
+    /// run backtick variables to show their values in hover; off by default
+    /// since it executes commands from whatever dagfile is open
+    evaluate_shell_vars: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_timeout: "30s".to_string(),
+            run_in_client: false,
+            evaluate_shell_vars: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Apply `{"defaultTimeout": "1m", "runInClient": true, "evaluateShellVars": true}`,
+    /// either at the top level or under `dagrun`
+    fn update(&mut self, value: &LSPAny) {
+        let value = value.get("dagrun").unwrap_or(value);
+        if let Some(timeout) = value.get("defaultTimeout").and_then(|v| v.as_str()) {
+            self.default_timeout = timeout.to_string();
+        }
+        if let Some(run_in_client) = value.get("runInClient").and_then(|v| v.as_bool()) {
+            self.run_in_client = run_in_client;
+        }
+        if let Some(evaluate) = value.get("evaluateShellVars").and_then(|v| v.as_bool()) {
+            self.evaluate_shell_vars = evaluate;
+        }
+    }
+}
+
 pub struct Backend {
     client: Client,
     documents: Arc<RwLock<HashMap<Uri, String>>>,
+    parsers: Arc<RwLock<HashMap<Uri, IncrementalParser>>>,
+    settings: Arc<RwLock<Settings>>,
+    workspace: Arc<RwLock<WorkspaceManager>>,
+    /// Output of shell-expansion variables for hover, keyed by command
+    evaluated_vars: Arc<RwLock<HashMap<String, ShellValue>>>,
+    /// Files each open document pulls in through `@include`, directly or not
+    include_graph: Arc<RwLock<HashMap<Uri, Vec<Uri>>>>,
 }
 
 impl Backend {
@@ -84,29 +144,131 @@ impl Backend {
         Self {
             client,
             documents: Arc::new(RwLock::new(HashMap::new())),
+            parsers: Arc::new(RwLock::new(HashMap::new())),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            workspace: Arc::new(RwLock::new(WorkspaceManager::default())),
+            evaluated_vars: Arc::new(RwLock::new(HashMap::new())),
+            include_graph: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    async fn publish_diagnostics(&self, uri: Uri, source: &str) {
-        let (ast, errors) = parse(source);
+    /// Run shell-expansion variables in the background so hover can show what
+    /// they evaluate to, when the `evaluateShellVars` setting is on. A command
+    /// runs once its definition stays unchanged for [`SHELL_VAR_DEBOUNCE`], and
+    /// its result is kept for [`SHELL_VAR_TTL`]
+    async fn evaluate_shell_vars(&self, uri: &Uri, ast: &SourceFile) {
+        if !self.settings.read().await.evaluate_shell_vars {
+            return;
+        }
+        let working_dir = uri
+            .to_file_path()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+        let mut evaluated = self.evaluated_vars.write().await;
+        for item in &ast.items {
+            let Item::Variable(var) = &item.node else {
+                continue;
+            };
+            let dr_ast::VariableValue::Shell(shell) = &var.value.node else {
+                continue;
+            };
+            let command = shell.command.node.clone();
+            if evaluated.contains_key(&command) {
+                continue;
+            }
+            evaluated.insert(command.clone(), ShellValue::Pending);
+
+            let uri = uri.clone();
+            let documents = Arc::clone(&self.documents);
+            let evaluated_vars = Arc::clone(&self.evaluated_vars);
+            let working_dir = working_dir.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(SHELL_VAR_DEBOUNCE).await;
+                // still being typed, the final command gets its own run
+                let current = documents
+                    .read()
+                    .await
+                    .get(&uri)
+                    .is_some_and(|source| source.contains(&format!("`{}`", command)));
+                if !current {
+                    evaluated_vars.write().await.remove(&command);
+                    return;
+                }
+                let value = run_shell_var(&command, working_dir.as_deref()).await;
+                evaluated_vars.write().await.insert(command.clone(), value);
+
+                // outputs like `git rev-parse HEAD` go stale, so run it again
+                // on the next edit or hover after a while
+                tokio::time::sleep(SHELL_VAR_TTL).await;
+                evaluated_vars.write().await.remove(&command);
+            });
+        }
+    }
 
+    /// Open documents from the same project as `uri`, parsed
+    async fn sibling_documents(&self, uri: &Uri, docs: &HashMap<Uri, String>) -> Vec<IncludedFile> {
+        self.workspace
+            .read()
+            .await
+            .siblings(uri, docs)
+            .into_iter()
+            .map(|(uri, source)| IncludedFile {
+                uri: uri.clone(),
+                source: source.clone(),
+                ast: parse(source).0,
+            })
+            .collect()
+    }
+
+    async fn publish_diagnostics(
+        &self,
+        uri: Uri,
+        source: &str,
+        ast: &SourceFile,
+        errors: &[ParseError],
+    ) {
         // parse errors
         let mut diagnostics: Vec<Diagnostic> =
             errors.iter().map(|e| to_diagnostic(source, e)).collect();
 
-        // semantic diagnostics
-        diagnostics.extend(check_undefined_variables(source, &ast));
-        diagnostics.extend(check_undefined_tasks(source, &ast));
-        diagnostics.extend(check_dependency_cycles(source, &ast));
-        diagnostics.extend(check_unused_variables(source, &ast));
-        diagnostics.extend(check_undefined_contexts(source, &ast));
-        diagnostics.extend(check_unused_contexts(source, &ast));
-
-        // filesystem diagnostics (paths, executables)
         let working_dir = uri
             .to_file_path()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()));
-        diagnostics.extend(check_filesystem(source, &ast, working_dir.as_deref()));
+        let mut includes = load_includes(working_dir.as_deref(), ast);
+        self.include_graph.write().await.insert(
+            uri.clone(),
+            includes.iter().map(|inc| inc.uri.clone()).collect(),
+        );
+
+        // included files are rarely open themselves, so report their parse
+        // errors from here
+        let closed = included_parse_diagnostics(&includes, &*self.documents.read().await);
+        for (included_uri, included_diagnostics) in closed {
+            self.client
+                .publish_diagnostics(included_uri, included_diagnostics, None)
+                .await;
+        }
+
+        // semantic diagnostics
+        diagnostics.extend(check_undefined_variables(source, ast, &includes));
+        diagnostics.extend(check_parameter_types(source, ast));
+        // tasks from other open dagfiles of the same project count as defined
+        let docs = self.documents.read().await;
+        includes.extend(self.sibling_documents(&uri, &docs).await);
+        drop(docs);
+        diagnostics.extend(check_undefined_tasks(source, ast, &includes));
+        diagnostics.extend(check_duplicate_tasks(&uri, source, ast));
+        diagnostics.extend(check_include_paths(source, ast, working_dir.as_deref()));
+        diagnostics.extend(check_dependency_cycles(source, ast));
+        diagnostics.extend(check_unused_variables(source, ast));
+        diagnostics.extend(check_undefined_contexts(source, ast));
+        diagnostics.extend(check_unused_contexts(source, ast));
+        diagnostics.extend(check_k8s_prune(source, ast));
+        diagnostics.extend(check_when_conditions(source, ast));
+        diagnostics.extend(check_orphaned_annotations(source, ast));
+        diagnostics.extend(check_lints(source, ast));
+
+        // filesystem diagnostics (paths, executables)
+        diagnostics.extend(check_filesystem(source, ast, working_dir.as_deref()));
 
         self.client
             .publish_diagnostics(uri, diagnostics, None)
@@ -115,11 +277,28 @@ impl Backend {
 }
 
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        if let Some(options) = &params.initialization_options {
+            self.settings.write().await.update(options);
+        }
+        {
+            let mut workspace = self.workspace.write().await;
+            for folder in params.workspace_folders.iter().flatten() {
+                workspace.add_root(&folder.uri);
+            }
+        }
+
+        // only advertise inlay hints to clients that can render them
+        let inlay_hints = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|td| td.inlay_hint.is_some());
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensOptions(
@@ -135,6 +314,7 @@ impl LanguageServer for Backend {
                     ),
                 ),
                 definition_provider: Some(OneOf::Left(true)),
+                type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 references_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Right(RenameOptions {
@@ -142,15 +322,54 @@ impl LanguageServer for Backend {
                     work_done_progress_options: Default::default(),
                 })),
                 document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
                 completion_provider: Some(CompletionOptions {
                     trigger_characters: Some(vec![
                         "{".to_string(), // for {{var}}
                         "@".to_string(), // for annotations
                         ":".to_string(), // after task name
                     ]),
+                    // everything is filled in up front
+                    resolve_provider: Some(false),
                     ..Default::default()
                 }),
                 document_formatting_provider: Some(OneOf::Left(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec![" ".to_string(), "=".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                inlay_hint_provider: inlay_hints.then_some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            EXTRACT_VARIABLE_KIND,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![RUN_TASK_COMMAND.to_string()],
+                    ..Default::default()
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
             ..Default::default()
@@ -159,6 +378,27 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         tracing::info!("dagrun-lsp initialized");
+
+        // included files are usually not open, so ask the client to tell us
+        // when they change on disk
+        let watchers = ["**/*.dr", "**/dagfile"]
+            .into_iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.to_string()),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "dagrun-watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            tracing::debug!("client did not register file watchers: {}", e);
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -168,27 +408,69 @@ impl LanguageServer for Backend {
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
         let text = params.text_document.text;
+        let parser = IncrementalParser::new(text.clone());
+        let (ast, errors) = (parser.file.clone(), parser.errors.clone());
+        self.parsers.write().await.insert(uri.clone(), parser);
         self.documents
             .write()
             .await
             .insert(uri.clone(), text.clone());
-        self.publish_diagnostics(uri, &text).await;
+        self.evaluate_shell_vars(&uri, &ast).await;
+        self.publish_diagnostics(uri, &text, &ast, &errors).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
-        if let Some(change) = params.content_changes.into_iter().next() {
-            self.documents
-                .write()
-                .await
-                .insert(uri.clone(), change.text.clone());
-            self.publish_diagnostics(uri, &change.text).await;
+        let (text, ast, errors) = {
+            let mut parsers = self.parsers.write().await;
+            let parser = parsers
+                .entry(uri.clone())
+                .or_insert_with(|| IncrementalParser::new(String::new()));
+            let mut parsed = (parser.file.clone(), parser.errors.clone());
+            for change in &params.content_changes {
+                parsed = parser.update(change);
+            }
+            (parser.source.clone(), parsed.0, parsed.1)
+        };
+        self.documents
+            .write()
+            .await
+            .insert(uri.clone(), text.clone());
+        self.evaluate_shell_vars(&uri, &ast).await;
+        self.publish_diagnostics(uri, &text, &ast, &errors).await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.settings.write().await.update(&params.settings);
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let mut workspace = self.workspace.write().await;
+        for folder in &params.event.removed {
+            workspace.remove_root(&folder.uri);
+        }
+        for folder in &params.event.added {
+            workspace.add_root(&folder.uri);
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let changed: Vec<Uri> = params.changes.into_iter().map(|c| c.uri).collect();
+        let dependents = dependents_of(&*self.include_graph.read().await, &changed);
+        for uri in dependents {
+            let Some(text) = self.documents.read().await.get(&uri).cloned() else {
+                continue;
+            };
+            let (ast, errors) = parse(&text);
+            self.publish_diagnostics(uri, &text, &ast, &errors).await;
         }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri;
         self.documents.write().await.remove(&uri);
+        self.parsers.write().await.remove(&uri);
+        self.include_graph.write().await.remove(&uri);
         self.client.publish_diagnostics(uri, vec![], None).await;
     }
 
@@ -235,6 +517,43 @@ impl LanguageServer for Backend {
             })));
         }
 
+        // fall back to tasks and variables from @include'd files, then from
+        // other open dagfiles of the same project
+        let working_dir = uri
+            .to_file_path()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+        let mut includes = load_includes(working_dir.as_deref(), &ast);
+        prefer_open_documents(&mut includes, &docs);
+        includes.extend(self.sibling_documents(&uri, &docs).await);
+        Ok(
+            find_included_definition(&ast, offset, working_dir.as_deref(), &includes)
+                .map(GotoDefinitionResponse::Scalar),
+        )
+    }
+
+    async fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> Result<Option<GotoTypeDefinitionResponse>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        let offset = position_to_offset(source, pos);
+
+        if let Some(def_span) = find_type_definition_at(source, &ast, offset) {
+            let range = span_to_range(source, def_span);
+            return Ok(Some(GotoTypeDefinitionResponse::Scalar(Location {
+                uri: uri.clone(),
+                range,
+            })));
+        }
+
         Ok(None)
     }
 
@@ -268,8 +587,10 @@ impl LanguageServer for Backend {
 
         let (ast, _) = parse(source);
         let offset = position_to_offset(source, pos);
+        self.evaluate_shell_vars(&uri, &ast).await;
+        let evaluated = self.evaluated_vars.read().await;
 
-        if let Some((content, range)) = get_hover_info(source, &ast, offset) {
+        if let Some((content, range)) = get_hover_info(source, &ast, offset, &evaluated) {
             return Ok(Some(Hover {
                 contents: HoverContents::Markup(MarkupContent {
                     kind: MarkupKind::Markdown,
@@ -325,8 +646,14 @@ impl LanguageServer for Backend {
         let (ast, _) = parse(source);
         let offset = position_to_offset(source, pos);
 
-        // check if cursor is on a renameable symbol
-        if let Some((name, span)) = get_symbol_at(source, &ast, offset) {
+        // check if cursor is on a symbol defined in this file or one it's
+        // connected to through @include
+        let graph = self.include_graph.read().await;
+        if let Some((name, span)) = get_symbol_at(source, &ast, offset)
+            && rename_scope(&docs, &graph, &uri)
+                .iter()
+                .any(|(_, doc)| symbol_role(&parse(doc).0, &name).any())
+        {
             return Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
                 range: span_to_range(source, span),
                 placeholder: name,
@@ -346,21 +673,11 @@ impl LanguageServer for Backend {
             return Ok(None);
         };
 
-        let (ast, _) = parse(source);
         let offset = position_to_offset(source, pos);
 
-        if let Some(refs) = find_all_references(source, &ast, offset, true) {
-            let edits: Vec<TextEdit> = refs
-                .into_iter()
-                .map(|span| TextEdit {
-                    range: span_to_range(source, span),
-                    new_text: new_name.clone(),
-                })
-                .collect();
-
-            let mut changes = HashMap::new();
-            changes.insert(uri, edits);
-
+        // references in files this one includes, or that include it, too
+        let graph = self.include_graph.read().await;
+        if let Some(changes) = workspace_rename_edits(&docs, &graph, &uri, offset, &new_name) {
             return Ok(Some(WorkspaceEdit {
                 changes: Some(changes),
                 ..Default::default()
@@ -387,7 +704,151 @@ impl LanguageServer for Backend {
         Ok(Some(DocumentSymbolResponse::Flat(symbols)))
     }
 
-    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<WorkspaceSymbolResponse>> {
+        let docs = self.documents.read().await;
+
+        let mut symbols = Vec::new();
+        for (uri, source) in docs.iter() {
+            let (ast, _) = parse(source);
+            for symbol in collect_document_symbols(source, &ast) {
+                if !symbol_matches(&symbol.name, &params.query) {
+                    continue;
+                }
+                symbols.push(WorkspaceSymbol {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    tags: None,
+                    container_name: None,
+                    location: OneOf::Left(Location {
+                        uri: uri.clone(),
+                        range: symbol.location.range,
+                    }),
+                    data: None,
+                });
+            }
+        }
+
+        Ok(Some(WorkspaceSymbolResponse::Nested(symbols)))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        let ranges = params
+            .positions
+            .iter()
+            .map(|pos| {
+                let offset = position_to_offset(source, *pos);
+                build_selection_range(source, &selection_spans(source, &ast, offset))
+            })
+            .collect();
+
+        Ok(Some(ranges))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        Ok(get_signature_help(source, pos))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        Ok(Some(collect_code_lenses(source, &ast, &uri)))
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        Ok(Some(collect_folding_ranges(source, &ast)))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        let working_dir = uri
+            .to_file_path()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+        Ok(Some(collect_document_links(
+            source,
+            &ast,
+            working_dir.as_deref(),
+        )))
+    }
+
+    async fn document_color(&self, params: DocumentColorParams) -> Result<Vec<ColorInformation>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(vec![]);
+        };
+
+        let (ast, _) = parse(source);
+        Ok(collect_comment_colors(source, &ast))
+    }
+
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> Result<Vec<ColorPresentation>> {
+        Ok(keyword_presentations(params.color, params.range))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, _) = parse(source);
+        let range = Span::new(
+            position_to_offset(source, params.range.start),
+            position_to_offset(source, params.range.end),
+        );
+        Ok(Some(collect_inlay_hints(source, &ast, range)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
         let uri = params.text_document.uri;
 
         let docs = self.documents.read().await;
@@ -396,27 +857,407 @@ impl LanguageServer for Backend {
         };
 
         let (ast, _) = parse(source);
+        let only = params.context.only.as_deref();
+        let mut actions = Vec::new();
+
+        if code_action_requested(only, &CodeActionKind::QUICKFIX) {
+            let timeout = self.settings.read().await.default_timeout.clone();
+            actions.extend(
+                params
+                    .context
+                    .diagnostics
+                    .iter()
+                    .filter_map(|d| add_timeout_action(source, &ast, &uri, d, &timeout))
+                    .map(CodeActionOrCommand::CodeAction),
+            );
+        }
+
+        if code_action_requested(only, &EXTRACT_VARIABLE_KIND)
+            && let Some(action) = extract_variable_action(source, &ast, &uri, params.range)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        if actions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(actions))
+        }
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<LSPAny>> {
+        if params.command != RUN_TASK_COMMAND {
+            return Err(tower_lsp_server::jsonrpc::Error::method_not_found());
+        }
+
+        let Some(task_name) = params
+            .arguments
+            .first()
+            .and_then(|a| a.as_str())
+            .map(|s| s.to_string())
+        else {
+            return Err(tower_lsp_server::jsonrpc::Error::invalid_params(
+                "expected task name argument",
+            ));
+        };
+        // the dagfile the lens was in; older clients only send the task name
+        let uri = params
+            .arguments
+            .get(1)
+            .and_then(|a| a.as_str())
+            .and_then(|s| s.parse::<Uri>().ok());
+
+        // let an editor extension run it where the user can see it
+        if self.settings.read().await.run_in_client {
+            self.client
+                .send_notification::<RunTaskNotification>(serde_json::json!({
+                    "task": task_name,
+                    "uri": uri.as_ref().map(|u| u.as_str()),
+                }))
+                .await;
+            return Ok(None);
+        }
+
+        // run in the background so the request returns immediately
+        let client = self.client.clone();
+        let dagfile = uri.and_then(|u| u.to_file_path().map(|p| p.into_owned()));
+        tokio::spawn(run_task_command(client, task_name, dagfile));
+
+        Ok(None)
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(source) = docs.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (ast, errors) = parse(source);
         let source_owned = source.clone();
         drop(docs); // release lock before async formatting
 
-        let edits = format_task_bodies(&source_owned, &ast, &params.options).await;
+        // a file that doesn't parse only gets its bodies formatted
+        if !errors.is_empty() {
+            let edits = format_task_bodies(&source_owned, &ast, &params.options).await;
+            return if edits.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(edits))
+            };
+        }
+
+        // canonical layout first, then shfmt/ruff over the bodies it produced
+        let canonical = dagrun_fmt::format(&source_owned, &ast);
+        let (canonical_ast, _) = parse(&canonical);
+        let edits = format_task_bodies(&canonical, &canonical_ast, &params.options).await;
+        let formatted = apply_text_edits(&canonical, &edits);
 
-        if edits.is_empty() {
+        if formatted == source_owned {
             Ok(None)
         } else {
-            Ok(Some(edits))
+            Ok(Some(vec![TextEdit {
+                range: Range::new(
+                    Position::new(0, 0),
+                    offset_to_position(&source_owned, source_owned.len()),
+                ),
+                new_text: formatted,
+            }]))
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-struct RawToken {
-    span: Span,
-    token_type: u32,
-    modifiers: u32,
+// ============================================================================
+// Incremental parsing
+// ============================================================================
+
+/// A document's text and parse, updated by reparsing only the items an edit
+/// touches and reusing (shifted) items after it
+struct IncrementalParser {
+    source: String,
+    file: SourceFile,
+    errors: Vec<ParseError>,
 }
 
-fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
+impl IncrementalParser {
+    fn new(source: String) -> Self {
+        let (file, errors) = parse(&source);
+        Self {
+            source,
+            file,
+            errors,
+        }
+    }
+
+    /// Apply one change and return the new parse, same as `parse()` would
+    fn update(&mut self, change: &TextDocumentContentChangeEvent) -> (SourceFile, Vec<ParseError>) {
+        let Some(range) = change.range else {
+            *self = Self::new(change.text.clone());
+            return (self.file.clone(), self.errors.clone());
+        };
+
+        let len = self.source.len() as u32;
+        let start = position_to_offset(&self.source, range.start).min(len);
+        let end = position_to_offset(&self.source, range.end).clamp(start, len);
+        // a range splitting a character can't be spliced or mapped onto the
+        // old spans, so widen it to whole characters and parse everything
+        let on_boundary = self.source.is_char_boundary(start as usize)
+            && self.source.is_char_boundary(end as usize);
+        let (start, end) = if on_boundary {
+            (start, end)
+        } else {
+            (
+                floor_char_boundary(&self.source, start as usize) as u32,
+                ceil_char_boundary(&self.source, end as usize) as u32,
+            )
+        };
+        self.source
+            .replace_range(start as usize..end as usize, &change.text);
+        let delta = change.text.len() as i64 - (end - start) as i64;
+
+        if !on_boundary || !self.reparse_window(start, end, delta) {
+            let (file, errors) = parse(&self.source);
+            self.file = file;
+            self.errors = errors;
+        }
+        (self.file.clone(), self.errors.clone())
+    }
+
+    /// Reparse from the item before the edit up to the first untouched
+    /// top-level item after it. Returns false if the window didn't parse on
+    /// its own and a full parse is needed.
+    fn reparse_window(&mut self, start: u32, end: u32, delta: i64) -> bool {
+        let items = &self.file.items;
+
+        // back up one item: an edit can pull a line into the item before it.
+        // tasks are emitted after the comments between their annotations and
+        // their name, so take the earliest start rather than the last
+        let first = items
+            .iter()
+            .position(|item| item.span.end >= start)
+            .unwrap_or(items.len());
+        let mut earliest = items[first.saturating_sub(1)..]
+            .iter()
+            .map(|item| item.span.start)
+            .fold(start, u32::min);
+
+        // restart on the line after a kept item, where the parser is back at
+        // the top level: not after a comment or error line since annotations
+        // stay pending across them, nor after an unclosed block since it
+        // consumed past its span
+        let unclosed = |item: &Spanned<Item>| {
+            self.errors.iter().any(|e| {
+                e.kind == ParseErrorKind::UnclosedLuaBlock && e.span.start == item.span.start
+            })
+        };
+        let mut keep = items
+            .iter()
+            .take_while(|item| item.span.end <= earliest)
+            .count();
+        let window_start = loop {
+            while keep > 0
+                && (matches!(items[keep - 1].node, Item::Comment(_) | Item::Error)
+                    || unclosed(&items[keep - 1]))
+            {
+                keep -= 1;
+                earliest = earliest.min(items[keep].span.start);
+            }
+            let Some(prev_end) = items[..keep].iter().map(|item| item.span.end).max() else {
+                break 0;
+            };
+            let line_start = self.source[prev_end as usize..]
+                .find('\n')
+                .map_or(self.source.len(), |i| prev_end as usize + i + 1)
+                as u32;
+            // an annotation after the item on its line would still be pending
+            let rest = &self.source[prev_end as usize..line_start as usize];
+            if line_start <= earliest && !rest.contains('@') {
+                break line_start;
+            }
+            keep -= 1;
+            earliest = earliest.min(items[keep].span.start);
+        };
+
+        // resync on the first run of items past the edit that still starts a
+        // line; again a task can start before comments emitted ahead of it
+        let mut suffix_start = vec![u32::MAX; items.len() + 1];
+        for (i, item) in items.iter().enumerate().rev() {
+            suffix_start[i] = suffix_start[i + 1].min(item.span.start);
+        }
+        let bytes = self.source.as_bytes();
+        let resync = (0..items.len()).find(|&i| {
+            let new_start = (suffix_start[i] as i64 + delta) as usize;
+            suffix_start[i] > end
+                && bytes.get(new_start.wrapping_sub(1)) == Some(&b'\n')
+                && bytes
+                    .get(new_start)
+                    .is_some_and(|b| !b.is_ascii_whitespace())
+        });
+        let old_resync = resync.map_or(u32::MAX, |i| suffix_start[i]);
+        let window_end = resync.map_or(self.source.len() as u32, |_| {
+            (old_resync as i64 + delta) as u32
+        });
+
+        let Some((window_items, window_errors)) =
+            parse_items_from_range(&self.source, window_start, window_end)
+        else {
+            return false;
+        };
+
+        let mut tail = resync.map_or_else(Vec::new, |i| items[i..].to_vec());
+        tail.shift(delta);
+
+        let mut new_items = items[..keep].to_vec();
+        new_items.extend(window_items);
+        new_items.extend(tail);
+
+        let mut errors: Vec<ParseError> = Vec::new();
+        for e in &self.errors {
+            if e.span.end <= window_start {
+                errors.push(e.clone());
+            } else if e.span.start >= old_resync {
+                let mut e = e.clone();
+                e.span.shift(delta);
+                errors.push(e);
+            }
+        }
+        errors.extend(window_errors);
+        errors.sort_by_key(|e| e.span.start);
+
+        self.file = SourceFile { items: new_items };
+        self.errors = errors;
+        true
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// How long a shell-expansion variable must stay unchanged before it is run
+const SHELL_VAR_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How long an evaluated shell variable is shown before it runs again
+const SHELL_VAR_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Shell-expansion variables that don't finish in time show as failed
+const SHELL_VAR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// What a shell-expansion variable evaluated to
+#[derive(Debug, Clone, PartialEq)]
+enum ShellValue {
+    Pending,
+    Output(String),
+    Failed,
+}
+
+/// `sh -c command` in the document's directory, with stdout trimmed the same
+/// way `dr` does when it loads the dagfile
+async fn run_shell_var(command: &str, working_dir: Option<&Path>) -> ShellValue {
+    use std::process::Stdio;
+
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = working_dir {
+        cmd.current_dir(dir);
+    }
+    match tokio::time::timeout(SHELL_VAR_TIMEOUT, cmd.output()).await {
+        Ok(Ok(output)) if output.status.success() => {
+            ShellValue::Output(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => ShellValue::Failed,
+    }
+}
+
+/// The `dr` invocation that runs `task_name`: `dr -c <dagfile> run <task>` from
+/// the dagfile's directory, so relative paths in the dagfile resolve as they do
+/// from a terminal there. Without a dagfile, `dr run <task>` in the server's cwd.
+fn run_task_process(task_name: &str, dagfile: Option<&Path>) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("dr");
+    if let Some(dagfile) = dagfile {
+        command.arg("-c").arg(dagfile);
+        if let Some(dir) = dagfile.parent() {
+            command.current_dir(dir);
+        }
+    }
+    command.arg("run").arg(task_name);
+    command
+}
+
+/// run `dr run <task>` as a separate process, streaming its output to the client log
+async fn run_task_command(client: Client, task_name: String, dagfile: Option<PathBuf>) {
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut child = match run_task_process(&task_name, dagfile.as_deref())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            client
+                .show_message(MessageType::ERROR, format!("failed to start dr: {}", e))
+                .await;
+            return;
+        }
+    };
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let forward_stdout = async {
+        if let Some(out) = stdout {
+            let mut lines = BufReader::new(out).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                client.log_message(MessageType::INFO, line).await;
+            }
+        }
+    };
+    let forward_stderr = async {
+        if let Some(err) = stderr {
+            let mut lines = BufReader::new(err).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                client.log_message(MessageType::WARNING, line).await;
+            }
+        }
+    };
+    tokio::join!(forward_stdout, forward_stderr);
+
+    match child.wait().await {
+        Ok(status) if status.success() => {
+            client
+                .show_message(MessageType::INFO, format!("task '{}' succeeded", task_name))
+                .await;
+        }
+        Ok(status) => {
+            client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("task '{}' failed ({})", task_name, status),
+                )
+                .await;
+        }
+        Err(e) => {
+            client
+                .show_message(MessageType::ERROR, format!("failed to wait on dr: {}", e))
+                .await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RawToken {
+    span: Span,
+    token_type: u32,
+    modifiers: u32,
+}
+
+fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
     let mut tokens = Vec::new();
 
     for item in &ast.items {
@@ -453,7 +1294,7 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                 tokens.push(RawToken {
                     span: task.name.span,
                     token_type: 0, // FUNCTION
-                    modifiers: 1,  // DEFINITION
+                    modifiers: 1,  // DECLARATION (bit 0)
                 });
                 // task parameters
                 for param in &task.parameters {
@@ -462,6 +1303,14 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                         token_type: 7, // PARAMETER
                         modifiers: 1,  // DEFINITION
                     });
+                    // highlight allowed values ({a,b,c})
+                    for value in param.node.allowed_values.iter().flatten() {
+                        tokens.push(RawToken {
+                            span: value.span,
+                            token_type: 5, // STRING
+                            modifiers: 0,
+                        });
+                    }
                     // highlight default value if present
                     if let Some(default) = &param.node.default {
                         match &default.node {
@@ -505,7 +1354,7 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                     tokens.push(RawToken {
                         span: dep.span,
                         token_type: 0, // FUNCTION (reference to another task)
-                        modifiers: 0,
+                        modifiers: 4,  // READONLY (bit 2), a use site
                     });
                 }
                 // body
@@ -583,6 +1432,14 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                     token_type: 0, // FUNCTION (treat context like a task definition)
                     modifiers: 1,  // DEFINITION
                 });
+                // parent context
+                if let Some(parent) = &ctx.extends {
+                    tokens.push(RawToken {
+                        span: parent.span,
+                        token_type: 0, // FUNCTION
+                        modifiers: 0,
+                    });
+                }
                 // annotations within context
                 for ann in &ctx.annotations {
                     tokens.push(RawToken {
@@ -633,6 +1490,19 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                 }
             }
 
+            Item::Include(include) => {
+                tokens.push(RawToken {
+                    span: include.open_span,
+                    token_type: 4, // KEYWORD
+                    modifiers: 0,
+                });
+                tokens.push(RawToken {
+                    span: include.path.span,
+                    token_type: 5, // STRING
+                    modifiers: 0,
+                });
+            }
+
             Item::Comment(_) => {
                 // entire comment line
                 tokens.push(RawToken {
@@ -641,6 +1511,8 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
                     modifiers: 0,
                 });
             }
+
+            Item::Error => {}
         }
     }
 
@@ -651,23 +1523,65 @@ fn collect_semantic_tokens(_source: &str, ast: &SourceFile) -> Vec<RawToken> {
 
 fn collect_annotation_tokens(kind: &AnnotationKind, tokens: &mut Vec<RawToken>) {
     match kind {
-        AnnotationKind::Timeout(val) | AnnotationKind::Retry(val) => {
+        AnnotationKind::Timeout(val)
+        | AnnotationKind::Retry(val)
+        | AnnotationKind::When(val)
+        | AnnotationKind::Cache(val) => {
             tokens.push(RawToken {
                 span: val.span,
                 token_type: 5, // STRING
                 modifiers: 0,
             });
         }
+        AnnotationKind::Matrix { axis, values } => {
+            tokens.push(RawToken {
+                span: axis.span,
+                token_type: 1, // VARIABLE
+                modifiers: 0,
+            });
+            for value in values {
+                tokens.push(RawToken {
+                    span: value.span,
+                    token_type: 5, // STRING
+                    modifiers: 0,
+                });
+            }
+        }
         AnnotationKind::PipeFrom(items) => {
             for item in items {
                 tokens.push(RawToken {
                     span: item.span,
                     token_type: 0, // FUNCTION (task reference)
+                    modifiers: 4,  // READONLY (bit 2), a use site
+                });
+            }
+        }
+        AnnotationKind::OnFailure(handler) => {
+            tokens.push(RawToken {
+                span: handler.span,
+                token_type: 0, // FUNCTION (task reference)
+                modifiers: 4,  // READONLY (bit 2), a use site
+            });
+        }
+        AnnotationKind::Outputs(paths)
+        | AnnotationKind::Inputs(paths)
+        | AnnotationKind::Tags(paths) => {
+            for path in paths {
+                tokens.push(RawToken {
+                    span: path.span,
+                    token_type: 5, // STRING
                     modifiers: 0,
                 });
             }
         }
         AnnotationKind::Ssh(ssh) => {
+            if let Some(host) = &ssh.host {
+                tokens.push(RawToken {
+                    span: host.span,
+                    token_type: 5, // STRING
+                    modifiers: 0,
+                });
+            }
             collect_kv_tokens(&ssh.options, tokens);
         }
         AnnotationKind::Upload(ft)
@@ -689,6 +1603,7 @@ fn collect_annotation_tokens(kind: &AnnotationKind, tokens: &mut Vec<RawToken>)
                 token_type: 5,
                 modifiers: 0,
             });
+            collect_kv_tokens(&ft.options, tokens);
         }
         AnnotationKind::Service(svc) | AnnotationKind::Extern(svc) => {
             collect_kv_tokens(&svc.options, tokens);
@@ -747,24 +1662,38 @@ fn collect_annotation_tokens(kind: &AnnotationKind, tokens: &mut Vec<RawToken>)
                 modifiers: 0,
             });
         }
-        AnnotationKind::Env(env) => {
-            tokens.push(RawToken {
-                span: env.key.span,
-                token_type: 7, // PARAMETER
-                modifiers: 0,
-            });
+        AnnotationKind::Env(vars) | AnnotationKind::Artifact(vars) => {
+            collect_kv_tokens(vars, tokens)
+        }
+        AnnotationKind::Webhook { url, method, body } => {
             tokens.push(RawToken {
-                span: env.eq_span,
-                token_type: 6, // OPERATOR
+                span: url.span,
+                token_type: 5, // STRING
                 modifiers: 0,
             });
+            if !method.span.is_empty() {
+                tokens.push(RawToken {
+                    span: method.span,
+                    token_type: 4, // KEYWORD
+                    modifiers: 0,
+                });
+            }
+            if let Some(body) = body {
+                tokens.push(RawToken {
+                    span: body.span,
+                    token_type: 5,
+                    modifiers: 0,
+                });
+            }
+        }
+        AnnotationKind::Join => {}
+        AnnotationKind::OutputCapture(name) => {
             tokens.push(RawToken {
-                span: env.value.span,
-                token_type: 5, // STRING
+                span: name.span,
+                token_type: 1, // VARIABLE
                 modifiers: 0,
             });
         }
-        AnnotationKind::Join => {}
         AnnotationKind::Use(context_name) => {
             tokens.push(RawToken {
                 span: context_name.span,
@@ -860,40 +1789,134 @@ fn span_to_range(source: &str, span: Span) -> Range {
 fn offset_to_position(source: &str, offset: usize) -> Position {
     let offset = offset.min(source.len());
     let before = &source[..offset];
-    let line = before.chars().filter(|&c| c == '\n').count() as u32;
+    let line = before.matches('\n').count() as u32;
     let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
-    let character = before[line_start..].chars().count() as u32;
+    let character = before[line_start..]
+        .trim_end_matches('\r')
+        .encode_utf16()
+        .count() as u32;
     Position { line, character }
 }
 
+/// Byte offset of an LSP position. Columns are UTF-16 code units, and lines end
+/// at `\n` or `\r\n`; a column past the end of its line lands at the line end.
 fn position_to_offset(source: &str, pos: Position) -> u32 {
-    let mut offset = 0;
-    for (i, line) in source.lines().enumerate() {
+    let mut line_start = 0;
+    for (i, line) in source.split_inclusive('\n').enumerate() {
         if i == pos.line as usize {
-            offset += line
-                .chars()
-                .take(pos.character as usize)
-                .map(|c| c.len_utf8())
-                .sum::<usize>();
-            break;
+            let content = line
+                .strip_suffix('\n')
+                .map_or(line, |l| l.strip_suffix('\r').unwrap_or(l));
+            return (line_start + utf16_to_byte(content, pos.character)) as u32;
+        }
+        line_start += line.len();
+    }
+    source.len() as u32
+}
+
+/// Byte index in `line` of a UTF-16 column, rounded down to a char boundary
+fn utf16_to_byte(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        units += c.len_utf16() as u32;
+        if units > character {
+            return i;
         }
-        offset += line.len() + 1; // +1 for newline
     }
-    offset as u32
+    line.len()
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+fn ceil_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
+
+// ============================================================================
+// Signature help
+// ============================================================================
+
+/// The options of the annotation being typed on the cursor's line, with the
+/// one being filled in (or the next one, by count of `key=` pairs) active
+fn get_signature_help(source: &str, pos: Position) -> Option<SignatureHelp> {
+    use dr_ast::docs;
+
+    let line = source.lines().nth(pos.line as usize).unwrap_or("");
+    let before_cursor = &line[..utf16_to_byte(line, pos.character)];
+
+    let trimmed = before_cursor.trim_start();
+    let after_at = trimmed
+        .strip_prefix('@')
+        .or_else(|| trimmed.strip_prefix("#@"))?;
+    // only once the keyword is complete
+    let (name, args) = after_at.split_once(char::is_whitespace)?;
+    let doc = docs::get_annotation_doc(name)?;
+    if doc.options.is_empty() {
+        return None;
+    }
+
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let typing = words
+        .last()
+        .filter(|_| !args.ends_with(char::is_whitespace));
+    let completed = words.iter().filter(|w| w.contains('=')).count()
+        - typing.is_some_and(|w| w.contains('=')) as usize;
+    let active = typing
+        .and_then(|w| w.split_once('='))
+        .and_then(|(key, _)| {
+            doc.options
+                .iter()
+                .position(|(opt, _)| opt.split('=').next() == Some(key))
+        })
+        .unwrap_or(completed)
+        .min(doc.options.len() - 1) as u32;
+
+    let options: Vec<&str> = doc.options.iter().map(|(opt, _)| *opt).collect();
+    let parameters = doc
+        .options
+        .iter()
+        .map(|(opt, desc)| ParameterInformation {
+            label: ParameterLabel::Simple(opt.to_string()),
+            documentation: Some(Documentation::String(desc.to_string())),
+        })
+        .collect();
+
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label: format!("@{} {}", doc.name, options.join(" ")),
+            documentation: Some(Documentation::String(doc.description.to_string())),
+            parameters: Some(parameters),
+            active_parameter: Some(active),
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(active),
+    })
 }
 
 /// Find the parameters of the task containing the given offset
-fn find_enclosing_task_params<'a>(ast: &'a SourceFile, offset: u32) -> Option<Vec<&'a str>> {
+fn find_enclosing_task_params(ast: &SourceFile, offset: u32) -> Option<Vec<&str>> {
     for item in &ast.items {
-        if let Item::Task(task) = &item.node {
-            if item.span.contains(offset) {
-                return Some(
-                    task.parameters
-                        .iter()
-                        .map(|p| p.node.name.node.as_str())
-                        .collect(),
-                );
+        if let Item::Task(task) = &item.node
+            && item.span.contains(offset)
+        {
+            let mut params: Vec<&str> = task
+                .parameters
+                .iter()
+                .map(|p| p.node.name.node.as_str())
+                .collect();
+            // the matrix axis behaves like a parameter inside the task
+            if let Some((axis, _)) = task.matrix() {
+                params.push(&axis.node);
             }
+            return Some(params);
         }
     }
     None
@@ -905,18 +1928,17 @@ fn find_enclosing_task_params<'a>(ast: &'a SourceFile, offset: u32) -> Option<Ve
 
 fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<CompletionItem> {
     let line = source.lines().nth(pos.line as usize).unwrap_or("");
-    let col = pos.character as usize;
-    let before_cursor = &line[..col.min(line.len())];
+    let before_cursor = &line[..utf16_to_byte(line, pos.character)];
 
     // collect defined names
     let mut variables: Vec<&str> = Vec::new();
-    let mut tasks: Vec<&str> = Vec::new();
+    let mut tasks: Vec<String> = Vec::new();
     let mut contexts: Vec<&str> = Vec::new();
 
     for item in &ast.items {
         match &item.node {
             Item::Variable(var) => variables.push(&var.name.node),
-            Item::Task(task) => tasks.push(&task.name.node),
+            Item::Task(task) => tasks.extend(task.expanded_names()),
             Item::ContextBlock(ctx) => contexts.push(&ctx.name.node),
             _ => {}
         }
@@ -953,11 +1975,9 @@ fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<Complet
 
     // context: after @ - complete annotation keywords or options
     let trimmed = before_cursor.trim_start();
-    if trimmed.starts_with('@') {
+    if let Some(after_at) = trimmed.strip_prefix('@') {
         use dr_ast::docs;
 
-        let after_at = &trimmed[1..];
-
         // if no space yet, complete annotation keywords
         if !after_at.contains(' ') {
             let mut items: Vec<CompletionItem> = docs::ANNOTATION_NAMES
@@ -967,6 +1987,8 @@ fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<Complet
                         label: name.to_string(),
                         kind: Some(CompletionItemKind::KEYWORD),
                         detail: Some(doc.description.to_string()),
+                        insert_text: Some(doc.snippet.to_string()),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
                         ..Default::default()
                     })
                 })
@@ -990,13 +2012,22 @@ fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<Complet
         // after keyword + space, complete options for that annotation
         let keyword = after_at.split_whitespace().next().unwrap_or("");
 
+        // @pipe_from takes task names, skip the ones already listed
+        if keyword == "pipe_from" {
+            let listed: HashSet<&str> = after_at["pipe_from".len()..]
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect();
+            return task_completions(tasks.iter().filter(|t| !listed.contains(t.as_str())));
+        }
+
         // special handling for @use - complete context names
         if keyword == "use" {
             return contexts
                 .iter()
                 .map(|name| CompletionItem {
                     label: name.to_string(),
-                    kind: Some(CompletionItemKind::CLASS),
+                    kind: Some(CompletionItemKind::MODULE),
                     detail: Some("context".to_string()),
                     ..Default::default()
                 })
@@ -1043,20 +2074,75 @@ fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<Complet
     // context: after task_name: - complete task dependencies
     if before_cursor.contains(':') && !before_cursor.contains(":=") {
         // likely in dependency list
-        return tasks
-            .iter()
-            .map(|name| CompletionItem {
-                label: name.to_string(),
-                kind: Some(CompletionItemKind::FUNCTION),
-                detail: Some("task".to_string()),
-                ..Default::default()
-            })
-            .collect();
+        let deps = before_cursor.split_once(':').map_or("", |(_, d)| d);
+        if let Some(items) = dependency_arg_completions(ast, deps) {
+            return items;
+        }
+        return task_completions(tasks.iter());
     }
 
     Vec::new()
 }
 
+fn task_completions<'a>(names: impl Iterator<Item = &'a String>) -> Vec<CompletionItem> {
+    names
+        .map(|name| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            detail: Some("task".to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Complete allowed values for a dependency argument, either `task <value>`
+/// (first parameter) or `task param=<value>`
+fn dependency_arg_completions(ast: &SourceFile, deps: &str) -> Option<Vec<CompletionItem>> {
+    let words: Vec<&str> = deps.split_whitespace().collect();
+    let typing = !deps.ends_with(char::is_whitespace);
+    let (current, previous) = if typing {
+        let (last, rest) = words.split_last()?;
+        (*last, rest)
+    } else {
+        ("", words.as_slice())
+    };
+
+    let find_task = |name: &str| {
+        ast.items.iter().find_map(|item| match &item.node {
+            Item::Task(task) if task.name.node == name => Some(task),
+            _ => None,
+        })
+    };
+    // the dependency these args belong to is the nearest preceding task name
+    let task = previous.iter().rev().find_map(|w| find_task(w))?;
+
+    let (param, prefix) = match current.split_once('=') {
+        Some((key, _)) => (
+            task.parameters.iter().find(|p| p.node.name.node == key)?,
+            format!("{}=", key),
+        ),
+        None if previous.last().is_some_and(|w| find_task(w).is_some()) => {
+            (task.parameters.first()?, String::new())
+        }
+        None => return None,
+    };
+
+    let values = param.node.allowed_values.as_ref()?;
+    Some(
+        values
+            .iter()
+            .map(|v| CompletionItem {
+                label: v.node.clone(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                detail: Some(format!("{} value", param.node.name.node)),
+                insert_text: Some(format!("{}{}", prefix, v.node)),
+                filter_text: Some(format!("{}{}", prefix, v.node)),
+                ..Default::default()
+            })
+            .collect(),
+    )
+}
+
 // ============================================================================
 // Go-to-definition
 // ============================================================================
@@ -1064,7 +2150,7 @@ fn get_completions(source: &str, ast: &SourceFile, pos: Position) -> Vec<Complet
 fn find_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Span> {
     // collect all definitions
     let mut var_defs: HashMap<&str, Span> = HashMap::new();
-    let mut task_defs: HashMap<&str, Span> = HashMap::new();
+    let mut task_defs: HashMap<String, Span> = HashMap::new();
 
     for item in &ast.items {
         match &item.node {
@@ -1072,7 +2158,9 @@ fn find_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Spa
                 var_defs.insert(&var.name.node, var.name.span);
             }
             Item::Task(task) => {
-                task_defs.insert(&task.name.node, task.name.span);
+                for name in task.expanded_names() {
+                    task_defs.insert(name, task.name.span);
+                }
             }
             _ => {}
         }
@@ -1082,11 +2170,24 @@ fn find_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Spa
     for item in &ast.items {
         if let Item::Task(task) = &item.node {
             // collect task-scoped parameter definitions
-            let param_defs: HashMap<&str, Span> = task
+            let mut param_defs: HashMap<&str, Span> = task
                 .parameters
                 .iter()
                 .map(|p| (p.node.name.node.as_str(), p.node.name.span))
                 .collect();
+            if let Some((axis, _)) = task.matrix() {
+                param_defs.insert(axis.node.as_str(), axis.span);
+            }
+
+            // check variable references in parameter defaults
+            for param in &task.parameters {
+                if let Some(default) = &param.node.default
+                    && let ParameterDefault::Variable(interp) = &default.node
+                    && interp.name.span.contains(offset)
+                {
+                    return var_defs.get(interp.name.node.as_str()).copied();
+                }
+            }
 
             // check dependencies
             for dep in &task.dependencies {
@@ -1104,16 +2205,16 @@ fn find_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Spa
                 for line in &body.lines {
                     if let BodyLine::Command(cmd) = &line.node {
                         for seg in &cmd.segments {
-                            if let CommandSegment::Interpolation(interp) = &seg.node {
-                                if interp.name.span.contains(offset) {
-                                    // check parameters first, then global variables
-                                    if let Some(span) =
-                                        param_defs.get(interp.name.node.as_str()).copied()
-                                    {
-                                        return Some(span);
-                                    }
-                                    return var_defs.get(interp.name.node.as_str()).copied();
+                            if let CommandSegment::Interpolation(interp) = &seg.node
+                                && interp.name.span.contains(offset)
+                            {
+                                // check parameters first, then global variables
+                                if let Some(span) =
+                                    param_defs.get(interp.name.node.as_str()).copied()
+                                {
+                                    return Some(span);
                                 }
+                                return var_defs.get(interp.name.node.as_str()).copied();
                             }
                         }
                     }
@@ -1134,6 +2235,26 @@ fn find_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Spa
     None
 }
 
+/// Type definition only applies to `{{var}}` parameter defaults, which resolve
+/// to the variable that supplies the value
+fn find_type_definition_at(source: &str, ast: &SourceFile, offset: u32) -> Option<Span> {
+    let on_default_var = ast.items.iter().any(|item| match &item.node {
+        Item::Task(task) => task.parameters.iter().any(|p| {
+            matches!(
+                p.node.default.as_ref().map(|d| &d.node),
+                Some(ParameterDefault::Variable(interp)) if interp.name.span.contains(offset)
+            )
+        }),
+        _ => false,
+    });
+
+    if on_default_var {
+        find_definition_at(source, ast, offset)
+    } else {
+        None
+    }
+}
+
 fn find_var_in_annotation(kind: &AnnotationKind, offset: u32, _source: &str) -> Option<Span> {
     let check_value = |val: &Spanned<String>| -> Option<Span> {
         for var_ref in find_interpolations(&val.node, val.span) {
@@ -1145,16 +2266,16 @@ fn find_var_in_annotation(kind: &AnnotationKind, offset: u32, _source: &str) ->
     };
 
     let check_kv_list = |opts: &[Spanned<KeyValue>]| -> Option<Span> {
-        for kv in opts {
-            if let Some(span) = check_value(&kv.node.value) {
-                return Some(span);
-            }
-        }
-        None
+        opts.iter()
+            .find_map(|kv| check_value(&kv.node.key).or_else(|| check_value(&kv.node.value)))
     };
 
     match kind {
-        AnnotationKind::Ssh(ssh) => check_kv_list(&ssh.options),
+        AnnotationKind::Ssh(ssh) => ssh
+            .host
+            .as_ref()
+            .and_then(check_value)
+            .or_else(|| check_kv_list(&ssh.options)),
         AnnotationKind::K8s(k8s) => check_kv_list(&k8s.options),
         AnnotationKind::Upload(ft)
         | AnnotationKind::Download(ft)
@@ -1163,76 +2284,381 @@ fn find_var_in_annotation(kind: &AnnotationKind, offset: u32, _source: &str) ->
             check_value(&ft.local).or_else(|| check_value(&ft.remote))
         }
         AnnotationKind::Service(svc) | AnnotationKind::Extern(svc) => check_kv_list(&svc.options),
+        AnnotationKind::Env(vars) | AnnotationKind::Artifact(vars) => check_kv_list(vars),
         _ => None,
     }
 }
 
 // ============================================================================
-// Semantic diagnostics: undefined variable checking
+// Workspace roots
 // ============================================================================
 
-fn check_undefined_variables(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
-    let mut diagnostics = Vec::new();
+/// Config file names `dr` looks for, each marks the root of a project
+const DAGFILE_NAMES: &[&str] = &["dagfile", "dagfile.dr", "dagfile.lua", ".dagrun"];
 
-    // collect all defined variable names
-    let defined: HashSet<&str> = ast
-        .items
-        .iter()
-        .filter_map(|item| match &item.node {
-            Item::Variable(var) => Some(var.name.node.as_str()),
-            _ => None,
-        })
-        .collect();
+/// Groups open documents into projects, so that in a monorepo with several
+/// dagfiles tasks only resolve against files of the same project
+#[derive(Debug, Default)]
+struct WorkspaceManager {
+    /// `workspaceFolders` from the client
+    roots: Vec<PathBuf>,
+}
 
-    // check all variable references
-    for item in &ast.items {
-        match &item.node {
-            Item::Task(task) => {
-                // collect task-scoped parameters
-                let params: HashSet<&str> = task
-                    .parameters
-                    .iter()
-                    .map(|p| p.node.name.node.as_str())
-                    .collect();
+impl WorkspaceManager {
+    fn add_root(&mut self, uri: &Uri) {
+        if let Some(path) = uri.to_file_path().map(|p| p.to_path_buf())
+            && !self.roots.contains(&path)
+        {
+            self.roots.push(path);
+        }
+    }
 
-                // check annotations
-                for ann in &task.annotations {
-                    check_annotation_vars(source, &ann.node.kind, &defined, &mut diagnostics);
-                }
+    fn remove_root(&mut self, uri: &Uri) {
+        if let Some(path) = uri.to_file_path().map(|p| p.to_path_buf()) {
+            self.roots.retain(|root| *root != path);
+        }
+    }
 
-                // check command body interpolations
-                if let Some(body) = &task.body {
-                    for line in &body.lines {
-                        if let BodyLine::Command(cmd) = &line.node {
-                            for seg in &cmd.segments {
-                                if let CommandSegment::Interpolation(interp) = &seg.node {
-                                    let name = &interp.name.node;
-                                    // check both global variables and task parameters
-                                    if !name.is_empty()
-                                        && !defined.contains(name.as_str())
-                                        && !params.contains(name.as_str())
-                                    {
-                                        diagnostics.push(Diagnostic {
-                                            range: span_to_range(source, interp.name.span),
-                                            severity: Some(DiagnosticSeverity::ERROR),
-                                            source: Some("dr".to_string()),
-                                            message: format!("undefined variable '{}'", name),
-                                            ..Default::default()
-                                        });
-                                    }
-                                }
-                            }
+    /// The project a file belongs to: the nearest directory holding a dagfile,
+    /// looking no higher than the workspace folder that contains it
+    fn project_of(&self, path: &Path) -> Option<PathBuf> {
+        let dir = path.parent()?;
+        let root = self
+            .roots
+            .iter()
+            .filter(|root| dir.starts_with(root))
+            .max_by_key(|root| root.components().count());
+        for ancestor in dir.ancestors() {
+            if DAGFILE_NAMES
+                .iter()
+                .any(|name| ancestor.join(name).is_file())
+            {
+                return Some(ancestor.to_path_buf());
+            }
+            if root.is_some_and(|root| root == ancestor) {
+                break;
+            }
+        }
+        Some(root.map_or(dir, PathBuf::as_path).to_path_buf())
+    }
+
+    /// Other open documents in the same project as `uri`
+    fn siblings<'a>(
+        &self,
+        uri: &Uri,
+        documents: &'a HashMap<Uri, String>,
+    ) -> Vec<(&'a Uri, &'a String)> {
+        let Some(project) = uri.to_file_path().and_then(|p| self.project_of(&p)) else {
+            return Vec::new();
+        };
+        documents
+            .iter()
+            .filter(|(other, _)| *other != uri)
+            .filter(|(other, _)| {
+                other
+                    .to_file_path()
+                    .and_then(|p| self.project_of(&p))
+                    .is_some_and(|p| p == project)
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// Includes
+// ============================================================================
+
+/// A file pulled in with `@include` and read from disk, or an open document
+/// of the same project
+struct IncludedFile {
+    uri: Uri,
+    source: String,
+    ast: SourceFile,
+}
+
+/// Every file reachable through `@include` from `ast`, each loaded once.
+/// Missing files are skipped here and reported by [`check_include_paths`].
+fn load_includes(base_dir: Option<&Path>, ast: &SourceFile) -> Vec<IncludedFile> {
+    fn walk(
+        base_dir: &Path,
+        ast: &SourceFile,
+        seen: &mut HashSet<PathBuf>,
+        out: &mut Vec<IncludedFile>,
+    ) {
+        for item in &ast.items {
+            let Item::Include(include) = &item.node else {
+                continue;
+            };
+            let Ok(path) = base_dir.join(&include.path.node).canonicalize() else {
+                continue;
+            };
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let (Ok(source), Some(uri)) =
+                (std::fs::read_to_string(&path), Uri::from_file_path(&path))
+            else {
+                continue;
+            };
+            let (included, _) = parse(&source);
+            if let Some(dir) = path.parent() {
+                walk(dir, &included, seen, out);
+            }
+            out.push(IncludedFile {
+                uri,
+                source,
+                ast: included,
+            });
+        }
+    }
+
+    let Some(base_dir) = base_dir else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    walk(base_dir, ast, &mut HashSet::new(), &mut out);
+    out
+}
+
+fn check_include_paths(source: &str, ast: &SourceFile, base_dir: Option<&Path>) -> Vec<Diagnostic> {
+    let Some(base_dir) = base_dir else {
+        return Vec::new();
+    };
+    ast.items
+        .iter()
+        .filter_map(|item| match &item.node {
+            Item::Include(include) if !base_dir.join(&include.path.node).is_file() => {
+                Some(Diagnostic {
+                    range: span_to_range(source, include.path.span),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("dr".to_string()),
+                    message: format!("included file not found: {}", include.path.node),
+                    ..Default::default()
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// `@include` paths as links to the files they name; missing files are left to
+/// the diagnostic from [`check_include_paths`]
+fn collect_document_links(
+    source: &str,
+    ast: &SourceFile,
+    base_dir: Option<&Path>,
+) -> Vec<DocumentLink> {
+    let Some(base_dir) = base_dir else {
+        return Vec::new();
+    };
+    ast.items
+        .iter()
+        .filter_map(|item| {
+            let Item::Include(include) = &item.node else {
+                return None;
+            };
+            let path = base_dir.join(&include.path.node).canonicalize().ok()?;
+            Some(DocumentLink {
+                range: span_to_range(source, include.path.span),
+                target: Some(Uri::from_file_path(&path)?),
+                tooltip: Some(path.display().to_string()),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Open documents that include any of the `changed` files
+fn dependents_of(include_graph: &HashMap<Uri, Vec<Uri>>, changed: &[Uri]) -> Vec<Uri> {
+    include_graph
+        .iter()
+        .filter(|(_, includes)| includes.iter().any(|inc| changed.contains(inc)))
+        .map(|(uri, _)| uri.clone())
+        .collect()
+}
+
+/// Parse errors of each included file that isn't open; an open one gets its
+/// diagnostics as a document of its own
+fn included_parse_diagnostics(
+    includes: &[IncludedFile],
+    documents: &HashMap<Uri, String>,
+) -> Vec<(Uri, Vec<Diagnostic>)> {
+    includes
+        .iter()
+        .filter(|inc| !documents.contains_key(&inc.uri))
+        .map(|inc| {
+            let (_, errors) = parse(&inc.source);
+            let diagnostics = errors
+                .iter()
+                .map(|e| to_diagnostic(&inc.source, e))
+                .collect();
+            (inc.uri.clone(), diagnostics)
+        })
+        .collect()
+}
+
+/// Use the editor's text for included files that are open, so unsaved edits
+/// are seen instead of what is on disk
+fn prefer_open_documents(includes: &mut [IncludedFile], documents: &HashMap<Uri, String>) {
+    for inc in includes {
+        if let Some(text) = documents.get(&inc.uri) {
+            inc.ast = parse(text).0;
+            inc.source = text.clone();
+        }
+    }
+}
+
+/// Definition of the task or variable under the cursor in an included file,
+/// or the start of the included file when the cursor is on an `@include` path
+fn find_included_definition(
+    ast: &SourceFile,
+    offset: u32,
+    base_dir: Option<&Path>,
+    includes: &[IncludedFile],
+) -> Option<Location> {
+    for item in &ast.items {
+        if let Item::Include(include) = &item.node
+            && span_contains(include.path.span, offset)
+        {
+            let path = base_dir?.join(&include.path.node).canonicalize().ok()?;
+            return Some(Location {
+                uri: Uri::from_file_path(path)?,
+                range: Range::default(),
+            });
+        }
+    }
+
+    let (name, _) = get_symbol_at("", ast, offset)?;
+    includes.iter().find_map(|inc| {
+        let span = inc.ast.items.iter().find_map(|item| match &item.node {
+            Item::Task(task) if task.name.node == name => Some(task.name.span),
+            Item::Variable(var) if var.name.node == name => Some(var.name.span),
+            _ => None,
+        })?;
+        Some(Location {
+            uri: inc.uri.clone(),
+            range: span_to_range(&inc.source, span),
+        })
+    })
+}
+
+// ============================================================================
+// Semantic diagnostics: undefined variable checking
+// ============================================================================
+
+fn check_undefined_variables(
+    source: &str,
+    ast: &SourceFile,
+    includes: &[IncludedFile],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // collect all defined variable names, including ones set at runtime by @output-capture
+    let defined: HashSet<&str> = ast
+        .items
+        .iter()
+        .chain(includes.iter().flat_map(|inc| &inc.ast.items))
+        .flat_map(|item| match &item.node {
+            Item::Variable(var) => vec![var.name.node.as_str()],
+            Item::Task(task) => task
+                .annotations
+                .iter()
+                .filter_map(|ann| match &ann.node.kind {
+                    AnnotationKind::OutputCapture(name) => Some(name.node.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        })
+        .collect();
+
+    // check all variable references
+    for item in &ast.items {
+        if let Item::Task(task) = &item.node {
+            // collect task-scoped parameters
+            let mut params: HashSet<&str> = task
+                .parameters
+                .iter()
+                .map(|p| p.node.name.node.as_str())
+                .collect();
+
+            // the matrix axis is also visible to the task's other annotations
+            let mut scoped = defined.clone();
+            if let Some((axis, _)) = task.matrix() {
+                params.insert(axis.node.as_str());
+                scoped.insert(axis.node.as_str());
+            }
+
+            // check annotations
+            for ann in &task.annotations {
+                check_annotation_vars(source, &ann.node.kind, &scoped, &mut diagnostics);
+            }
+
+            // check command body interpolations
+            if let Some(body) = &task.body {
+                for line in &body.lines {
+                    if let BodyLine::Command(cmd) = &line.node {
+                        for seg in &cmd.segments {
+                            if let CommandSegment::Interpolation(interp) = &seg.node {
+                                let name = &interp.name.node;
+                                // check both global variables and task parameters;
+                                // artifact paths are only known once their task has run
+                                if !name.is_empty()
+                                    && !name.starts_with("artifact.")
+                                    && !defined.contains(name.as_str())
+                                    && !params.contains(name.as_str())
+                                {
+                                    diagnostics.push(Diagnostic {
+                                        range: span_to_range(source, interp.name.span),
+                                        severity: Some(DiagnosticSeverity::ERROR),
+                                        source: Some("dr".to_string()),
+                                        message: format!("undefined variable '{}'", name),
+                                        ..Default::default()
+                                    });
+                                }
+                            }
                         }
                     }
                 }
             }
-            _ => {}
         }
     }
 
     diagnostics
 }
 
+/// Literal parameter defaults that don't parse as the declared `name:type`
+fn check_parameter_types(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for item in &ast.items {
+        let Item::Task(task) = &item.node else {
+            continue;
+        };
+        for param in &task.parameters {
+            let (Some(ty), Some(default)) = (param.node.param_type, &param.node.default) else {
+                continue;
+            };
+            if let ParameterDefault::Literal(value) = &default.node
+                && !ty.accepts(value)
+            {
+                diagnostics.push(Diagnostic {
+                    range: span_to_range(source, default.span),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("dr".to_string()),
+                    message: format!(
+                        "default '{}' for parameter '{}' is not a valid {}",
+                        value,
+                        param.node.name.node,
+                        ty.name()
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
 fn check_annotation_vars(
     source: &str,
     kind: &AnnotationKind,
@@ -1256,12 +2682,16 @@ fn check_annotation_vars(
 
     let check_kv_list = |opts: &[Spanned<KeyValue>], diagnostics: &mut Vec<Diagnostic>| {
         for kv in opts {
+            check_value(&kv.node.key, diagnostics);
             check_value(&kv.node.value, diagnostics);
         }
     };
 
     match kind {
         AnnotationKind::Ssh(ssh) => {
+            if let Some(host) = &ssh.host {
+                check_value(host, diagnostics);
+            }
             check_kv_list(&ssh.options, diagnostics);
         }
         AnnotationKind::K8s(k8s) => {
@@ -1286,6 +2716,12 @@ fn check_annotation_vars(
             check_value(&pf.resource, diagnostics);
             check_value(&pf.remote_port, diagnostics);
         }
+        AnnotationKind::Env(vars) | AnnotationKind::Artifact(vars) => {
+            check_kv_list(vars, diagnostics);
+        }
+        AnnotationKind::When(condition) | AnnotationKind::Cache(condition) => {
+            check_value(condition, diagnostics);
+        }
         _ => {}
     }
 }
@@ -1330,16 +2766,21 @@ fn find_interpolations(text: &str, base_span: Span) -> Vec<VarRef> {
 // Undefined task checking
 // ============================================================================
 
-fn check_undefined_tasks(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+fn check_undefined_tasks(
+    source: &str,
+    ast: &SourceFile,
+    includes: &[IncludedFile],
+) -> Vec<Diagnostic> {
     let mut diagnostics = Vec::new();
 
-    // collect all defined task names
-    let defined_tasks: HashSet<&str> = ast
+    // collect all defined task names, including those from @include'd files
+    let defined_tasks: HashSet<String> = ast
         .items
         .iter()
-        .filter_map(|item| match &item.node {
-            Item::Task(task) => Some(task.name.node.as_str()),
-            _ => None,
+        .chain(includes.iter().flat_map(|inc| &inc.ast.items))
+        .flat_map(|item| match &item.node {
+            Item::Task(task) => task.expanded_names(),
+            _ => vec![],
         })
         .collect();
 
@@ -1369,6 +2810,50 @@ fn check_undefined_tasks(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
     diagnostics
 }
 
+/// Later definitions of a task name silently replace earlier ones, so flag every
+/// one after the first and link back to it
+fn check_duplicate_tasks(uri: &Uri, source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    let mut definitions: HashMap<String, Vec<Span>> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+    for item in &ast.items {
+        if let Item::Task(task) = &item.node {
+            for name in task.expanded_names() {
+                let spans = definitions.entry(name.clone()).or_default();
+                if spans.is_empty() {
+                    order.push(name);
+                }
+                spans.push(task.name.span);
+            }
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for name in &order {
+        let spans = &definitions[name];
+        let Some((first, duplicates)) = spans.split_first() else {
+            continue;
+        };
+        for span in duplicates {
+            diagnostics.push(Diagnostic {
+                range: span_to_range(source, *span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("dr".to_string()),
+                message: format!("task '{}' is already defined", name),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: span_to_range(source, *first),
+                    },
+                    message: "first defined here".to_string(),
+                }]),
+                ..Default::default()
+            });
+        }
+    }
+
+    diagnostics
+}
+
 // ============================================================================
 // Context validation
 // ============================================================================
@@ -1386,20 +2871,36 @@ fn check_undefined_contexts(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
         })
         .collect();
 
+    // check `extends` parents
+    for item in &ast.items {
+        if let Item::ContextBlock(ctx) = &item.node
+            && let Some(parent) = &ctx.extends
+            && !defined_contexts.contains(parent.node.as_str())
+        {
+            diagnostics.push(Diagnostic {
+                range: span_to_range(source, parent.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("dr".to_string()),
+                message: format!("undefined context '{}'", parent.node),
+                ..Default::default()
+            });
+        }
+    }
+
     // check all @use annotations
     for item in &ast.items {
         if let Item::Task(task) = &item.node {
             for ann in &task.annotations {
-                if let AnnotationKind::Use(ctx_name) = &ann.node.kind {
-                    if !defined_contexts.contains(ctx_name.node.as_str()) {
-                        diagnostics.push(Diagnostic {
-                            range: span_to_range(source, ctx_name.span),
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            source: Some("dr".to_string()),
-                            message: format!("undefined context '{}'", ctx_name.node),
-                            ..Default::default()
-                        });
-                    }
+                if let AnnotationKind::Use(ctx_name) = &ann.node.kind
+                    && !defined_contexts.contains(ctx_name.node.as_str())
+                {
+                    diagnostics.push(Diagnostic {
+                        range: span_to_range(source, ctx_name.span),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        source: Some("dr".to_string()),
+                        message: format!("undefined context '{}'", ctx_name.node),
+                        ..Default::default()
+                    });
                 }
             }
         }
@@ -1434,6 +2935,15 @@ fn check_unused_contexts(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
         }
     }
 
+    // a context extended by another counts as used
+    for item in &ast.items {
+        if let Item::ContextBlock(ctx) = &item.node
+            && let Some(parent) = &ctx.extends
+        {
+            used_contexts.insert(&parent.node);
+        }
+    }
+
     // report unused contexts (except "default" which is always considered used)
     for (name, span) in &defined_contexts {
         if *name != "default" && !used_contexts.contains(*name) {
@@ -1450,6 +2960,122 @@ fn check_unused_contexts(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
     diagnostics
 }
 
+fn check_k8s_prune(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let annotations = ast.items.iter().flat_map(|item| match &item.node {
+        Item::Task(task) => task.annotations.as_slice(),
+        Item::ContextBlock(ctx) => ctx.annotations.as_slice(),
+        _ => &[],
+    });
+
+    // prune=true only makes sense relative to a manifest directory
+    for ann in annotations {
+        if let AnnotationKind::K8s(k8s) = &ann.node.kind
+            && let Some(prune) = k8s
+                .options
+                .iter()
+                .find(|kv| kv.node.key.node == "prune" && kv.node.value.node == "true")
+            && !k8s.options.iter().any(|kv| kv.node.key.node == "path")
+        {
+            diagnostics.push(Diagnostic {
+                range: span_to_range(source, prune.span),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("dr".to_string()),
+                message: "prune=true has no effect without path=".to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Annotations before anything but a task are parse errors, but a blank line
+/// between an annotation and its task is accepted silently. It usually means
+/// the annotation was left behind when tasks were moved around
+fn check_orphaned_annotations(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for item in &ast.items {
+        let Item::Task(task) = &item.node else {
+            continue;
+        };
+        let next_starts = task
+            .annotations
+            .iter()
+            .skip(1)
+            .map(|ann| ann.span.start)
+            .chain([task.name.span.start]);
+        for (ann, next) in task.annotations.iter().zip(next_starts) {
+            let gap = source
+                .get(ann.span.end as usize..next as usize)
+                .unwrap_or("");
+            // lines strictly between the two, comments don't count as blank
+            let lines: Vec<&str> = gap.split('\n').collect();
+            let blank = lines.len() > 2
+                && lines[1..lines.len() - 1]
+                    .iter()
+                    .any(|line| line.trim().is_empty());
+            if blank {
+                diagnostics.push(Diagnostic {
+                    range: span_to_range(source, ann.span),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    source: Some("dr".to_string()),
+                    message: format!(
+                        "annotation is separated from task '{}' by a blank line",
+                        task.name.node
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+/// `@when` conditions only run at execution time, so flag them as unchecked
+fn check_when_conditions(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    let annotations = ast.items.iter().flat_map(|item| match &item.node {
+        Item::Task(task) => task.annotations.as_slice(),
+        Item::ContextBlock(ctx) => ctx.annotations.as_slice(),
+        _ => &[],
+    });
+
+    annotations
+        .filter_map(|ann| match &ann.node.kind {
+            AnnotationKind::When(condition) => Some(Diagnostic {
+                range: span_to_range(source, condition.span),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                source: Some("dr".to_string()),
+                message: "condition is evaluated at runtime and cannot be checked statically"
+                    .to_string(),
+                ..Default::default()
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_lints(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
+    dr_ast::validate::lint(ast)
+        .into_iter()
+        .map(|lint| Diagnostic {
+            range: span_to_range(source, lint.span),
+            severity: Some(match lint.level {
+                LintLevel::Warning => DiagnosticSeverity::WARNING,
+                LintLevel::Hint => DiagnosticSeverity::HINT,
+            }),
+            code: Some(NumberOrString::String(lint.code.as_str().to_string())),
+            source: Some("dr".to_string()),
+            message: lint.message,
+            // the task name, so the quick fix can find it again
+            data: (lint.code == LintCode::MissingTimeout)
+                .then(|| serde_json::json!({ "task": lint.span.text(source) })),
+            ..Default::default()
+        })
+        .collect()
+}
+
 // ============================================================================
 // Dependency cycle detection
 // ============================================================================
@@ -1610,13 +3236,13 @@ fn check_filesystem(source: &str, ast: &SourceFile, working_dir: Option<&Path>)
 
     // detect shell env var assignments like VAR=value or VAR="value"
     let is_env_var_assignment = |word: &str| -> bool {
-        if let Some(eq_idx) = word.find('=') {
-            if eq_idx > 0 {
-                let var_name = &word[..eq_idx];
-                return var_name
-                    .chars()
-                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
-            }
+        if let Some(eq_idx) = word.find('=')
+            && eq_idx > 0
+        {
+            let var_name = &word[..eq_idx];
+            return var_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
         }
         false
     };
@@ -1688,37 +3314,31 @@ fn check_filesystem(source: &str, ast: &SourceFile, working_dir: Option<&Path>)
                 if is_shell_body {
                     // check first word of commands for executables
                     for line in &body.lines {
-                        if let BodyLine::Command(cmd) = &line.node {
-                            if let Some(first_seg) = cmd.segments.first() {
-                                if let CommandSegment::Text(text) = &first_seg.node {
-                                    // skip env var assignments (VAR=value) to find actual command
-                                    let first_word = text
-                                        .split_whitespace()
-                                        .find(|word| !is_env_var_assignment(word))
-                                        .unwrap_or("");
-                                    if !first_word.is_empty()
-                                        && !first_word.contains('/')
-                                        && !first_word.contains("{{")
-                                        && !first_word.starts_with('$')
-                                        && !first_word.starts_with('-')
-                                    {
-                                        if checked_executables.insert(first_word.to_string())
-                                            && !is_builtin(first_word)
-                                            && which::which(first_word).is_err()
-                                        {
-                                            diagnostics.push(Diagnostic {
-                                                range: span_to_range(source, first_seg.span),
-                                                severity: Some(DiagnosticSeverity::WARNING),
-                                                source: Some("dr".to_string()),
-                                                message: format!(
-                                                    "command not found in PATH: {}",
-                                                    first_word
-                                                ),
-                                                ..Default::default()
-                                            });
-                                        }
-                                    }
-                                }
+                        if let BodyLine::Command(cmd) = &line.node
+                            && let Some(first_seg) = cmd.segments.first()
+                            && let CommandSegment::Text(text) = &first_seg.node
+                        {
+                            // skip env var assignments (VAR=value) to find actual command
+                            let first_word = text
+                                .split_whitespace()
+                                .find(|word| !is_env_var_assignment(word))
+                                .unwrap_or("");
+                            if !first_word.is_empty()
+                                && !first_word.contains('/')
+                                && !first_word.contains("{{")
+                                && !first_word.starts_with('$')
+                                && !first_word.starts_with('-')
+                                && checked_executables.insert(first_word.to_string())
+                                && !is_builtin(first_word)
+                                && which::which(first_word).is_err()
+                            {
+                                diagnostics.push(Diagnostic {
+                                    range: span_to_range(source, first_seg.span),
+                                    severity: Some(DiagnosticSeverity::WARNING),
+                                    source: Some("dr".to_string()),
+                                    message: format!("command not found in PATH: {}", first_word),
+                                    ..Default::default()
+                                });
                             }
                         }
                     }
@@ -1789,16 +3409,23 @@ fn check_unused_variables(source: &str, ast: &SourceFile) -> Vec<Diagnostic> {
 fn collect_used_vars_in_annotation<'a>(kind: &'a AnnotationKind, used: &mut HashSet<&'a str>) {
     match kind {
         AnnotationKind::Ssh(ssh) => {
+            if let Some(var) = ssh.host.as_ref().and_then(|h| extract_var(&h.node)) {
+                used.insert(var);
+            }
             for kv in &ssh.options {
-                if let Some(var) = extract_var(&kv.node.value.node) {
-                    used.insert(var);
+                for part in [&kv.node.key, &kv.node.value] {
+                    if let Some(var) = extract_var(&part.node) {
+                        used.insert(var);
+                    }
                 }
             }
         }
         AnnotationKind::K8s(k8s) => {
             for kv in &k8s.options {
-                if let Some(var) = extract_var(&kv.node.value.node) {
-                    used.insert(var);
+                for part in [&kv.node.key, &kv.node.value] {
+                    if let Some(var) = extract_var(&part.node) {
+                        used.insert(var);
+                    }
                 }
             }
         }
@@ -1818,23 +3445,34 @@ fn collect_used_vars_in_annotation<'a>(kind: &'a AnnotationKind, used: &mut Hash
                 used.insert(var);
             }
         }
-        AnnotationKind::Timeout(t) => {
+        AnnotationKind::Timeout(t) | AnnotationKind::When(t) | AnnotationKind::Cache(t) => {
             if let Some(var) = extract_var(&t.node) {
                 used.insert(var);
             }
         }
+        AnnotationKind::Env(vars) | AnnotationKind::Artifact(vars) => {
+            for kv in vars {
+                for part in [&kv.node.key, &kv.node.value] {
+                    if let Some(var) = extract_var(&part.node) {
+                        used.insert(var);
+                    }
+                }
+            }
+        }
         AnnotationKind::Service(s) | AnnotationKind::Extern(s) => {
             for kv in &s.options {
-                if let Some(var) = extract_var(&kv.node.value.node) {
-                    used.insert(var);
+                for part in [&kv.node.key, &kv.node.value] {
+                    if let Some(var) = extract_var(&part.node) {
+                        used.insert(var);
+                    }
                 }
             }
         }
         AnnotationKind::Unknown { rest, .. } => {
-            if let Some(rest) = rest {
-                if let Some(var) = extract_var(&rest.node) {
-                    used.insert(var);
-                }
+            if let Some(rest) = rest
+                && let Some(var) = extract_var(&rest.node)
+            {
+                used.insert(var);
             }
         }
         _ => {}
@@ -1842,10 +3480,10 @@ fn collect_used_vars_in_annotation<'a>(kind: &'a AnnotationKind, used: &mut Hash
 }
 
 fn extract_var(s: &str) -> Option<&str> {
-    if let Some(start) = s.find("{{") {
-        if let Some(end) = s[start..].find("}}") {
-            return Some(s[start + 2..start + end].trim());
-        }
+    if let Some(start) = s.find("{{")
+        && let Some(end) = s[start..].find("}}")
+    {
+        return Some(s[start + 2..start + end].trim());
     }
     None
 }
@@ -1854,35 +3492,45 @@ fn extract_var(s: &str) -> Option<&str> {
 // Hover documentation
 // ============================================================================
 
-fn get_hover_info(source: &str, ast: &SourceFile, offset: u32) -> Option<(String, Range)> {
-    for item in &ast.items {
-        if let Item::Task(task) = &item.node {
+fn get_hover_info(
+    source: &str,
+    ast: &SourceFile,
+    offset: u32,
+    evaluated: &HashMap<String, ShellValue>,
+) -> Option<(String, Range)> {
+    for item in &ast.items {
+        if let Item::ContextBlock(ctx) = &item.node {
+            if span_contains(ctx.name.span, offset) {
+                let doc = get_context_hover(source, ast, ctx);
+                return Some((doc, span_to_range(source, ctx.name.span)));
+            }
+            for ann in &ctx.annotations {
+                if span_contains(ann.span, offset) {
+                    if let Some(hover) = get_option_key_hover(&ann.node.kind, offset, source) {
+                        return Some(hover);
+                    }
+                    return get_annotation_hover(&ann.node.kind, ann.span, source);
+                }
+            }
+        }
+
+        if let Item::Task(task) = &item.node {
             for ann in &task.annotations {
                 if span_contains(ann.span, offset) {
+                    if let Some(hover) = get_option_key_hover(&ann.node.kind, offset, source) {
+                        return Some(hover);
+                    }
                     return get_annotation_hover(&ann.node.kind, ann.span, source);
                 }
             }
 
             // hover over task name definition
             if span_contains(task.name.span, offset) {
-                let deps: Vec<&str> = task
-                    .dependencies
-                    .iter()
-                    .map(|d| match &d.node {
-                        Dependency::Task(t) => t.as_str(),
-                        Dependency::Service(s) => s.as_str(),
-                    })
-                    .collect();
-
-                let doc = if deps.is_empty() {
-                    format!("**Task:** `{}`\n\nNo dependencies.", task.name.node)
-                } else {
-                    format!(
-                        "**Task:** `{}`\n\n**Dependencies:** {}",
-                        task.name.node,
-                        deps.join(", ")
-                    )
-                };
+                let doc = format!(
+                    "**Task:** `{}`\n\n{}",
+                    task.name.node,
+                    task_summary_table(task)
+                );
                 return Some((doc, span_to_range(source, task.name.span)));
             }
 
@@ -1904,20 +3552,16 @@ fn get_hover_info(source: &str, ast: &SourceFile, offset: u32) -> Option<(String
                 for line in &body.lines {
                     if let BodyLine::Command(cmd) = &line.node {
                         for seg in &cmd.segments {
-                            if let CommandSegment::Interpolation(interp) = &seg.node {
-                                if span_contains(seg.span, offset) {
-                                    let def = find_variable_def(ast, &interp.name.node);
-                                    let doc = if let Some(value) = def {
-                                        format!(
-                                            "**Variable:** `{}`\n\n**Value:** `{}`",
-                                            interp.name.node,
-                                            format_var_value(value)
-                                        )
-                                    } else {
-                                        format!("**Variable:** `{}` (undefined)", interp.name.node)
-                                    };
-                                    return Some((doc, span_to_range(source, seg.span)));
-                                }
+                            if let CommandSegment::Interpolation(interp) = &seg.node
+                                && span_contains(seg.span, offset)
+                            {
+                                let def = find_variable_def(ast, &interp.name.node);
+                                let doc = if let Some(value) = def {
+                                    variable_hover(&interp.name.node, value, evaluated)
+                                } else {
+                                    format!("**Variable:** `{}` (undefined)", interp.name.node)
+                                };
+                                return Some((doc, span_to_range(source, seg.span)));
                             }
                         }
                     }
@@ -1925,15 +3569,15 @@ fn get_hover_info(source: &str, ast: &SourceFile, offset: u32) -> Option<(String
             }
         }
 
-        // hover over variable definitions
+        // hover over variable definitions, including values continued over
+        // several lines
         if let Item::Variable(var) = &item.node {
-            if span_contains(var.name.span, offset) {
-                let doc = format!(
-                    "**Variable:** `{}`\n\n**Value:** `{}`",
-                    var.name.node,
-                    format_var_value(&var.value.node)
-                );
-                return Some((doc, span_to_range(source, var.name.span)));
+            let span = [var.name.span, var.value.span]
+                .into_iter()
+                .find(|span| span_contains(*span, offset));
+            if let Some(span) = span {
+                let doc = variable_hover(&var.name.node, &var.value.node, evaluated);
+                return Some((doc, span_to_range(source, span)));
             }
         }
     }
@@ -1941,6 +3585,128 @@ fn get_hover_info(source: &str, ast: &SourceFile, offset: u32) -> Option<(String
     None
 }
 
+/// Same columns as `dr list --format table`, as a markdown table
+fn task_summary_table(task: &dr_ast::TaskDecl) -> String {
+    let deps: Vec<&str> = task
+        .dependencies
+        .iter()
+        .map(|d| match &d.node {
+            Dependency::Task(t) => t.as_str(),
+            Dependency::Service(s) => s.as_str(),
+        })
+        .collect();
+
+    let mut row = vec![
+        task.name.node.clone(),
+        deps.join(", "),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ];
+    for ann in &task.annotations {
+        match &ann.node.kind {
+            AnnotationKind::Timeout(val) => row[2] = val.node.clone(),
+            AnnotationKind::Retry(val) => row[3] = val.node.clone(),
+            AnnotationKind::Ssh(ssh) => {
+                row[4] = ssh
+                    .options
+                    .iter()
+                    .find(|kv| kv.node.key.node == "host")
+                    .map(|kv| &kv.node.value)
+                    .or(ssh.host.as_ref())
+                    .map(|host| host.node.clone())
+                    .unwrap_or_default();
+            }
+            AnnotationKind::K8s(k8s) => {
+                row[5] = k8s
+                    .mode
+                    .as_ref()
+                    .map(|m| m.node.clone())
+                    .unwrap_or_else(|| "job".to_string());
+            }
+            _ => {}
+        }
+    }
+    for cell in &mut row {
+        if cell.is_empty() {
+            *cell = "-".to_string();
+        }
+    }
+
+    format!(
+        "| Name | Dependencies | Timeout | Retry | SSH | K8s |\n|---|---|---|---|---|---|\n| {} |",
+        row.join(" | ")
+    )
+}
+
+/// Hover for a context name: its own annotations plus what it inherits via `extends`
+fn get_context_hover(source: &str, ast: &SourceFile, ctx: &dr_ast::ContextBlock) -> String {
+    let by_name: HashMap<&str, &dr_ast::ContextBlock> = ast
+        .items
+        .iter()
+        .filter_map(|item| match &item.node {
+            Item::ContextBlock(c) => Some((c.name.node.as_str(), c)),
+            _ => None,
+        })
+        .collect();
+
+    let mut doc = format!("**@context** `{}`", ctx.name.node);
+    if let Some(parent) = &ctx.extends {
+        doc.push_str(&format!(" extends `{}`", parent.node));
+    }
+
+    let list = |anns: &[&Spanned<dr_ast::Annotation>]| -> String {
+        anns.iter()
+            .map(|a| format!("- `{}`", a.span.text(source).trim()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let own: Vec<_> = ctx.annotations.iter().collect();
+    if !own.is_empty() {
+        doc.push_str(&format!("\n\n{}", list(&own)));
+    }
+
+    // walk the parent chain, skipping kinds already set closer to this context
+    let mut seen_kinds: Vec<_> = ctx
+        .annotations
+        .iter()
+        .map(|a| std::mem::discriminant(&a.node.kind))
+        .collect();
+    let mut visited: HashSet<&str> = HashSet::from([ctx.name.node.as_str()]);
+    let mut current = ctx;
+    while let Some(parent_name) = &current.extends {
+        let Some(parent) = by_name.get(parent_name.node.as_str()) else {
+            break;
+        };
+        if !visited.insert(parent.name.node.as_str()) {
+            break;
+        }
+        let inherited: Vec<_> = parent
+            .annotations
+            .iter()
+            .filter(|a| !seen_kinds.contains(&std::mem::discriminant(&a.node.kind)))
+            .collect();
+        if !inherited.is_empty() {
+            doc.push_str(&format!(
+                "\n\n**Inherited from `{}`:**\n{}",
+                parent.name.node,
+                list(&inherited)
+            ));
+        }
+        seen_kinds.extend(
+            parent
+                .annotations
+                .iter()
+                .map(|a| std::mem::discriminant(&a.node.kind)),
+        );
+        current = parent;
+    }
+
+    doc
+}
+
 fn get_annotation_hover(
     kind: &AnnotationKind,
     span: Span,
@@ -1963,19 +3729,24 @@ fn get_annotation_hover(
         AnnotationKind::Service(_) => docs::SERVICE.to_markdown(),
         AnnotationKind::Extern(_) => docs::EXTERN.to_markdown(),
         AnnotationKind::PipeFrom(_) => docs::PIPE_FROM.to_markdown(),
+        AnnotationKind::Outputs(_) => docs::OUTPUTS.to_markdown(),
+        AnnotationKind::Inputs(_) => docs::INPUTS.to_markdown(),
+        AnnotationKind::OutputCapture(_) => docs::OUTPUT_CAPTURE.to_markdown(),
+        AnnotationKind::Tags(_) => docs::TAGS.to_markdown(),
+        AnnotationKind::When(_) => docs::WHEN.to_markdown(),
+        AnnotationKind::Cache(_) => docs::CACHE.to_markdown(),
+        AnnotationKind::Artifact(_) => docs::ARTIFACT.to_markdown(),
+        AnnotationKind::OnFailure(_) => docs::ON_FAILURE.to_markdown(),
+        AnnotationKind::Matrix { .. } => docs::MATRIX.to_markdown(),
         AnnotationKind::Join => docs::JOIN.to_markdown(),
+        AnnotationKind::Webhook { .. } => docs::WEBHOOK.to_markdown(),
         AnnotationKind::Use(ctx_name) => {
             format!(
                 "**@use** `{}`\n\nApply annotations from the named context to this task.",
                 ctx_name.node
             )
         }
-        AnnotationKind::Env(env) => {
-            format!(
-                "**@env** `{}={}`\n\nSet environment variable on remote host before command execution.",
-                env.key.node, env.value.node
-            )
-        }
+        AnnotationKind::Env(_) => docs::ENV.to_markdown(),
         AnnotationKind::Unknown { name, .. } => {
             format!("**Unknown annotation:** `@{}`", name.node)
         }
@@ -1983,17 +3754,65 @@ fn get_annotation_hover(
     Some((doc, span_to_range(source, span)))
 }
 
+/// docs for just the option whose `key` is under the cursor, e.g. `host` in `@ssh host=...`
+fn get_option_key_hover(
+    kind: &AnnotationKind,
+    offset: u32,
+    source: &str,
+) -> Option<(String, Range)> {
+    use dr_ast::docs;
+
+    let (doc, options) = match kind {
+        AnnotationKind::Ssh(ssh) => (&docs::SSH, &ssh.options),
+        AnnotationKind::K8s(k8s) => (&docs::K8S, &k8s.options),
+        AnnotationKind::Service(svc) => (&docs::SERVICE, &svc.options),
+        AnnotationKind::Extern(svc) => (&docs::EXTERN, &svc.options),
+        _ => return None,
+    };
+    let key = &options
+        .iter()
+        .find(|kv| span_contains(kv.node.key.span, offset))?
+        .node
+        .key;
+    let md = doc.option_markdown(&key.node)?;
+    Some((md, span_to_range(source, key.span)))
+}
+
 fn find_variable_def<'a>(ast: &'a SourceFile, name: &str) -> Option<&'a dr_ast::VariableValue> {
     for item in &ast.items {
-        if let Item::Variable(var) = &item.node {
-            if var.name.node == name {
-                return Some(&var.value.node);
-            }
+        if let Item::Variable(var) = &item.node
+            && var.name.node == name
+        {
+            return Some(&var.value.node);
         }
     }
     None
 }
 
+/// Hover for a variable: its value as written and, for shell expansions that
+/// have finished running, what they evaluated to
+fn variable_hover(
+    name: &str,
+    value: &dr_ast::VariableValue,
+    evaluated: &HashMap<String, ShellValue>,
+) -> String {
+    let mut doc = format!(
+        "**Variable:** `{}`\n\n**Value:** `{}`",
+        name,
+        format_var_value(value)
+    );
+    if let dr_ast::VariableValue::Shell(shell) = value {
+        match evaluated.get(&shell.command.node) {
+            Some(ShellValue::Output(output)) => {
+                doc.push_str(&format!("\n\n**Value (evaluated):** `{}`", output));
+            }
+            Some(ShellValue::Failed) => doc.push_str("\n\n**(evaluation error)**"),
+            Some(ShellValue::Pending) | None => {}
+        }
+    }
+    doc
+}
+
 fn format_var_value(value: &dr_ast::VariableValue) -> String {
     match value {
         dr_ast::VariableValue::Static(s) => s.clone(),
@@ -2013,10 +3832,8 @@ fn span_contains(span: Span, offset: u32) -> bool {
 fn get_symbol_at(_source: &str, ast: &SourceFile, offset: u32) -> Option<(String, Span)> {
     for item in &ast.items {
         match &item.node {
-            Item::Variable(var) => {
-                if span_contains(var.name.span, offset) {
-                    return Some((var.name.node.clone(), var.name.span));
-                }
+            Item::Variable(var) if span_contains(var.name.span, offset) => {
+                return Some((var.name.node.clone(), var.name.span));
             }
             Item::Task(task) => {
                 // task name definition
@@ -2038,10 +3855,10 @@ fn get_symbol_at(_source: &str, ast: &SourceFile, offset: u32) -> Option<(String
                     for line in &body.lines {
                         if let BodyLine::Command(cmd) = &line.node {
                             for seg in &cmd.segments {
-                                if let CommandSegment::Interpolation(interp) = &seg.node {
-                                    if span_contains(seg.span, offset) {
-                                        return Some((interp.name.node.clone(), interp.name.span));
-                                    }
+                                if let CommandSegment::Interpolation(interp) = &seg.node
+                                    && span_contains(seg.span, offset)
+                                {
+                                    return Some((interp.name.node.clone(), interp.name.span));
                                 }
                             }
                         }
@@ -2066,11 +3883,11 @@ fn find_var_name_in_annotation(kind: &AnnotationKind, offset: u32) -> Option<(St
     let check_spanned = |s: &Spanned<String>| -> Option<(String, Span)> {
         if span_contains(s.span, offset) && s.node.contains("{{") {
             // crude but works: if we're in the span and there's a var, extract it
-            if let Some(start) = s.node.find("{{") {
-                if let Some(end) = s.node[start..].find("}}") {
-                    let var_name = s.node[start + 2..start + end].trim().to_string();
-                    return Some((var_name, s.span));
-                }
+            if let Some(start) = s.node.find("{{")
+                && let Some(end) = s.node[start..].find("}}")
+            {
+                let var_name = s.node[start + 2..start + end].trim().to_string();
+                return Some((var_name, s.span));
             }
         }
         None
@@ -2078,6 +3895,9 @@ fn find_var_name_in_annotation(kind: &AnnotationKind, offset: u32) -> Option<(St
 
     match kind {
         AnnotationKind::Ssh(ssh) => {
+            if let Some(r) = ssh.host.as_ref().and_then(check_spanned) {
+                return Some(r);
+            }
             for kv in &ssh.options {
                 if let Some(r) = check_spanned(&kv.node.value) {
                     return Some(r);
@@ -2115,25 +3935,60 @@ fn find_all_references(
     include_decl: bool,
 ) -> Option<Vec<Span>> {
     let (name, _) = get_symbol_at(source, ast, offset)?;
+    let refs = find_references_to(ast, &name, symbol_role(ast, &name), include_decl);
+    if refs.is_empty() { None } else { Some(refs) }
+}
 
-    // determine if it's a variable or task
-    let is_variable = ast
-        .items
-        .iter()
-        .any(|item| matches!(&item.node, Item::Variable(v) if v.name.node == name));
-    let is_task = ast
-        .items
-        .iter()
-        .any(|item| matches!(&item.node, Item::Task(t) if t.name.node == name));
+/// Whether `name` is defined in this file as a variable and/or a task
+#[derive(Debug, Clone, Copy, Default)]
+struct SymbolRole {
+    is_variable: bool,
+    is_task: bool,
+}
+
+impl SymbolRole {
+    fn any(self) -> bool {
+        self.is_variable || self.is_task
+    }
+
+    fn or(self, other: SymbolRole) -> SymbolRole {
+        SymbolRole {
+            is_variable: self.is_variable || other.is_variable,
+            is_task: self.is_task || other.is_task,
+        }
+    }
+}
+
+fn symbol_role(ast: &SourceFile, name: &str) -> SymbolRole {
+    SymbolRole {
+        is_variable: ast
+            .items
+            .iter()
+            .any(|item| matches!(&item.node, Item::Variable(v) if v.name.node == name)),
+        is_task: ast
+            .items
+            .iter()
+            .any(|item| matches!(&item.node, Item::Task(t) if t.name.node == name)),
+    }
+}
 
+/// References to `name` in one file; `kind` may come from another file that defines it
+fn find_references_to(
+    ast: &SourceFile,
+    name: &str,
+    kind: SymbolRole,
+    include_decl: bool,
+) -> Vec<Span> {
+    let SymbolRole {
+        is_variable,
+        is_task,
+    } = kind;
     let mut refs = Vec::new();
 
     for item in &ast.items {
         match &item.node {
-            Item::Variable(var) if is_variable && var.name.node == name => {
-                if include_decl {
-                    refs.push(var.name.span);
-                }
+            Item::Variable(var) if is_variable && var.name.node == name && include_decl => {
+                refs.push(var.name.span);
             }
             Item::Task(task) => {
                 // task definition
@@ -2147,7 +4002,7 @@ fn find_all_references(
                             Dependency::Task(t) => t,
                             Dependency::Service(s) => s,
                         };
-                        if dep_name == &name {
+                        if dep_name == name {
                             refs.push(dep.span);
                         }
                     }
@@ -2158,10 +4013,10 @@ fn find_all_references(
                         for line in &body.lines {
                             if let BodyLine::Command(cmd) = &line.node {
                                 for seg in &cmd.segments {
-                                    if let CommandSegment::Interpolation(interp) = &seg.node {
-                                        if interp.name.node == name {
-                                            refs.push(interp.name.span);
-                                        }
+                                    if let CommandSegment::Interpolation(interp) = &seg.node
+                                        && interp.name.node == name
+                                    {
+                                        refs.push(interp.name.span);
                                     }
                                 }
                             }
@@ -2169,7 +4024,7 @@ fn find_all_references(
                     }
                     // variable usages in annotations
                     for ann in &task.annotations {
-                        refs.extend(find_var_refs_in_annotation(&ann.node.kind, &name));
+                        refs.extend(find_var_refs_in_annotation(&ann.node.kind, name));
                     }
                 }
             }
@@ -2177,50 +4032,115 @@ fn find_all_references(
         }
     }
 
-    if refs.is_empty() { None } else { Some(refs) }
+    refs
 }
 
-fn find_var_refs_in_annotation(kind: &AnnotationKind, var_name: &str) -> Vec<Span> {
-    let mut refs = Vec::new();
-
-    let check_spanned = |s: &Spanned<String>| -> Option<Span> {
-        if s.node.contains(&format!("{{{{{}}}}}", var_name)) {
-            Some(s.span)
-        } else {
-            None
-        }
-    };
-
-    match kind {
-        AnnotationKind::Ssh(ssh) => {
-            for kv in &ssh.options {
-                if let Some(span) = check_spanned(&kv.node.value) {
-                    refs.push(span);
-                }
+/// `uri` and every file connected to it through `@include`: the files it
+/// includes, the open documents that include it, and so on from those. Open
+/// documents use their editor contents; the rest are read from disk.
+fn rename_scope(
+    documents: &HashMap<Uri, String>,
+    include_graph: &HashMap<Uri, Vec<Uri>>,
+    uri: &Uri,
+) -> Vec<(Uri, String)> {
+    let mut component = vec![uri.clone()];
+    let mut next = 0;
+    while let Some(current) = component.get(next).cloned() {
+        let linked = include_graph
+            .get(&current)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(dependents_of(include_graph, std::slice::from_ref(&current)));
+        for linked in linked {
+            if !component.contains(&linked) {
+                component.push(linked);
             }
         }
-        AnnotationKind::K8s(k8s) => {
-            for kv in &k8s.options {
-                if let Some(span) = check_spanned(&kv.node.value) {
-                    refs.push(span);
-                }
-            }
-        }
-        AnnotationKind::Upload(ft)
-        | AnnotationKind::Download(ft)
-        | AnnotationKind::K8sUpload(ft)
-        | AnnotationKind::K8sDownload(ft) => {
-            if let Some(span) = check_spanned(&ft.local) {
-                refs.push(span);
-            }
-            if let Some(span) = check_spanned(&ft.remote) {
-                refs.push(span);
-            }
+        next += 1;
+    }
+
+    component
+        .into_iter()
+        .filter_map(|uri| {
+            let source = match documents.get(&uri) {
+                Some(source) => source.clone(),
+                None => std::fs::read_to_string(uri.to_file_path()?).ok()?,
+            };
+            Some((uri, source))
+        })
+        .collect()
+}
+
+/// Rename edits for the symbol at `offset` in `uri`, across the files in its
+/// [`rename_scope`]
+fn workspace_rename_edits(
+    documents: &HashMap<Uri, String>,
+    include_graph: &HashMap<Uri, Vec<Uri>>,
+    uri: &Uri,
+    offset: u32,
+    new_name: &str,
+) -> Option<HashMap<Uri, Vec<TextEdit>>> {
+    let source = documents.get(uri)?;
+    let (ast, _) = parse(source);
+    let (name, _) = get_symbol_at(source, &ast, offset)?;
+
+    let scope = rename_scope(documents, include_graph, uri);
+    let parsed: Vec<(&Uri, &String, SourceFile)> = scope
+        .iter()
+        .map(|(uri, source)| (uri, source, parse(source).0))
+        .collect();
+    // a dependency may name a task defined in a different file
+    let kind = parsed
+        .iter()
+        .map(|(_, _, ast)| symbol_role(ast, &name))
+        .fold(SymbolRole::default(), SymbolRole::or);
+
+    let mut changes = HashMap::new();
+    for (doc_uri, source, ast) in &parsed {
+        let refs = find_references_to(ast, &name, kind, true);
+        if refs.is_empty() {
+            continue;
         }
-        _ => {}
+        let edits = refs
+            .into_iter()
+            .map(|span| TextEdit {
+                range: span_to_range(source, span),
+                new_text: new_name.to_string(),
+            })
+            .collect();
+        changes.insert((*doc_uri).clone(), edits);
     }
 
-    refs
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes)
+    }
+}
+
+/// Spans of the `name` inside every `{{name}}` in an annotation's values
+fn find_var_refs_in_annotation(kind: &AnnotationKind, var_name: &str) -> Vec<Span> {
+    annotation_values(kind)
+        .into_iter()
+        .flat_map(|val| interpolation_name_spans(val, var_name))
+        .collect()
+}
+
+/// Spans of `var_name` itself in each `{{var_name}}` of `val`, without the
+/// braces or any whitespace around the name
+fn interpolation_name_spans(val: &Spanned<String>, var_name: &str) -> Vec<Span> {
+    find_interpolations(&val.node, val.span)
+        .into_iter()
+        .filter(|r| r.name == var_name)
+        .filter_map(|r| {
+            let inner = val.node.get(
+                (r.span.start - val.span.start) as usize..(r.span.end - val.span.start) as usize,
+            )?;
+            let start = r.span.start + (inner.len() - inner.trim_start().len()) as u32;
+            Some(Span::new(start, start + var_name.len() as u32))
+        })
+        .collect()
 }
 
 // ============================================================================
@@ -2247,18 +4167,20 @@ fn collect_document_symbols(source: &str, ast: &SourceFile) -> Vec<SymbolInforma
                 });
             }
             Item::Task(task) => {
-                #[allow(deprecated)]
-                symbols.push(SymbolInformation {
-                    name: task.name.node.clone(),
-                    kind: SymbolKind::FUNCTION,
-                    tags: None,
-                    deprecated: None,
-                    location: Location {
-                        uri: "file:///".parse().unwrap(),
-                        range: span_to_range(source, item.span),
-                    },
-                    container_name: None,
-                });
+                for name in task.expanded_names() {
+                    #[allow(deprecated)]
+                    symbols.push(SymbolInformation {
+                        name,
+                        kind: SymbolKind::FUNCTION,
+                        tags: None,
+                        deprecated: None,
+                        location: Location {
+                            uri: "file:///".parse().unwrap(),
+                            range: span_to_range(source, item.span),
+                        },
+                        container_name: None,
+                    });
+                }
             }
             Item::ContextBlock(ctx) => {
                 #[allow(deprecated)]
@@ -2281,6 +4203,591 @@ fn collect_document_symbols(source: &str, ast: &SourceFile) -> Vec<SymbolInforma
     symbols
 }
 
+/// Case-insensitive fuzzy match: every query char appears in `name`, in order
+fn symbol_matches(name: &str, query: &str) -> bool {
+    let mut chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| chars.any(|c| c == q))
+}
+
+// ============================================================================
+// Selection ranges
+// ============================================================================
+
+/// Spans containing `offset`, innermost first: word, interpolation, command
+/// segment, command line, task body, task (with annotations), file
+fn selection_spans(source: &str, ast: &SourceFile, offset: u32) -> Vec<Span> {
+    // inclusive end so a cursor right after a word still selects it
+    let covers = |span: Span| span.start <= offset && offset <= span.end;
+    let mut spans = Vec::new();
+
+    for item in ast.items.iter().filter(|i| covers(i.span)) {
+        if let Item::Task(task) = &item.node {
+            if let Some(body) = task.body.as_ref().filter(|b| covers(b.span)) {
+                for line in body.lines.iter().filter(|l| covers(l.span)) {
+                    if let BodyLine::Command(cmd) = &line.node {
+                        for seg in cmd.segments.iter().filter(|s| covers(s.span)) {
+                            match &seg.node {
+                                CommandSegment::Interpolation(interp) => {
+                                    if covers(interp.name.span) {
+                                        spans.push(interp.name.span);
+                                    }
+                                }
+                                CommandSegment::Text(_) => {
+                                    spans.extend(word_span_at(source, seg.span, offset));
+                                }
+                            }
+                            spans.push(seg.span);
+                        }
+                        // command without its indentation
+                        if let (Some(first), Some(last)) =
+                            (cmd.segments.first(), cmd.segments.last())
+                        {
+                            spans.push(first.span.merge(last.span));
+                        }
+                    }
+                    spans.push(line.span);
+                }
+                spans.push(body.span);
+            } else {
+                let header = task
+                    .annotations
+                    .iter()
+                    .map(|a| a.span)
+                    .chain(std::iter::once(task.name.span))
+                    .chain(task.parameters.iter().map(|p| p.span))
+                    .chain(task.dependencies.iter().map(|d| d.span));
+                spans.extend(header.filter(|s| covers(*s)).take(1));
+            }
+        }
+        spans.push(item.span);
+    }
+    spans.push(Span::new(0, source.len() as u32));
+
+    // a cursor on a segment boundary can match two siblings; keep only nested spans
+    let mut nested: Vec<Span> = Vec::new();
+    for span in spans {
+        if nested.last().is_none_or(|inner| {
+            span != *inner && span.start <= inner.start && inner.end <= span.end
+        }) {
+            nested.push(span);
+        }
+    }
+    nested
+}
+
+/// Whitespace-delimited word around `offset`, limited to `bounds`
+fn word_span_at(source: &str, bounds: Span, offset: u32) -> Option<Span> {
+    let bytes = source.as_bytes();
+    let is_word = |i: u32| {
+        bytes
+            .get(i as usize)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+    };
+    let mut start = offset;
+    while start > bounds.start && is_word(start - 1) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while end < bounds.end && is_word(end) {
+        end += 1;
+    }
+    (start < end).then(|| Span::new(start, end))
+}
+
+/// Chain spans (innermost first) into nested `SelectionRange`s
+fn build_selection_range(source: &str, spans: &[Span]) -> SelectionRange {
+    let mut spans = spans.iter().rev();
+    let outer = spans.next().copied().unwrap_or_default();
+    let mut range = SelectionRange {
+        range: span_to_range(source, outer),
+        parent: None,
+    };
+    for span in spans {
+        range = SelectionRange {
+            range: span_to_range(source, *span),
+            parent: Some(Box::new(range)),
+        };
+    }
+    range
+}
+
+// ============================================================================
+// Inlay hints
+// ============================================================================
+
+/// The resolved value after each `{{var}}` in task bodies within `range`, or
+/// `(shell)` for backtick variables since their output can change between runs
+fn collect_inlay_hints(source: &str, ast: &SourceFile, range: Span) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+
+    for item in &ast.items {
+        let Item::Task(task) = &item.node else {
+            continue;
+        };
+        let Some(body) = &task.body else {
+            continue;
+        };
+        if body.span.end < range.start || range.end < body.span.start {
+            continue;
+        }
+
+        for line in &body.lines {
+            let BodyLine::Command(cmd) = &line.node else {
+                continue;
+            };
+            for seg in &cmd.segments {
+                let CommandSegment::Interpolation(interp) = &seg.node else {
+                    continue;
+                };
+                let Some(close) = interp.close_span else {
+                    continue;
+                };
+                if close.end < range.start || range.end < close.end {
+                    continue;
+                }
+                // parameters and the matrix axis shadow globals and are only known at run time
+                let name = interp.name.node.as_str();
+                let shadowed = task.parameters.iter().any(|p| p.node.name.node == name)
+                    || task.matrix().is_some_and(|(axis, _)| axis.node == name);
+                if shadowed {
+                    continue;
+                }
+                let label = match find_variable_def(ast, name) {
+                    Some(dr_ast::VariableValue::Static(value)) => format!("({})", value),
+                    Some(dr_ast::VariableValue::Shell(_)) => "(shell)".to_string(),
+                    None => continue,
+                };
+                hints.push(InlayHint {
+                    position: offset_to_position(source, close.end as usize),
+                    label: InlayHintLabel::String(label),
+                    kind: Some(InlayHintKind::PARAMETER),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+    }
+
+    hints
+}
+
+// ============================================================================
+// Code actions
+// ============================================================================
+
+const EXTRACT_VARIABLE_KIND: CodeActionKind = CodeActionKind::new("refactor.extract.variable");
+
+/// Whether a client's `only` filter admits `kind` (a requested kind matches its sub-kinds)
+fn code_action_requested(only: Option<&[CodeActionKind]>, kind: &CodeActionKind) -> bool {
+    only.is_none_or(|only| {
+        only.iter().any(|k| {
+            kind.as_str() == k.as_str() || kind.as_str().starts_with(&format!("{}.", k.as_str()))
+        })
+    })
+}
+
+/// Quick fix for a `missing-timeout` diagnostic: `@timeout <default>` on the line
+/// before the task name
+fn add_timeout_action(
+    source: &str,
+    ast: &SourceFile,
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    timeout: &str,
+) -> Option<CodeAction> {
+    let code = NumberOrString::String(LintCode::MissingTimeout.as_str().to_string());
+    if diagnostic.code.as_ref() != Some(&code) {
+        return None;
+    }
+    let name = diagnostic.data.as_ref()?.get("task")?.as_str()?;
+    let task = ast.items.iter().find_map(|item| match &item.node {
+        Item::Task(task) if task.name.node == name => Some(task),
+        _ => None,
+    })?;
+
+    let line = span_to_range(source, task.name.span).start.line;
+    let edit = TextEdit {
+        range: Range::new(Position::new(line, 0), Position::new(line, 0)),
+        new_text: format!("@timeout {}\n", timeout),
+    };
+
+    Some(CodeAction {
+        title: format!("Add @timeout {}", timeout),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// "Extract to variable" for a selected literal: declares `name := literal` at the
+/// top of the file and replaces every occurrence in bodies and annotations with `{{name}}`
+fn extract_variable_action(
+    source: &str,
+    ast: &SourceFile,
+    uri: &Uri,
+    range: Range,
+) -> Option<CodeAction> {
+    let start = position_to_offset(source, range.start);
+    let end = position_to_offset(source, range.end);
+    let selected = source.get(start as usize..end as usize)?;
+    let literal = selected.trim();
+    if literal.is_empty() || literal.contains(['\n', '\r']) {
+        return None;
+    }
+    if literal.contains("{{") || literal.contains("}}") {
+        return None;
+    }
+
+    // the selection itself must be one of the replaceable occurrences
+    let literal_start = start + (selected.len() - selected.trim_start().len()) as u32;
+    let occurrences = literal_occurrences(source, ast, literal);
+    if !occurrences.iter().any(|s| s.start == literal_start) {
+        return None;
+    }
+
+    let name = extract_variable_name(ast, literal);
+    let mut edits = vec![TextEdit {
+        range: Range::default(),
+        new_text: format!("{} := {}\n", name, literal),
+    }];
+    edits.extend(occurrences.iter().map(|span| TextEdit {
+        range: span_to_range(source, *span),
+        new_text: format!("{{{{{}}}}}", name),
+    }));
+
+    Some(CodeAction {
+        title: format!("Extract '{}' to variable '{}'", literal, name),
+        kind: Some(EXTRACT_VARIABLE_KIND),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Every occurrence of `literal` in command text and annotation values, outside interpolations
+fn literal_occurrences(source: &str, ast: &SourceFile, literal: &str) -> Vec<Span> {
+    let mut regions = Vec::new();
+    for item in &ast.items {
+        let annotations = match &item.node {
+            Item::Task(task) => &task.annotations,
+            Item::ContextBlock(ctx) => &ctx.annotations,
+            _ => continue,
+        };
+        for ann in annotations {
+            regions.extend(annotation_values(&ann.node.kind).iter().map(|v| v.span));
+        }
+        if let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            for line in &body.lines {
+                if let BodyLine::Command(cmd) = &line.node {
+                    regions.extend(
+                        cmd.segments
+                            .iter()
+                            .filter(|seg| matches!(seg.node, CommandSegment::Text(_)))
+                            .map(|seg| seg.span),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut spans = Vec::new();
+    for region in regions {
+        let text = region.text(source);
+        // annotation values keep their {{...}} inline, so skip matches inside them
+        let interpolations: Vec<Span> = find_interpolations(text, region)
+            .into_iter()
+            .map(|r| Span::new(r.span.start - 2, r.span.end + 2))
+            .collect();
+        for (idx, _) in text.match_indices(literal) {
+            // only whole words, so `app` isn't pulled out of `myapp`
+            if !whole_word(text, idx, literal) {
+                continue;
+            }
+            let start = region.start + idx as u32;
+            let span = Span::new(start, start + literal.len() as u32);
+            if !interpolations
+                .iter()
+                .any(|i| span.start < i.end && i.start < span.end)
+            {
+                spans.push(span);
+            }
+        }
+    }
+    spans.sort_by_key(|s| s.start);
+    spans
+}
+
+/// Whether `literal` at `idx` in `text` isn't part of a longer word
+fn whole_word(text: &str, idx: usize, literal: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let starts_word = literal.starts_with(is_word);
+    let ends_word = literal.ends_with(is_word);
+    let before = text[..idx].chars().next_back();
+    let after = text[idx + literal.len()..].chars().next();
+    let joins_before = starts_word && before.is_some_and(is_word);
+    let joins_after = ends_word && after.is_some_and(is_word);
+    !joins_before && !joins_after
+}
+
+/// Literal string values of an annotation (not names of tasks, contexts or variables)
+fn annotation_values(kind: &AnnotationKind) -> Vec<&Spanned<String>> {
+    fn kv_values(opts: &[Spanned<KeyValue>]) -> Vec<&Spanned<String>> {
+        opts.iter().map(|kv| &kv.node.value).collect()
+    }
+
+    match kind {
+        AnnotationKind::Timeout(val)
+        | AnnotationKind::Retry(val)
+        | AnnotationKind::When(val)
+        | AnnotationKind::Cache(val) => {
+            vec![val]
+        }
+        AnnotationKind::Outputs(paths) | AnnotationKind::Inputs(paths) => paths.iter().collect(),
+        AnnotationKind::Ssh(ssh) => ssh.host.iter().chain(kv_values(&ssh.options)).collect(),
+        AnnotationKind::Upload(ft)
+        | AnnotationKind::Download(ft)
+        | AnnotationKind::K8sUpload(ft)
+        | AnnotationKind::K8sDownload(ft) => [&ft.local, &ft.remote]
+            .into_iter()
+            .chain(kv_values(&ft.options))
+            .collect(),
+        AnnotationKind::Service(svc) | AnnotationKind::Extern(svc) => kv_values(&svc.options),
+        AnnotationKind::K8s(k8s) => kv_values(&k8s.options),
+        AnnotationKind::K8sConfigmap(cm) | AnnotationKind::K8sSecret(cm) => vec![&cm.path],
+        AnnotationKind::K8sForward(pf) => vec![&pf.local_port, &pf.resource, &pf.remote_port],
+        AnnotationKind::Env(vars) | AnnotationKind::Artifact(vars) => kv_values(vars),
+        AnnotationKind::Webhook { url, body, .. } => std::iter::once(url).chain(body).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Variable name derived from a literal, e.g. `--target x86_64` becomes `target_x86_64`,
+/// suffixed with a number if it would clash with an existing variable or parameter
+fn extract_variable_name(ast: &SourceFile, literal: &str) -> String {
+    let mut base = String::new();
+    for c in literal.chars() {
+        if c.is_ascii_alphanumeric() {
+            base.push(c.to_ascii_lowercase());
+        } else if !base.is_empty() && !base.ends_with('_') {
+            base.push('_');
+        }
+    }
+    base.truncate(32);
+    let mut base = base.trim_end_matches('_').to_string();
+    if base.is_empty() || base.starts_with(|c: char| c.is_ascii_digit()) {
+        base = format!("value_{}", base).trim_end_matches('_').to_string();
+    }
+
+    let taken: HashSet<&str> = ast
+        .items
+        .iter()
+        .flat_map(|item| match &item.node {
+            Item::Variable(var) => vec![var.name.node.as_str()],
+            Item::Task(task) => task
+                .parameters
+                .iter()
+                .map(|p| p.node.name.node.as_str())
+                .collect(),
+            _ => vec![],
+        })
+        .collect();
+    (1..)
+        .map(|n| {
+            if n == 1 {
+                base.clone()
+            } else {
+                format!("{}_{}", base, n)
+            }
+        })
+        .find(|name| !taken.contains(name.as_str()))
+        .unwrap()
+}
+
+// ============================================================================
+// Code lenses
+// ============================================================================
+
+/// A "▶ Run" lens on each task name (one per name for `@matrix` tasks)
+fn collect_code_lenses(source: &str, ast: &SourceFile, uri: &Uri) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+
+    for item in &ast.items {
+        let Item::Task(task) = &item.node else {
+            continue;
+        };
+        let names = task.expanded_names();
+        for name in &names {
+            let title = if task.matrix().is_some() {
+                format!("▶ Run {}", name)
+            } else {
+                "▶ Run".to_string()
+            };
+            lenses.push(CodeLens {
+                range: span_to_range(source, task.name.span),
+                command: Some(Command {
+                    title,
+                    command: RUN_TASK_COMMAND.to_string(),
+                    arguments: Some(vec![
+                        LSPAny::String(name.clone()),
+                        LSPAny::String(uri.as_str().to_string()),
+                    ]),
+                }),
+                data: None,
+            });
+        }
+    }
+
+    lenses
+}
+
+// ============================================================================
+// Folding ranges
+// ============================================================================
+
+/// One fold per task (with its annotations and the comments directly above it),
+/// lua block and context block
+fn collect_folding_ranges(source: &str, ast: &SourceFile) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+
+    for (i, item) in ast.items.iter().enumerate() {
+        let kind = match &item.node {
+            Item::Task(_) | Item::ContextBlock(_) => FoldingRangeKind::Region,
+            Item::LuaBlock(_) => FoldingRangeKind::Imports,
+            _ => continue,
+        };
+        let range = span_to_range(source, item.span);
+        let mut start_line = range.start.line;
+        // spans that include the trailing newline end at column 0 of the next line
+        let end_line = if range.end.character == 0 && range.end.line > start_line {
+            range.end.line - 1
+        } else {
+            range.end.line
+        };
+
+        if let Item::Task(_) = &item.node {
+            for prev in ast.items[..i].iter().rev() {
+                let Item::Comment(_) = &prev.node else {
+                    break;
+                };
+                let prev = span_to_range(source, prev.span);
+                if prev.end.line + 1 < start_line {
+                    break;
+                }
+                start_line = prev.start.line;
+            }
+        }
+
+        if end_line > start_line {
+            ranges.push(FoldingRange {
+                start_line,
+                start_character: None,
+                end_line,
+                end_character: None,
+                kind: Some(kind),
+                collapsed_text: None,
+            });
+        }
+    }
+
+    ranges
+}
+
+// ============================================================================
+// Document colors (status keywords in comments)
+// ============================================================================
+
+const YELLOW: Color = Color {
+    red: 0.9,
+    green: 0.75,
+    blue: 0.1,
+    alpha: 1.0,
+};
+const RED: Color = Color {
+    red: 0.85,
+    green: 0.2,
+    blue: 0.2,
+    alpha: 1.0,
+};
+const BLUE: Color = Color {
+    red: 0.25,
+    green: 0.5,
+    blue: 0.9,
+    alpha: 1.0,
+};
+
+/// keywords that get a color swatch when they appear in a comment
+const COMMENT_KEYWORDS: &[(&str, Color)] = &[
+    ("TODO", YELLOW),
+    ("FIXME", RED),
+    ("BUG", RED),
+    ("NOTE", BLUE),
+    ("INFO", BLUE),
+];
+
+fn collect_comment_colors(source: &str, ast: &SourceFile) -> Vec<ColorInformation> {
+    let mut colors = Vec::new();
+
+    for item in &ast.items {
+        let Item::Comment(comment) = &item.node else {
+            continue;
+        };
+        let text = comment.text.as_str();
+        for (keyword, color) in COMMENT_KEYWORDS {
+            for (idx, _) in text.match_indices(keyword) {
+                // only whole words, so `TODOS` or `INFORMATION` don't match
+                let end = idx + keyword.len();
+                let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+                if text[..idx].chars().next_back().is_some_and(is_word_char)
+                    || text[end..].chars().next().is_some_and(is_word_char)
+                {
+                    continue;
+                }
+                let start = item.span.start + idx as u32;
+                colors.push(ColorInformation {
+                    range: span_to_range(source, Span::new(start, start + keyword.len() as u32)),
+                    color: *color,
+                });
+            }
+        }
+    }
+
+    colors
+}
+
+/// offer every keyword as a replacement, with the ones matching the picked color first
+fn keyword_presentations(color: Color, range: Range) -> Vec<ColorPresentation> {
+    let (mut matching, others): (Vec<_>, Vec<_>) =
+        COMMENT_KEYWORDS.iter().partition(|(_, c)| *c == color);
+    matching.extend(others);
+
+    matching
+        .into_iter()
+        .map(|(keyword, _)| ColorPresentation {
+            label: keyword.to_string(),
+            text_edit: Some(TextEdit {
+                range,
+                new_text: keyword.to_string(),
+            }),
+            additional_text_edits: None,
+        })
+        .collect()
+}
+
 // ============================================================================
 // Formatting
 // ============================================================================
@@ -2293,12 +4800,11 @@ async fn format_task_bodies(
     let mut tasks = Vec::new();
 
     for item in &ast.items {
-        if let Item::Task(task) = &item.node {
-            if let Some(body) = &task.body {
-                if let Some(task) = prepare_format_task(source, body) {
-                    tasks.push(task);
-                }
-            }
+        if let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+            && let Some(task) = prepare_format_task(source, body)
+        {
+            tasks.push(task);
         }
     }
 
@@ -2308,6 +4814,26 @@ async fn format_task_bodies(
     results.into_iter().flatten().collect()
 }
 
+/// Apply non-overlapping edits to `source`
+fn apply_text_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut spans: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|e| {
+            let start = position_to_offset(source, e.range.start) as usize;
+            let end = position_to_offset(source, e.range.end) as usize;
+            (start, end, e.new_text.as_str())
+        })
+        .collect();
+    // back to front so earlier offsets stay valid
+    spans.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+
+    let mut text = source.to_string();
+    for (start, end, new_text) in spans {
+        text.replace_range(start..end, new_text);
+    }
+    text
+}
+
 struct FormatTask {
     language: BodyLanguage,
     masked_content: String,
@@ -2442,39 +4968,39 @@ fn mask_interpolations(text: &str) -> (String, Vec<String>) {
     let mut chars = text.char_indices().peekable();
 
     while let Some((i, c)) = chars.next() {
-        if c == '{' {
-            if let Some(&(_, '{')) = chars.peek() {
-                chars.next();
-                // find closing }}
-                let start = i;
-                let mut depth = 1;
-                let mut end = start + 2;
-
-                while let Some((j, ch)) = chars.next() {
-                    end = j + ch.len_utf8();
-                    if ch == '{' {
-                        if let Some(&(_, '{')) = chars.peek() {
-                            chars.next();
-                            depth += 1;
-                        }
-                    } else if ch == '}' {
-                        if let Some(&(_, '}')) = chars.peek() {
-                            chars.next();
-                            depth -= 1;
-                            if depth == 0 {
-                                end += 1;
-                                break;
-                            }
-                        }
+        if c == '{'
+            && let Some(&(_, '{')) = chars.peek()
+        {
+            chars.next();
+            // find closing }}
+            let start = i;
+            let mut depth = 1;
+            let mut end = start + 2;
+
+            while let Some((j, ch)) = chars.next() {
+                end = j + ch.len_utf8();
+                if ch == '{' {
+                    if let Some(&(_, '{')) = chars.peek() {
+                        chars.next();
+                        depth += 1;
+                    }
+                } else if ch == '}'
+                    && let Some(&(_, '}')) = chars.peek()
+                {
+                    chars.next();
+                    depth -= 1;
+                    if depth == 0 {
+                        end += 1;
+                        break;
                     }
                 }
-
-                let var_text = &text[start..end];
-                let placeholder = format!("__DAGRUN_VAR_{}__", masks.len());
-                masks.push(var_text.to_string());
-                result.push_str(&placeholder);
-                continue;
             }
+
+            let var_text = &text[start..end];
+            let placeholder = format!("__DAGRUN_VAR_{}__", masks.len());
+            masks.push(var_text.to_string());
+            result.push_str(&placeholder);
+            continue;
         }
         result.push(c);
     }
@@ -2677,6 +5203,25 @@ mod tests {
         assert_eq!(unmasked, input);
     }
 
+    #[test]
+    fn test_apply_text_edits() {
+        let source = "build:\n\techo a\n\techo b\n";
+        let edits = [
+            TextEdit {
+                range: Range::new(Position::new(2, 1), Position::new(2, 7)),
+                new_text: "true".to_string(),
+            },
+            TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 5)),
+                new_text: "compile".to_string(),
+            },
+        ];
+        assert_eq!(
+            apply_text_edits(source, &edits),
+            "compile:\n\techo a\n\ttrue\n"
+        );
+    }
+
     #[test]
     fn test_dedent_block() {
         let input = "    echo hello\n    echo world";
@@ -2765,12 +5310,11 @@ mod tests {
     fn test_detect_language_default_shell() {
         let source = "task:\n    echo hello";
         let (ast, _) = parse(source);
-        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_))) {
-            if let Item::Task(task) = &item.node {
-                if let Some(body) = &task.body {
-                    assert_eq!(detect_language(body), BodyLanguage::Shell);
-                }
-            }
+        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_)))
+            && let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            assert_eq!(detect_language(body), BodyLanguage::Shell);
         }
     }
 
@@ -2778,12 +5322,11 @@ mod tests {
     fn test_detect_language_python_shebang() {
         let source = "task:\n    #!python\n    print('hello')";
         let (ast, _) = parse(source);
-        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_))) {
-            if let Item::Task(task) = &item.node {
-                if let Some(body) = &task.body {
-                    assert_eq!(detect_language(body), BodyLanguage::Python);
-                }
-            }
+        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_)))
+            && let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            assert_eq!(detect_language(body), BodyLanguage::Python);
         }
     }
 
@@ -2791,12 +5334,11 @@ mod tests {
     fn test_detect_language_env_python() {
         let source = "task:\n    #!/usr/bin/env python3\n    print('hello')";
         let (ast, _) = parse(source);
-        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_))) {
-            if let Item::Task(task) = &item.node {
-                if let Some(body) = &task.body {
-                    assert_eq!(detect_language(body), BodyLanguage::Python);
-                }
-            }
+        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_)))
+            && let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            assert_eq!(detect_language(body), BodyLanguage::Python);
         }
     }
 
@@ -2804,12 +5346,11 @@ mod tests {
     fn test_detect_language_uv_script() {
         let source = "task:\n    #!/usr/bin/env -S uv run --script\n    print('hello')";
         let (ast, _) = parse(source);
-        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_))) {
-            if let Item::Task(task) = &item.node {
-                if let Some(body) = &task.body {
-                    assert_eq!(detect_language(body), BodyLanguage::Python);
-                }
-            }
+        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_)))
+            && let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            assert_eq!(detect_language(body), BodyLanguage::Python);
         }
     }
 
@@ -2817,12 +5358,830 @@ mod tests {
     fn test_detect_language_bash_shebang() {
         let source = "task:\n    #!/bin/bash\n    echo hello";
         let (ast, _) = parse(source);
-        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_))) {
-            if let Item::Task(task) = &item.node {
-                if let Some(body) = &task.body {
-                    assert_eq!(detect_language(body), BodyLanguage::Shell);
-                }
+        if let Some(item) = ast.items.iter().find(|i| matches!(i.node, Item::Task(_)))
+            && let Item::Task(task) = &item.node
+            && let Some(body) = &task.body
+        {
+            assert_eq!(detect_language(body), BodyLanguage::Shell);
+        }
+    }
+
+    #[test]
+    fn test_slash_comment_semantic_token() {
+        let source = "// build it\nbuild:\n    echo hi";
+        let (ast, _) = parse(source);
+        let tokens = collect_semantic_tokens(source, &ast);
+        let first = tokens.first().expect("expected tokens");
+        assert_eq!(first.token_type, 3); // COMMENT
+        assert_eq!(first.span.text(source), "// build it");
+    }
+
+    #[test]
+    fn test_task_name_token_modifiers() {
+        let source = "gen:\n\techo\n\n@pipe_from gen\nbuild: gen\n\tcat\n";
+        let (ast, _) = parse(source);
+        let tokens = collect_semantic_tokens(source, &ast);
+        let modifiers: Vec<(&str, u32)> = tokens
+            .iter()
+            .filter(|t| t.token_type == 0)
+            .map(|t| (t.span.text(source), t.modifiers))
+            .collect();
+        assert_eq!(
+            modifiers,
+            [("gen", 1), ("gen", 4), ("build", 1), ("gen", 4)]
+        );
+    }
+
+    #[test]
+    fn test_type_definition_for_param_default() {
+        let source = "version := 1.0\n\ndeploy v={{version}}:\n    echo {{v}}\n";
+        let (ast, _) = parse(source);
+        let offset = source.find("{{version}}").unwrap() as u32 + 3;
+        let span = find_type_definition_at(source, &ast, offset).expect("expected definition");
+        assert_eq!(span.text(source), "version");
+        assert_eq!(span.start, 0);
+
+        // not on a parameter default
+        let body_offset = source.find("{{v}}").unwrap() as u32 + 2;
+        assert!(find_type_definition_at(source, &ast, body_offset).is_none());
+    }
+
+    #[test]
+    fn test_comment_keyword_colors() {
+        let source = "# TODO: fails on prod\n# FIXME broken, see NOTE below\n# TODOS and INFORMATION\nbuild:\n    echo hi\n";
+        let (ast, _) = parse(source);
+        let colors = collect_comment_colors(source, &ast);
+
+        let found: Vec<_> = colors
+            .iter()
+            .map(|c| (c.range.start.line, c.range.start.character, c.color == RED))
+            .collect();
+        assert_eq!(found.len(), 3);
+        assert!(found.contains(&(0, 2, false))); // TODO
+        assert!(found.contains(&(1, 2, true))); // FIXME
+        assert!(found.contains(&(1, 20, false))); // NOTE
+    }
+
+    #[test]
+    fn test_context_hover_shows_inherited() {
+        let source = "@context base\n@ssh host=example.com\n@timeout 5m\n@end\n\n@context prod extends base\n@timeout 10m\n@end\n";
+        let (ast, _) = parse(source);
+        let offset = source.find("prod").unwrap() as u32;
+        let (doc, _) =
+            get_hover_info(source, &ast, offset, &HashMap::new()).expect("expected hover");
+        assert!(doc.contains("extends `base`"));
+        assert!(doc.contains("`@timeout 10m`"));
+        assert!(doc.contains("**Inherited from `base`:**\n- `@ssh host=example.com`"));
+        assert!(!doc.contains("@timeout 5m"));
+    }
+
+    #[test]
+    fn test_duplicate_task_definitions() {
+        let source = "build:\n\techo 1\n\ntest:\n\techo\n\nbuild:\n\techo 2\n";
+        let (ast, _) = parse(source);
+        let uri: Uri = "file:///dagfile".parse().unwrap();
+
+        let diagnostics = check_duplicate_tasks(&uri, source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "task 'build' is already defined");
+        assert_eq!(diagnostics[0].range.start, Position::new(6, 0));
+        let related = &diagnostics[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(related.location.range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_included_tasks_are_defined() {
+        let dir = std::env::temp_dir().join(format!("dr-lsp-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("tasks")).unwrap();
+        std::fs::write(dir.join("tasks/build.dr"), "build:\n\techo\n").unwrap();
+
+        let source = "@include ./tasks/build.dr\n@include ./missing.dr\n\ndeploy: build\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let includes = load_includes(Some(&dir), &ast);
+        assert_eq!(includes.len(), 1);
+        assert!(check_undefined_tasks(source, &ast, &includes).is_empty());
+
+        // the included file is published too, here without errors, unless
+        // it's open and so published on its own
+        let published = included_parse_diagnostics(&includes, &HashMap::new());
+        assert_eq!(published.len(), 1);
+        assert!(published[0].0.as_str().ends_with("tasks/build.dr"));
+        assert!(published[0].1.is_empty());
+        let open = HashMap::from([(includes[0].uri.clone(), String::new())]);
+        assert!(included_parse_diagnostics(&includes, &open).is_empty());
+        assert_eq!(check_undefined_tasks(source, &ast, &[]).len(), 1);
+
+        let missing = check_include_paths(source, &ast, Some(&dir));
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].message.contains("missing.dr"));
+
+        // only the file that exists gets a link
+        let links = collect_document_links(source, &ast, Some(&dir));
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].range.start, Position::new(0, 9));
+        assert!(
+            links[0]
+                .target
+                .as_ref()
+                .unwrap()
+                .as_str()
+                .ends_with("tasks/build.dr")
+        );
+
+        let offset = source.find("build\n\techo").unwrap() as u32;
+        let location = find_included_definition(&ast, offset, Some(&dir), &includes).unwrap();
+        assert!(location.uri.as_str().ends_with("tasks/build.dr"));
+        assert_eq!(location.range.start.line, 0);
+
+        // an open buffer of the included file wins over the file on disk
+        let mut includes = includes;
+        let documents = HashMap::from([(
+            includes[0].uri.clone(),
+            "# unsaved\nbuild:\n\techo\n".to_string(),
+        )]);
+        prefer_open_documents(&mut includes, &documents);
+        let location = find_included_definition(&ast, offset, Some(&dir), &includes).unwrap();
+        assert_eq!(location.range.start.line, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sibling_documents_share_a_project() {
+        let dir = std::env::temp_dir().join(format!("dr-lsp-workspace-{}", std::process::id()));
+        for service in ["api", "worker"] {
+            std::fs::create_dir_all(dir.join("services").join(service).join("ci")).unwrap();
+            std::fs::write(dir.join("services").join(service).join(".dagrun"), "").unwrap();
+        }
+        let uri = |path: &str| Uri::from_file_path(dir.join(path)).unwrap();
+        let api = uri("services/api/.dagrun");
+        let api_ci = uri("services/api/ci/release.dr");
+        let worker = uri("services/worker/.dagrun");
+        let documents = HashMap::from([
+            (api.clone(), String::new()),
+            (api_ci.clone(), String::new()),
+            (worker.clone(), String::new()),
+        ]);
+
+        let mut workspace = WorkspaceManager::default();
+        workspace.add_root(&Uri::from_file_path(&dir).unwrap());
+        let siblings: Vec<&Uri> = workspace
+            .siblings(&api_ci, &documents)
+            .into_iter()
+            .map(|(uri, _)| uri)
+            .collect();
+        assert_eq!(siblings, [&api]);
+        assert!(workspace.siblings(&worker, &documents).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dependents_of_changed_include() {
+        let uri = |path: &str| -> Uri { format!("file:///{}", path).parse().unwrap() };
+        let graph = HashMap::from([
+            (uri("main.dr"), vec![uri("shared.dr"), uri("deep.dr")]),
+            (uri("other.dr"), vec![]),
+        ]);
+        assert_eq!(dependents_of(&graph, &[uri("deep.dr")]), [uri("main.dr")]);
+        assert!(dependents_of(&graph, &[uri("unrelated.dr")]).is_empty());
+    }
+
+    #[test]
+    fn test_incremental_parse_matches_full() {
+        fn change(
+            source: &str,
+            from: usize,
+            to: usize,
+            text: &str,
+        ) -> TextDocumentContentChangeEvent {
+            TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    offset_to_position(source, from),
+                    offset_to_position(source, to),
+                )),
+                range_length: None,
+                text: text.to_string(),
             }
         }
+
+        let source = "version := 1.0\n\n@timeout 5m\nbuild:\n\techo {{version}}\n\n@lua\nfoo()\n@end\n\ntest: build\n\techo test\n";
+        let mut parser = IncrementalParser::new(source.to_string());
+        let edits = [
+            ("echo test", "cargo test"),
+            ("1.0", "2.0"),
+            ("@timeout 5m\n", ""),
+            ("@end\n", ""),
+            ("foo()", "foo()\n@end"),
+            ("build:", "build: lint\n\nlint:"),
+        ];
+        for (old, new) in edits {
+            let from = parser.source.find(old).unwrap();
+            let event = change(&parser.source, from, from + old.len(), new);
+            let (file, errors) = parser.update(&event);
+            let (expected_file, mut expected_errors) = parse(&parser.source);
+            expected_errors.sort_by_key(|e| e.span.start);
+            assert_eq!(
+                format!("{:?}", file),
+                format!("{:?}", expected_file),
+                "after {:?}",
+                new
+            );
+            assert_eq!(format!("{:?}", errors), format!("{:?}", expected_errors));
+        }
+        assert!(parser.source.contains("cargo test"));
+    }
+
+    #[test]
+    fn test_incremental_parse_crlf_and_multibyte() {
+        let source = "name := café\r\n\r\nbuild:\r\n\techo {{name}} 🚀 done\r\n\r\ntest: build\r\n\techo ok\r\n";
+        // 🚀 is two UTF-16 units, so "done" starts at column 18, not 17
+        let done = source.find("done").unwrap();
+        assert_eq!(offset_to_position(source, done), Position::new(3, 18));
+        assert_eq!(
+            position_to_offset(source, Position::new(3, 18)),
+            done as u32
+        );
+        // past the end of a CRLF line stops before the \r
+        assert_eq!(
+            position_to_offset(source, Position::new(0, 40)),
+            source.find('\r').unwrap() as u32
+        );
+
+        let mut parser = IncrementalParser::new(source.to_string());
+        let insert = |line, character, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        };
+        for event in [
+            insert(5, 11, " lint"),
+            insert(3, 18, "almost "),
+            insert(0, 12, "!"),
+            // inside the surrogate pair of 🚀
+            insert(3, 16, "x"),
+        ] {
+            let (file, _) = parser.update(&event);
+            let (expected, _) = parse(&parser.source);
+            assert_eq!(format!("{:?}", file), format!("{:?}", expected));
+        }
+        assert!(parser.source.contains("test: build lint\r\n"));
+        assert!(parser.source.contains("🚀 almost done"));
+        assert!(parser.source.starts_with("name := café!\r\n"));
+    }
+
+    #[test]
+    fn test_env_annotation_undefined_variable() {
+        let source = "port := 8080\n\n@env PORT={{port}} HOST={{host}}\nserve:\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let diagnostics = check_undefined_variables(source, &ast, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "undefined variable 'host'");
+    }
+
+    #[test]
+    fn test_option_key_variables() {
+        let source =
+            "prefix := APP\n\n@env {{prefix}}_PORT=8080 {{missing}}_HOST=x\nserve:\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let diagnostics = check_undefined_variables(source, &ast, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "undefined variable 'missing'");
+
+        // a variable only used in a key still counts as used
+        assert!(check_unused_variables(source, &ast).is_empty());
+    }
+
+    #[test]
+    fn test_parameter_type_defaults() {
+        let source = "release version:int=\"1.x\" patch:bool=\"false\":\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let diagnostics = check_parameter_types(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "default '1.x' for parameter 'version' is not a valid int"
+        );
+    }
+
+    #[test]
+    fn test_hover_shows_evaluated_shell_value() {
+        let source =
+            "sha := `git rev-parse HEAD`\nbad := `false`\n\nbuild:\n\techo {{sha}} {{bad}}\n";
+        let (ast, _) = parse(source);
+        let evaluated = HashMap::from([
+            (
+                "git rev-parse HEAD".to_string(),
+                ShellValue::Output("abc123".to_string()),
+            ),
+            ("false".to_string(), ShellValue::Failed),
+        ]);
+
+        let offset = source.find("{{sha}}").unwrap() as u32 + 2;
+        let (doc, _) = get_hover_info(source, &ast, offset, &evaluated).unwrap();
+        assert!(
+            doc.ends_with("\n\n**Value (evaluated):** `abc123`"),
+            "{}",
+            doc
+        );
+
+        let (doc, _) = get_hover_info(source, &ast, 30, &evaluated).unwrap();
+        assert!(doc.ends_with("**(evaluation error)**"), "{}", doc);
+
+        // nothing extra while it is still running
+        let (doc, _) = get_hover_info(source, &ast, offset, &HashMap::new()).unwrap();
+        assert!(!doc.contains("evaluated"));
+    }
+
+    #[tokio::test]
+    async fn test_run_shell_var() {
+        assert_eq!(
+            run_shell_var("echo ' hi '", None).await,
+            ShellValue::Output("hi".to_string())
+        );
+        assert_eq!(run_shell_var("exit 1", None).await, ShellValue::Failed);
+    }
+
+    #[test]
+    fn test_annotation_separated_by_blank_line() {
+        let source = "build:\n\techo\n\n@timeout 5m\n\n# deploys\n@retry 2\ndeploy:\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let diagnostics = check_orphaned_annotations(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(3, 0));
+        assert_eq!(
+            diagnostics[0].message,
+            "annotation is separated from task 'deploy' by a blank line"
+        );
+    }
+
+    #[test]
+    fn test_option_key_hover() {
+        let source = "@ssh host=server workdir=/app\ndeploy:\n\techo\n";
+        let (ast, _) = parse(source);
+
+        let offset = source.find("workdir").unwrap() as u32 + 2;
+        let (doc, range) =
+            get_hover_info(source, &ast, offset, &HashMap::new()).expect("expected hover");
+        assert!(
+            doc.starts_with("**@ssh** `workdir`\n\nRemote working directory\n\n"),
+            "{}",
+            doc
+        );
+        assert!(doc.contains("**Required:** no"), "{}", doc);
+        assert_eq!(range.start.character, 17);
+        assert_eq!(range.end.character, 24);
+
+        // the value falls back to the whole annotation
+        let offset = source.find("/app").unwrap() as u32;
+        let (doc, _) =
+            get_hover_info(source, &ast, offset, &HashMap::new()).expect("expected hover");
+        assert!(doc.starts_with("**@ssh** - "));
+    }
+
+    #[test]
+    fn test_continued_variable_hover() {
+        let source = "flags := --release \\\n  --locked\nbuild:\n\tcargo build {{flags}}\n";
+        let (ast, _) = parse(source);
+
+        let offset = source.find("--locked").unwrap() as u32;
+        let (doc, range) =
+            get_hover_info(source, &ast, offset, &HashMap::new()).expect("expected hover");
+        assert_eq!(
+            doc,
+            "**Variable:** `flags`\n\n**Value:** `--release --locked`"
+        );
+        assert_eq!(range.start.line, 0);
+        assert_eq!(range.end.line, 1);
+    }
+
+    #[test]
+    fn test_task_hover_summary_table() {
+        let source = "@ssh host=deploy@prod\n@timeout 5m\ndeploy: build\n\techo\n";
+        let (ast, _) = parse(source);
+        let offset = source.find("deploy:").unwrap() as u32;
+        let (doc, _) =
+            get_hover_info(source, &ast, offset, &HashMap::new()).expect("expected hover");
+        assert!(doc.contains("| Name | Dependencies | Timeout | Retry | SSH | K8s |\n|---|"));
+        assert!(doc.contains("| deploy | build | 5m | - | deploy@prod | - |"));
+    }
+
+    #[test]
+    fn test_k8s_prune_without_path() {
+        let source = "@k8s apply prune=true
+deploy:
+	echo
+
+@k8s apply path=./manifests prune=true
+sync:
+	echo
+";
+        let (ast, _) = parse(source);
+        let diagnostics = check_k8s_prune(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 0);
+    }
+
+    #[test]
+    fn test_when_condition_is_informational() {
+        let source = "@when test -f {{flag}}\nmigrate:\n\t./migrate.sh\n";
+        let (ast, _) = parse(source);
+        let diagnostics = check_when_conditions(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(diagnostics[0].range.start.character, 6);
+
+        // its variables are still checked
+        let diagnostics = check_undefined_variables(source, &ast, &[]);
+        assert!(diagnostics[0].message.contains("'flag'"));
+    }
+
+    #[test]
+    fn test_artifact_references_are_defined() {
+        let source = "@artifact bin=target/app\nbuild:\n\tmake\n\nship: build\n\tscp {{artifact.build.bin}} {{host}}:\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let diagnostics = check_undefined_variables(source, &ast, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "undefined variable 'host'");
+    }
+
+    #[test]
+    fn test_matrix_expanded_tasks() {
+        let source = "@matrix target amd64 arm64\nbuild:\n\techo {{target}}\n\nrelease: build-amd64 build-x86\n\techo\n";
+        let (ast, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // the axis is in scope inside the task
+        assert!(check_undefined_variables(source, &ast, &[]).is_empty());
+
+        let diagnostics = check_undefined_tasks(source, &ast, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "undefined task 'build-x86'");
+
+        let names: Vec<String> = collect_document_symbols(source, &ast)
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["build-amd64", "build-arm64", "release"]);
+    }
+
+    #[test]
+    fn test_rename_follows_include_graph() {
+        let dir = std::env::temp_dir().join(format!("dr-lsp-rename-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // lib.dr isn't open, so its edits come from the file on disk
+        std::fs::write(dir.join("lib.dr"), "build:\n\tcargo build\n").unwrap();
+        let uri = |name: &str| Uri::from_file_path(dir.join(name)).unwrap();
+        let (main, lib, user, other) =
+            (uri("dagfile"), uri("lib.dr"), uri("ci.dr"), uri("other.dr"));
+        let documents = HashMap::from([
+            (
+                main.clone(),
+                "@include lib.dr\n\nrelease: build\n\techo\n".to_string(),
+            ),
+            (
+                user.clone(),
+                "@include lib.dr\n\nci: build\n\techo\n".to_string(),
+            ),
+            // an unrelated project with its own `build`
+            (
+                other.clone(),
+                "build:\n\tmake\n\nlint: build\n\tcargo clippy\n".to_string(),
+            ),
+        ]);
+        let include_graph = HashMap::from([
+            (main.clone(), vec![lib.clone()]),
+            (user.clone(), vec![lib.clone()]),
+            (other.clone(), vec![]),
+        ]);
+
+        // renaming the dependency reaches the definition in the included file
+        // and the other file that includes it
+        let changes =
+            workspace_rename_edits(&documents, &include_graph, &main, 27, "compile").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[&main][0].range.start, Position::new(2, 9));
+        assert_eq!(changes[&lib][0].range.start, Position::new(0, 0));
+        assert_eq!(changes[&user][0].range.start, Position::new(2, 4));
+        assert!(changes[&lib].iter().all(|e| e.new_text == "compile"));
+        assert!(!changes.contains_key(&other));
+    }
+
+    #[test]
+    fn test_workspace_symbol_matching() {
+        assert!(symbol_matches("deploy-prod", "dep"));
+        assert!(symbol_matches("deploy-prod", "DPrd"));
+        assert!(symbol_matches("build", ""));
+        assert!(!symbol_matches("build", "deploy"));
+        assert!(!symbol_matches("deploy", "yd"));
+    }
+
+    #[test]
+    fn test_dependency_arg_allowed_values_completion() {
+        let source = "deploy env{dev,staging,prod}:\n\techo {{env}}\n\nrelease: deploy \n\techo\n";
+        let (ast, _) = parse(source);
+        let labels = |character| -> Vec<String> {
+            get_completions(source, &ast, Position::new(3, character))
+                .into_iter()
+                .map(|c| c.label)
+                .collect()
+        };
+        assert_eq!(labels(16), vec!["dev", "staging", "prod"]);
+        // before any arguments, offer task names
+        assert!(labels(9).contains(&"deploy".to_string()));
+    }
+
+    #[test]
+    fn test_annotation_completion_snippets() {
+        let source = "@s\nbuild:\n\techo\n";
+        let (ast, _) = parse(source);
+        let items = get_completions(source, &ast, Position::new(0, 2));
+        let ssh = items.iter().find(|c| c.label == "ssh").unwrap();
+        assert_eq!(
+            ssh.insert_text.as_deref(),
+            Some("ssh host=$1 user=$2 workdir=$3")
+        );
+        assert_eq!(ssh.insert_text_format, Some(InsertTextFormat::SNIPPET));
+    }
+
+    #[test]
+    fn test_pipe_from_completes_task_names() {
+        let source = "gen:\n\techo\n\nlint:\n\techo\n\n@pipe_from gen, \nprocess:\n\tcat\n";
+        let (ast, _) = parse(source);
+        let items = get_completions(source, &ast, Position::new(6, 15));
+        let labels: Vec<&str> = items.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["lint", "process"]);
+        assert!(
+            items
+                .iter()
+                .all(|c| c.kind == Some(CompletionItemKind::FUNCTION))
+        );
+    }
+
+    #[test]
+    fn test_use_completes_and_checks_context_names() {
+        let source = "@context remote\n@ssh host=prod\n@end\n\n@use \nbuild:\n\techo\n";
+        let (ast, _) = parse(source);
+        let items = get_completions(source, &ast, Position::new(4, 5));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "remote");
+        assert_eq!(items[0].kind, Some(CompletionItemKind::MODULE));
+
+        let source = "@context remote\n@ssh host=prod\n@end\n\n@use local\ntest:\n\techo\n";
+        let (ast, _) = parse(source);
+        let diagnostics = check_undefined_contexts(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "undefined context 'local'");
+    }
+
+    #[test]
+    fn test_inlay_hints_show_variable_values() {
+        let source = "flags := --release\nsha := `git rev-parse HEAD`\n\nbuild profile:\n\tcargo build {{flags}} {{sha}} {{profile}} {{nope}}\n";
+        let (ast, _) = parse(source);
+        let hints = collect_inlay_hints(source, &ast, Span::new(0, source.len() as u32));
+        let labels: Vec<String> = hints
+            .iter()
+            .map(|h| match &h.label {
+                InlayHintLabel::String(s) => s.clone(),
+                InlayHintLabel::LabelParts(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(labels, ["(--release)", "(shell)"]);
+        // right after the closing braces
+        assert_eq!(hints[0].position, Position::new(4, 22));
+    }
+
+    #[test]
+    fn test_add_timeout_quick_fix() {
+        let source = "# fetch the data\nfetch:\n\tcurl https://example.com\n";
+        let (ast, _) = parse(source);
+        let uri: Uri = "file:///dagfile".parse().unwrap();
+
+        let diagnostics = check_lints(source, &ast);
+        assert_eq!(diagnostics.len(), 1);
+        let action = add_timeout_action(source, &ast, &uri, &diagnostics[0], "45s").unwrap();
+        assert_eq!(action.title, "Add @timeout 45s");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edit = &changes[&uri][0];
+        assert_eq!(edit.range.start, Position::new(1, 0));
+        assert_eq!(edit.new_text, "@timeout 45s\n");
+    }
+
+    #[test]
+    fn test_settings_default_timeout() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.default_timeout, "30s");
+        settings.update(&serde_json::json!({ "dagrun": { "defaultTimeout": "2m" } }));
+        assert_eq!(settings.default_timeout, "2m");
+    }
+
+    #[test]
+    fn test_settings_evaluate_shell_vars_is_opt_in() {
+        let mut settings = Settings::default();
+        assert!(!settings.evaluate_shell_vars);
+        settings.update(&serde_json::json!({ "evaluateShellVars": true }));
+        assert!(settings.evaluate_shell_vars);
+    }
+
+    #[test]
+    fn test_extract_variable_action() {
+        let source = "@env URL=https://api.example.com\nsync:\n\tcurl https://api.example.com/health\n\techo {{url}}\n";
+        let (ast, _) = parse(source);
+        let uri: Uri = "file:///dagfile".parse().unwrap();
+        let select = |start, end| Range::new(Position::new(2, start), Position::new(2, end));
+
+        let action = extract_variable_action(source, &ast, &uri, select(6, 29)).unwrap();
+        assert_eq!(action.kind, Some(EXTRACT_VARIABLE_KIND));
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = &changes[&uri];
+        assert_eq!(
+            edits[0].new_text,
+            "https_api_example_com := https://api.example.com\n"
+        );
+        // the annotation value and the body occurrence
+        assert_eq!(edits.len(), 3);
+        assert!(
+            edits[1..]
+                .iter()
+                .all(|e| e.new_text == "{{https_api_example_com}}")
+        );
+        assert_eq!(edits[2].range, select(6, 29));
+
+        // nothing to extract across an interpolation
+        let range = Range::new(Position::new(3, 1), Position::new(3, 12));
+        assert!(extract_variable_action(source, &ast, &uri, range).is_none());
+    }
+
+    #[test]
+    fn test_extract_variable_matches_whole_words() {
+        let source = "build:\n\tcp app myapp app_v2 app.tar\n";
+        let (ast, _) = parse(source);
+        let uri: Uri = "file:///dagfile".parse().unwrap();
+        let select = Range::new(Position::new(1, 4), Position::new(1, 7));
+
+        let action = extract_variable_action(source, &ast, &uri, select).unwrap();
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        let ranges: Vec<(u32, u32)> = edits[1..]
+            .iter()
+            .map(|e| (e.range.start.character, e.range.end.character))
+            .collect();
+        // `app` and the `app` of `app.tar`, but not `myapp` or `app_v2`
+        assert_eq!(ranges, [(4, 7), (21, 24)]);
+    }
+
+    #[test]
+    fn test_rename_variable_in_annotation_value() {
+        let uri: Uri = "file:///dagfile".parse().unwrap();
+        let source = "host := box\n\n@ssh host={{host}} workdir=/srv/{{host}}/app user={{hostname}}\ndeploy:\n\techo {{host}}\n";
+        let documents = HashMap::from([(uri.clone(), source.to_string())]);
+
+        let changes =
+            workspace_rename_edits(&documents, &HashMap::new(), &uri, 0, "server").unwrap();
+        let mut edits: Vec<(Position, Position)> = changes[&uri]
+            .iter()
+            .map(|e| {
+                assert_eq!(e.new_text, "server");
+                (e.range.start, e.range.end)
+            })
+            .collect();
+        edits.sort_by_key(|(start, _)| (start.line, start.character));
+        // only the names inside the braces; `{{hostname}}` is a different variable
+        assert_eq!(
+            edits,
+            [
+                (Position::new(0, 0), Position::new(0, 4)),
+                (Position::new(2, 12), Position::new(2, 16)),
+                (Position::new(2, 34), Position::new(2, 38)),
+                (Position::new(4, 8), Position::new(4, 12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signature_help_for_annotation_options() {
+        let active = |line: &str| {
+            let help = get_signature_help(line, Position::new(0, line.len() as u32))?;
+            help.active_parameter
+        };
+
+        // nothing until the keyword is followed by a space
+        assert_eq!(active("@ssh"), None);
+        assert_eq!(active("@timeout 5m"), None);
+        assert_eq!(active("@ssh "), Some(0));
+        // the option whose value is being typed
+        assert_eq!(active("@ssh host=a port="), Some(4));
+        // otherwise one past the pairs already written
+        assert_eq!(active("@ssh host=a workdir=/app "), Some(2));
+
+        let help = get_signature_help("@ssh ", Position::new(0, 5)).unwrap();
+        let params = help.signatures[0].parameters.as_ref().unwrap();
+        assert_eq!(params.len(), dr_ast::docs::SSH.options.len());
+    }
+
+    #[test]
+    fn test_code_lenses_run_each_task() {
+        let source = "build:\n\tcargo build\n\n@matrix target a b\ntest:\n\techo {{target}}\n";
+        let (ast, _) = parse(source);
+        let uri: Uri = "file:///work/app/dagfile".parse().unwrap();
+        let lenses = collect_code_lenses(source, &ast, &uri);
+        let commands: Vec<(String, Vec<LSPAny>)> = lenses
+            .iter()
+            .map(|l| {
+                let cmd = l.command.as_ref().unwrap();
+                assert_eq!(cmd.command, RUN_TASK_COMMAND);
+                (cmd.title.clone(), cmd.arguments.clone().unwrap())
+            })
+            .collect();
+        let dagfile = serde_json::json!("file:///work/app/dagfile");
+        assert_eq!(
+            commands,
+            [
+                (
+                    "▶ Run".to_string(),
+                    vec![serde_json::json!("build"), dagfile.clone()]
+                ),
+                (
+                    "▶ Run test-a".to_string(),
+                    vec![serde_json::json!("test-a"), dagfile.clone()]
+                ),
+                (
+                    "▶ Run test-b".to_string(),
+                    vec![serde_json::json!("test-b"), dagfile]
+                ),
+            ]
+        );
+        assert_eq!(lenses[1].range.start, Position::new(4, 0));
+    }
+
+    #[test]
+    fn test_run_task_process_uses_dagfile() {
+        let command = run_task_process("build", Some(Path::new("/work/app/tasks.dr")));
+        let command = command.as_std();
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args, ["-c", "/work/app/tasks.dr", "run", "build"]);
+        assert_eq!(command.get_current_dir(), Some(Path::new("/work/app")));
+
+        let command = run_task_process("build", None);
+        let args: Vec<&std::ffi::OsStr> = command.as_std().get_args().collect();
+        assert_eq!(args, ["run", "build"]);
+        assert_eq!(command.as_std().get_current_dir(), None);
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let source = "# build\n# the crate\n@timeout 5m\nbuild:\n\tcargo build\n\tcargo test\n\n# unrelated\n\n@lua\nprint(1)\n@end\n\nquick:\n\techo\n";
+        let (ast, _) = parse(source);
+        let folds: Vec<(u32, u32, FoldingRangeKind)> = collect_folding_ranges(source, &ast)
+            .into_iter()
+            .map(|f| (f.start_line, f.end_line, f.kind.unwrap()))
+            .collect();
+        assert_eq!(
+            folds,
+            [
+                (0, 5, FoldingRangeKind::Region),
+                (9, 11, FoldingRangeKind::Imports),
+                (13, 14, FoldingRangeKind::Region),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_spans_expand_outward() {
+        let source = "@timeout 5m\ndeploy:\n\techo {{env}} done\n";
+        let (ast, _) = parse(source);
+        let offset = source.find("env").unwrap() as u32 + 1;
+        let texts: Vec<&str> = selection_spans(source, &ast, offset)
+            .iter()
+            .map(|s| s.text(source))
+            .collect();
+        assert_eq!(texts[0], "env");
+        assert_eq!(texts[1], "{{env}}");
+        assert!(texts.contains(&"echo {{env}} done"));
+        assert!(texts[texts.len() - 2].starts_with("@timeout 5m"));
+        assert_eq!(*texts.last().unwrap(), source);
+
+        let range = build_selection_range(source, &selection_spans(source, &ast, offset));
+        assert_eq!(range.range.start, Position::new(2, 8));
+        assert!(range.parent.is_some());
     }
 }
@@ -0,0 +1,128 @@
+//! Detached task runs for `dr run --background` and `dr kill`
+//!
+//! A background run re-executes `dr` in its own session with output sent to
+//! `.dagrun/logs/<task>.log`, and records its pid in `.dagrun/pids/<task>.pid`.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+fn pid_path(task: &str) -> PathBuf {
+    PathBuf::from(".dagrun")
+        .join("pids")
+        .join(format!("{}.pid", task))
+}
+
+fn log_path(task: &str) -> PathBuf {
+    PathBuf::from(".dagrun")
+        .join("logs")
+        .join(format!("{}.log", task))
+}
+
+/// Pid of a live background run of `task`; stale pid files are removed
+pub fn running_pid(task: &str) -> Option<u32> {
+    let path = pid_path(task);
+    let pid: u32 = fs::read_to_string(&path).ok()?.trim().parse().ok()?;
+    if is_alive(pid) {
+        Some(pid)
+    } else {
+        let _ = fs::remove_file(path);
+        None
+    }
+}
+
+/// Tasks with a live background run and their pids, sorted by task name
+pub fn running() -> Vec<(String, u32)> {
+    let Ok(entries) = fs::read_dir(PathBuf::from(".dagrun").join("pids")) else {
+        return Vec::new();
+    };
+    let mut running: Vec<(String, u32)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let task = path
+                .file_name()?
+                .to_str()?
+                .strip_suffix(".pid")?
+                .to_string();
+            let pid = running_pid(&task)?;
+            Some((task, pid))
+        })
+        .collect();
+    running.sort();
+    running
+}
+
+/// Remove the pid file if it belongs to this process
+pub fn clear_own_pid(task: &str) {
+    if running_pid(task) == Some(std::process::id()) {
+        let _ = fs::remove_file(pid_path(task));
+    }
+}
+
+/// Re-run `dr` with `args` detached from the terminal, returning the child pid
+pub fn spawn(task: &str, args: &[String]) -> io::Result<u32> {
+    let log = log_path(task);
+    fs::create_dir_all(log.parent().unwrap())?;
+    let log_file = fs::File::create(&log)?;
+
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file);
+
+    // own process group, so the terminal's signals skip it and `kill` can reach
+    // every process the run starts
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    let child = cmd.spawn()?;
+    let pid = child.id();
+
+    let path = pid_path(task);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, pid.to_string())?;
+
+    Ok(pid)
+}
+
+/// Send SIGTERM to the background run of `task` and every process it started,
+/// returning its pid
+pub fn kill(task: &str) -> io::Result<u32> {
+    let pid = running_pid(task).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("task '{}' is not running in the background", task),
+        )
+    })?;
+
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::Pid;
+
+        // the run leads its own process group, so the negative pid reaches the whole group
+        kill(Pid::from_raw(-(pid as i32)), Signal::SIGTERM).map_err(io::Error::from)?;
+    }
+
+    let _ = fs::remove_file(pid_path(task));
+    Ok(pid)
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    use nix::sys::signal::kill;
+    use nix::unistd::Pid;
+
+    // signal 0 only checks that the process exists
+    kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+    false
+}
@@ -0,0 +1,128 @@
+//! Table and CSV renderings for `dr list`
+
+use crate::dag::TaskGraph;
+use dr_ast::Task;
+
+const HEADERS: [&str; 6] = ["Name", "Dependencies", "Timeout", "Retry", "SSH", "K8s"];
+
+/// Fixed-width table, one row per task
+pub fn format_table(graph: &TaskGraph) -> String {
+    let rows: Vec<Vec<String>> = tasks(graph)
+        .map(|task| {
+            row(task)
+                .into_iter()
+                .map(|cell| if cell.is_empty() { "-".into() } else { cell })
+                .collect()
+        })
+        .collect();
+    render_table(&HEADERS, &rows)
+}
+
+/// Header row plus one row per task, for spreadsheet import
+pub fn format_csv(graph: &TaskGraph) -> String {
+    let mut out = csv_line(HEADERS.iter().map(|h| h.to_string()).collect());
+    for task in tasks(graph) {
+        out.push_str(&csv_line(row(task)));
+    }
+    out
+}
+
+fn tasks(graph: &TaskGraph) -> impl Iterator<Item = &Task> {
+    graph
+        .task_names()
+        .into_iter()
+        .filter_map(|name| graph.task(name))
+}
+
+fn row(task: &Task) -> Vec<String> {
+    vec![
+        task.name.clone(),
+        task.depends_on.join(", "),
+        task.timeout
+            .map(|d| humantime::format_duration(d).to_string())
+            .unwrap_or_default(),
+        if task.retry > 0 {
+            task.retry.to_string()
+        } else {
+            String::new()
+        },
+        task.ssh
+            .as_ref()
+            .map(|ssh| ssh.destination())
+            .unwrap_or_default(),
+        task.k8s
+            .as_ref()
+            .map(|k8s| k8s.mode.as_str().to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+/// Render rows as a fixed-width table, each column as wide as its widest cell
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let render = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut lines = vec![render(headers.to_vec())];
+    lines.push(render(rule.iter().map(String::as_str).collect()));
+    for row in rows {
+        lines.push(render(row.iter().map(String::as_str).collect()));
+    }
+    lines.join("\n")
+}
+
+fn csv_line(cells: Vec<String>) -> String {
+    let escaped: Vec<String> = cells
+        .into_iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        })
+        .collect();
+    format!("{}\n", escaped.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_columns_align() {
+        let rows = vec![
+            vec!["build".to_string(), "-".to_string()],
+            vec!["integration-test".to_string(), "build".to_string()],
+        ];
+        let table = render_table(&["Name", "Dependencies"], &rows);
+        assert_eq!(
+            table,
+            "Name              Dependencies\n\
+             ----------------  ------------\n\
+             build             -\n\
+             integration-test  build"
+        );
+    }
+
+    #[test]
+    fn test_csv_escapes_cells() {
+        assert_eq!(
+            csv_line(vec!["a".into(), "b,c".into(), "say \"hi\"".into()]),
+            "a,\"b,c\",\"say \"\"hi\"\"\"\n"
+        );
+    }
+}
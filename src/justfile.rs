@@ -10,22 +10,40 @@ use dr_ast::Config;
 pub enum ParseError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("parse error at line {0}: {1}")]
-    Syntax(usize, String),
+    #[error("{message} (line {line}, column {column})")]
+    Syntax {
+        message: String,
+        line: usize,
+        column: usize,
+    },
     #[error("lua error: {0}")]
     Lua(String),
 }
 
 pub fn load_justflow<P: AsRef<Path>>(path: P) -> Result<Config, ParseError> {
-    let content = std::fs::read_to_string(path)?;
-    parse_justflow(&content)
+    let content = std::fs::read_to_string(&path)?;
+    // @include paths are relative to the dagfile
+    finish_justflow(&content, dr_ast::parse_config_at(&content, path.as_ref()))
 }
 
-pub fn parse_justflow(content: &str) -> Result<Config, ParseError> {
+/// Parse dagfile source without a path, so `@include` resolves against the cwd
+#[cfg(test)]
+fn parse_justflow(content: &str) -> Result<Config, ParseError> {
+    finish_justflow(content, dr_ast::parse_config(content))
+}
+
+fn finish_justflow(
+    content: &str,
+    parsed: Result<Config, dr_ast::ParseConfigError>,
+) -> Result<Config, ParseError> {
     // parse using the new semantic parser
-    let mut config = dr_ast::parse_config(content).map_err(|e| {
-        let line = content[..e.span.start as usize].lines().count();
-        ParseError::Syntax(line, e.message)
+    let mut config = parsed.map_err(|e| {
+        let (line, column) = e.span.line_col(content);
+        ParseError::Syntax {
+            message: e.message,
+            line,
+            column,
+        }
     })?;
 
     // process lua blocks separately (dr_ast doesn't have mlua dependency)
@@ -178,7 +196,6 @@ mod tests {
     fn test_env_annotation() {
         let config = parse_justflow("@ssh host=server\n@env FOO=bar\ntask:\n\techo hi").unwrap();
         let task = config.tasks.get("task").unwrap();
-        let ssh = task.ssh.as_ref().unwrap();
-        assert_eq!(ssh.env.get("FOO"), Some(&"bar".to_string()));
+        assert_eq!(task.env.get("FOO"), Some(&"bar".to_string()));
     }
 }
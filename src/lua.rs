@@ -36,7 +36,9 @@ use std::path::Path;
 use std::time::Duration;
 use thiserror::Error;
 
-use dr_ast::{Config, DotenvSettings, K8sConfig, K8sMode, Shebang, SshConfig, Task};
+use dr_ast::{
+    Config, DotenvSettings, ErrorHandling, K8sConfig, K8sMode, PagerMode, Shebang, SshConfig, Task,
+};
 
 #[derive(Error, Debug)]
 pub enum LuaConfigError {
@@ -84,6 +86,10 @@ pub fn parse_lua_config(content: &str) -> Result<Config, LuaConfigError> {
     Ok(Config {
         tasks,
         dotenv: DotenvSettings::default(),
+        error_handling: ErrorHandling::default(),
+        k8s_default_ttl: None,
+        pager: PagerMode::default(),
+        concurrency_by_tag: HashMap::new(),
     })
 }
 
@@ -97,6 +103,10 @@ fn parse_ssh_config(opts: &Table) -> LuaResult<SshConfig> {
         upload: Vec::new(),
         download: Vec::new(),
         env: HashMap::new(),
+        stderr_prefix: opts.get("stderr_prefix").ok(),
+        pty: opts.get("pty").unwrap_or(false),
+        password_env: opts.get("password_env").ok(),
+        proxy: opts.get("proxy").ok(),
     })
 }
 
@@ -105,6 +115,9 @@ fn parse_k8s_config(opts: &Table) -> LuaResult<K8sConfig> {
     let mode = match mode_str.as_str() {
         "exec" => K8sMode::Exec,
         "apply" => K8sMode::Apply,
+        "debug" => K8sMode::Debug {
+            image: opts.get("image")?,
+        },
         _ => K8sMode::Job,
     };
 
@@ -148,6 +161,11 @@ fn parse_k8s_config(opts: &Table) -> LuaResult<K8sConfig> {
         service_account: opts.get("service_account").ok(),
         ttl_seconds: opts.get("ttl_seconds").ok(),
         path: opts.get("path").ok(),
+        prune: opts.get("prune").unwrap_or(false),
+        server_side: opts.get("server_side").unwrap_or(false),
+        force_conflicts: opts.get("force_conflicts").unwrap_or(false),
+        use_kubectl_cp: opts.get("use_kubectl_cp").unwrap_or(false),
+        setup_cmd: opts.get("setup_cmd").ok(),
         wait_for,
         wait_timeout,
         upload: Vec::new(),
@@ -213,6 +231,16 @@ fn parse_task_table(name: &str, opts: &Table) -> LuaResult<Task> {
         k8s,
         service: None,
         shebang,
+        inputs: Vec::new(),
+        output_capture: None,
+        tags: opts.get::<Vec<String>>("tags").unwrap_or_default(),
+        outputs: Vec::new(),
+        webhook: None,
+        env: HashMap::new(),
+        condition: opts.get("when").ok(),
+        cache_key: opts.get("cache").ok(),
+        artifacts: HashMap::new(),
+        on_failure: None,
         span: None,
     })
 }
@@ -1,21 +1,25 @@
-mod dag;
-mod env;
-mod executor;
+mod background;
+mod display;
 mod history;
 mod justfile;
-mod k8s;
 mod lua;
-mod progress;
-mod recorder;
-mod service;
-mod ssh;
+mod manpage;
+mod pager;
+mod stats;
 mod tui;
+mod watch;
+
+use dagrun_executor::{DryRunFormat, dag, env, executor, progress, recorder};
 
 use clap::{Parser, Subcommand};
 use colored::Colorize;
+use opentelemetry_sdk::trace::TracerProvider;
+use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command as StdCommand;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer};
@@ -24,8 +28,10 @@ use crate::dag::TaskGraph;
 use crate::executor::{Executor, TaskStatus};
 use crate::justfile::load_justflow;
 use crate::lua::load_lua_config;
+use crate::pager::Pager;
 use crate::recorder::{NoOpRecorder, Recorder, SqliteRecorder};
-use dr_ast::{Config, Task};
+use dr_ast::validate::LintLevel;
+use dr_ast::{Config, K8sMode, PagerMode, Task};
 use serde::Serialize;
 
 /// JSON output for `list --format json`
@@ -99,7 +105,7 @@ impl TaskInfo {
 }
 
 #[derive(Parser)]
-#[command(name = "dr")]
+#[command(name = "dr", version)]
 #[command(about = "DAG-based task runner with retry and timeout support", long_about = None)]
 struct Cli {
     /// Path to config file (dagfile or .lua)
@@ -114,41 +120,133 @@ struct Cli {
     #[arg(short, long, global = true)]
     quiet: bool,
 
+    /// Don't export spans, even if OTEL_EXPORTER_OTLP_ENDPOINT is set
+    #[arg(long, global = true)]
+    no_telemetry: bool,
+
+    /// Load an env file for this run (repeatable; later files win)
+    #[arg(long = "env-file", global = true, value_name = "PATH")]
+    env_files: Vec<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Options shared by `dr run <task>` and the implicit `dr <task>` form
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Task name to run
+    task: String,
+
+    /// Run only this task, skip dependencies
+    #[arg(long)]
+    only: bool,
+
+    /// Disable run history recording
+    #[arg(long)]
+    no_record: bool,
+
+    /// Run all tasks in the dependency closure concurrently, ignoring ordering (unsafe)
+    #[arg(long, conflicts_with = "only")]
+    parallel: bool,
+
+    /// Cap how many tasks with a tag run at once, e.g. `deploy=1,test=4`. Only
+    /// matters where tasks run at once: with --parallel, or in `dr run-all`
+    #[arg(long, value_name = "TAG=N,...", value_delimiter = ',', value_parser = parse_tag_limit)]
+    concurrency_by_tag: Vec<(String, usize)>,
+
+    /// Cap how many tasks run at once (default: no limit)
+    #[arg(long, value_name = "N", value_parser = parse_concurrency)]
+    concurrency: Option<usize>,
+
+    /// Start at this task in the chain, skipping the tasks it doesn't lead to
+    #[arg(long, value_name = "TASK", conflicts_with_all = ["only", "parallel"])]
+    from: Option<String>,
+
+    /// Leave these tasks out of the chain; tasks depending on them still run
+    #[arg(long, value_name = "TASK,...", value_delimiter = ',', conflicts_with_all = ["only", "parallel"])]
+    skip: Vec<String>,
+
+    /// Don't pass piped stdin to the first task
+    #[arg(long)]
+    no_stdin: bool,
+
+    /// Set an environment variable for task commands (repeatable). A value
+    /// without `=` sets the task's `env` parameter instead, as in `--env=prod`
+    #[arg(long = "env", value_name = "KEY=VALUE")]
+    env: Vec<String>,
+
+    /// Read task arguments from a file, one per line (before any positional args)
+    #[arg(long, value_name = "PATH")]
+    args_from_file: Option<PathBuf>,
+
+    /// Page output through `less` when stdout is a terminal (same as `set pager := auto`)
+    #[arg(long)]
+    pager: bool,
+
+    /// Detach and run in the background, logging to .dagrun/logs/<task>.log
+    #[arg(long, conflicts_with = "pager")]
+    background: bool,
+
+    /// Attach the task to the terminal for debugging (no output capture, no timeout)
+    #[arg(long, conflicts_with_all = ["parallel", "background", "pager"])]
+    interactive: bool,
+
+    /// Print the commands that would run as a shell script, without running anything
+    #[arg(long, conflicts_with_all = ["parallel", "background", "interactive"])]
+    dry_run: bool,
+
+    /// With --dry-run: `script` prints a shell script you could run, `lines` prints
+    /// one `[dry-run] <task>: <cmd>` line per task
+    #[arg(long, value_name = "FORMAT", default_value = "script", value_parser = ["script", "lines"], requires = "dry_run")]
+    dry_run_format: String,
+
+    /// Results format: text, or json for a list of task results on stdout
+    #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+    format: String,
+
+    /// With --format json, include each task's output
+    #[arg(long)]
+    capture: bool,
+
+    /// Positional arguments for task parameters. Flags after them are still
+    /// parsed as flags; pass values starting with `-` after `--`
+    #[arg(allow_negative_numbers = true)]
+    args: Vec<String>,
+}
+
+/// Parser for the implicit form, where the external subcommand is the task name
+#[derive(Parser)]
+#[command(name = "dr", no_binary_name = true)]
+struct ImplicitRun {
+    #[command(flatten)]
+    run: RunArgs,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Run a specific task and its dependencies
     #[command(hide = true)]
-    Run {
-        /// Task name to run
-        task: String,
-
-        /// Run only this task, skip dependencies
-        #[arg(long)]
-        only: bool,
-
-        /// Disable run history recording
-        #[arg(long)]
-        no_record: bool,
-
-        /// Positional arguments for task parameters
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
+    Run(RunArgs),
 
     /// Run all tasks in the graph
     RunAll {
         /// Disable run history recording
         #[arg(long)]
         no_record: bool,
+
+        /// Cap how many tasks with a tag run at once, e.g. `deploy=1,test=4`
+        #[arg(long, value_name = "TAG=N,...", value_delimiter = ',', value_parser = parse_tag_limit)]
+        concurrency_by_tag: Vec<(String, usize)>,
+
+        /// Cap how many tasks run at once (default: no limit)
+        #[arg(long, value_name = "N", value_parser = parse_concurrency)]
+        concurrency: Option<usize>,
     },
 
     /// List all available tasks
     List {
-        /// Output format: text or json
+        /// Output format: text, json, table, or csv
         #[arg(short, long, default_value = "text")]
         format: String,
     },
@@ -162,10 +260,77 @@ enum Commands {
         /// Output file for png format
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Only show this task and the tasks it depends on
+        #[arg(long, value_name = "TASK")]
+        subgraph: Option<String>,
+
+        /// With --subgraph, show the tasks that depend on it instead
+        #[arg(long, requires = "subgraph")]
+        reverse: bool,
     },
 
     /// Validate the config file
-    Validate,
+    Validate {
+        /// Also report likely mistakes, failing if any are found
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Rewrite the dagfile in canonical form
+    Fmt {
+        /// Fail instead of writing if the file isn't already formatted
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Run a task, then run it again whenever the dagfile or an uploaded file changes
+    Watch {
+        /// Task name
+        task: String,
+    },
+
+    /// Stop a task started with `dr run --background`
+    Kill {
+        /// Task name
+        task: String,
+    },
+
+    /// Show background runs, and with --metrics the latest service metrics
+    Status {
+        /// Also show the newest metrics scraped from `@service metrics=...`
+        #[arg(long)]
+        metrics: bool,
+    },
+
+    /// Show timing stats for a task from .dagrun/stats.json
+    Profile {
+        /// Task name
+        task: String,
+
+        /// Also list each recorded run, oldest first
+        #[arg(long)]
+        history: bool,
+    },
+
+    /// Trim .dagrun/stats.json to the most recent entries
+    Gc {
+        /// Number of entries to keep
+        #[arg(long, default_value = "1000")]
+        keep: usize,
+
+        /// Also delete Kubernetes jobs left behind by killed runs once their TTL
+        /// (`@k8s ttl=` or `set k8s-default-ttl`) has passed
+        #[arg(long)]
+        k8s: bool,
+    },
+
+    /// Print the manpage (roff) to stdout or a file
+    Man {
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 
     /// Browse run history in a TUI
     Tui,
@@ -202,58 +367,88 @@ enum Commands {
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    setup_tracing(cli.verbose, cli.quiet);
+    // flushes spans on return; drop it before `std::process::exit`
+    let otel = TracingGuard(setup_tracing(cli.verbose, cli.quiet, cli.no_telemetry));
+
+    // the manpage doesn't need a dagfile
+    if let Commands::Man { output } = &cli.command {
+        use clap::CommandFactory;
+        match output {
+            Some(path) => manpage::render(Cli::command(), &mut std::fs::File::create(path)?)?,
+            None => manpage::render(Cli::command(), &mut std::io::stdout().lock())?,
+        }
+        return Ok(());
+    }
 
     let config_path = match cli.config {
         Some(p) => p,
         None => find_config_file()?,
     };
+
+    // formatting only needs the syntax tree, not a loadable config
+    if let Commands::Fmt { check } = &cli.command {
+        if config_path.extension().is_some_and(|e| e == "lua") {
+            anyhow::bail!("dr fmt only formats dagfiles, not lua configs");
+        }
+        let source = std::fs::read_to_string(&config_path)?;
+        let formatted = dagrun_fmt::format_source(&source).map_err(|errors| {
+            anyhow::anyhow!(
+                "cannot format {}: {} ({})",
+                config_path.display(),
+                errors[0],
+                errors[0].span.display(&source)
+            )
+        })?;
+        if formatted == source {
+            return Ok(());
+        }
+        if *check {
+            anyhow::bail!("{} is not formatted", config_path.display());
+        }
+        std::fs::write(&config_path, formatted)?;
+        println!("{} Formatted {}", "✓".green(), config_path.display());
+        return Ok(());
+    }
+
     let config = load_config(&config_path)?;
 
+    // --env-file takes priority over the dagfile's dotenv settings, even with
+    // dotenv-override
+    let env_file_vars = match env::load_env_files(&cli.env_files) {
+        Ok(names) => names,
+        Err(e) => anyhow::bail!("Failed to load env file: {}", e),
+    };
+
     // load dotenv files if configured
-    if let Err(e) = env::load_dotenv(&config.dotenv) {
+    if let Err(e) = env::load_dotenv(&config.dotenv, &env_file_vars) {
         anyhow::bail!("Failed to load dotenv: {}", e);
     }
 
+    let error_handling = config.error_handling;
+    let pager_mode = config.pager;
+    let tag_limits = config.concurrency_by_tag.clone();
     let graph = TaskGraph::from_config(config)?;
 
     // determine what to run: explicit subcommand or implicit task name
-    let (task, only, no_record, args) = match cli.command {
-        Commands::Run {
-            task,
-            only,
-            no_record,
-            args,
-        } => (task, only, no_record, args),
+    let run_args = match cli.command {
+        Commands::Run(run_args) => run_args,
         Commands::External(ext_args) => {
-            // parse external args: first is task name, rest are args
-            // check for --only and --no-record flags
-            let mut task_name = None;
-            let mut only = false;
-            let mut no_record = false;
-            let mut task_args = Vec::new();
-
-            for arg in ext_args.iter() {
-                if arg == "--only" {
-                    only = true;
-                } else if arg == "--no-record" {
-                    no_record = true;
-                } else if task_name.is_none() {
-                    task_name = Some(arg);
-                } else {
-                    task_args.push(arg.to_owned());
-                }
-            }
-
-            let Some(task) = task_name else {
+            if ext_args.is_empty() {
                 // no task specified, show help
                 use clap::CommandFactory;
                 Cli::command().print_help()?;
                 return Ok(());
-            };
-            (task.to_owned(), only, no_record, task_args)
+            }
+            // the task name comes first, followed by the same flags `dr run` takes
+            ImplicitRun::try_parse_from(&ext_args)
+                .unwrap_or_else(|e| e.exit())
+                .run
         }
-        Commands::RunAll { no_record } => {
+        Commands::RunAll {
+            no_record,
+            concurrency_by_tag,
+            concurrency,
+        } => {
             let recorder: Arc<dyn Recorder> = if no_record {
                 Arc::new(NoOpRecorder)
             } else {
@@ -265,17 +460,29 @@ async fn main() -> anyhow::Result<()> {
                     }
                 }
             };
-            let executor = Executor::new(graph, recorder);
+            let cancel = cancel_on_ctrl_c();
+            let executor = Executor::new(graph, recorder)
+                .with_config_path(config_path.to_string_lossy())
+                .with_error_handling(error_handling)
+                .with_tag_concurrency(tag_limits.into_iter().chain(concurrency_by_tag))
+                .with_concurrency(concurrency)
+                .with_cancellation(cancel.clone());
             executor.register_services().await;
-            let config_path_str = config_path.to_string_lossy().to_string();
-            let results = executor.run_all(&config_path_str).await?;
+            let results = executor.run_all().await?;
             executor.close().await;
+            drop(otel);
             print_results(&results);
+            if cancel.is_cancelled() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
             if results.iter().any(|r| r.status == TaskStatus::Failed) {
                 std::process::exit(1);
             }
             return Ok(());
         }
+        Commands::Man { .. } | Commands::Fmt { .. } => {
+            unreachable!("handled before loading config")
+        }
         Commands::Tui => {
             tui::run_tui().await?;
             return Ok(());
@@ -300,6 +507,8 @@ async fn main() -> anyhow::Result<()> {
                     let output = ListOutput::from_graph(&graph);
                     println!("{}", serde_json::to_string_pretty(&output).unwrap());
                 }
+                "table" => println!("{}", display::format_table(&graph)),
+                "csv" => print!("{}", display::format_csv(&graph)),
                 _ => {
                     println!("{}", "Tasks:".bold());
                     for name in graph.task_names() {
@@ -315,7 +524,21 @@ async fn main() -> anyhow::Result<()> {
             }
             return Ok(());
         }
-        Commands::Graph { format, output } => {
+        Commands::Graph {
+            format,
+            output,
+            subgraph,
+            reverse,
+        } => {
+            let subgraph = match subgraph {
+                Some(name) if graph.task(&name).is_none() => {
+                    anyhow::bail!("Task '{}' not found", name);
+                }
+                Some(name) if reverse => Some(graph.dependents_subgraph_for(&name)),
+                Some(name) => Some(graph.subgraph_for(&name)),
+                None => None,
+            };
+            let graph = subgraph.as_ref().unwrap_or(&graph);
             match format.as_str() {
                 "ascii" => {
                     println!("{}", graph.to_ascii());
@@ -334,7 +557,6 @@ async fn main() -> anyhow::Result<()> {
                         .stdin(std::process::Stdio::piped())
                         .spawn()?;
 
-                    use std::io::Write;
                     child.stdin.as_mut().unwrap().write_all(dot.as_bytes())?;
                     child.wait()?;
 
@@ -346,13 +568,192 @@ async fn main() -> anyhow::Result<()> {
             }
             return Ok(());
         }
-        Commands::Validate => {
+        Commands::Validate { strict } => {
+            let is_lua = config_path.extension().is_some_and(|e| e == "lua");
+            if strict && !is_lua {
+                let source = std::fs::read_to_string(&config_path)?;
+                let (ast, _) = dr_ast::parse(&source);
+                let lints = dr_ast::validate::lint(&ast);
+                for lint in &lints {
+                    let level = match lint.level {
+                        LintLevel::Warning => "warning".yellow(),
+                        LintLevel::Hint => "hint".cyan(),
+                    };
+                    println!(
+                        "{}: {} ({})",
+                        level,
+                        lint.message,
+                        lint.span.display(&source)
+                    );
+                }
+                if lints.iter().any(|l| l.level == LintLevel::Warning) {
+                    anyhow::bail!("config has warnings");
+                }
+            }
             println!("{} Config is valid!", "✓".green());
             println!("  {} tasks defined", graph.task_names().len());
             return Ok(());
         }
+        Commands::Watch { task } => {
+            if graph.task(&task).is_none() {
+                anyhow::bail!("Task '{}' not found", task);
+            }
+            watch::run(&config_path, &task).await?;
+            return Ok(());
+        }
+        Commands::Kill { task } => {
+            let pid = background::kill(&task)?;
+            println!("{} Sent SIGTERM to '{}' (pid {})", "✓".green(), task, pid);
+            return Ok(());
+        }
+        Commands::Status { metrics } => {
+            let running = background::running();
+            if running.is_empty() {
+                println!("No background runs");
+            }
+            for (task, pid) in running {
+                println!("  {} {} (pid {})", "▶".blue(), task, pid);
+            }
+            if metrics {
+                print_service_metrics()?;
+            }
+            return Ok(());
+        }
+        Commands::Profile { task, history } => {
+            let entries: Vec<_> = stats::load()?
+                .into_iter()
+                .filter(|e| e.task == task)
+                .collect();
+            let durations: Vec<u64> = entries.iter().map(|e| e.duration_ms).collect();
+            let Some(summary) = stats::summarize(&durations) else {
+                anyhow::bail!("No recorded runs for '{}'", task);
+            };
+
+            println!("{} ({} runs)", task.bold(), summary.count);
+            for (label, ms) in [
+                ("mean", summary.mean),
+                ("p50", summary.p50),
+                ("p95", summary.p95),
+                ("min", summary.min),
+                ("max", summary.max),
+            ] {
+                println!("  {:<5} {}", label, stats::format_ms(ms));
+            }
+            println!();
+            print!("{}", stats::histogram(&durations, 10));
+
+            if history {
+                println!();
+                for entry in &entries {
+                    let when =
+                        std::time::UNIX_EPOCH + std::time::Duration::from_secs(entry.timestamp);
+                    println!(
+                        "  {}  {}",
+                        humantime::format_rfc3339_seconds(when).to_string().dimmed(),
+                        stats::format_ms(entry.duration_ms)
+                    );
+                }
+            }
+            return Ok(());
+        }
+        Commands::Gc { keep, k8s } => {
+            let dropped = stats::truncate(keep)?;
+            println!(
+                "{} Removed {} stats entries, kept at most {}",
+                "✓".green(),
+                dropped,
+                keep
+            );
+            if k8s {
+                let deleted = dagrun_executor::k8s::gc_jobs().await?;
+                for job in &deleted {
+                    println!("  deleted job {}", job);
+                }
+                println!("{} Deleted {} stale k8s jobs", "✓".green(), deleted.len());
+            }
+            return Ok(());
+        }
     };
 
+    let RunArgs {
+        task,
+        only,
+        no_record,
+        parallel,
+        concurrency_by_tag,
+        concurrency,
+        from,
+        skip,
+        no_stdin,
+        env,
+        args_from_file,
+        pager,
+        background,
+        interactive,
+        dry_run,
+        dry_run_format,
+        format,
+        capture,
+        mut args,
+    } = run_args;
+
+    if let Some(pid) = background::running_pid(&task)
+        && pid != std::process::id()
+    {
+        anyhow::bail!(
+            "Task '{}' is already running in the background (pid {}); stop it with `dr kill {}`",
+            task,
+            pid,
+            task
+        );
+    }
+    if background {
+        // the detached copy gets the same command line, minus --background
+        let child_args: Vec<String> = std::env::args()
+            .skip(1)
+            .filter(|a| a != "--background")
+            .collect();
+        let pid = background::spawn(&task, &child_args)?;
+        println!(
+            "{} Started '{}' in the background (pid {}), logging to .dagrun/logs/{}.log",
+            "✓".green(),
+            task,
+            pid,
+            task
+        );
+        return Ok(());
+    }
+
+    if let Some(path) = args_from_file {
+        let mut file_args = read_args_file(&path)?;
+        file_args.append(&mut args);
+        args = file_args;
+    }
+    // `--env=prod` names an `env` parameter when the task has one
+    let has_env_param = graph
+        .task(&task)
+        .is_some_and(|t| t.parameters.iter().any(|p| p.name == "env"));
+    let (env_params, env): (Vec<String>, Vec<String>) = env
+        .into_iter()
+        .partition(|value| has_env_param && !value.contains('='));
+    args.extend(
+        env_params
+            .into_iter()
+            .map(|value| format!("--env={}", value)),
+    );
+    let env = env
+        .iter()
+        .map(|value| parse_env_var(value))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(anyhow::Error::msg)?;
+    if let Some(t) = graph.task(&task) {
+        args = resolve_named_args(t, args)?;
+        // reject values that don't fit a parameter's type or {a,b,c} list before anything runs
+        for (param, value) in t.parameters.iter().zip(&args) {
+            param.check_value(value).map_err(anyhow::Error::msg)?;
+        }
+    }
+
     // Create recorder based on --no-record flag
     let recorder: Arc<dyn Recorder> = if no_record {
         Arc::new(NoOpRecorder)
@@ -366,34 +767,268 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    let executor = Executor::new(graph, recorder);
-    executor.register_services().await;
+    if interactive && let Some(t) = graph.task(&task) {
+        if !t.pipe_from.is_empty() {
+            anyhow::bail!(
+                "--interactive can't be used with '{}': it reads piped input (@pipe_from)",
+                task
+            );
+        }
+        if t.ssh.is_some() || t.k8s.is_some() {
+            anyhow::bail!("--interactive only supports local tasks");
+        }
+        if let Some(ref name) = t.output_capture {
+            anyhow::bail!(
+                "--interactive can't be used with '{}': its output goes to the terminal, so @output-capture {} would be empty",
+                task,
+                name
+            );
+        }
+    }
 
-    let config_path_str = config_path.to_string_lossy().to_string();
-    let results = if only {
-        // run just this task (no deps)
-        if let Some(t) = executor.graph.task(&task) {
-            let bound_task = bind_task_parameters(t, &args)?;
-            vec![executor.execute_single(&bound_task).await]
-        } else {
-            executor.close().await;
-            anyhow::bail!("Task '{}' not found", task);
+    // debug containers attach to the terminal, so there is nothing to print instead
+    if dry_run
+        && let Ok(order) = graph.execution_order_for(&task)
+        && let Some(t) = order
+            .iter()
+            .find(|t| matches!(t.k8s.as_ref().map(|k| &k.mode), Some(K8sMode::Debug { .. })))
+    {
+        anyhow::bail!(
+            "--dry-run can't be used with '{}': @k8s debug is interactive",
+            t.name
+        );
+    }
+    // a captured value only exists once its task has run
+    if dry_run && let Ok(order) = graph.execution_order_for(&task) {
+        for producer in &order {
+            let Some(ref name) = producer.output_capture else {
+                continue;
+            };
+            let placeholder = format!("{{{{{}}}}}", name);
+            if let Some(user) = order.iter().find(|t| {
+                t.run
+                    .as_deref()
+                    .is_some_and(|run| run.contains(&placeholder))
+            }) {
+                anyhow::bail!(
+                    "--dry-run can't be used with '{}': it uses {} from @output-capture, which is only known once '{}' runs",
+                    user.name,
+                    placeholder,
+                    producer.name
+                );
+            }
         }
+    }
+
+    // with --format json, stdout carries only the results
+    let mut results_out = if format == "json" && !dry_run {
+        Some(stdout_for_results()?)
+    } else {
+        None
+    };
+
+    let cancel = cancel_on_ctrl_c();
+    let mut executor = Executor::new(graph, recorder)
+        .with_config_path(config_path.to_string_lossy())
+        .with_error_handling(error_handling)
+        .with_tag_concurrency(tag_limits.into_iter().chain(concurrency_by_tag))
+        .with_concurrency(concurrency)
+        .with_env(env)
+        .with_dry_run(dry_run.then_some(match dry_run_format.as_str() {
+            "lines" => DryRunFormat::Lines,
+            _ => DryRunFormat::Script,
+        }))
+        .with_cancellation(cancel.clone())
+        // an interactive task reads the terminal itself
+        .with_forward_stdin(!no_stdin && !interactive);
+    if interactive {
+        executor = executor.with_interactive(&task);
+    }
+    if let Some(from) = from {
+        executor = executor.with_from(from);
+    }
+    if !skip.is_empty() {
+        warn_skipped_dependencies(&executor.graph, &task, &skip);
+        executor = executor.with_skip(skip);
+    }
+    executor.register_services().await;
+
+    let pager = if results_out.is_none() && (pager || pager_mode == PagerMode::Auto) {
+        Pager::start()
     } else {
-        executor
-            .run_task_with_args(&task, &config_path_str, &args)
-            .await?
+        None
     };
 
+    let started = std::time::Instant::now();
+    let run_span = tracing::info_span!("dagrun_run", task = %task);
+    let results = async {
+        let results = if only {
+            // run just this task (no deps)
+            if let Some(t) = executor.graph.task(&task) {
+                let bound_task = bind_task_parameters(t, &args)?;
+                vec![executor.execute_single(&bound_task).await]
+            } else {
+                executor.close().await;
+                anyhow::bail!("Task '{}' not found", task);
+            }
+        } else if parallel {
+            eprintln!(
+                "{}",
+                "WARNING: --parallel ignores dependency ordering; tasks may fail because their dependencies have not run yet"
+                    .red()
+                    .bold()
+            );
+            executor.run_task_parallel(&task).await?
+        } else {
+            executor.run_task_with_args(&task, &args).await?
+        };
+        Ok(results)
+    }
+    .instrument(run_span)
+    .await?;
+
     executor.close().await;
-    print_results(&results);
-    if results.iter().any(|r| r.status == TaskStatus::Failed) {
+    drop(otel);
+    background::clear_own_pid(&task);
+
+    let failed = results.iter().any(|r| r.status == TaskStatus::Failed);
+    let interrupted = cancel.is_cancelled();
+    if !failed
+        && !interrupted
+        && !dry_run
+        && !no_record
+        && let Err(e) = stats::record(&stats::StatEntry::new(&task, started.elapsed(), 0))
+    {
+        eprintln!("Warning: Failed to write .dagrun/stats.json: {}", e);
+    }
+    // keep dry-run output a plain script
+    if let Some(out) = results_out.as_mut() {
+        print_results_json(out, &results, capture)?;
+    } else if !dry_run {
+        print_results(&results);
+    }
+    // wait for the pager before exiting, since exit() skips destructors
+    drop(pager);
+    if interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    if failed {
         std::process::exit(1);
     }
 
     Ok(())
 }
 
+/// Exit status after Ctrl-C, 128 + SIGINT as shells report it
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// A token cancelled by Ctrl-C, so the executor stops running tasks and the
+/// caller can stop services and clean up before exiting. A second Ctrl-C exits
+/// right away.
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("\n{}", "Interrupted, stopping tasks...".yellow());
+        cancel.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+    token
+}
+
+/// warn about `--skip`ped tasks that a task still in the run depends on
+fn warn_skipped_dependencies(graph: &TaskGraph, target: &str, skip: &[String]) {
+    let Ok(order) = graph.execution_order_for(target) else {
+        return;
+    };
+    for task in order.iter().filter(|t| !skip.contains(&t.name)) {
+        for dep in task.depends_on.iter().filter(|d| skip.contains(d)) {
+            eprintln!(
+                "{} skipping '{}', which '{}' depends on",
+                "Warning:".yellow(),
+                dep,
+                task.name
+            );
+        }
+    }
+}
+
+/// read task arguments for `--args-from-file`: one per line, skipping blanks and `#` comments
+fn read_args_file(path: &std::path::Path) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read args file {}: {}", path.display(), e))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// parse a `TAG=N` pair for `--concurrency-by-tag`
+fn parse_tag_limit(s: &str) -> Result<(String, usize), String> {
+    match s.split_once('=').map(|(tag, n)| (tag, n.parse::<usize>())) {
+        Some((tag, Ok(limit))) if !tag.is_empty() && limit > 0 => Ok((tag.to_string(), limit)),
+        _ => Err(format!(
+            "invalid tag limit '{}': expected TAG=N with N > 0",
+            s
+        )),
+    }
+}
+
+/// parse a positive task count for `--concurrency`
+fn parse_concurrency(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid concurrency '{}': expected N > 0", s)),
+    }
+}
+
+/// Print the newest scrape of each service with `@service metrics=...`
+fn print_service_metrics() -> anyhow::Result<()> {
+    let saved = dagrun_executor::service::saved_metrics()?;
+    println!();
+    if saved.is_empty() {
+        println!("No service metrics scraped yet");
+    }
+    for (service, sample) in saved {
+        let age = sample.scraped_at.elapsed().unwrap_or_default();
+        println!(
+            "{} {}",
+            service.bold(),
+            format!(
+                "(scraped {} ago)",
+                humantime::format_duration(std::time::Duration::from_secs(age.as_secs()))
+            )
+            .dimmed()
+        );
+        let mut values: Vec<_> = sample.values.into_iter().collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        let width = values
+            .iter()
+            .map(|(series, _)| series.len())
+            .max()
+            .unwrap_or(0);
+        for (series, value) in values {
+            println!("  {:<width$}  {}", series, value, width = width);
+        }
+    }
+    Ok(())
+}
+
+/// parse a `KEY=VALUE` pair for `--env`
+fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid env var '{}': expected KEY=VALUE", s)),
+    }
+}
+
 fn find_config_file() -> anyhow::Result<PathBuf> {
     let names = ["dagfile", "dagfile.dr", "dagfile.lua", ".dagrun"];
     let mut dir = std::env::current_dir()?;
@@ -433,6 +1068,58 @@ fn load_config(path: &PathBuf) -> anyhow::Result<Config> {
     }
 }
 
+/// Reorder `name=value` / `--name=value` arguments into positional order.
+/// Bare `key=value` tokens whose key isn't a parameter stay positional.
+fn resolve_named_args(task: &Task, args: Vec<String>) -> anyhow::Result<Vec<String>> {
+    let mut named: Vec<Option<String>> = vec![None; task.parameters.len()];
+    let mut positional = 0;
+
+    for arg in &args {
+        let flag = arg.strip_prefix("--");
+        let Some((key, value)) = flag.unwrap_or(arg).split_once('=') else {
+            positional += 1;
+            continue;
+        };
+        let Some(idx) = task.parameters.iter().position(|p| p.name == key) else {
+            if flag.is_some() {
+                anyhow::bail!("Task '{}' has no parameter '{}'", task.name, key);
+            }
+            positional += 1;
+            continue;
+        };
+        if named[idx].is_some() {
+            anyhow::bail!(
+                "Parameter '{}' of task '{}' was given more than once",
+                key,
+                task.name
+            );
+        }
+        named[idx] = Some(value.to_string());
+    }
+
+    if named.iter().all(Option::is_none) {
+        return Ok(args);
+    }
+    if positional > 0 {
+        anyhow::bail!(
+            "Task '{}' got both positional and named arguments; use one form",
+            task.name
+        );
+    }
+
+    // parameters are bound by position, so fill skipped ones with their default
+    let last = named.iter().rposition(Option::is_some).unwrap_or(0);
+    task.parameters[..=last]
+        .iter()
+        .zip(named)
+        .map(|(param, value)| {
+            value.or_else(|| param.default.clone()).ok_or_else(|| {
+                anyhow::anyhow!("Task '{}' requires argument '{}'", task.name, param.name)
+            })
+        })
+        .collect()
+}
+
 /// Bind CLI arguments to task parameters, substituting in the task body
 fn bind_task_parameters(task: &Task, args: &[String]) -> anyhow::Result<Task> {
     let params = &task.parameters;
@@ -472,6 +1159,7 @@ fn bind_task_parameters(task: &Task, args: &[String]) -> anyhow::Result<Task> {
         } else {
             anyhow::bail!("Missing required argument '{}'", param.name);
         };
+        param.check_value(&value).map_err(anyhow::Error::msg)?;
         bindings.insert(param.name.clone(), value);
     }
 
@@ -491,34 +1179,124 @@ fn bind_task_parameters(task: &Task, args: &[String]) -> anyhow::Result<Task> {
     })
 }
 
-fn setup_tracing(verbose: bool, quiet: bool) {
+/// Install the tracing layers. Returns the OpenTelemetry provider when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set and `--no-telemetry` isn't, so spans
+/// can be flushed before exit.
+fn setup_tracing(verbose: bool, quiet: bool, no_telemetry: bool) -> Option<TracerProvider> {
     use crate::progress::PrettyProgressLayer;
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing_subscriber::filter::{LevelFilter, Targets};
 
-    if quiet {
-        // no output at all from tracing
-        return;
-    }
-
-    let progress_layer = PrettyProgressLayer::new();
+    // --quiet drops terminal output, but spans are still exported
+    let progress_layer = (!quiet).then(PrettyProgressLayer::new);
 
-    if verbose {
-        // add standard fmt layer for debug output
-        let fmt_layer = tracing_subscriber::fmt::layer()
-            .with_target(true)
-            .with_level(true);
+    // add standard fmt layer for debug output
+    let fmt_layer = (verbose && !quiet).then(|| {
         let filter =
             EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("dr=debug"));
+        tracing_subscriber::fmt::layer()
+            .with_target(true)
+            .with_level(true)
+            .with_filter(filter)
+    });
 
-        tracing_subscriber::registry()
-            .with(progress_layer)
-            .with(fmt_layer.with_filter(filter))
-            .init();
-    } else {
-        // just pretty progress, filter out non-progress events
-        tracing_subscriber::registry().with(progress_layer).init();
+    let provider = std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .filter(|_| !no_telemetry)
+        .and_then(|_| match otel_provider() {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                eprintln!("Warning: Failed to set up OTLP exporter: {}", e);
+                None
+            }
+        });
+    // only our own spans, not the http/grpc clients underneath
+    let otel_layer = provider.as_ref().map(|p| {
+        tracing_opentelemetry::layer()
+            .with_tracer(p.tracer("dagrun"))
+            .with_filter(
+                Targets::new()
+                    .with_target("dr", LevelFilter::INFO)
+                    .with_target("dagrun_executor", LevelFilter::INFO),
+            )
+    });
+
+    tracing_subscriber::registry()
+        .with(progress_layer)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
+    provider
+}
+
+/// Batch-export spans over OTLP; the endpoint comes from `OTEL_EXPORTER_OTLP_ENDPOINT`
+fn otel_provider() -> anyhow::Result<TracerProvider> {
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::{Resource, runtime};
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", "dagrun")]))
+        .build())
+}
+
+/// Flushes exported spans when dropped, so every way out of `main` sends them.
+/// `std::process::exit` skips destructors, so drop it explicitly before that.
+struct TracingGuard(Option<TracerProvider>);
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.0.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Warning: Failed to flush traces: {}", e);
+        }
     }
 }
 
+/// Point stdout at stderr for the rest of the run, so task output and
+/// messages land there, and return the original stdout for the results
+#[cfg(unix)]
+fn stdout_for_results() -> anyhow::Result<Box<dyn Write>> {
+    use nix::unistd::{dup, dup2};
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    std::io::stdout().flush()?;
+    let stdout_fd = std::io::stdout().as_raw_fd();
+    let saved_stdout = dup(stdout_fd)?;
+    dup2(std::io::stderr().as_raw_fd(), stdout_fd)?;
+    // SAFETY: `saved_stdout` was just duplicated and nothing else owns it
+    Ok(Box::new(unsafe {
+        std::fs::File::from_raw_fd(saved_stdout)
+    }))
+}
+
+#[cfg(not(unix))]
+fn stdout_for_results() -> anyhow::Result<Box<dyn Write>> {
+    Ok(Box::new(std::io::stdout()))
+}
+
+/// Results as a JSON array; output is left out unless `capture` is set
+fn print_results_json(
+    out: &mut impl Write,
+    results: &[executor::TaskResult],
+    capture: bool,
+) -> std::io::Result<()> {
+    let results: Vec<executor::TaskResult> = results
+        .iter()
+        .cloned()
+        .map(|mut result| {
+            if !capture {
+                result.output.clear();
+            }
+            result
+        })
+        .collect();
+    writeln!(out, "{}", serde_json::to_string_pretty(&results).unwrap())
+}
+
 fn print_results(results: &[executor::TaskResult]) {
     println!("\n{}", "Results:".bold());
     for result in results {
@@ -536,6 +1314,12 @@ fn print_results(results: &[executor::TaskResult]) {
         } else {
             String::new()
         };
-        println!("  {} {}{}", status, result.task_name, attempts);
+        let exit_code = match result.exit_code {
+            Some(code) if result.status == TaskStatus::Failed => {
+                format!(" (exit {})", code).dimmed().to_string()
+            }
+            _ => String::new(),
+        };
+        println!("  {} {}{}{}", status, result.task_name, attempts, exit_code);
     }
 }
@@ -0,0 +1,116 @@
+//! `dr man`: a roff manpage built from the clap definitions
+//!
+//! clap_mangen covers the top-level synopsis, options, and subcommand list.
+//! Per-subcommand flags and the dagfile annotation reference are appended as
+//! extra sections so one page documents everything.
+
+use clap_mangen::Man;
+use clap_mangen::roff::{Roff, bold, italic, roman};
+use std::io::{self, Write};
+
+use dr_ast::docs;
+
+/// Render the full manpage for `cmd` into `out`
+pub fn render(mut cmd: clap::Command, out: &mut dyn Write) -> io::Result<()> {
+    // propagate globals and generated flags into subcommands
+    cmd.build();
+
+    let man = Man::new(cmd.clone());
+    let mut sections = Vec::new();
+    man.render_name_section(&mut sections)?;
+    man.render_synopsis_section(&mut sections)?;
+    man.render_description_section(&mut sections)?;
+    man.render_options_section(&mut sections)?;
+    man.render_subcommands_section(&mut sections)?;
+    commands_section(&cmd).to_writer(&mut sections)?;
+    files_section().to_writer(&mut sections)?;
+    man.render_version_section(&mut sections)?;
+
+    // each section is rendered with roff's apostrophe preamble, which belongs
+    // once at the top of the page along with .TH
+    let preamble = Roff::new().render();
+    let sections = String::from_utf8_lossy(&sections).replace(&preamble, "");
+    man.render_title(out)?;
+    out.write_all(sections.as_bytes())
+}
+
+/// flags of every subcommand, one `.SS` per command
+fn commands_section(cmd: &clap::Command) -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["COMMANDS"]);
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        let title = format!("{} {}", cmd.get_name(), sub.get_name());
+        roff.control("SS", [title.as_str()]);
+        if let Some(about) = sub.get_about() {
+            roff.text([roman(about.to_string())]);
+        }
+        for arg in sub.get_arguments() {
+            if arg.is_hide_set() || arg.is_global_set() || arg.get_id() == "help" {
+                continue;
+            }
+            roff.control("TP", []);
+            roff.text([bold(arg_label(arg))]);
+            if let Some(help) = arg.get_help() {
+                roff.text([roman(help.to_string())]);
+            }
+        }
+    }
+    roff
+}
+
+/// `-t, --task <TASK>` style label for an argument
+fn arg_label(arg: &clap::Arg) -> String {
+    let value = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| arg.get_id().to_string().to_uppercase());
+    if arg.is_positional() {
+        return format!("<{}>", value);
+    }
+
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("--{}", long));
+    }
+    let mut label = names.join(", ");
+    if arg.get_action().takes_values() {
+        label.push_str(&format!(" <{}>", value));
+    }
+    label
+}
+
+/// where config is found and the annotation reference from `dr_ast::docs`
+fn files_section() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["FILES"]);
+    roff.text([
+        roman("The first of "),
+        italic("dagfile"),
+        roman(", "),
+        italic("dagfile.dr"),
+        roman(", "),
+        italic("dagfile.lua"),
+        roman(" or "),
+        italic(".dagrun"),
+        roman(" found in the current directory or a parent is loaded unless "),
+        bold("-c"),
+        roman(" is given. Tasks are configured with the annotations below."),
+    ]);
+    for doc in docs::ANNOTATION_NAMES
+        .iter()
+        .filter_map(|name| docs::get_annotation_doc(name))
+    {
+        roff.control("TP", []);
+        roff.text([bold(doc.syntax)]);
+        roff.text([roman(doc.description)]);
+        for (opt, desc) in doc.options {
+            roff.control("br", []);
+            roff.text([bold(*opt), roman(format!(" \u{2014} {}", desc))]);
+        }
+    }
+    roff
+}
@@ -0,0 +1,75 @@
+//! Page task output through `less` when stdout is a terminal
+
+use std::io::{IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+/// A running `less -R` that our stdout is redirected into
+pub struct Pager {
+    child: Child,
+    /// original stdout, restored when paging ends
+    #[cfg(unix)]
+    saved_stdout: std::os::fd::RawFd,
+}
+
+impl Pager {
+    /// Start `less -R` and point stdout at it. Returns None when stdout isn't a
+    /// terminal or the pager can't be started, in which case output is unchanged.
+    #[cfg(unix)]
+    pub fn start() -> Option<Self> {
+        use nix::unistd::{close, dup, dup2};
+        use std::os::fd::AsRawFd;
+
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+
+        let mut child = Command::new("less")
+            .arg("-R")
+            .stdin(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let pipe = child.stdin.take()?;
+
+        let stdout_fd = std::io::stdout().as_raw_fd();
+        let saved_stdout = dup(stdout_fd).ok()?;
+        if dup2(pipe.as_raw_fd(), stdout_fd).is_err() {
+            let _ = close(saved_stdout);
+            let _ = child.kill();
+            return None;
+        }
+        // stdout now holds the write end of the pipe
+        drop(pipe);
+
+        // output is headed for less -R, so keep colors
+        colored::control::set_override(true);
+
+        Some(Pager {
+            child,
+            saved_stdout,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start() -> Option<Self> {
+        None
+    }
+}
+
+impl Drop for Pager {
+    /// Restore stdout and wait for the user to quit the pager
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+
+        // closing our end of the pipe lets less see EOF
+        #[cfg(unix)]
+        {
+            use nix::unistd::{close, dup2};
+            use std::os::fd::AsRawFd;
+
+            let _ = dup2(self.saved_stdout, std::io::stdout().as_raw_fd());
+            let _ = close(self.saved_stdout);
+        }
+
+        let _ = self.child.wait();
+    }
+}
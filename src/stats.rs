@@ -0,0 +1,201 @@
+//! Run timings for `dr profile` and `dr gc`
+//!
+//! Each successful run appends one JSON object per line to `.dagrun/stats.json`
+//! with a single `O_APPEND` write, so concurrent `dr` runs don't lose each
+//! other's entries. `dr gc` compacts the file through a temp file and a rename,
+//! keeping anything recorded after it started.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn stats_path() -> PathBuf {
+    PathBuf::from(".dagrun").join("stats.json")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatEntry {
+    pub task: String,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    /// unix seconds when the run finished
+    pub timestamp: u64,
+}
+
+impl StatEntry {
+    pub fn new(task: &str, duration: Duration, exit_code: i32) -> Self {
+        StatEntry {
+            task: task.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            exit_code,
+            timestamp: unix_now(),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// All recorded entries, oldest first; unreadable lines are skipped
+pub fn load() -> io::Result<Vec<StatEntry>> {
+    Ok(parse(&read()?))
+}
+
+/// The raw stats file, empty if there is none yet
+fn read() -> io::Result<String> {
+    match fs::read_to_string(stats_path()) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse(content: &str) -> Vec<StatEntry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Append an entry for a finished run
+pub fn record(entry: &StatEntry) -> io::Result<()> {
+    let path = stats_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // one write per line, so appends from other processes never interleave
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+/// Keep only the newest `keep` entries, returning how many were dropped.
+/// Runs that finish while gc is running are never dropped: entries stamped
+/// at or after its start are kept, and lines appended to the file after it
+/// was read are copied over before the rename.
+pub fn truncate(keep: usize) -> io::Result<usize> {
+    let started = unix_now();
+    let content = read()?;
+    let (older, newer): (Vec<StatEntry>, Vec<StatEntry>) = parse(&content)
+        .into_iter()
+        .partition(|entry| entry.timestamp < started);
+    let dropped = older.len().saturating_sub(keep);
+    if dropped == 0 {
+        return Ok(0);
+    }
+
+    let path = stats_path();
+    let mut tmp = tempfile::NamedTempFile::new_in(path.parent().unwrap())?;
+    for entry in older[dropped..].iter().chain(&newer) {
+        serde_json::to_writer(&mut tmp, entry)?;
+        tmp.write_all(b"\n")?;
+    }
+    // appends only ever add to the end, so whatever follows what was read is new
+    if let Some(appended) = read()?.get(content.len()..) {
+        tmp.write_all(appended.as_bytes())?;
+    }
+    tmp.persist(&path).map_err(|e| e.error)?;
+    Ok(dropped)
+}
+
+/// Timing summary over a set of durations (milliseconds)
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+pub fn summarize(durations: &[u64]) -> Option<Summary> {
+    let mut sorted = durations.to_vec();
+    sorted.sort_unstable();
+    let count = sorted.len();
+    if count == 0 {
+        return None;
+    }
+    // nearest-rank percentile
+    let percentile = |p: usize| sorted[(count * p).div_ceil(100).max(1) - 1];
+    Some(Summary {
+        count,
+        mean: sorted.iter().sum::<u64>() / count as u64,
+        p50: percentile(50),
+        p95: percentile(95),
+        min: sorted[0],
+        max: sorted[count - 1],
+    })
+}
+
+/// Bar chart of `durations` split into `buckets` equal-width ranges
+pub fn histogram(durations: &[u64], buckets: usize) -> String {
+    const WIDTH: usize = 40;
+    let (Some(&min), Some(&max)) = (durations.iter().min(), durations.iter().max()) else {
+        return String::new();
+    };
+    let buckets = if min == max { 1 } else { buckets.max(1) };
+    let step = (max - min).div_ceil(buckets as u64).max(1);
+
+    let mut counts = vec![0usize; buckets];
+    for &d in durations {
+        let idx = (((d - min) / step) as usize).min(buckets - 1);
+        counts[idx] += 1;
+    }
+    let most = counts.iter().copied().max().unwrap_or(1);
+
+    let mut out = String::new();
+    for (i, count) in counts.iter().enumerate() {
+        let low = min + step * i as u64;
+        let bar = "█".repeat((count * WIDTH).div_ceil(most));
+        out.push_str(&format!("{:>10} │{} {}\n", format_ms(low), bar, count));
+    }
+    out
+}
+
+pub fn format_ms(ms: u64) -> String {
+    humantime::format_duration(Duration::from_millis(ms)).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let durations: Vec<u64> = (1..=20).map(|n| n * 100).collect();
+        let summary = summarize(&durations).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                count: 20,
+                mean: 1050,
+                p50: 1000,
+                p95: 1900,
+                min: 100,
+                max: 2000,
+            }
+        );
+        assert!(summarize(&[]).is_none());
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let chart = histogram(&[100, 100, 150, 900], 4);
+        assert_eq!(chart.lines().count(), 4);
+        assert!(chart.lines().next().unwrap().ends_with(" 3"));
+        assert!(chart.lines().last().unwrap().ends_with(" 1"));
+
+        // identical timings collapse into one bucket
+        assert_eq!(histogram(&[50, 50], 10).lines().count(), 1);
+    }
+}
@@ -120,10 +120,10 @@ impl TuiApp {
             KeyCode::Up | KeyCode::Char('k') => {
                 self.state.run_cursor = self.state.run_cursor.saturating_sub(1);
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.state.run_cursor + 1 < self.state.runs.len() {
-                    self.state.run_cursor += 1;
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.state.run_cursor + 1 < self.state.runs.len() =>
+            {
+                self.state.run_cursor += 1;
             }
             KeyCode::Enter => {
                 if let Some(run) = self.state.runs.get(self.state.run_cursor) {
@@ -154,10 +154,10 @@ impl TuiApp {
             KeyCode::Up | KeyCode::Char('k') => {
                 self.state.task_cursor = self.state.task_cursor.saturating_sub(1);
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.state.task_cursor + 1 < self.state.tasks.len() {
-                    self.state.task_cursor += 1;
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.state.task_cursor + 1 < self.state.tasks.len() =>
+            {
+                self.state.task_cursor += 1;
             }
             KeyCode::Enter => {
                 if let Some(task) = self.state.tasks.get(self.state.task_cursor) {
@@ -187,10 +187,10 @@ impl TuiApp {
             KeyCode::Up | KeyCode::Char('k') => {
                 self.state.output_scroll = self.state.output_scroll.saturating_sub(1);
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.state.output_scroll + 1 < self.state.output.len() {
-                    self.state.output_scroll += 1;
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.state.output_scroll + 1 < self.state.output.len() =>
+            {
+                self.state.output_scroll += 1;
             }
             KeyCode::PageUp => {
                 self.state.output_scroll = self.state.output_scroll.saturating_sub(20);
@@ -216,7 +216,7 @@ impl TuiApp {
                 self.state.run_cursor = self
                     .state
                     .run_cursor
-                    .min(self.state.runs.len().saturating_sub(1).max(0));
+                    .min(self.state.runs.len().saturating_sub(1));
             }
             Err(e) => {
                 self.state.message = Some(format!("Failed to load runs: {}", e));
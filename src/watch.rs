@@ -0,0 +1,216 @@
+//! `dr watch <task>`: run a task, then run it again whenever the dagfile, a file
+//! it includes, or a file uploaded by one of its tasks changes
+//!
+//! The config is reloaded and the executor recreated for every run, so edits
+//! to the dagfile take effect and no SSH session outlives a run. Watch runs
+//! aren't recorded in history.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use colored::Colorize;
+use notify::{Event, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::dag::TaskGraph;
+use crate::executor::Executor;
+use dr_ast::Task;
+
+/// Changes this close together, e.g. an editor writing a swap file and then
+/// the file itself, trigger one run
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// The dagfile, the files it includes, and the local side of every `@upload`
+/// and `@k8s-upload` in `order`, as absolute paths
+pub fn watched_paths(config_path: &Path, order: &[&Task]) -> Vec<PathBuf> {
+    let includes = std::fs::read_to_string(config_path)
+        .map(|source| dr_ast::included_paths(&source, config_path))
+        .unwrap_or_default();
+    let uploads = order
+        .iter()
+        .flat_map(|task| {
+            let ssh = task.ssh.iter().flat_map(|ssh| &ssh.upload);
+            let k8s = task.k8s.iter().flat_map(|k8s| &k8s.upload);
+            ssh.chain(k8s)
+        })
+        .map(|upload| Path::new(&upload.local));
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let includes = includes.iter().map(PathBuf::as_path);
+    for path in std::iter::once(config_path).chain(includes).chain(uploads) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+pub async fn run(config_path: &Path, task: &str) -> anyhow::Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = tx.send(event);
+    })?;
+    let mut watched_dirs = Vec::new();
+    let mut paths = watched_paths(config_path, &[]);
+
+    loop {
+        match load_graph(config_path) {
+            Ok(graph) => {
+                if let Ok(order) = graph.execution_order_for(task) {
+                    paths = watched_paths(config_path, &order);
+                }
+                watch_dirs(&mut watcher, &mut watched_dirs, &paths);
+
+                let executor = Executor::new_without_recording(graph);
+                executor.register_services().await;
+                match executor.run_task(task).await {
+                    Ok(results) => crate::print_results(&results),
+                    Err(e) => eprintln!("{} {}", "Error:".red(), e),
+                }
+                executor.close().await;
+            }
+            // keep watching so fixing the dagfile starts the next run
+            Err(e) => {
+                watch_dirs(&mut watcher, &mut watched_dirs, &paths);
+                eprintln!("{} {}", "Error:".red(), e);
+            }
+        }
+
+        let Some(changed) = next_change(&mut rx, &paths).await else {
+            return Ok(());
+        };
+        println!(
+            "\n{}\n",
+            format!("--- re-running after change to {} ---", changed.display()).bold()
+        );
+    }
+}
+
+fn load_graph(config_path: &Path) -> anyhow::Result<TaskGraph> {
+    let config = crate::load_config(&config_path.to_path_buf())?;
+    Ok(TaskGraph::from_config(config)?)
+}
+
+/// Watch the directories holding `paths` rather than the files themselves,
+/// since editors often save by writing a new file and renaming it over the old one.
+/// A path that is itself a directory, like an uploaded one, is watched recursively.
+fn watch_dirs(
+    watcher: &mut impl Watcher,
+    watched: &mut Vec<(PathBuf, RecursiveMode)>,
+    paths: &[PathBuf],
+) {
+    let recursive: Vec<&PathBuf> = paths.iter().filter(|p| p.is_dir()).collect();
+    let mut dirs: Vec<(PathBuf, RecursiveMode)> = recursive
+        .iter()
+        .map(|dir| (dir.to_path_buf(), RecursiveMode::Recursive))
+        .collect();
+    for dir in paths
+        .iter()
+        .filter(|p| !p.is_dir())
+        .filter_map(|p| p.parent())
+    {
+        // already covered by a recursive watch
+        if !recursive.iter().any(|r| dir.starts_with(r)) {
+            dirs.push((dir.to_path_buf(), RecursiveMode::NonRecursive));
+        }
+    }
+    dirs.sort_by(|a, b| a.0.cmp(&b.0));
+    dirs.dedup();
+
+    for (dir, _) in watched.iter().filter(|d| !dirs.contains(d)) {
+        let _ = watcher.unwatch(dir);
+    }
+    watched.retain(|d| dirs.contains(d));
+    for (dir, mode) in dirs {
+        if !watched.contains(&(dir.clone(), mode)) && watcher.watch(&dir, mode).is_ok() {
+            watched.push((dir, mode));
+        }
+    }
+}
+
+/// Wait for an event touching one of `paths`, or anything inside one that is a
+/// directory, then drain whatever follows
+/// within [`DEBOUNCE`]. `None` once the watcher is gone
+async fn next_change(
+    rx: &mut mpsc::UnboundedReceiver<notify::Result<Event>>,
+    paths: &[PathBuf],
+) -> Option<PathBuf> {
+    let changed = loop {
+        let Ok(event) = rx.recv().await? else {
+            continue;
+        };
+        if event.kind.is_access() {
+            continue;
+        }
+        if let Some(path) = event
+            .paths
+            .into_iter()
+            .find(|p| paths.iter().any(|watched| p.starts_with(watched)))
+        {
+            break path;
+        }
+    };
+    while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+    Some(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watched_paths_include_uploads_once() {
+        let config = dr_ast::parse_config(
+            "build:\n\techo\n\n@ssh host=box\n@upload ./app.toml:/etc/app.toml\n@upload ./app.toml:/tmp/app.toml\ndeploy: build\n\techo\n\n@k8s exec pod=api\n@k8s-upload /etc/hosts:/etc/hosts\nsync:\n\techo\n",
+        )
+        .unwrap();
+        let order: Vec<&Task> = ["build", "deploy", "sync"]
+            .iter()
+            .map(|name| &config.tasks[*name])
+            .collect();
+        let cwd = std::env::current_dir().unwrap();
+
+        let paths = watched_paths(Path::new("dagfile"), &order);
+        assert_eq!(
+            paths,
+            [
+                cwd.join("dagfile"),
+                cwd.join("app.toml"),
+                PathBuf::from("/etc/hosts")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watched_paths_follow_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("lib")).unwrap();
+        std::fs::create_dir(root.join("assets")).unwrap();
+        std::fs::write(root.join("dagfile"), "@include lib/common.dr\n").unwrap();
+        // nested includes are relative to the including file
+        std::fs::write(
+            root.join("lib/common.dr"),
+            "@include ../shared.dr\n@include missing.dr\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("shared.dr"), "@include lib/common.dr\n").unwrap();
+
+        let config =
+            dr_ast::parse_config("@ssh host=box\n@upload ./assets:/srv/assets\ndeploy:\n\techo\n")
+                .unwrap();
+        let order = vec![&config.tasks["deploy"]];
+        let cwd = std::env::current_dir().unwrap();
+
+        let paths = watched_paths(&root.join("dagfile"), &order);
+        assert_eq!(
+            paths,
+            [
+                root.join("dagfile"),
+                root.join("lib/common.dr"),
+                root.join("shared.dr"),
+                cwd.join("assets"),
+            ]
+        );
+    }
+}
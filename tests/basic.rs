@@ -305,6 +305,51 @@ test: build
     assert!(json["tasks"].is_array());
 }
 
+#[test]
+fn test_list_table_and_csv_formats() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    cargo build
+
+@timeout 5m
+@retry 2
+test: build
+    cargo test
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("list")
+        .arg("-f")
+        .arg("table")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Name   Dependencies  Timeout  Retry  SSH  K8s",
+        ))
+        .stdout(predicate::str::contains(
+            "test   build         5m       2      -    -",
+        ));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("list")
+        .arg("-f")
+        .arg("csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Name,Dependencies,Timeout,Retry,SSH,K8s\n",
+        ))
+        .stdout(predicate::str::contains("test,build,5m,2,,\n"));
+}
+
 #[test]
 fn test_validate_command() {
     let dir = TempDir::new().unwrap();
@@ -374,6 +419,51 @@ b: a
         .stdout(predicate::str::contains("->"));
 }
 
+#[test]
+fn test_graph_subgraph() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+a:
+    echo a
+
+b: a
+    echo b
+
+unrelated:
+    echo c
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("graph")
+        .arg("-f")
+        .arg("dot")
+        .arg("--subgraph")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"a\" -> \"b\""))
+        .stdout(predicate::str::contains("unrelated").not());
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("graph")
+        .arg("-f")
+        .arg("dot")
+        .arg("--subgraph")
+        .arg("a")
+        .arg("--reverse")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"a\" -> \"b\""))
+        .stdout(predicate::str::contains("unrelated").not());
+}
+
 #[test]
 fn test_cycle_detection() {
     let dir = TempDir::new().unwrap();
@@ -421,17 +511,24 @@ build:
 }
 
 #[test]
-fn test_run_all() {
+fn test_concurrency_by_tag() {
     let dir = TempDir::new().unwrap();
+    let lock = dir.path().join("lock");
+    // each task fails if the other holds the lock, so they must not overlap
     let config = create_dagfile(
         &dir,
-        r#"
-a:
-    echo "running a"
+        &format!(
+            r#"
+@tags deploy
+deploy-a:
+    mkdir {lock} && sleep 0.3 && rmdir {lock}
 
-b:
-    echo "running b"
+@tags deploy
+deploy-b:
+    mkdir {lock} && sleep 0.3 && rmdir {lock}
 "#,
+            lock = lock.display()
+        ),
     );
 
     dr_cmd()
@@ -439,22 +536,55 @@ b:
         .arg(&config)
         .arg("run-all")
         .arg("--no-record")
+        .arg("--concurrency-by-tag")
+        .arg("deploy=1")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_concurrency_by_tag_before_task_name() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@tags deploy
+deploy:
+    echo "deploying"
+"#,
+    );
+
+    // the limits take one comma-separated value, leaving the task name alone
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--concurrency-by-tag")
+        .arg("deploy=1,test=4")
+        .arg("deploy")
         .assert()
         .success()
-        .stdout(predicate::str::contains("running a"))
-        .stdout(predicate::str::contains("running b"));
+        .stdout(predicate::str::contains("deploying"));
 }
 
 #[test]
-fn test_multiline_command() {
+fn test_run_from_task() {
     let dir = TempDir::new().unwrap();
     let config = create_dagfile(
         &dir,
         r#"
-multi:
-    echo "line 1"
-    echo "line 2"
-    echo "line 3"
+build:
+    echo "building"
+
+package: build
+    echo "packaging"
+
+deploy: package
+    echo "deploying"
+
+docs:
+    echo "docs"
 "#,
     );
 
@@ -463,10 +593,1818 @@ multi:
         .arg(&config)
         .arg("run")
         .arg("--no-record")
-        .arg("multi")
+        .arg("--from")
+        .arg("package")
+        .arg("deploy")
         .assert()
         .success()
-        .stdout(predicate::str::contains("line 1"))
-        .stdout(predicate::str::contains("line 2"))
-        .stdout(predicate::str::contains("line 3"));
+        .stdout(predicate::str::contains("packaging"))
+        .stdout(predicate::str::contains("deploying"))
+        .stdout(predicate::str::contains("building").not());
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--from")
+        .arg("docs")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'docs' is not in the dependency chain of 'deploy'",
+        ));
+}
+
+#[test]
+fn test_run_skip_tasks() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    echo "building"
+
+integration-test: build
+    echo "testing"
+
+deploy: integration-test
+    echo "deploying"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--skip")
+        .arg("integration-test")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building"))
+        .stdout(predicate::str::contains("deploying"))
+        .stdout(predicate::str::contains("testing").not())
+        .stderr(predicate::str::contains(
+            "skipping 'integration-test', which 'deploy' depends on",
+        ));
+}
+
+#[test]
+fn test_forward_stdin_to_first_task() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+process:
+    read line; echo "got: $line"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("process")
+        .write_stdin("from the pipe\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("got: from the pipe"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--no-stdin")
+        .arg("process")
+        .write_stdin("from the pipe\n")
+        .assert()
+        .stdout(predicate::str::contains("from the pipe").not());
+}
+
+#[test]
+fn test_open_stdin_does_not_block_task() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    echo built
+"#,
+    );
+
+    // like `tail -f log | dr run build`: the writer never closes the pipe
+    #[allow(deprecated)]
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("dr"))
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("build")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let _writer = child.stdin.take();
+
+    let start = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > std::time::Duration::from_secs(20) {
+            child.kill().unwrap();
+            panic!("dr waited for stdin to close");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    };
+    assert!(status.success());
+}
+
+#[test]
+fn test_artifact_passed_to_later_task() {
+    let dir = TempDir::new().unwrap();
+    let bin = dir.path().join("app.bin");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+@artifact bin={bin}
+build:
+    echo built > {bin}
+
+ship: build
+    cat {{{{artifact.build.bin}}}}
+"#,
+            bin = bin.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("ship")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("built"));
+}
+
+#[test]
+fn test_missing_artifact_fails_task() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("missing.bin");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+@artifact bin={}
+build:
+    echo not writing it
+"#,
+            missing.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("did not produce artifact 'bin'"));
+}
+
+#[test]
+fn test_artifact_in_annotation_values() {
+    let dir = TempDir::new().unwrap();
+    let bin = dir.path().join("app.bin");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+@artifact bin={bin}
+build:
+    echo built > {bin}
+
+@env BIN={{{{artifact.build.bin}}}}
+ship: build
+    cat "$BIN"
+"#,
+            bin = bin.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("ship")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("built"));
+}
+
+#[test]
+fn test_unresolved_artifact_fails_task() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ran");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+@artifact bin=app.bin
+build:
+    echo built > app.bin
+
+ship:
+    cat {{{{artifact.build.bin}}}} && touch {marker}
+"#,
+            marker = marker.display()
+        ),
+    );
+
+    // build isn't a dependency, so its artifact doesn't exist in this run
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("ship")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'ship' uses {{artifact.build.bin}}, but no task that ran before it produced that artifact",
+        ));
+    assert!(!marker.exists());
+}
+
+#[test]
+fn test_concurrency_limit() {
+    let dir = TempDir::new().unwrap();
+    let lock = dir.path().join("lock");
+    // each task fails if another holds the lock, so they must run one at a time
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+a:
+    mkdir {lock} && sleep 0.3 && rmdir {lock}
+
+b:
+    mkdir {lock} && sleep 0.3 && rmdir {lock}
+
+c:
+    mkdir {lock} && sleep 0.3 && rmdir {lock}
+"#,
+            lock = lock.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run-all")
+        .arg("--no-record")
+        .arg("--concurrency")
+        .arg("1")
+        .assert()
+        .success();
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run-all")
+        .arg("--no-record")
+        .arg("--concurrency")
+        .arg("0")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected N > 0"));
+}
+
+#[test]
+fn test_run_all() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+a:
+    echo "running a"
+
+b:
+    echo "running b"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run-all")
+        .arg("--no-record")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("running a"))
+        .stdout(predicate::str::contains("running b"));
+}
+
+#[test]
+fn test_multiline_command() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+multi:
+    echo "line 1"
+    echo "line 2"
+    echo "line 3"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("multi")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("line 1"))
+        .stdout(predicate::str::contains("line 2"))
+        .stdout(predicate::str::contains("line 3"));
+}
+
+#[test]
+fn test_error_handling_continue() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+set error-handling := continue
+
+fail:
+    exit 1
+
+independent:
+    echo "independent ran"
+
+after: fail
+    echo "should not run"
+
+end: independent after
+    echo "end should not run"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("end")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("independent ran"))
+        .stdout(predicate::str::contains("should not run").not());
+}
+
+#[test]
+fn test_cli_env_vars() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+show:
+    echo "level=$RUST_LOG incr=$CARGO_INCREMENTAL"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--env")
+        .arg("RUST_LOG=debug")
+        .arg("--env")
+        .arg("CARGO_INCREMENTAL=0")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("level=debug incr=0"));
+}
+
+#[test]
+fn test_implicit_run_only() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+setup:
+    echo "setup ran"
+
+build: setup
+    echo "build ran"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("build")
+        .arg("--only")
+        .arg("--no-record")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build ran"))
+        .stdout(predicate::str::contains("setup ran").not());
+}
+
+#[test]
+fn test_implicit_run_env_and_args() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+deploy target:
+    echo "deploying {{target}} to $STAGE"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("--env")
+        .arg("STAGE=prod")
+        .arg("version=1.0")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying version=1.0 to prod"));
+}
+
+#[test]
+fn test_flags_after_task_args() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+deploy version:
+    echo "deploying {{version}}"
+"#,
+    );
+
+    for run in [&["deploy"][..], &["run", "deploy"][..]] {
+        // a flag after a task argument is still a flag
+        dr_cmd()
+            .arg("-c")
+            .arg(&config)
+            .args(run)
+            .arg("1.0")
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("# task: deploy"))
+            .stdout(predicate::str::contains("sh -c 'echo \"deploying 1.0\"'"));
+    }
+
+    // after `--` it's an argument
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("--")
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying --dry-run"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("-5")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying -5"));
+}
+
+#[test]
+fn test_implicit_run_args_from_file() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+greet greeting name:
+    echo "{{greeting}}, {{name}}!"
+"#,
+    );
+    let args_file = dir.path().join("args.txt");
+    fs::write(&args_file, "# greeting first\nHello there\n").unwrap();
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("greet")
+        .arg("--no-record")
+        .arg("--args-from-file")
+        .arg(&args_file)
+        .arg("--")
+        .arg("--world")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello there, --world!"));
+}
+
+#[test]
+fn test_background_run_and_kill() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+serve:
+    echo "serving" && sleep 30
+"#,
+    );
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--background")
+        .arg("serve")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Started 'serve' in the background",
+        ));
+    assert!(dir.path().join(".dagrun/pids/serve.pid").exists());
+
+    // a second foreground run sees the live pid file
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("serve")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already running"));
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("kill")
+        .arg("serve")
+        .assert()
+        .success();
+    assert!(!dir.path().join(".dagrun/pids/serve.pid").exists());
+}
+
+#[test]
+fn test_kill_stops_task_children() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+serve:
+    sleep 30 & echo $! > child.pid; wait
+"#,
+    );
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--background")
+        .arg("serve")
+        .assert()
+        .success();
+
+    let pid_file = dir.path().join("child.pid");
+    let mut child = String::new();
+    for _ in 0..50 {
+        child = std::fs::read_to_string(&pid_file).unwrap_or_default();
+        if !child.trim().is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let child = child.trim().to_string();
+    assert!(!child.is_empty(), "task never started its child");
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("kill")
+        .arg("serve")
+        .assert()
+        .success();
+
+    let proc_dir = std::path::Path::new("/proc").join(&child);
+    let mut gone = false;
+    for _ in 0..50 {
+        let state = std::fs::read_to_string(proc_dir.join("stat")).unwrap_or_default();
+        // a zombie has already exited, it just hasn't been reaped yet
+        if state.is_empty() || state.contains(") Z ") {
+            gone = true;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(gone, "sleep {} survived dr kill", child);
+}
+
+#[test]
+fn test_inputs_skip_unchanged() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@inputs src/*.txt
+build:
+    echo "building $(cat src/a.txt)"
+
+@pipe_from build
+package: build
+    tr a-z A-Z
+"#,
+    );
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.txt"), "v1").unwrap();
+
+    let run = || {
+        dr_cmd()
+            .current_dir(dir.path())
+            .arg("-c")
+            .arg(&config)
+            .arg("run")
+            .arg("--no-record")
+            .arg("package")
+            .assert()
+            .success()
+    };
+
+    run().stdout(predicate::str::contains("building v1"));
+    // skipped, but its last output still reaches the task reading it
+    run()
+        .stdout(predicate::str::contains("building").not())
+        .stdout(predicate::str::contains("BUILDING V1"));
+
+    fs::write(dir.path().join("src/a.txt"), "v2").unwrap();
+    run().stdout(predicate::str::contains("building v2"));
+}
+
+#[test]
+fn test_inputs_skip_needs_declared_outputs() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@inputs src/*.txt
+@outputs out/app
+build:
+    mkdir -p out && cp src/a.txt out/app && echo "built"
+"#,
+    );
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.txt"), "v1").unwrap();
+
+    let run = || {
+        dr_cmd()
+            .current_dir(dir.path())
+            .arg("-c")
+            .arg(&config)
+            .arg("run")
+            .arg("--no-record")
+            .arg("build")
+            .assert()
+            .success()
+    };
+
+    run().stdout(predicate::str::contains("built"));
+    run().stdout(predicate::str::contains("built").not());
+
+    // same inputs, but the output is gone
+    fs::remove_file(dir.path().join("out/app")).unwrap();
+    run().stdout(predicate::str::contains("built"));
+    assert!(dir.path().join("out/app").exists());
+}
+
+#[test]
+fn test_inputs_skip_is_per_argument() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@inputs src/*.txt
+deploy target:
+    echo "deploying $(cat src/a.txt) to {{target}}"
+"#,
+    );
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.txt"), "v1").unwrap();
+
+    let run = |target: &str| {
+        dr_cmd()
+            .current_dir(dir.path())
+            .arg("-c")
+            .arg(&config)
+            .arg("run")
+            .arg("--no-record")
+            .arg("deploy")
+            .arg(target)
+            .assert()
+            .success()
+    };
+
+    run("prod").stdout(predicate::str::contains("deploying v1 to prod"));
+    run("staging")
+        .stdout(predicate::str::contains("deploying v1 to staging"))
+        .stdout(predicate::str::contains("to prod").not());
+    // same argument and files as the last run: skipped
+    run("staging").stdout(predicate::str::contains("deploying").not());
+}
+
+#[test]
+fn test_interactive_run_uses_terminal_stdin() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+source:
+    echo "data"
+
+ask:
+    read name && echo "hello $name"
+
+@pipe_from source
+consume: source
+    cat
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--interactive")
+        .arg("ask")
+        .write_stdin("bob\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hello bob"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--interactive")
+        .arg("consume")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("@pipe_from"));
+}
+
+#[test]
+fn test_dry_run_prints_commands() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ran");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+build:
+    touch {}
+
+deploy: build
+    echo deploying
+"#,
+            marker.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--dry-run")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("# task: build"))
+        .stdout(predicate::str::contains("sh -c 'echo deploying'"))
+        .stdout(predicate::str::contains("Results:").not());
+    assert!(!marker.exists());
+}
+
+#[test]
+fn test_dry_run_lines_format() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ran");
+    let config = create_dagfile(
+        &dir,
+        &format!(
+            r#"
+build:
+    touch {}
+
+deploy: build
+    echo deploying
+"#,
+            marker.display()
+        ),
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--dry-run")
+        .arg("--dry-run-format")
+        .arg("lines")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "[dry-run] build: touch {}",
+            marker.display()
+        )))
+        .stdout(predicate::str::contains("[dry-run] deploy: echo deploying"))
+        .stdout(predicate::str::contains("# task:").not());
+    assert!(!marker.exists());
+
+    // the format only means something alongside --dry-run
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--dry-run-format")
+        .arg("lines")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--dry-run"));
+    assert!(!marker.exists());
+}
+
+#[test]
+fn test_env_file_flag_later_files_win() {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().join("base.env");
+    let staging = dir.path().join("staging.env");
+    fs::write(&base, "DR_TARGET=base\nDR_REGION=us-east\n").unwrap();
+    fs::write(&staging, "DR_TARGET=staging\n").unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+show:
+    echo "$DR_TARGET $DR_REGION"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("show")
+        .arg("--env-file")
+        .arg(&base)
+        .arg("--env-file")
+        .arg(&staging)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("staging us-east"));
+}
+
+#[test]
+fn test_env_file_flag_beats_dotenv_override() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".env"),
+        "DR_TARGET=dotenv\nDR_REGION=us-east\n",
+    )
+    .unwrap();
+    let staging = dir.path().join("staging.env");
+    fs::write(&staging, "DR_TARGET=staging\n").unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+set dotenv-load
+set dotenv-override := true
+
+show:
+    echo "$DR_TARGET $DR_REGION"
+"#,
+    );
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("show")
+        .arg("--env-file")
+        .arg(&staging)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("staging us-east"));
+}
+
+#[test]
+fn test_implicit_run_named_args() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+deploy env version="latest" region="us-east":
+    echo "deploying {{version}} to {{env}} in {{region}}"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("region=eu-west")
+        .arg("--env=prod")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "deploying latest to prod in eu-west",
+        ));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("prod")
+        .arg("version=1.2")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("both positional and named"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("env=prod")
+        .arg("env=staging")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("given more than once"));
+}
+
+#[test]
+fn test_env_flag_sets_env_parameter() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+deploy env region="us-east":
+    echo "deploying to $env_name {{env}} in {{region}}"
+"#,
+    );
+
+    // first after the task name, where clap sees it as the run flag
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--env=prod")
+        .arg("--no-record")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying to  prod in us-east"));
+
+    // values with `=` are still environment variables
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--env=prod")
+        .arg("--env")
+        .arg("env_name=blue")
+        .arg("--no-record")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "deploying to blue prod in us-east",
+        ));
+
+    // tasks without an `env` parameter get the usual error
+    create_dagfile(&dir, "build:\n    echo built\n");
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("build")
+        .arg("--env=prod")
+        .arg("--no-record")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected KEY=VALUE"));
+}
+
+#[test]
+fn test_parameter_allowed_values() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+deploy env{dev,staging,prod}:
+    echo "deploying to {{env}}"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("staging")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying to staging"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("deploy")
+        .arg("--no-record")
+        .arg("qa")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("deploying").not())
+        .stderr(predicate::str::contains(
+            "invalid value 'qa' for parameter 'env': expected one of: dev, staging, prod",
+        ));
+}
+
+#[test]
+fn test_parameter_types() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+release version:int patch:bool="false":
+    echo "releasing {{version}} patch={{patch}}"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("release")
+        .arg("--no-record")
+        .arg("3")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("releasing 3 patch=false"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("release")
+        .arg("--no-record")
+        .arg("three")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("releasing").not())
+        .stderr(predicate::str::contains(
+            "invalid value 'three' for parameter 'version': expected int",
+        ));
+}
+
+#[test]
+fn test_validate_strict_warns_on_service_without_ready() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@service
+server:
+    sleep 60
+
+test: server
+    echo ok
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("validate")
+        .assert()
+        .success();
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("validate")
+        .arg("--strict")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("no ready= check"));
+}
+
+#[test]
+fn test_fmt_rewrites_dagfile() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        "b := 2\na := 1\nbuild:\n\techo {{a}}{{b}}\n\n\ntest: build\n\techo ok\n",
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("fmt")
+        .arg("--check")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not formatted"));
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("fmt")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&config).unwrap(),
+        "a := 1\nb := 2\n\nbuild:\n    echo {{a}}{{b}}\n\ntest: build\n    echo ok\n"
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("fmt")
+        .arg("--check")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_failed_task_reports_exit_code() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+fail:
+    exit 3
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("fail")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("fail (exit 3)"));
+}
+
+#[test]
+fn test_on_failure_runs_handler() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@on-failure cleanup
+deploy:
+    exit 3
+
+cleanup:
+    echo "cleaning up"
+    exit 1
+"#,
+    );
+
+    // the handler's own failure doesn't show up in the results
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("cleaning up"))
+        .stdout(predicate::str::contains("deploy (exit 3)"))
+        .stdout(predicate::str::contains("cleanup (exit 1)").not());
+}
+
+#[test]
+fn test_on_failure_handler_runs_after_running_as_dependency() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+cleanup:
+    echo "cleaning up"
+
+@on-failure cleanup
+deploy: cleanup
+    exit 3
+"#,
+    );
+
+    let output = dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).unwrap();
+    // once as the dependency, once more for the failure
+    assert_eq!(stdout.matches("cleaning up").count(), 2, "{}", stdout);
+}
+
+#[test]
+fn test_output_capture_sets_variable_for_later_tasks() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@output-capture VERSION
+version:
+    echo "  1.2.3  "
+
+release: version
+    echo "releasing [{{VERSION}}]"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("release")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("releasing [1.2.3]"));
+}
+
+#[test]
+fn test_output_capture_in_every_run_mode() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@output-capture VERSION
+version:
+    echo "1.2.3"
+
+release channel: version
+    echo "releasing {{VERSION}} to {{channel}}"
+"#,
+    );
+    let run = || {
+        let mut cmd = dr_cmd();
+        cmd.arg("-c").arg(&config).arg("run").arg("--no-record");
+        cmd
+    };
+
+    // with task arguments
+    run()
+        .arg("release")
+        .arg("beta")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("releasing 1.2.3 to beta"));
+
+    // attached to the terminal
+    run()
+        .arg("--interactive")
+        .arg("release")
+        .arg("stable")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("releasing 1.2.3 to stable"));
+
+    // the captured value isn't known without running anything
+    run()
+        .arg("--dry-run")
+        .arg("release")
+        .arg("stable")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "it uses {{VERSION}} from @output-capture, which is only known once 'version' runs",
+        ));
+
+    // an interactive task's output isn't collected, so there'd be nothing to capture
+    run()
+        .arg("--interactive")
+        .arg("version")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "@output-capture VERSION would be empty",
+        ));
+}
+
+#[test]
+fn test_when_condition_skips_task_but_not_dependents() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@when test -f needs-migration
+migrate:
+    echo migrating
+
+deploy: migrate
+    echo deploying
+"#,
+    );
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploying"))
+        .stdout(predicate::str::contains("migrating").not());
+
+    std::fs::write(dir.path().join("needs-migration"), "").unwrap();
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("migrating"));
+}
+
+#[test]
+fn test_matrix_runs_each_expanded_task() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@matrix target amd64,arm64
+build:
+    echo "building {{target}}"
+
+release: build-amd64 build-arm64
+    echo released
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("release")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building amd64"))
+        .stdout(predicate::str::contains("building arm64"))
+        .stdout(predicate::str::contains("released"));
+}
+
+#[test]
+fn test_profile_and_gc() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    echo "building"
+"#,
+    );
+    fs::create_dir(dir.path().join(".dagrun")).unwrap();
+    let lines: String = [100, 200, 300, 400]
+        .iter()
+        .enumerate()
+        .map(|(i, ms)| {
+            format!(
+                "{{\"task\":\"build\",\"duration_ms\":{},\"exit_code\":0,\"timestamp\":{}}}\n",
+                ms,
+                1_700_000_000 + i
+            )
+        })
+        .collect();
+    fs::write(dir.path().join(".dagrun/stats.json"), lines).unwrap();
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("profile")
+        .arg("build")
+        .arg("--history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build (4 runs)"))
+        .stdout(predicate::str::contains("p95"))
+        .stdout(predicate::str::contains("2023-11-14T22:13:20Z"));
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("gc")
+        .arg("--keep")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 2"));
+    let remaining = fs::read_to_string(dir.path().join(".dagrun/stats.json")).unwrap();
+    assert_eq!(remaining.lines().count(), 2);
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("profile")
+        .arg("missing")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No recorded runs"));
+}
+
+#[test]
+fn test_gc_k8s_keeps_jobs_within_ttl() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(&dir, "build:\n    echo building\n");
+    fs::create_dir(dir.path().join(".dagrun")).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let log = format!(
+        "{{\"name\":\"build-abc12\",\"namespace\":\"default\",\"created\":{},\"ttl_seconds\":3600}}\n",
+        now
+    );
+    fs::write(dir.path().join(".dagrun/k8s-jobs.json"), &log).unwrap();
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("gc")
+        .arg("--k8s")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Deleted 0 stale k8s jobs"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join(".dagrun/k8s-jobs.json")).unwrap(),
+        log
+    );
+}
+
+#[test]
+fn test_status_shows_saved_metrics() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(&dir, "build:\n    echo building\n");
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("status")
+        .arg("--metrics")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No background runs"))
+        .stdout(predicate::str::contains("No service metrics scraped yet"));
+
+    fs::create_dir_all(dir.path().join(".dagrun/metrics")).unwrap();
+    fs::write(
+        dir.path().join(".dagrun/metrics/api.json"),
+        r#"{"scraped_at":{"secs_since_epoch":1700000000,"nanos_since_epoch":0},"values":{"http_requests_total{code=\"200\"}":1027.0,"up":1.0}}"#,
+    )
+    .unwrap();
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("status")
+        .arg("--metrics")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("api"))
+        .stdout(predicate::str::contains(
+            "http_requests_total{code=\"200\"}  1027",
+        ))
+        .stdout(predicate::str::contains("up"));
+}
+
+#[test]
+fn test_gc_keeps_entries_newer_than_its_start() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(&dir, "build:\n    echo building\n");
+    fs::create_dir(dir.path().join(".dagrun")).unwrap();
+    // the last entry stands in for a run that finished after gc started
+    let lines: String = [1_700_000_000u64, 1_700_000_001, 1_700_000_002, u64::MAX / 2]
+        .iter()
+        .map(|timestamp| {
+            format!(
+                "{{\"task\":\"build\",\"duration_ms\":100,\"exit_code\":0,\"timestamp\":{}}}\n",
+                timestamp
+            )
+        })
+        .collect();
+    fs::write(dir.path().join(".dagrun/stats.json"), lines).unwrap();
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("gc")
+        .arg("--keep")
+        .arg("1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 2"));
+    let remaining = fs::read_to_string(dir.path().join(".dagrun/stats.json")).unwrap();
+    let timestamps: Vec<&str> = remaining
+        .lines()
+        .map(|line| line.rsplit(':').next().unwrap().trim_end_matches('}'))
+        .collect();
+    assert_eq!(
+        timestamps,
+        ["1700000002", (u64::MAX / 2).to_string().as_str()]
+    );
+}
+
+#[test]
+fn test_concurrent_runs_keep_all_stats() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    echo "building"
+"#,
+    );
+
+    let runs: Vec<_> = (0..8)
+        .map(|_| {
+            std::process::Command::new(env!("CARGO_BIN_EXE_dr"))
+                .current_dir(dir.path())
+                .arg("-c")
+                .arg(&config)
+                .arg("build")
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut run in runs {
+        assert!(run.wait().unwrap().success());
+    }
+
+    let stats = fs::read_to_string(dir.path().join(".dagrun/stats.json")).unwrap();
+    assert_eq!(stats.lines().count(), 8);
+}
+
+#[test]
+fn test_dotenv_override() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(".env"), "GREETING=from-file\n").unwrap();
+
+    let run = |config: &std::path::Path| {
+        dr_cmd()
+            .current_dir(dir.path())
+            .env("GREETING", "from-host")
+            .arg("-c")
+            .arg(config)
+            .arg("run")
+            .arg("--no-record")
+            .arg("hello")
+            .assert()
+            .success()
+    };
+
+    let config = create_dagfile(&dir, "set dotenv-load\n\nhello:\n    echo $GREETING\n");
+    run(&config).stdout(predicate::str::contains("from-host"));
+
+    let config = create_dagfile(
+        &dir,
+        "set dotenv-load\nset dotenv-override\n\nhello:\n    echo $GREETING\n",
+    );
+    run(&config).stdout(predicate::str::contains("from-file"));
+}
+
+#[test]
+fn test_man_page() {
+    // no dagfile needed
+    let dir = TempDir::new().unwrap();
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("man")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".TH dr"))
+        .stdout(predicate::str::contains("dr profile"))
+        .stdout(predicate::str::contains(".SH FILES"))
+        .stdout(predicate::str::contains("#@ssh"));
+
+    let out = dir.path().join("dr.1");
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("man")
+        .arg("--output")
+        .arg(&out)
+        .assert()
+        .success();
+    let page = fs::read_to_string(out).unwrap();
+    assert!(page.contains(".SH COMMANDS"));
+    // one preamble and title, at the top
+    assert!(page.starts_with(".ie \\n(.g .ds Aq \\(aq\n.el .ds Aq '\n.TH dr"));
+    assert_eq!(page.matches(".ds Aq").count(), 2);
+    assert_eq!(page.matches(".TH ").count(), 1);
+}
+
+#[test]
+fn test_include() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("tasks")).unwrap();
+    fs::write(
+        dir.path().join("tasks/build.dr"),
+        "target := release\n\nbuild:\n    echo \"building {{target}}\"\n",
+    )
+    .unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+@include ./tasks/build.dr
+
+deploy: build
+    echo "deploying {{target}}"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building release"))
+        .stdout(predicate::str::contains("deploying release"));
+}
+
+#[test]
+fn test_include_local_definitions_win() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("shared.dr"),
+        "build := included\n\nlint:\n    echo \"included lint\"\n",
+    )
+    .unwrap();
+    // the include comes after the local definitions, which still win
+    let config = create_dagfile(
+        &dir,
+        r#"
+build := local-build
+
+lint:
+    echo "local lint"
+
+@include shared.dr
+
+ci: lint
+    echo "ci {{build}}"
+"#,
+    );
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("ci")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("local lint"))
+        .stdout(predicate::str::contains("ci local-build"))
+        .stdout(predicate::str::contains("included").not());
+}
+
+#[test]
+fn test_include_cycle() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.dr"), "@include b.dr\n").unwrap();
+    fs::write(dir.path().join("b.dr"), "@include a.dr\n").unwrap();
+    let config = create_dagfile(&dir, "@include a.dr\n\nbuild:\n    echo hi\n");
+
+    dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("circular include"))
+        .stderr(predicate::str::contains("a.dr -> "));
+}
+
+#[test]
+fn test_env_annotation() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        "port := 8080\n\n@env RUST_LOG=debug PORT={{port}}\nserve:\n    echo \"$RUST_LOG on $PORT\"\n",
+    );
+
+    dr_cmd()
+        .current_dir(dir.path())
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("serve")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("debug on 8080"));
+}
+
+#[test]
+fn test_json_results_format() {
+    let dir = TempDir::new().unwrap();
+    let config = create_dagfile(
+        &dir,
+        r#"
+build:
+    echo built
+
+test: build
+    echo tested
+"#,
+    );
+
+    let output = dr_cmd()
+        .arg("-c")
+        .arg(&config)
+        .arg("run")
+        .arg("--no-record")
+        .arg("--format")
+        .arg("json")
+        .arg("--capture")
+        .arg("test")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // task output goes to stderr, so stdout is only the results
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["task_name"], "build");
+    assert_eq!(results[0]["status"], "success");
+    assert!(results[0]["duration_ms"].is_u64());
+    assert_eq!(results[1]["output"], "tested\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("built"));
 }